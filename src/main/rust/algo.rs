@@ -2,7 +2,9 @@
 //use log::debug;
 use crate::error;
 use crate::graph;
+use crate::graph::Weight;
 use crate::prng;
+use std::borrow;
 use std::cmp;
 use std::collections;
 use std::fmt;
@@ -46,10 +48,10 @@ impl<T> PartialOrd for MultiLabelState<T>
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub struct MultiSourceState
+#[derive(Copy, Clone)]
+pub struct MultiSourceState<W = i64>
 {
-    pub cost: i64,
+    pub cost: W,
     pub source: usize,
     pub v: usize
 }
@@ -57,52 +59,172 @@ pub struct MultiSourceState
 // The priority queue depends on `Ord`.
 // Explicitly implement the trait so the queue becomes a min-heap
 // instead of a max-heap.
-impl Ord for MultiSourceState {
+impl<W> Ord for MultiSourceState<W>
+    where W: graph::Weight
+{
     fn cmp(&self, other: &Self) -> cmp::Ordering
     {
         // Notice that we flip the ordering on costs.
         // In case of a tie we compare positions - this step is necessary
         // to make implementations of `PartialEq` and `Ord` consistent.
-        other.cost.cmp (&self.cost)
+        other.cost.compare (&self.cost)
             .then_with (|| self.v.cmp(&other.v))
             .then_with (|| self.source.cmp(&other.source))
     }
 }
 
 // `PartialOrd` needs to be implemented as well.
-impl PartialOrd for MultiSourceState {
+impl<W> PartialOrd for MultiSourceState<W>
+    where W: graph::Weight
+{
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+// Weights need not be Eq (f64), so equality follows the total order
+impl<W> PartialEq for MultiSourceState<W>
+    where W: graph::Weight
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.cmp (other) == cmp::Ordering::Equal
+    }
+}
+
+impl<W> Eq for MultiSourceState<W>
+    where W: graph::Weight
+{
+}
+
+#[derive(Copy, Clone)]
+struct SearchState<W = i64>
+{
+    cost: W,
+    v: usize
+}
+
+// Ordered like MultiSourceState so the queue becomes a min-heap on cost
+impl<W> Ord for SearchState<W>
+    where W: graph::Weight
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering
+    {
+        other.cost.compare (&self.cost)
+            .then_with (|| self.v.cmp(&other.v))
+    }
+}
+
+impl<W> PartialOrd for SearchState<W>
+    where W: graph::Weight
+{
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering>
     {
         Some(self.cmp(other))
     }
 }
 
+impl<W> PartialEq for SearchState<W>
+    where W: graph::Weight
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.cmp (other) == cmp::Ordering::Equal
+    }
+}
+
+impl<W> Eq for SearchState<W>
+    where W: graph::Weight
+{
+}
+
+// A vertex reached over a given number of edges. Ties on cost go to the
+// state with fewer hops.
+#[derive(Copy, Clone)]
+struct HopState<W = i64>
+{
+    cost: W,
+    hops: usize,
+    v: usize
+}
+
+impl<W> Ord for HopState<W>
+    where W: graph::Weight
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering
+    {
+        other.cost.compare (&self.cost)
+            .then_with (|| other.hops.cmp(&self.hops))
+            .then_with (|| self.v.cmp(&other.v))
+    }
+}
+
+impl<W> PartialOrd for HopState<W>
+    where W: graph::Weight
+{
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+impl<W> PartialEq for HopState<W>
+    where W: graph::Weight
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.cmp (other) == cmp::Ordering::Equal
+    }
+}
+
+impl<W> Eq for HopState<W>
+    where W: graph::Weight
+{
+}
+
+// A path cost extended by an edge, an error rather than an overflow
+fn path_cost<W: graph::Weight> (cost: &W, weight: &W)
+    -> Result<W, error::GraphError>
+{
+    cost.checked_combine (weight)
+        .ok_or (error::GraphError::EdgeError (format! ("Path cost {:?} plus {:?} overflows", cost, weight)))
+}
+
+fn path_labels<G: graph::LabelledGraphAny> (g: &G, path: &[usize])
+    -> Result<Vec<G::Label>, error::GraphError>
+{
+    path.iter ().map (|v| g.vertex_label (v)).collect ()
+}
+
+type DistancesAllPaths<W> = (collections::HashMap<usize, W>, collections::HashMap<usize, collections::HashSet<Vec<usize>>>);
+
 pub fn all_shortest_paths<G: graph::GraphAny> (g: &G, sources: &collections::HashSet<usize>)
-    -> Result<collections::HashMap<usize, (collections::HashMap<usize, i64>, collections::HashMap<usize, collections::HashSet<Vec<usize>>>)>, error::GraphError>
+    -> Result<collections::HashMap<usize, DistancesAllPaths<G::Weight>>, error::GraphError>
 {
-    let mut r = collections::HashMap::<usize, (collections::HashMap<usize,i64>, collections::HashMap<usize, collections::HashSet<Vec<usize>>>)>::new ();
-    let mut fringe = collections::BinaryHeap::<MultiSourceState>::new ();
+    let mut r = collections::HashMap::<usize, DistancesAllPaths<G::Weight>>::new ();
+    let mut fringe = collections::BinaryHeap::<MultiSourceState<G::Weight>>::new ();
 
     for source in sources
     {
-        fringe.push (MultiSourceState { cost: 0, source: *source, v: *source });
-        r.insert (*source, ( collections::HashMap::<usize,i64>::from ([ ( *source, 0 ) ]), collections::HashMap::<usize, collections::HashSet<Vec<usize>>>::from ([ ( *source, collections::HashSet::<Vec<usize>>::from ([ Vec::from ([*source]) ]) ) ])) );
+        fringe.push (MultiSourceState { cost: G::Weight::zero (), source: *source, v: *source });
+        r.insert (*source, ( collections::HashMap::<usize,G::Weight>::from ([ ( *source, G::Weight::zero () ) ]), collections::HashMap::<usize, collections::HashSet<Vec<usize>>>::from ([ ( *source, collections::HashSet::<Vec<usize>>::from ([ Vec::from ([*source]) ]) ) ])) );
     }
 
     while let Some (MultiSourceState { cost, source, v }) = fringe.pop ()
     {
-        if cost > *r.get (&source).unwrap ().0.get (&v).unwrap ()
+        if cost.compare (r.get (&source).unwrap ().0.get (&v).unwrap ()) == cmp::Ordering::Greater
         {
             continue;
         }
         else
         {
-            for v_child in g.adjacent (&v)?
+            for v_child in g.adjacent_iter (&v)?
             {
                 let e = (v, v_child);
                 let ew = g.weight (&e)?;
 
-                let mss_next = MultiSourceState { cost: cost + ew, source: source, v: v_child };
+                let mss_next = MultiSourceState { cost: path_cost (&cost, &ew)?, source, v: v_child };
 
                 let paths_nxt = r.get (&source).unwrap ().1.get (&v_child)
                     .cloned ()
@@ -144,7 +266,7 @@ pub fn all_shortest_paths<G: graph::GraphAny> (g: &G, sources: &collections::Has
 
                 if let Some (dist_next) = r.get (&source).unwrap ().0.get (&mss_next.v).copied ()
                 {
-                    if mss_next.cost <= dist_next && !paths_new.is_empty ()
+                    if mss_next.cost.compare (&dist_next) != cmp::Ordering::Greater && !paths_new.is_empty ()
                     {
                         r.get_mut (&source).unwrap ().0.insert (mss_next.v, mss_next.cost);
                         r.get_mut (&source).unwrap ().1.insert (mss_next.v, paths_combined);
@@ -165,7 +287,7 @@ pub fn all_shortest_paths<G: graph::GraphAny> (g: &G, sources: &collections::Has
     Ok (r)
 }
 
-pub fn ancestors (g: &graph::Graph, source: usize)
+pub fn ancestors<G: graph::DiGraphAny> (g: &G, source: usize)
     -> Result<collections::HashSet<usize>, error::GraphError>
 {
     Ok (bfs_edges_reverse (g, source)?.into_iter ()
@@ -173,77 +295,361 @@ pub fn ancestors (g: &graph::Graph, source: usize)
         .collect::<collections::HashSet<_>> ())
 }
 
+type CostPath<W> = (W, Vec<usize>);
+
+// A* from source to target, None when the target cannot be reached. The
+// heuristic estimates the remaining cost from a vertex to the target and must
+// never overestimate it for the result to be a shortest path. Weights must not
+// be negative.
+pub fn astar<G, H> (g: &G, source: usize, target: usize, heuristic: H)
+    -> Result<Option<CostPath<G::Weight>>, error::GraphError>
+    where
+        G: graph::GraphAny,
+        H: Fn (usize) -> G::Weight
+{
+    for v in [source, target]
+    {
+        if !g.vertices ().contains (&v)
+        {
+            return Err (error::GraphError::VertexError (format! ("Vertex: {} not found in graph", v)));
+        }
+    }
+
+    let mut distances = collections::HashMap::<usize, G::Weight>::from ([(source, G::Weight::zero ())]);
+    let mut predecessors = collections::HashMap::<usize, usize>::new ();
+    let mut fringe = collections::BinaryHeap::<SearchState<G::Weight>>::from ([SearchState { cost: heuristic (source), v: source }]);
+
+    while let Some (SearchState { cost, v }) = fringe.pop ()
+    {
+        let distance = distances[&v];
+        if v == target
+        {
+            return Ok (Some ( (distance, predecessor_path (&predecessors, target)) ));
+        }
+        // a cheaper route to v was queued after this one
+        if cost.compare (&path_cost (&distance, &heuristic (v))?) == cmp::Ordering::Greater
+        {
+            continue;
+        }
+        for v_child in g.adjacent_iter (&v)?
+        {
+            let distance_next = path_cost (&distance, &g.weight (&(v, v_child))?)?;
+            if distances.get (&v_child).is_none_or (|x| distance_next.compare (x) == cmp::Ordering::Less)
+            {
+                distances.insert (v_child, distance_next);
+                predecessors.insert (v_child, v);
+                fringe.push (SearchState { cost: path_cost (&distance_next, &heuristic (v_child))?, v: v_child });
+            }
+        }
+    }
+    Ok (None)
+}
+
+// Bellman-Ford from a single source. Slower than Dijkstra but correct with
+// negative weights; a negative cycle reachable from the source is an error
+// naming the cycle.
+pub fn bellman_ford<G: graph::GraphAny> (g: &G, source: usize)
+    -> Result<DistancesPaths<G::Weight>, error::GraphError>
+{
+    if !g.vertices ().contains (&source)
+    {
+        return Err (error::GraphError::VertexError (format! ("Vertex: {} not found in graph", source)));
+    }
+    let (distances, predecessors) = bellman_ford_relax (g, collections::HashMap::from ([(source, G::Weight::zero ())]))?;
+    let paths = distances.keys ()
+        .map (|v| (*v, predecessor_path (&predecessors, *v)))
+        .collect ();
+    Ok ( (distances, paths) )
+}
+
+pub fn multi_source_bellman_ford<G: graph::GraphAny> (g: &G, sources: &collections::HashSet<usize>)
+    -> Result<collections::HashMap<usize, DistancesPaths<G::Weight>>, error::GraphError>
+{
+    sources.iter ()
+        .map (|source| Ok ( (*source, bellman_ford (g, *source)?) ))
+        .collect ()
+}
+
+type DistancesPredecessors<W> = (collections::HashMap<usize, W>, collections::HashMap<usize, usize>);
+
+// Relaxes every edge until the distances settle, starting from the given ones
+fn bellman_ford_relax<G: graph::GraphAny> (g: &G, mut distances: collections::HashMap<usize, G::Weight>)
+    -> Result<DistancesPredecessors<G::Weight>, error::GraphError>
+{
+    let mut vertices = g.vertices ().iter ().copied ().collect::<Vec<usize>> ();
+    vertices.sort ();
+    let mut edges = Vec::<((usize, usize), G::Weight)>::new ();
+    for v in vertices.iter ()
+    {
+        let mut children = g.adjacent_iter (v)?.collect::<Vec<usize>> ();
+        children.sort ();
+        for w in children
+        {
+            edges.push ( ((*v, w), g.weight (&(*v, w))?) );
+        }
+    }
+
+    let mut predecessors = collections::HashMap::<usize, usize>::new ();
+    let mut relaxed = None;
+    // shortest paths have fewer edges than there are vertices, so anything
+    // still improving after that many rounds goes round a negative cycle
+    for _ in 0..vertices.len ()
+    {
+        relaxed = None;
+        for ((a, b), w) in edges.iter ()
+        {
+            if let Some (da) = distances.get (a).copied ()
+            {
+                let db = path_cost (&da, w)?;
+                if distances.get (b).is_none_or (|x| db.compare (x) == cmp::Ordering::Less)
+                {
+                    distances.insert (*b, db);
+                    predecessors.insert (*b, *a);
+                    relaxed = Some (*b);
+                }
+            }
+        }
+        if relaxed.is_none ()
+        {
+            break;
+        }
+    }
+
+    match relaxed
+    {
+        None => Ok ( (distances, predecessors) ),
+        Some (mut v) => {
+            let predecessor = |v: usize| predecessors.get (&v).copied ()
+                .ok_or (error::GraphError::AlgorithmError (format! ("No predecessor for {}", v)));
+            // walking back far enough is sure to land on the cycle
+            for _ in 0..vertices.len ()
+            {
+                v = predecessor (v)?;
+            }
+            let mut cycle = Vec::<usize>::from ([v]);
+            let mut u = predecessor (v)?;
+            while u != v
+            {
+                cycle.push (u);
+                u = predecessor (u)?;
+            }
+            cycle.reverse ();
+            let start = cycle.iter ().enumerate ().min_by_key (|(_, x)| **x).map (|(i, _)| i).unwrap_or (0);
+            cycle.rotate_left (start);
+            Err (error::GraphError::EdgeError (format! ("Graph contains a negative cycle: {}", cycle_path (&cycle))))
+        }
+    }
+}
+
+fn predecessor_path (predecessors: &collections::HashMap<usize, usize>, target: usize)
+    -> Vec<usize>
+{
+    let mut path = Vec::<usize>::from ([target]);
+    while let Some (v) = predecessors.get (path.last ().unwrap_or (&target))
+    {
+        path.push (*v);
+    }
+    path.reverse ();
+    path
+}
+
+// The cheapest path from source to target in a predecessor map of
+// dijkstra_with_options, None when target was not reached from source
+pub fn reconstruct_path (predecessors: &HopPredecessors, source: usize, target: usize)
+    -> Option<Vec<usize>>
+{
+    let mut path = Vec::<usize>::from ([target]);
+    let mut step = predecessors.get (&target).and_then (|x| x.first ()).copied ();
+    while let Some ( (hops, u) ) = step
+    {
+        path.push (u);
+        // u was reached over one edge less, which picks its path among those kept
+        step = match hops
+        {
+            1 => None,
+            _ => Some ( (hops - 1, predecessors.get (&u)?.iter ().find (|(h, _)| *h == hops - 1)?.1) )
+        };
+    }
+    if path.last () != Some (&source)
+    {
+        return None;
+    }
+    path.reverse ();
+    Some (path)
+}
+
 pub fn bfs_edges<G: graph::GraphAny> (g: &G, source: usize)
     -> Result<Vec<(usize, usize)>, error::GraphError>
 {
-    let neighbours = g.vertices ().iter ().fold (collections::HashMap::<usize, collections::HashSet<usize>>::new (), |mut acc, item| {
-        acc.insert (*item, g.adjacent (item).unwrap ());
-        acc
-        });
-    let mut neighbours_iters = neighbours.iter ().fold (collections::HashMap::<usize, collections::hash_set::Iter<'_, usize>>::new (), |mut acc, (k, v)| {
-        acc.insert (*k, v.iter () );
-        acc
-    });
     let mut visited = collections::HashSet::<usize>::from ([source]);
     let mut r = Vec::<(usize, usize)>::new ();
-    let mut queue = collections::VecDeque::<(usize, usize)>::from (vec![(source, 0)]);
+    let mut queue = collections::VecDeque::<usize>::from (vec![source]);
 
-    while let Some ( (parent, current_depth ) ) = queue.front ()
+    while let Some (parent) = queue.pop_front ()
     {
-        if let Some (child) = neighbours_iters.get_mut (parent).ok_or (error::GraphError::AlgorithmError (format! ("No neighbours iter for {}", parent)))?.next ()
+        for child in g.adjacent_iter (&parent)?
         {
-            if !visited.contains (child)
+            if visited.insert (child)
             {
-                r.push ( (*parent, *child) );
-                visited.insert (*child);
-                queue.push_back ( (*child, *current_depth + 1) );
+                r.push ( (parent, child) );
+                queue.push_back (child);
             }
         }
-        else
-        {
-                queue.pop_front ();
-        }
     }
 
     Ok (r)
 }
 
-pub fn bfs_edges_reverse (g: &graph::Graph, source: usize)
+type LabelledEdges<L> = Vec<(L, L)>;
+
+pub fn bfs_edges_labelled<G, Q> (g: &G, source: &Q)
+    -> Result<LabelledEdges<G::Label>, error::GraphError>
+    where
+        G: graph::LabelledGraphAny,
+        G::Label: borrow::Borrow<Q>,
+        Q: hash::Hash + Eq + fmt::Debug + ?Sized
+{
+    bfs_edges (g, g.vertex (source)?)?.iter ()
+        .map (|(a, b)| Ok ( (g.vertex_label (a)?, g.vertex_label (b)?) ))
+        .collect ()
+}
+
+pub fn bfs_edges_reverse<G: graph::DiGraphAny> (g: &G, source: usize)
     -> Result<Vec<(usize, usize)>, error::GraphError>
 {
-    let neighbours = g.vertices ().iter ().fold (collections::HashMap::<usize, collections::HashSet<usize>>::new (), |mut acc, item| {
-        acc.insert (*item, g.inbound (item).unwrap ());
-        acc
-        });
-    let mut neighbours_iters = neighbours.iter ().fold (collections::HashMap::<usize, collections::hash_set::Iter<'_, usize>>::new (), |mut acc, (k, v)| {
-        acc.insert (*k, v.iter () );
-        acc
-    });
     let mut visited = collections::HashSet::<usize>::from ([source]);
     let mut r = Vec::<(usize, usize)>::new ();
-    let mut queue = collections::VecDeque::<(usize, usize)>::from (vec![(source, 0)]);
+    let mut queue = collections::VecDeque::<usize>::from (vec![source]);
 
-    while let Some ( (child, current_depth ) ) = queue.front ()
+    while let Some (child) = queue.pop_front ()
     {
-        if let Some (parent) = neighbours_iters.get_mut (child).ok_or (error::GraphError::AlgorithmError (format! ("No neighbours iter for {}", child)))?.next ()
+        for parent in g.inbound_iter (&child)?
         {
-            if !visited.contains (parent)
+            if visited.insert (parent)
             {
-                r.push ( (*parent, *child) );
-                visited.insert (*parent);
-                queue.push_back ( (*parent, *current_depth + 1) );
+                r.push ( (parent, child) );
+                queue.push_back (parent);
             }
         }
-        else
+    }
+
+    Ok (r)
+}
+
+// Dijkstra from both ends at once, forwards along outbound edges from the
+// source and backwards along inbound edges from the target. None when the
+// target cannot be reached. Weights must not be negative.
+pub fn bidirectional_dijkstra<G: graph::DiGraphAny> (g: &G, source: usize, target: usize)
+    -> Result<Option<CostPath<G::Weight>>, error::GraphError>
+{
+    let forward = |v: usize| g.outbound_iter (&v)?.map (|w| Ok ( (w, g.weight (&(v, w))?) )).collect ();
+    let backward = |v: usize| g.inbound_iter (&v)?.map (|w| Ok ( (w, g.weight (&(w, v))?) )).collect ();
+    bidirectional_search (g, source, target, forward, backward)
+}
+
+// As bidirectional_dijkstra with edge directions ignored. On a directed graph
+// an edge can be walked either way, taking the lighter of a pair of opposite
+// edges.
+pub fn bidirectional_dijkstra_undirected<G: graph::GraphAny> (g: &G, source: usize, target: usize)
+    -> Result<Option<CostPath<G::Weight>>, error::GraphError>
+{
+    let neighbours = |v: usize| g.neighbours_iter (&v)?.map (|w| Ok ( (w, undirected_weight (g, v, w)?) )).collect ();
+    bidirectional_search (g, source, target, neighbours, neighbours)
+}
+
+fn undirected_weight<G: graph::GraphAny> (g: &G, v: usize, w: usize)
+    -> Result<G::Weight, error::GraphError>
+{
+    match (g.weight (&(v, w)), g.weight (&(w, v)))
+    {
+        (Ok (x), Ok (y)) => Ok (if y.compare (&x) == cmp::Ordering::Less { y } else { x }),
+        (Ok (x), Err (_)) | (Err (_), Ok (x)) => Ok (x),
+        (Err (e), Err (_)) => Err (e)
+    }
+}
+
+// forward and backward give the next vertices on each side with the weight
+// of the edge leading to them
+fn bidirectional_search<G, F, B> (g: &G, source: usize, target: usize, forward: F, backward: B)
+    -> Result<Option<CostPath<G::Weight>>, error::GraphError>
+    where
+        G: graph::GraphAny,
+        F: Fn (usize) -> Result<Vec<(usize, G::Weight)>, error::GraphError>,
+        B: Fn (usize) -> Result<Vec<(usize, G::Weight)>, error::GraphError>
+{
+    for v in [source, target]
+    {
+        if !g.vertices ().contains (&v)
         {
-                queue.pop_front ();
+            return Err (error::GraphError::VertexError (format! ("Vertex: {} not found in graph", v)));
         }
     }
+    if source == target
+    {
+        return Ok (Some ( (G::Weight::zero (), Vec::from ([source])) ));
+    }
 
-    Ok (r)
+    // index 0 searches forwards from the source, index 1 backwards from the target
+    let mut distances = [
+        collections::HashMap::<usize, G::Weight>::from ([(source, G::Weight::zero ())]),
+        collections::HashMap::<usize, G::Weight>::from ([(target, G::Weight::zero ())])
+    ];
+    let mut predecessors = [collections::HashMap::<usize, usize>::new (), collections::HashMap::<usize, usize>::new ()];
+    let mut settled = [collections::HashSet::<usize>::new (), collections::HashSet::<usize>::new ()];
+    let mut fringe = [
+        collections::BinaryHeap::<SearchState<G::Weight>>::from ([SearchState { cost: G::Weight::zero (), v: source }]),
+        collections::BinaryHeap::<SearchState<G::Weight>>::from ([SearchState { cost: G::Weight::zero (), v: target }])
+    ];
+    let mut best: Option<(G::Weight, usize)> = None;
+
+    while let (Some (f), Some (b)) = (fringe[0].peek (), fringe[1].peek ())
+    {
+        // no path through an unsettled vertex can beat the best one any more,
+        // which an overflowing sum certainly cannot
+        if let Some ( (cost, _) ) = best && f.cost.checked_combine (&b.cost).is_none_or (|x| x.compare (&cost) != cmp::Ordering::Less)
+        {
+            break;
+        }
+        let side = if f.cost.compare (&b.cost) == cmp::Ordering::Greater { 1 } else { 0 };
+        let Some (SearchState { cost, v }) = fringe[side].pop () else { break };
+        if !settled[side].insert (v)
+        {
+            continue;
+        }
+        let next = if side == 0 { forward (v)? } else { backward (v)? };
+        for (w, weight) in next
+        {
+            let distance_next = path_cost (&cost, &weight)?;
+            if distances[side].get (&w).is_none_or (|x| distance_next.compare (x) == cmp::Ordering::Less)
+            {
+                distances[side].insert (w, distance_next);
+                predecessors[side].insert (w, v);
+                fringe[side].push (SearchState { cost: distance_next, v: w });
+            }
+            if let Some (other) = distances[1 - side].get (&w)
+            {
+                let total = path_cost (&distances[side][&w], other)?;
+                if best.is_none_or (|(x, _)| total.compare (&x) == cmp::Ordering::Less)
+                {
+                    best = Some ( (total, w) );
+                }
+            }
+        }
+    }
+
+    Ok (best.map (|(cost, meet)| {
+        let mut path = predecessor_path (&predecessors[0], meet);
+        let mut v = meet;
+        while let Some (w) = predecessors[1].get (&v)
+        {
+            path.push (*w);
+            v = *w;
+        }
+        (cost, path)
+    }))
 }
 
-pub fn descendants (g: &graph::Graph, source: usize)
+pub fn descendants<G: graph::GraphAny> (g: &G, source: usize)
     -> Result<collections::HashSet<usize>, error::GraphError>
 {
     Ok (bfs_edges (g, source)?.into_iter ()
@@ -254,23 +660,13 @@ pub fn descendants (g: &graph::Graph, source: usize)
 pub fn edge_bfs<G: graph::GraphAny> (g: &G, source: usize)
     -> Result<Vec<(usize, usize)>, error::GraphError>
 {
-    let neighbours = g.vertices ().iter ().fold (collections::HashMap::<usize, collections::HashSet<usize>>::new (), |mut acc, item| {
-        acc.insert (*item, g.adjacent (item).unwrap ());
-        acc
-        });
-    let mut neighbours_iters = neighbours.iter ().fold (collections::HashMap::<usize, collections::hash_set::Iter<'_, usize>>::new (), |mut acc, (k, v)| {
-        acc.insert (*k, v.iter () );
-        acc
-    });
-
     let mut visited_vertices = collections::HashSet::<usize>::from ([source]);
     let mut visited_edges = collections::HashSet::<(usize, usize)>::new ();
     let mut r = Vec::<(usize, usize)>::new ();
 
-    let source_edges = neighbours_iters.get_mut (&source)
-        .ok_or (error::GraphError::AlgorithmError (format! ("No neighbours iter for {}", source)))?
+    let source_edges = g.adjacent_iter (&source)?
         .map (|x| {
-            let ex = (source, *x);
+            let ex = (source, x);
             if g.has_edge_raw (&ex) { ex } else { (ex.1, ex.0) }
         })
         .collect::<collections::HashSet<(usize,usize)>> ();
@@ -286,10 +682,9 @@ pub fn edge_bfs<G: graph::GraphAny> (g: &G, source: usize)
                 if !visited_vertices.contains (&ev.1)
                 {
                     visited_vertices.insert (ev.1);
-                    let child_edges = neighbours_iters.get_mut (&ev.1)
-                        .ok_or (error::GraphError::AlgorithmError (format! ("No neighbours iter for {}", ev.1)))?
+                    let child_edges = g.adjacent_iter (&ev.1)?
                         .map (|x| {
-                            let ex = (ev.1, *x);
+                            let ex = (ev.1, x);
                             if g.has_edge_raw (&ex) { ex } else { (ex.1, ex.0) }
                         })
                         .collect::<collections::HashSet<(usize,usize)>> ();
@@ -301,10 +696,9 @@ pub fn edge_bfs<G: graph::GraphAny> (g: &G, source: usize)
                 if !visited_vertices.contains (&ev.0)
                 {
                     visited_vertices.insert (ev.0);
-                    let child_edges = neighbours_iters.get_mut (&ev.0)
-                        .ok_or (error::GraphError::AlgorithmError (format! ("No neighbours iter for {}", ev.0)))?
+                    let child_edges = g.adjacent_iter (&ev.0)?
                         .map (|x| {
-                            let ex = (ev.0, *x);
+                            let ex = (ev.0, x);
                             if g.has_edge_raw (&ex) { ex } else { (ex.1, ex.0) }
                         })
                         .collect::<collections::HashSet<(usize,usize)>> ();
@@ -322,25 +716,15 @@ pub fn edge_bfs<G: graph::GraphAny> (g: &G, source: usize)
     Ok (r)
 }
 
-pub fn edge_bfs_reverse (g: &graph::Graph, source: usize)
+pub fn edge_bfs_reverse<G: graph::DiGraphAny> (g: &G, source: usize)
     -> Result<Vec<(usize, usize)>, error::GraphError>
 {
-    let neighbours = g.vertices ().iter ().fold (collections::HashMap::<usize, collections::HashSet<usize>>::new (), |mut acc, item| {
-        acc.insert (*item, g.inbound (item).unwrap ());
-        acc
-        });
-    let mut neighbours_iters = neighbours.iter ().fold (collections::HashMap::<usize, collections::hash_set::Iter<'_, usize>>::new (), |mut acc, (k, v)| {
-        acc.insert (*k, v.iter () );
-        acc
-    });
-
     let mut visited_vertices = collections::HashSet::<usize>::from ([source]);
     let mut visited_edges = collections::HashSet::<(usize, usize)>::new ();
     let mut r = Vec::<(usize, usize)>::new ();
 
-    let source_edges = neighbours_iters.get_mut (&source)
-        .ok_or (error::GraphError::AlgorithmError (format! ("No neighbours iter for {}", source)))?
-        .map (|x| { (*x, source) })
+    let source_edges = g.inbound_iter (&source)?
+        .map (|x| { (x, source) })
         .collect::<Vec<(usize,usize)>> ();
 
     let mut queue = collections::VecDeque::<(usize, usize)>::from (source_edges);
@@ -351,9 +735,8 @@ pub fn edge_bfs_reverse (g: &graph::Graph, source: usize)
         if !visited_vertices.contains (&ev.0)
         {
             visited_vertices.insert (ev.0);
-            let parent_edges = neighbours_iters.get_mut (&ev.0)
-                .ok_or (error::GraphError::AlgorithmError (format! ("No neighbours iter for {}", ev.0)))?
-                .map (|x| { (*x, ev.0) })
+            let parent_edges = g.inbound_iter (&ev.0)?
+                .map (|x| { (x, ev.0) })
                 .collect::<Vec<(usize,usize)>> ();
             queue.extend (parent_edges);
         }
@@ -367,7 +750,7 @@ pub fn edge_bfs_reverse (g: &graph::Graph, source: usize)
     Ok (r)
 }
 
-pub fn connected_components (g: &graph::UGraph)
+pub fn connected_components<W: graph::Weight> (g: &graph::UGraph<W>)
     -> Result<Vec<collections::HashSet<usize>>, error::GraphError>
 {
     let mut r = Vec::<collections::HashSet<usize>>::new ();
@@ -389,52 +772,238 @@ pub fn connected_components (g: &graph::UGraph)
     Ok (r)
 }
 
-pub fn dfs_edges (g: &graph::Graph, source: usize)
+pub fn connected_components_labelled<W, V, E, L> (g: &graph::LabelledUGraph<W, V, E, L>)
+    -> Result<Vec<collections::HashSet<L>>, error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label
+{
+    connected_components (g.graph ())?.iter ()
+        .map (|component| component.iter ().map (|v| g.vertex_label (v)).collect ())
+        .collect ()
+}
+
+type Condensation<W> = (graph::Graph<W>, collections::HashMap<usize, usize>);
+
+// Collapses each strongly connected component into a single vertex. Component
+// ids follow the order of strongly_connected_components, so they are already a
+// topological order of the result. Edges inside a component are dropped and
+// parallel edges between two components keep the lightest weight.
+pub fn condensation<G: graph::DiGraphAny> (g: &G)
+    -> Result<Condensation<G::Weight>, error::GraphError>
+{
+    let components = strongly_connected_components (g)?;
+    let mut r = graph::Graph::<G::Weight>::new_weighted ();
+    let mut membership = collections::HashMap::<usize, usize>::with_capacity (g.vertices ().len ());
+    for (c, component) in components.iter ().enumerate ()
+    {
+        r.add_vertex_raw (c)?;
+        membership.extend (component.iter ().map (|v| (*v, c)));
+    }
+
+    let mut edges = collections::HashMap::<(usize, usize), G::Weight>::new ();
+    for ((a, b), w) in g.edges_iter ()
+    {
+        let (ca, cb) = (membership[&a], membership[&b]);
+        if ca != cb
+        {
+            edges.entry ( (ca, cb) )
+                .and_modify (|x| if w.compare (x) == cmp::Ordering::Less { *x = w; })
+                .or_insert (w);
+        }
+    }
+    for ((ca, cb), w) in edges
+    {
+        r.add_edge_raw (ca, cb, w)?;
+    }
+    Ok ( (r, membership) )
+}
+
+pub fn dfs_edges<G: graph::GraphAny> (g: &G, source: usize)
     -> Result<Vec<((usize, usize), usize)>, error::GraphError>
 {
-    let children = g.vertices ().iter ().fold (collections::HashMap::<usize, Vec<usize>>::new (), |mut acc, item| {
-        let mut outbound_sorted = g.outbound (item).unwrap ().into_iter ().collect::<Vec<_>> ();
-        outbound_sorted.sort ();
-        acc.insert (*item, outbound_sorted);
-        acc
-        });
-    let mut children_iters = children.iter ().fold (collections::HashMap::<usize, std::slice::Iter<'_, usize>>::new (), |mut acc, (k, v)| {
-        acc.insert (*k, v.iter () );
-        acc
-    });
+    let sorted_children = |v: usize| {
+        let mut children = g.adjacent_iter (&v)?.collect::<Vec<_>> ();
+        children.sort ();
+        Ok::<_, error::GraphError> (children.into_iter ())
+    };
 
     let mut visited = collections::HashSet::<usize>::new ();
-    let mut queue = collections::VecDeque::<(usize, usize, usize)>::from (vec![(0, source, source)]);
+    let mut stack = Vec::<(usize, usize, std::vec::IntoIter<usize>)>::from ([(0, source, sorted_children (source)?)]);
     let mut r = Vec::<((usize, usize), usize)>::new ();
 
-    while let Some ( (current_depth , parent, _) ) = queue.back ().copied ()
+    while let Some ( (current_depth, parent, children) ) = stack.last_mut ()
     {
-        if let Some (child) = children_iters.get_mut (&parent).ok_or (error::GraphError::AlgorithmError (format! ("No outbound iter for {}", parent)))?.next ()
+        let (current_depth, parent) = (*current_depth, *parent);
+        if let Some (child) = children.next ()
         {
-            if !visited.contains (child)
+            if visited.insert (child)
             {
-                visited.insert (*child);
-                queue.push_back ( ( current_depth + 1, *child, parent) );
-                r.push ( ( (parent, *child), current_depth ) );
+                stack.push ( (current_depth + 1, child, sorted_children (child)?) );
+                r.push ( ( (parent, child), current_depth ) );
             }
         }
         else
         {
-            queue.pop_back ();
+            stack.pop ();
         }
     }
 
     Ok (r)
 }
 
+// Bounds on a Dijkstra search. The search stops once every target is
+// settled, never reaches past max_cost and never follows a path further than
+// max_hops edges from its source. Under a hop bound every vertex gets the
+// cheapest cost of the paths within the bound, even when a cheaper path with
+// too many edges exists.
+#[derive(Clone, Debug)]
+pub struct DijkstraOptions<W = i64>
+{
+    pub targets: Option<collections::HashSet<usize>>,
+    pub max_cost: Option<W>,
+    pub max_hops: Option<usize>
+}
+
+impl<W> Default for DijkstraOptions<W>
+{
+    fn default () -> Self
+    {
+        Self { targets: None, max_cost: None, max_hops: None }
+    }
+}
+
+impl<W> DijkstraOptions<W>
+{
+    pub fn new () -> Self
+    {
+        Self::default ()
+    }
+
+    pub fn with_targets (mut self, targets: collections::HashSet<usize>) -> Self
+    {
+        self.targets = Some (targets);
+        self
+    }
+
+    pub fn with_max_cost (mut self, max_cost: W) -> Self
+    {
+        self.max_cost = Some (max_cost);
+        self
+    }
+
+    pub fn with_max_hops (mut self, max_hops: usize) -> Self
+    {
+        self.max_hops = Some (max_hops);
+        self
+    }
+}
+
+// Predecessors of the vertices settled by dijkstra_with_options as (hops,
+// predecessor) pairs, one for each path kept to a vertex with the cheapest
+// first. Without a hop bound that is only the cheapest path, with one a vertex
+// also keeps the dearer paths taking fewer edges, which may lead on to
+// vertices the cheapest path has no hops left for.
+pub type HopPredecessors = collections::HashMap<usize, Vec<(usize, usize)>>;
+
+type DistancesHopPredecessors<W> = (collections::HashMap<usize, W>, HopPredecessors);
+
+// Dijkstra from each source on its own, giving the distances of the settled
+// vertices and their predecessors on the shortest paths. Paths are recovered
+// with reconstruct_path.
+pub fn dijkstra_with_options<G: graph::GraphAny> (g: &G, sources: &collections::HashSet<usize>, options: &DijkstraOptions<G::Weight>)
+    -> Result<collections::HashMap<usize, DistancesHopPredecessors<G::Weight>>, error::GraphError>
+{
+    sources.iter ()
+        .map (|source| Ok ( (*source, dijkstra_from (g, *source, options)?) ))
+        .collect ()
+}
+
+fn dijkstra_from<G: graph::GraphAny> (g: &G, source: usize, options: &DijkstraOptions<G::Weight>)
+    -> Result<DistancesHopPredecessors<G::Weight>, error::GraphError>
+{
+    if !g.vertices ().contains (&source)
+    {
+        return Err (error::GraphError::VertexError (format! ("Vertex: {} not found in graph", source)));
+    }
+
+    // Under a hop bound the search runs over (vertex, hops) states. A state
+    // popped after its vertex was settled only matters when it took fewer
+    // hops than every path kept so far, as it can then reach further. Without
+    // a bound all states of a vertex share one entry, as in plain Dijkstra.
+    let bounded = options.max_hops.is_some ();
+    let state = |v: usize, hops: usize| (v, if bounded { hops } else { 0 });
+    let mut tentative = collections::HashMap::<(usize, usize), (G::Weight, usize)>::from ([(state (source, 0), (G::Weight::zero (), 0))]);
+    let mut tentative_predecessors = collections::HashMap::<(usize, usize), usize>::new ();
+    let mut fewest_hops = collections::HashMap::<usize, usize>::new ();
+    let mut distances = collections::HashMap::<usize, G::Weight>::new ();
+    let mut predecessors = HopPredecessors::new ();
+    let mut remaining = options.targets.clone ();
+    let mut fringe = collections::BinaryHeap::<HopState<G::Weight>>::from ([HopState { cost: G::Weight::zero (), hops: 0, v: source }]);
+    let dominated = |fewest_hops: &collections::HashMap<usize, usize>, v: usize, hops: usize| fewest_hops.get (&v).is_some_and (|h| !bounded || hops >= *h);
+
+    while let Some (HopState { cost, hops, v }) = fringe.pop ()
+    {
+        if dominated (&fewest_hops, v, hops)
+        {
+            continue;
+        }
+        fewest_hops.insert (v, hops);
+        if let Some (u) = tentative_predecessors.get (&state (v, hops))
+        {
+            predecessors.entry (v).or_default ().push ( (hops, *u) );
+        }
+        if let collections::hash_map::Entry::Vacant (e) = distances.entry (v)
+        {
+            e.insert (cost);
+            if let Some (remaining) = remaining.as_mut ()
+            {
+                remaining.remove (&v);
+                if remaining.is_empty ()
+                {
+                    break;
+                }
+            }
+        }
+
+        if options.max_hops.is_some_and (|m| hops >= m)
+        {
+            continue;
+        }
+        for v_child in g.adjacent_iter (&v)?
+        {
+            if dominated (&fewest_hops, v_child, hops + 1)
+            {
+                continue;
+            }
+            let cost_next = path_cost (&cost, &g.weight (&(v, v_child))?)?;
+            if options.max_cost.is_some_and (|m| cost_next.compare (&m) == cmp::Ordering::Greater)
+            {
+                continue;
+            }
+            // on equal cost prefer fewer hops, which keeps more of the graph within max_hops
+            let better = tentative.get (&state (v_child, hops + 1)).is_none_or (|(x, x_hops)| match cost_next.compare (x)
+            {
+                cmp::Ordering::Less => true,
+                cmp::Ordering::Equal => hops + 1 < *x_hops,
+                cmp::Ordering::Greater => false
+            });
+            if better
+            {
+                tentative.insert (state (v_child, hops + 1), (cost_next, hops + 1));
+                tentative_predecessors.insert (state (v_child, hops + 1), v);
+                fringe.push (HopState { cost: cost_next, hops: hops + 1, v: v_child });
+            }
+        }
+    }
+    Ok ( (distances, predecessors) )
+}
+
 pub fn fast_label_propagation<G: graph::GraphAny> (g: &G, seed: &mut u64)
     -> Result<(collections::HashMap<usize, usize>, collections::HashMap<usize, collections::HashSet<usize>>), error::GraphError>
 {
-    let neighbours = g.vertices ().iter ().fold (collections::HashMap::<usize, collections::HashSet<usize>>::new (), |mut acc, item| {
-        acc.insert (*item, g.neighbours (item).unwrap ());
-        acc
-        });
-
     let mut q = collections::VecDeque::<usize>::from (Vec::from_iter (g.vertices ().iter ().copied ()));
     prng::shuffle (q.make_contiguous (), seed)?;
 
@@ -442,9 +1011,9 @@ pub fn fast_label_propagation<G: graph::GraphAny> (g: &G, seed: &mut u64)
 
     while let Some (v) = q.pop_front ()
     {
-        let nlc = neighbours[&v].iter ()
+        let nlc = g.neighbours_iter (&v)?
             .fold (collections::HashMap::<usize,usize>::new (), |mut acc, item| {
-            *acc.entry (labels[item]).or_insert (0) += 1;
+            *acc.entry (labels[&item]).or_insert (0) += 1;
             acc
         });
         let max_freq = *nlc.values ().max ().unwrap_or (&0);
@@ -460,11 +1029,11 @@ pub fn fast_label_propagation<G: graph::GraphAny> (g: &G, seed: &mut u64)
         if labels[&v] != mpl
         {
             labels.insert (v, mpl);
-            for vn in neighbours[&v].iter ()
+            for vn in g.neighbours_iter (&v)?
             {
-                if labels[vn] != mpl
+                if labels[&vn] != mpl
                 {
-                    q.push_back (*vn);
+                    q.push_back (vn);
                 }
             }
         }
@@ -481,9 +1050,137 @@ pub fn fast_label_propagation<G: graph::GraphAny> (g: &G, seed: &mut u64)
     Ok ( (r_node_to_label, r_label_to_node) )
 }
 
-pub fn overlapping_components<T,F> (g: &graph::Graph, cut: &collections::HashSet<usize>, f: F)
+// A cycle as a vertex path: each vertex leads to the next and the last leads
+// back to the first. A self loop is a cycle of one vertex.
+pub fn find_cycle<G: graph::DiGraphAny> (g: &G)
+    -> Result<Option<Vec<usize>>, error::GraphError>
+{
+    let sorted_children = |v: usize| {
+        let mut children = g.outbound_iter (&v)?.collect::<Vec<_>> ();
+        children.sort ();
+        Ok::<_, error::GraphError> (children.into_iter ())
+    };
+    cycle_search (g, sorted_children, false)
+}
+
+pub fn find_cycle_labelled<W, V, E, L> (g: &graph::LabelledGraph<W, V, E, L>)
+    -> Result<Option<Vec<L>>, error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label
+{
+    find_cycle (g.graph ())?.map (|cycle| path_labels (g, &cycle)).transpose ()
+}
+
+// As find_cycle with edge directions ignored, so an edge is never walked back
+// along itself
+pub fn find_cycle_undirected<G: graph::GraphAny> (g: &G)
+    -> Result<Option<Vec<usize>>, error::GraphError>
+{
+    let sorted_children = |v: usize| {
+        let mut children = g.neighbours_iter (&v)?.collect::<Vec<_>> ();
+        children.sort ();
+        Ok::<_, error::GraphError> (children.into_iter ())
+    };
+    cycle_search (g, sorted_children, true)
+}
+
+pub fn find_cycle_undirected_labelled<W, V, E, L> (g: &graph::LabelledUGraph<W, V, E, L>)
+    -> Result<Option<Vec<L>>, error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label
+{
+    find_cycle_undirected (g.graph ())?.map (|cycle| path_labels (g, &cycle)).transpose ()
+}
+
+fn cycle_search<G, F> (g: &G, children: F, undirected: bool)
+    -> Result<Option<Vec<usize>>, error::GraphError>
+    where
+        G: graph::GraphAny,
+        F: Fn (usize) -> Result<std::vec::IntoIter<usize>, error::GraphError>
+{
+    let mut roots = g.vertices ().iter ().copied ().collect::<Vec<usize>> ();
+    roots.sort ();
+
+    let mut visited = collections::HashSet::<usize>::new ();
+    for root in roots
+    {
+        if !visited.insert (root)
+        {
+            continue;
+        }
+        let mut path = Vec::<usize>::from ([root]);
+        let mut on_path = collections::HashSet::<usize>::from ([root]);
+        let mut stack = Vec::<std::vec::IntoIter<usize>>::from ([children (root)?]);
+
+        while let Some (v_children) = stack.last_mut ()
+        {
+            if let Some (w) = v_children.next ()
+            {
+                if on_path.contains (&w)
+                {
+                    let parent = path.len ().checked_sub (2).map (|i| path[i]);
+                    if !undirected || parent != Some (w)
+                    {
+                        let start = path.iter ().position (|x| *x == w).unwrap_or (0);
+                        return Ok (Some (path.split_off (start)));
+                    }
+                }
+                else if visited.insert (w)
+                {
+                    path.push (w);
+                    on_path.insert (w);
+                    stack.push (children (w)?);
+                }
+            }
+            else
+            {
+                stack.pop ();
+                if let Some (v) = path.pop ()
+                {
+                    on_path.remove (&v);
+                }
+            }
+        }
+    }
+    Ok (None)
+}
+
+// All pairs shortest paths with negative weights allowed. Bellman-Ford from a
+// virtual source joined to every vertex gives potentials h that make every
+// weight w(a,b) + h(a) - h(b) non-negative, so Dijkstra can run from every
+// vertex on the reweighted graph before the potentials are taken back off.
+pub fn johnson<G> (g: &G)
+    -> Result<collections::HashMap<usize, DistancesPaths<G::Weight>>, error::GraphError>
+    where
+        G: graph::GraphAny,
+        G::Weight: ops::Sub<Output = G::Weight>
+{
+    let potentials = g.vertices ().iter ()
+        .map (|v| (*v, G::Weight::zero ()))
+        .collect ();
+    let (h, _) = bellman_ford_relax (g, potentials)?;
+    let reweighted = |e: &(usize, usize)| Ok (g.weight (e)?.combine (&h[&e.0]) - h[&e.1]);
+    let mut r = multi_source_dijkstra_by (g, g.vertices (), reweighted)?;
+    for (source, (distances, _)) in r.iter_mut ()
+    {
+        for (v, d) in distances.iter_mut ()
+        {
+            *d = d.combine (&h[v]) - h[source];
+        }
+    }
+    Ok (r)
+}
+
+pub fn overlapping_components<G,T,F> (g: &G, cut: &collections::HashSet<usize>, f: F)
     -> Result<collections::HashMap<T, collections::HashSet<usize>>, error::GraphError>
     where
+        G: graph::GraphAny,
         T: fmt::Debug + Clone + hash::Hash + Ord + Sized,
         F: Fn(usize) -> Result<Option<T>, error::GraphError>
 {
@@ -513,12 +1210,12 @@ pub fn overlapping_components<T,F> (g: &graph::Graph, cut: &collections::HashSet
 
     let neighbours = g.vertices ()
         .iter ()
-        .fold (collections::HashMap::<usize, Vec<usize>>::new (), |mut acc, item| {
-            let mut nvec = g.neighbours (item).unwrap ().into_iter ().collect::<Vec<_>> ();
+        .try_fold (collections::HashMap::<usize, Vec<usize>>::new (), |mut acc, item| {
+            let mut nvec = g.neighbours_iter (item)?.collect::<Vec<_>> ();
             nvec.sort ();
             acc.insert (*item, nvec);
-            acc
-        });
+            Ok::<_, error::GraphError> (acc)
+        })?;
 
     let mut neighbours_iters = collections::HashMap::<T, collections::HashMap::<usize, ops::Range<usize>>>::new ();
 
@@ -568,9 +1265,10 @@ pub fn overlapping_components<T,F> (g: &graph::Graph, cut: &collections::HashSet
     Ok (r)
 }
 
-pub fn overlapping_components_extend<T,F> (g: &graph::Graph, extend: &collections::HashSet<usize>, f: F)
+pub fn overlapping_components_extend<G,T,F> (g: &G, extend: &collections::HashSet<usize>, f: F)
     -> Result<collections::HashMap<T, collections::HashSet<usize>>, error::GraphError>
     where
+        G: graph::GraphAny,
         T: fmt::Debug + Clone + hash::Hash + Ord + Sized,
         F: Fn(usize) -> Result<collections::HashSet<T>, error::GraphError>
 {
@@ -588,12 +1286,12 @@ pub fn overlapping_components_extend<T,F> (g: &graph::Graph, extend: &collection
 
     let neighbours = g.vertices ()
         .iter ()
-        .fold (collections::HashMap::<usize, Vec<usize>>::new (), |mut acc, item| {
-            let mut nvec = g.neighbours (item).unwrap ().into_iter ().collect::<Vec<_>> ();
+        .try_fold (collections::HashMap::<usize, Vec<usize>>::new (), |mut acc, item| {
+            let mut nvec = g.neighbours_iter (item)?.collect::<Vec<_>> ();
             nvec.sort ();
             acc.insert (*item, nvec);
-            acc
-        });
+            Ok::<_, error::GraphError> (acc)
+        })?;
 
     let mut neighbours_iters = collections::HashMap::<T, collections::HashMap::<usize, ops::Range<usize>>>::new ();
 
@@ -647,9 +1345,10 @@ pub fn overlapping_components_extend<T,F> (g: &graph::Graph, extend: &collection
     Ok (r)
 }
 
-pub fn overlapping_components_extend_indirect<T,F> (g: &graph::Graph, extend: &collections::HashSet<usize>, f: F)
+pub fn overlapping_components_extend_indirect<G,T,F> (g: &G, extend: &collections::HashSet<usize>, f: F)
     -> Result<(collections::HashMap<T, usize>, collections::HashMap<usize, collections::HashSet<usize>>), error::GraphError>
     where
+        G: graph::GraphAny,
         T: fmt::Debug + Clone + hash::Hash + Ord + Sized,
         F: Fn(usize) -> Result<collections::HashSet<T>, error::GraphError>
 {
@@ -667,12 +1366,12 @@ pub fn overlapping_components_extend_indirect<T,F> (g: &graph::Graph, extend: &c
 
     let neighbours = g.vertices ()
         .iter ()
-        .fold (collections::HashMap::<usize, Vec<usize>>::new (), |mut acc, item| {
-            let mut nvec = g.neighbours (item).unwrap ().into_iter ().collect::<Vec<_>> ();
+        .try_fold (collections::HashMap::<usize, Vec<usize>>::new (), |mut acc, item| {
+            let mut nvec = g.neighbours_iter (item)?.collect::<Vec<_>> ();
             nvec.sort ();
             acc.insert (*item, nvec);
-            acc
-        });
+            Ok::<_, error::GraphError> (acc)
+        })?;
 
     let mut neighbours_iters = collections::HashMap::<usize, collections::HashMap::<usize, ops::Range<usize>>>::new ();
 
@@ -733,71 +1432,292 @@ pub fn overlapping_components_extend_indirect<T,F> (g: &graph::Graph, extend: &c
 pub fn single_shortest_path<G: graph::GraphAny> (g: &G, source: usize)
     -> Result<collections::HashMap<usize, Vec<usize>>, error::GraphError>
 {
-    let neighbours = g.vertices ().iter ().fold (collections::HashMap::<usize, collections::HashSet<usize>>::new (), |mut acc, item| {
-        acc.insert (*item, g.adjacent (item).unwrap ());
-        acc
-        });
-    let mut neighbours_iters = neighbours.iter ().fold (collections::HashMap::<usize, collections::hash_set::Iter<'_, usize>>::new (), |mut acc, (k, v)| {
-        acc.insert (*k, v.iter () );
-        acc
-    });
-
     let mut r = collections::HashMap::<usize, Vec<usize>>::from ([ (source, vec![source]) ]);
-    let mut queue = collections::VecDeque::<(usize, usize)>::from (vec![(source, 0)]);
+    let mut queue = collections::VecDeque::<usize>::from (vec![source]);
+
+    while let Some (parent) = queue.pop_front ()
+    {
+        for child in g.adjacent_iter (&parent)?
+        {
+            if !r.contains_key (&child)
+            {
+                let mut path = Vec::with_capacity (r[&parent].len () +1);
+                path.extend (r[&parent].as_slice ());
+                path.push (child);
+                r.insert (child, path);
+                queue.push_back (child);
+            }
+        }
+    }
+
+    Ok (r)
+}
+
+pub fn single_shortest_path_labelled<G, Q> (g: &G, source: &Q)
+    -> Result<collections::HashMap<G::Label, Vec<G::Label>>, error::GraphError>
+    where
+        G: graph::LabelledGraphAny,
+        G::Label: borrow::Borrow<Q>,
+        Q: hash::Hash + Eq + fmt::Debug + ?Sized
+{
+    single_shortest_path (g, g.vertex (source)?)?.iter ()
+        .map (|(v, path)| Ok ( (g.vertex_label (v)?, path_labels (g, path)?) ))
+        .collect ()
+}
+
+// Johnson's algorithm for the elementary cycles of a directed graph, each
+// given as in find_cycle and starting at its smallest vertex. max_length
+// bounds the number of vertices in a cycle, in which case the search switches
+// to the length bounded blocking of Gupta and Suzumura. max_count stops the
+// enumeration early.
+pub fn simple_cycles<G: graph::DiGraphAny> (g: &G, max_length: Option<usize>, max_count: Option<usize>)
+    -> Result<Vec<Vec<usize>>, error::GraphError>
+{
+    let mut r = Vec::<Vec<usize>>::new ();
+    let full = |r: &Vec<Vec<usize>>| max_count.is_some_and (|n| r.len () >= n);
+    if max_length == Some (0) || full (&r)
+    {
+        return Ok (r);
+    }
+
+    let mut vertices = g.vertices ().iter ().copied ().collect::<Vec<usize>> ();
+    vertices.sort ();
+    for v in vertices.iter ()
+    {
+        if g.has_edge_raw (&(*v, *v))
+        {
+            r.push (vec![*v]);
+            if full (&r)
+            {
+                return Ok (r);
+            }
+        }
+    }
+
+    // self loops are accounted for, the searches below only follow edges
+    // between distinct vertices of one component
+    let adjacency = vertices.iter ()
+        .map (|v| {
+            let mut children = g.outbound_iter (v)?.filter (|w| w != v).collect::<Vec<usize>> ();
+            children.sort ();
+            Ok ( (*v, children) )
+        })
+        .collect::<Result<collections::HashMap<usize, Vec<usize>>, error::GraphError>> ()?;
+    let components_within = |within: &[usize]| {
+        let members = within.iter ().copied ().collect::<collections::HashSet<usize>> ();
+        let children = |v: usize| Ok (adjacency[&v].iter ().copied ().filter (|w| members.contains (w)).collect::<Vec<usize>> ().into_iter ());
+        Ok::<_, error::GraphError> (tarjan (within, children)?.into_iter ()
+            .filter (|component| component.len () > 1)
+            .collect::<Vec<_>> ())
+    };
+
+    let mut components = components_within (&vertices)?;
+    while let Some (component) = components.pop ()
+    {
+        let mut members = component.into_iter ().collect::<Vec<usize>> ();
+        members.sort ();
+        let start = members.remove (0);
+        let within = members.iter ().copied ().chain (iter::once (start)).collect::<collections::HashSet<usize>> ();
+        let component_adjacency = within.iter ()
+            .map (|v| (*v, adjacency[v].iter ().copied ().filter (|w| within.contains (w)).collect::<Vec<usize>> ()))
+            .collect::<collections::HashMap<usize, Vec<usize>>> ();
+        match max_length
+        {
+            Some (max_length) => bounded_cycle_search (&component_adjacency, start, max_length, &mut r, max_count),
+            None => johnson_cycle_search (&component_adjacency, start, &mut r, max_count)
+        }
+        if full (&r)
+        {
+            break;
+        }
+        components.extend (components_within (&members)?);
+    }
+    Ok (r)
+}
+
+pub fn simple_cycles_labelled<W, V, E, L> (g: &graph::LabelledGraph<W, V, E, L>, max_length: Option<usize>, max_count: Option<usize>)
+    -> Result<Vec<Vec<L>>, error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label
+{
+    simple_cycles (g.graph (), max_length, max_count)?.iter ()
+        .map (|cycle| path_labels (g, cycle))
+        .collect ()
+}
+
+fn johnson_cycle_search (adjacency: &collections::HashMap<usize, Vec<usize>>, start: usize, r: &mut Vec<Vec<usize>>, max_count: Option<usize>)
+{
+    let mut path = Vec::<usize>::from ([start]);
+    let mut blocked = collections::HashSet::<usize>::from ([start]);
+    let mut blocked_by = collections::HashMap::<usize, collections::HashSet<usize>>::new ();
+    let mut closed = Vec::<bool>::from ([false]);
+    let mut stack = Vec::<std::slice::Iter<usize>>::from ([adjacency[&start].iter ()]);
 
-    while let Some ( (parent, current_depth ) ) = queue.front ()
+    while let Some (v_children) = stack.last_mut ()
     {
-        if let Some (child) = neighbours_iters.get_mut (parent).ok_or (error::GraphError::AlgorithmError (format! ("No neigbours iter for {}", parent)))?.next ()
+        if max_count.is_some_and (|n| r.len () >= n)
+        {
+            return;
+        }
+        if let Some (w) = v_children.next ().copied ()
+        {
+            if w == start
+            {
+                r.push (path.clone ());
+                if let Some (c) = closed.last_mut ()
+                {
+                    *c = true;
+                }
+            }
+            else if blocked.insert (w)
+            {
+                path.push (w);
+                closed.push (false);
+                stack.push (adjacency[&w].iter ());
+            }
+        }
+        else
         {
-            if !r.contains_key (child)
+            stack.pop ();
+            let (Some (v), Some (v_closed)) = (path.pop (), closed.pop ()) else { break };
+            if v_closed
+            {
+                // v lies on a cycle, so it and everything waiting on it may be revisited
+                if let Some (c) = closed.last_mut ()
+                {
+                    *c = true;
+                }
+                let mut unblock = Vec::<usize>::from ([v]);
+                while let Some (u) = unblock.pop ()
+                {
+                    if blocked.remove (&u) && let Some (waiting) = blocked_by.get_mut (&u)
+                    {
+                        unblock.extend (waiting.drain ());
+                    }
+                }
+            }
+            else
+            {
+                for w in adjacency[&v].iter ()
+                {
+                    blocked_by.entry (*w).or_default ().insert (v);
+                }
+            }
+        }
+    }
+}
+
+fn bounded_cycle_search (adjacency: &collections::HashMap<usize, Vec<usize>>, start: usize, max_length: usize, r: &mut Vec<Vec<usize>>, max_count: Option<usize>)
+{
+    // a vertex is locked while the path is at least as long as its lock, the
+    // lock rises once a cycle within the bound is known to pass through it
+    let mut path = Vec::<usize>::from ([start]);
+    let mut lock = collections::HashMap::<usize, usize>::from ([(start, 0)]);
+    let mut blocked_by = collections::HashMap::<usize, collections::HashSet<usize>>::new ();
+    let mut blen = Vec::<usize>::from ([max_length]);
+    let mut stack = Vec::<std::slice::Iter<usize>>::from ([adjacency[&start].iter ()]);
+
+    while let Some (v_children) = stack.last_mut ()
+    {
+        if max_count.is_some_and (|n| r.len () >= n)
+        {
+            return;
+        }
+        if let Some (w) = v_children.next ().copied ()
+        {
+            if w == start
+            {
+                r.push (path.clone ());
+                if let Some (l) = blen.last_mut ()
+                {
+                    *l = 1;
+                }
+            }
+            else if path.len () < *lock.get (&w).unwrap_or (&max_length)
+            {
+                lock.insert (w, path.len ());
+                path.push (w);
+                blen.push (max_length);
+                stack.push (adjacency[&w].iter ());
+            }
+        }
+        else
+        {
+            stack.pop ();
+            let (Some (v), Some (v_blen)) = (path.pop (), blen.pop ()) else { break };
+            if let Some (l) = blen.last_mut ()
+            {
+                *l = cmp::min (*l, v_blen);
+            }
+            if v_blen < max_length
+            {
+                let mut relax = Vec::<(usize, usize)>::from ([(v_blen, v)]);
+                while let Some ( (u_blen, u) ) = relax.pop ()
+                {
+                    if *lock.get (&u).unwrap_or (&max_length) < max_length - u_blen + 1
+                    {
+                        lock.insert (u, max_length - u_blen + 1);
+                        if let Some (waiting) = blocked_by.get (&u)
+                        {
+                            relax.extend (waiting.iter ().filter (|x| !path.contains (x)).map (|x| (u_blen + 1, *x)));
+                        }
+                    }
+                }
+            }
+            else
             {
-                //r.push ( (*parent, *child) );
-                let mut path = Vec::with_capacity (r[parent].len () +1);
-                path.extend (r[parent].as_slice ());
-                path.push (*child);
-                r.insert (*child, path);
-                queue.push_back ( (*child, *current_depth + 1) );
+                for w in adjacency[&v].iter ()
+                {
+                    blocked_by.entry (*w).or_default ().insert (v);
+                }
             }
         }
-        else
-        {
-                queue.pop_front ();
-        }
     }
-
-
-    Ok (r)
 }
 
+type DistancesPaths<W> = (collections::HashMap<usize, W>, collections::HashMap<usize, Vec<usize>>);
+
 // I want to reuse as much as possible between paths
 pub fn multi_source_dijkstra<G: graph::GraphAny> (g: &G, sources: &collections::HashSet<usize>)
-    -> Result<collections::HashMap<usize, (collections::HashMap<usize,i64>, collections::HashMap<usize, Vec<usize>>)>, error::GraphError>
+    -> Result<collections::HashMap<usize, DistancesPaths<G::Weight>>, error::GraphError>
+{
+    multi_source_dijkstra_by (g, sources, |e| g.weight (e))
+}
+
+fn multi_source_dijkstra_by<G, F> (g: &G, sources: &collections::HashSet<usize>, weight: F)
+    -> Result<collections::HashMap<usize, DistancesPaths<G::Weight>>, error::GraphError>
+    where
+        G: graph::GraphAny,
+        F: Fn (&(usize, usize)) -> Result<G::Weight, error::GraphError>
 {
-    let mut r = collections::HashMap::<usize, (collections::HashMap<usize,i64>, collections::HashMap<usize, Vec<usize>>)>::new ();
-    let mut fringe = collections::BinaryHeap::<MultiSourceState>::new ();
+    let mut r = collections::HashMap::<usize, DistancesPaths<G::Weight>>::new ();
+    let mut fringe = collections::BinaryHeap::<MultiSourceState<G::Weight>>::new ();
 
     for source in sources
     {
-        fringe.push (MultiSourceState { cost: 0, source: *source, v: *source });
-        r.insert (*source, ( collections::HashMap::<usize,i64>::from ([ ( *source, 0 ) ]), collections::HashMap::<usize, Vec<usize>>::from ([ ( *source, Vec::from ([*source]) ) ])) );
+        fringe.push (MultiSourceState { cost: G::Weight::zero (), source: *source, v: *source });
+        r.insert (*source, ( collections::HashMap::<usize,G::Weight>::from ([ ( *source, G::Weight::zero () ) ]), collections::HashMap::<usize, Vec<usize>>::from ([ ( *source, Vec::from ([*source]) ) ])) );
     }
 
     while let Some (MultiSourceState { cost, source, v }) = fringe.pop ()
     {
         //debug! ("current mss ({}, {}, {})", cost, source, v);
 
-        if cost > *r.get (&source).unwrap ().0.get (&v).unwrap ()
+        if cost.compare (r.get (&source).unwrap ().0.get (&v).unwrap ()) == cmp::Ordering::Greater
         {
             continue;
         }
         else
         {
-            for v_child in g.adjacent (&v)?
+            for v_child in g.adjacent_iter (&v)?
             {
                 let e = (v, v_child);
-                let ew = g.weight (&e)?;
+                let ew = weight (&e)?;
 
-                let mss_next = MultiSourceState { cost: cost + ew, source: source, v: v_child };
+                let mss_next = MultiSourceState { cost: path_cost (&cost, &ew)?, source, v: v_child };
 
                 let mut path_new = r.get (&source).unwrap ().1.get (&v)
                     .ok_or (error::GraphError::AlgorithmError (format! ("Path for {} not found for source {}", v, source)))?
@@ -805,9 +1725,10 @@ pub fn multi_source_dijkstra<G: graph::GraphAny> (g: &G, sources: &collections::
                 path_new.push (mss_next.v);
                 if let Some (dist_next) = r.get (&source).unwrap ().0.get (&mss_next.v).copied ()
                 {
-                    if mss_next.cost < dist_next
+                    if mss_next.cost.compare (&dist_next) == cmp::Ordering::Less
                     {
                         r.get_mut (&source).unwrap ().0.insert (mss_next.v, mss_next.cost);
+                        r.get_mut (&source).unwrap ().1.insert (mss_next.v, path_new);
                         fringe.push (mss_next);
                     }
                 }
@@ -824,41 +1745,202 @@ pub fn multi_source_dijkstra<G: graph::GraphAny> (g: &G, sources: &collections::
     Ok (r)
 }
 
-pub fn topological_sort (g: &graph::Graph)
-    -> Result<Vec<usize>, error::GraphError>
+type LabelledDistancesPaths<L, W> = (collections::HashMap<L, W>, collections::HashMap<L, Vec<L>>);
+type LabelledSourcesDistancesPaths<L, W> = collections::HashMap<L, LabelledDistancesPaths<L, W>>;
+
+pub fn multi_source_dijkstra_labelled<G: graph::LabelledGraphAny> (g: &G, sources: &collections::HashSet<G::Label>)
+    -> Result<LabelledSourcesDistancesPaths<G::Label, G::Weight>, error::GraphError>
+{
+    let source_ids = sources.iter ().map (|x| g.vertex (x)).collect::<Result<collections::HashSet<_>, _>> ()?;
+    multi_source_dijkstra (g, &source_ids)?.iter ()
+        .map (|(source, (distances, paths))| {
+            let distances = distances.iter ()
+                .map (|(v, d)| Ok ( (g.vertex_label (v)?, *d) ))
+                .collect::<Result<collections::HashMap<_, _>, error::GraphError>> ()?;
+            let paths = paths.iter ()
+                .map (|(v, path)| Ok ( (g.vertex_label (v)?, path_labels (g, path)?) ))
+                .collect::<Result<collections::HashMap<_, _>, error::GraphError>> ()?;
+            Ok ( (g.vertex_label (source)?, (distances, paths)) )
+        })
+        .collect ()
+}
+
+// Tarjan's algorithm without recursion. Components are returned in topological
+// order: no edge leads from a component to an earlier one.
+pub fn strongly_connected_components<G: graph::DiGraphAny> (g: &G)
+    -> Result<Vec<collections::HashSet<usize>>, error::GraphError>
+{
+    let sorted_children = |v: usize| {
+        let mut children = g.outbound_iter (&v)?.collect::<Vec<_>> ();
+        children.sort ();
+        Ok::<_, error::GraphError> (children.into_iter ())
+    };
+
+    let mut roots = g.vertices ().iter ().copied ().collect::<Vec<usize>> ();
+    roots.sort ();
+    tarjan (&roots, sorted_children)
+}
+
+fn tarjan<F> (roots: &[usize], children: F)
+    -> Result<Vec<collections::HashSet<usize>>, error::GraphError>
+    where
+        F: Fn (usize) -> Result<std::vec::IntoIter<usize>, error::GraphError>
+{
+    let mut index = collections::HashMap::<usize, usize>::with_capacity (roots.len ());
+    let mut lowlink = collections::HashMap::<usize, usize>::with_capacity (roots.len ());
+    let mut on_stack = collections::HashSet::<usize>::new ();
+    let mut stack = Vec::<usize>::new ();
+    let mut r = Vec::<collections::HashSet<usize>>::new ();
+
+    for root in roots.iter ().copied ()
+    {
+        if index.contains_key (&root)
+        {
+            continue;
+        }
+        index.insert (root, index.len ());
+        lowlink.insert (root, index[&root]);
+        stack.push (root);
+        on_stack.insert (root);
+        let mut call_stack = Vec::<(usize, std::vec::IntoIter<usize>)>::from ([(root, children (root)?)]);
+
+        while let Some ( (v, v_children) ) = call_stack.last_mut ()
+        {
+            let v = *v;
+            if let Some (w) = v_children.next ()
+            {
+                if !index.contains_key (&w)
+                {
+                    index.insert (w, index.len ());
+                    lowlink.insert (w, index[&w]);
+                    stack.push (w);
+                    on_stack.insert (w);
+                    call_stack.push ( (w, children (w)?) );
+                }
+                else if on_stack.contains (&w)
+                {
+                    let low = cmp::min (lowlink[&v], index[&w]);
+                    lowlink.insert (v, low);
+                }
+            }
+            else
+            {
+                call_stack.pop ();
+                if lowlink[&v] == index[&v]
+                {
+                    let mut component = collections::HashSet::<usize>::new ();
+                    while let Some (w) = stack.pop ()
+                    {
+                        on_stack.remove (&w);
+                        component.insert (w);
+                        if w == v
+                        {
+                            break;
+                        }
+                    }
+                    r.push (component);
+                }
+                if let Some ( (parent, _) ) = call_stack.last ()
+                {
+                    let low = cmp::min (lowlink[parent], lowlink[&v]);
+                    lowlink.insert (*parent, low);
+                }
+            }
+        }
+    }
+
+    // Tarjan emits a component only after everything reachable from it
+    r.reverse ();
+    Ok (r)
+}
+
+pub fn strongly_connected_components_labelled<W, V, E, L> (g: &graph::LabelledGraph<W, V, E, L>)
+    -> Result<Vec<collections::HashSet<L>>, error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label
 {
-    let mut r = Vec::<usize>::new ();
-    let mut gc = g.clone ();
-    let mut sources = gc.sources ();
+    strongly_connected_components (g.graph ())?.iter ()
+        .map (|component| component.iter ().map (|v| g.vertex_label (v)).collect ())
+        .collect ()
+}
 
-    while !sources.is_empty ()
+pub fn topological_sort<G: graph::DiGraphAny> (g: &G)
+    -> Result<Vec<usize>, error::GraphError>
+{
+    let mut r = Vec::<usize>::with_capacity (g.vertices ().len ());
+    let mut in_degree = g.vertices ().iter ()
+        .map (|v| Ok ( (*v, g.inbound_iter (v)?.count ()) ))
+        .collect::<Result<collections::HashMap<usize, usize>, error::GraphError>> ()?;
+    let mut sources = in_degree.iter ()
+        .filter (|(_, d)| **d == 0)
+        .map (|(v, _)| *v)
+        .collect::<Vec<usize>> ();
+
+    while let Some (n) = sources.pop ()
     {
-        let n = *sources.iter ().next ().unwrap ();
-        sources.remove (&n);
         r.push (n);
-        //debug! ("leaves: {:?} n: {}", leaves, n);
-        for m in gc.outbound (&n)?
+        for m in g.outbound_iter (&n)?
         {
-            gc.remove_edge_raw (&n, &m)?;
-            //debug! ("are {} is_leaf? {}", m, gc.is_leaf (&m)?);
-            if gc.is_source (&m)?
+            let d = in_degree.get_mut (&m)
+                .ok_or (error::GraphError::AlgorithmError (format! ("No in degree for {}", m)))?;
+            *d -= 1;
+            if *d == 0
             {
-                sources.insert  (m);
+                sources.push (m);
             }
         }
     }
 
-    if gc.edges ().is_empty ()
+    if r.len () == g.vertices ().len ()
     {
         Ok (r)
     }
     else
     {
-        Err (error::GraphError::EdgeError (String::from ("Graph contains at least one cycle")))
+        let cycle = find_cycle (g)?.unwrap_or_default ();
+        Err (cycle_error (&cycle))
+    }
+}
+
+pub fn topological_sort_labelled<W, V, E, L> (g: &graph::LabelledGraph<W, V, E, L>)
+    -> Result<Vec<L>, error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label + fmt::Display
+{
+    match topological_sort (g.graph ())
+    {
+        Ok (r) => path_labels (g, &r),
+        Err (e) => match find_cycle_labelled (g)?
+        {
+            Some (cycle) => Err (cycle_error (&cycle)),
+            None => Err (e)
+        }
     }
 }
 
-pub fn tree_sort (g: &graph::Graph)
+fn cycle_error<T: fmt::Display> (cycle: &[T])
+    -> error::GraphError
+{
+    error::GraphError::EdgeError (format! ("Graph contains at least one cycle: {}", cycle_path (cycle)))
+}
+
+// The cycle written out with its first vertex repeated at the end
+fn cycle_path<T: fmt::Display> (cycle: &[T])
+    -> String
+{
+    cycle.iter ().chain (cycle.first ())
+        .map (|v| v.to_string ())
+        .collect::<Vec<String>> ()
+        .join (" -> ")
+}
+
+pub fn tree_sort<G: graph::DiGraphAny> (g: &G)
     -> Result<Vec<usize>, error::GraphError>
 {
     let roots = g.sources ();
@@ -889,11 +1971,57 @@ pub fn tree_sort (g: &graph::Graph)
     }
 }
 
+// Components of the graph with edge directions ignored
+pub fn weakly_connected_components<G: graph::GraphAny> (g: &G)
+    -> Result<Vec<collections::HashSet<usize>>, error::GraphError>
+{
+    let mut roots = g.vertices ().iter ().copied ().collect::<Vec<usize>> ();
+    roots.sort ();
+
+    let mut r = Vec::<collections::HashSet<usize>>::new ();
+    let mut seen = collections::HashSet::<usize>::new ();
+    for root in roots
+    {
+        if seen.insert (root)
+        {
+            let mut component = collections::HashSet::<usize>::from ([root]);
+            let mut queue = collections::VecDeque::<usize>::from ([root]);
+            while let Some (v) = queue.pop_front ()
+            {
+                for w in g.neighbours_iter (&v)?
+                {
+                    if seen.insert (w)
+                    {
+                        component.insert (w);
+                        queue.push_back (w);
+                    }
+                }
+            }
+            r.push (component);
+        }
+    }
+    Ok (r)
+}
+
+pub fn weakly_connected_components_labelled<W, V, E, L> (g: &graph::LabelledGraph<W, V, E, L>)
+    -> Result<Vec<collections::HashSet<L>>, error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label
+{
+    weakly_connected_components (g.graph ())?.iter ()
+        .map (|component| component.iter ().map (|v| g.vertex_label (v)).collect ())
+        .collect ()
+}
+
 #[cfg(test)]
 mod tests
 {
     //use log::debug;
     use crate::graph;
+    use std::cmp;
     use std::collections;
     use std::sync;
 
@@ -901,7 +2029,7 @@ mod tests
 
     fn init ()
     {
-        INIT.call_once (env_logger::init);
+        INIT.call_once (|| { let _ = env_logger::try_init (); });
     }
 
     #[test]
@@ -939,6 +2067,155 @@ mod tests
         assert! (solutions.contains (&r), "{:?} not found in {:?}", r, solutions);
     }
 
+    #[test]
+    fn test_topo_sort_cycle ()
+    {
+        init ();
+        let mut g = graph::Graph::new ();
+        // 1 -> 2 -> 3 -> 2
+        g.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,0).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (3,2,0).expect ("Failed to add edge 3 -> 2");
+
+        assert_eq! (super::topological_sort (&g).unwrap_err ().to_string (), "Edge error: Graph contains at least one cycle: 2 -> 3 -> 2");
+        // the input is left untouched
+        assert_eq! (g.edges ().len (), 3);
+    }
+
+    // Lexicographic (cost, hops) so ties on cost prefer fewer edges
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct CostHops (u32, u32);
+
+    impl graph::Weight for CostHops
+    {
+        fn zero ()
+            -> Self
+        {
+            CostHops (0, 0)
+        }
+
+        fn combine (&self, other: &Self)
+            -> Self
+        {
+            CostHops (self.0 + other.0, self.1 + other.1)
+        }
+
+        fn compare (&self, other: &Self)
+            -> cmp::Ordering
+        {
+            (self.0, self.1).cmp (&(other.0, other.1))
+        }
+    }
+
+    #[test]
+    fn test_weights ()
+    {
+        init ();
+        let mut g = graph::Graph::<f64>::new_weighted ();
+        g.add_edge_raw (1,2,0.5).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,0.25).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (1,3,1.0).expect ("Failed to add edge 1 -> 3");
+        let r = super::multi_source_dijkstra (&g, &collections::HashSet::from ([1])).expect ("Failed dijkstra");
+        assert_eq! (r[&1].0[&3], 0.75);
+        assert_eq! (r[&1].1[&3], vec![1,2,3]);
+
+        let mut ug = graph::UGraph::<u32>::new_weighted ();
+        ug.add_edge_raw (1,2,1).expect ("Failed to add edge 1 -- 2");
+        ug.add_edge_raw (2,4,1).expect ("Failed to add edge 2 -- 4");
+        ug.add_edge_raw (1,3,1).expect ("Failed to add edge 1 -- 3");
+        ug.add_edge_raw (3,4,1).expect ("Failed to add edge 3 -- 4");
+        let r = super::all_shortest_paths (&ug, &collections::HashSet::from ([1])).expect ("Failed all shortest paths");
+        assert_eq! (r[&1].0[&4], 2u32);
+        assert_eq! (r[&1].1[&4], collections::HashSet::from ([vec![1,2,4], vec![1,3,4]]));
+
+        let mut hg = graph::Graph::<CostHops>::new_weighted ();
+        hg.add_edge_raw (1,2,CostHops (1,1)).expect ("Failed to add edge 1 -> 2");
+        hg.add_edge_raw (2,4,CostHops (1,1)).expect ("Failed to add edge 2 -> 4");
+        hg.add_edge_raw (1,4,CostHops (2,1)).expect ("Failed to add edge 1 -> 4");
+        let r = super::multi_source_dijkstra (&hg, &collections::HashSet::from ([1])).expect ("Failed dijkstra");
+        assert_eq! (r[&1].0[&4], CostHops (2,1));
+        assert_eq! (r[&1].1[&4], vec![1,4]);
+    }
+
+    #[test]
+    fn test_weight_overflow ()
+    {
+        init ();
+        let mut g = graph::Graph::<u32>::new_weighted ();
+        g.add_edge_raw (1,2,u32::MAX - 1).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,1).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (3,4,1).expect ("Failed to add edge 3 -> 4");
+        let sources = collections::HashSet::from ([1]);
+
+        // a path up to u32::MAX is still fine
+        assert_eq! (super::astar (&g, 1, 3, |_| 0).unwrap (), Some ( (u32::MAX, vec![1,2,3]) ));
+        assert_eq! (super::bidirectional_dijkstra (&g, 1, 3).unwrap (), Some ( (u32::MAX, vec![1,2,3]) ));
+        let options = super::DijkstraOptions::new ().with_targets (collections::HashSet::from ([3]));
+        assert_eq! (super::dijkstra_with_options (&g, &sources, &options).unwrap ()[&1].0[&3], u32::MAX);
+
+        let overflow = "Edge error: Path cost 4294967295 plus 1 overflows";
+        assert_eq! (super::multi_source_dijkstra (&g, &sources).unwrap_err ().to_string (), overflow);
+        assert_eq! (super::astar (&g, 1, 4, |_| 0).unwrap_err ().to_string (), overflow);
+        assert! (super::bidirectional_dijkstra (&g, 1, 4).is_err ());
+        assert_eq! (super::dijkstra_with_options (&g, &sources, &super::DijkstraOptions::new ()).unwrap_err ().to_string (), overflow);
+    }
+
+    #[test]
+    fn test_self_loops ()
+    {
+        init ();
+        let mut g = graph::Graph::new ();
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,2,0).expect ("Failed to add edge 2 -> 2");
+        assert_eq! (super::topological_sort (&g).unwrap_err ().to_string (), "Edge error: Graph contains at least one cycle: 2 -> 2");
+        // the source is never its own descendant, loop or not
+        assert_eq! (super::descendants (&g, 1).unwrap (), collections::HashSet::from ([2]));
+        assert! (super::descendants (&g, 2).unwrap ().is_empty ());
+
+        let mut ug = graph::UGraph::new ();
+        ug.set_self_loops (true).expect ("Failed to allow self loops");
+        ug.add_edge_raw (1,1,0).expect ("Failed to add edge 1 -- 1");
+        ug.add_edge_raw (2,3,0).expect ("Failed to add edge 2 -- 3");
+        let mut r = super::connected_components (&ug).unwrap ();
+        r.sort_by_key (|x| x.len ());
+        assert_eq! (r, vec![ collections::HashSet::from ([1]), collections::HashSet::from ([2,3]) ]);
+    }
+
+    #[test]
+    fn test_directed_labelled ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        //       r
+        //      / \
+        //     *   *
+        //    x     y
+        //    |
+        //    *
+        //    z
+        g.add_edge (String::from ("r"), String::from ("x"), None).expect ("Failed to add edge r -> x");
+        g.add_edge (String::from ("r"), String::from ("y"), None).expect ("Failed to add edge r -> y");
+        g.add_edge (String::from ("x"), String::from ("z"), None).expect ("Failed to add edge x -> z");
+        let r = g.vertex ("r").expect ("Failed to find r");
+        let x = g.vertex ("x").expect ("Failed to find x");
+        let y = g.vertex ("y").expect ("Failed to find y");
+        let z = g.vertex ("z").expect ("Failed to find z");
+
+        assert_eq! (super::ancestors (&g, z).unwrap (), collections::HashSet::from ([r, x]));
+        assert_eq! (super::descendants (&g, x).unwrap (), collections::HashSet::from ([z]));
+        assert_eq! (super::descendants (&g, r).unwrap (), collections::HashSet::from ([x, y, z]));
+        assert_eq! (super::edge_bfs_reverse (&g, z).unwrap (), vec![(x, z), (r, x)]);
+
+        let order = super::tree_sort (&g).expect ("Failed tree_sort");
+        assert_eq! (order.len (), 4);
+        assert_eq! (order[0], r);
+
+        let order = super::topological_sort (&g).expect ("Failed topological_sort");
+        let position = |v: usize| order.iter ().position (|&x| x == v).unwrap ();
+        assert! (graph::GraphAny::edges_iter (&g).all (|((a, b), _)| position (a) < position (b)), "{:?} is not a topological order", order);
+    }
+
     #[test]
     fn test_tree_sort ()
     {
@@ -1432,6 +2709,143 @@ mod tests
         assert_eq! (r,solution);
     }
 
+    #[test]
+    fn test_strongly_connected_components ()
+    {
+        init ();
+        let mut g = graph::Graph::new ();
+        // 1 <-> 2 -> 3 -> 4 -> 5
+        //            ^         |
+        //            +---------+    6
+        g.add_edge_raw (1,2,3).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,1,5).expect ("Failed to add edge 2 -> 1");
+        g.add_edge_raw (2,3,7).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (1,3,2).expect ("Failed to add edge 1 -> 3");
+        g.add_edge_raw (3,4,1).expect ("Failed to add edge 3 -> 4");
+        g.add_edge_raw (4,5,1).expect ("Failed to add edge 4 -> 5");
+        g.add_edge_raw (5,3,1).expect ("Failed to add edge 5 -> 3");
+        g.add_vertex_raw (6).expect ("Failed to add vertex 6");
+
+        let r = super::strongly_connected_components (&g).expect ("Failed to find strongly connected components");
+        assert_eq! (r.len (), 3);
+        let position = |v: usize| r.iter ().position (|c| c.contains (&v)).unwrap ();
+        assert_eq! (r[position (1)], collections::HashSet::from ([1,2]));
+        assert_eq! (r[position (3)], collections::HashSet::from ([3,4,5]));
+        assert_eq! (r[position (6)], collections::HashSet::from ([6]));
+        assert! (position (1) < position (3));
+
+        let mut r = super::weakly_connected_components (&g).expect ("Failed to find weakly connected components");
+        r.sort_by_key (|x| x.len ());
+        assert_eq! (r, vec![
+            collections::HashSet::from ([6]),
+            collections::HashSet::from ([1,2,3,4,5])
+        ]);
+
+        let (dag, membership) = super::condensation (&g).expect ("Failed to condense graph");
+        assert_eq! (dag.vertices ().len (), 3);
+        assert_eq! (membership.len (), 6);
+        assert_eq! (membership[&1], membership[&2]);
+        assert_eq! (membership[&3], membership[&5]);
+        assert_eq! (dag.edges ().len (), 1);
+        assert_eq! (dag.edges ()[&(membership[&1], membership[&3])], 2);
+        let order = super::topological_sort (&dag).expect ("Failed to sort condensation");
+        assert! (order.iter ().position (|c| *c == membership[&1]) < order.iter ().position (|c| *c == membership[&3]));
+    }
+
+    #[test]
+    fn test_strongly_connected_components_labelled ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        g.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -> b");
+        g.add_edge (String::from ("b"), String::from ("a"), None).expect ("Failed to add edge b -> a");
+        g.add_edge (String::from ("c"), String::from ("b"), None).expect ("Failed to add edge c -> b");
+
+        assert_eq! (super::strongly_connected_components_labelled (&g).unwrap (), vec![
+            collections::HashSet::from ([String::from ("c")]),
+            collections::HashSet::from ([String::from ("a"), String::from ("b")])
+        ]);
+        assert_eq! (super::weakly_connected_components_labelled (&g).unwrap (), vec![
+            collections::HashSet::from ([String::from ("a"), String::from ("b"), String::from ("c")])
+        ]);
+    }
+
+    #[test]
+    fn test_find_cycle ()
+    {
+        init ();
+        let mut g = graph::Graph::new ();
+        // 1 -> 2 -> 3 -> 4 -> 2, 1 -> 5
+        g.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,0).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (3,4,0).expect ("Failed to add edge 3 -> 4");
+        g.add_edge_raw (1,5,0).expect ("Failed to add edge 1 -> 5");
+        assert_eq! (super::find_cycle (&g).unwrap (), None);
+        // ignoring directions 1 -> 2 and 2 -> 1 would be walked back along
+        g.add_edge_raw (2,1,0).expect ("Failed to add edge 2 -> 1");
+        assert_eq! (super::find_cycle (&g).unwrap (), Some (vec![1,2]));
+        assert_eq! (super::find_cycle_undirected (&g).unwrap (), None);
+        g.remove_edge_raw (&2, &1).expect ("Failed to remove edge 2 -> 1");
+        g.add_edge_raw (4,2,0).expect ("Failed to add edge 4 -> 2");
+        assert_eq! (super::find_cycle (&g).unwrap (), Some (vec![2,3,4]));
+        assert_eq! (super::topological_sort (&g).unwrap_err ().to_string (), "Edge error: Graph contains at least one cycle: 2 -> 3 -> 4 -> 2");
+
+        let mut ug = graph::UGraph::new ();
+        ug.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -- 2");
+        ug.add_edge_raw (2,3,0).expect ("Failed to add edge 2 -- 3");
+        ug.add_edge_raw (3,4,0).expect ("Failed to add edge 3 -- 4");
+        assert_eq! (super::find_cycle_undirected (&ug).unwrap (), None);
+        ug.add_edge_raw (4,2,0).expect ("Failed to add edge 4 -- 2");
+        assert_eq! (super::find_cycle_undirected (&ug).unwrap (), Some (vec![2,3,4]));
+
+        let mut lg = graph::LabelledGraph::new ();
+        lg.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -> b");
+        lg.add_edge (String::from ("b"), String::from ("c"), None).expect ("Failed to add edge b -> c");
+        lg.add_edge (String::from ("c"), String::from ("b"), None).expect ("Failed to add edge c -> b");
+        assert_eq! (super::topological_sort_labelled (&lg).unwrap_err ().to_string (), "Edge error: Graph contains at least one cycle: b -> c -> b");
+    }
+
+    #[test]
+    fn test_simple_cycles ()
+    {
+        init ();
+        // every ordered pair of 1..=4 is an edge, giving 6 cycles of two
+        // vertices, 8 of three and 6 of four
+        let mut g = graph::Graph::new ();
+        for a in 1..=4
+        {
+            for b in 1..=4
+            {
+                if a != b
+                {
+                    g.add_edge_raw (a,b,0).expect ("Failed to add edge");
+                }
+            }
+        }
+        let r = super::simple_cycles (&g, None, None).expect ("Failed to enumerate cycles");
+        assert_eq! (r.len (), 20);
+        assert_eq! (r.iter ().cloned ().collect::<collections::HashSet<_>> ().len (), 20);
+        assert! (r.iter ().all (|c| c.iter ().zip (c.iter ().cycle ().skip (1)).all (|(a, b)| g.has_edge_raw (&(*a, *b)))));
+        assert! (r.iter ().all (|c| c[0] == *c.iter ().min ().unwrap ()));
+        let bounded = super::simple_cycles (&g, Some (4), None).expect ("Failed to enumerate cycles");
+        assert_eq! (bounded.iter ().cloned ().collect::<collections::HashSet<_>> (), r.iter ().cloned ().collect::<collections::HashSet<_>> ());
+        assert_eq! (super::simple_cycles (&g, Some (3), None).expect ("Failed to enumerate cycles").len (), 14);
+        assert_eq! (super::simple_cycles (&g, Some (2), None).expect ("Failed to enumerate cycles").len (), 6);
+        assert_eq! (super::simple_cycles (&g, None, Some (5)).expect ("Failed to enumerate cycles").len (), 5);
+
+        let mut g = graph::Graph::new ();
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        // 1 -> 2 -> 3 -> 1, 3 -> 3, 3 -> 4
+        g.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,0).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (3,1,0).expect ("Failed to add edge 3 -> 1");
+        g.add_edge_raw (3,3,0).expect ("Failed to add edge 3 -> 3");
+        g.add_edge_raw (3,4,0).expect ("Failed to add edge 3 -> 4");
+        assert_eq! (super::simple_cycles (&g, None, None).unwrap (), vec![vec![3], vec![1,2,3]]);
+        assert_eq! (super::simple_cycles (&g, Some (2), None).unwrap (), vec![vec![3]]);
+        assert! (super::simple_cycles (&g, Some (0), None).unwrap ().is_empty ());
+    }
+
     #[test]
     fn test_dfs ()
     {
@@ -1682,6 +3096,166 @@ mod tests
         assert_eq! (r, solution);
     }
 
+    #[test]
+    fn test_bellman_ford ()
+    {
+        init ();
+        let mut g = graph::Graph::new ();
+        // 1 -> 2 -> 3 costs 1 and beats the direct 1 -> 3 at 2
+        // 1 -> 4 -> 3 costs 6
+        g.add_edge_raw (1,2,4).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,-3).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (1,3,2).expect ("Failed to add edge 1 -> 3");
+        g.add_edge_raw (1,4,-1).expect ("Failed to add edge 1 -> 4");
+        g.add_edge_raw (4,3,7).expect ("Failed to add edge 4 -> 3");
+        g.add_vertex_raw (5).expect ("Failed to add vertex 5");
+
+        let (distances, paths) = super::bellman_ford (&g, 1).expect ("Failed bellman ford");
+        assert_eq! (distances, collections::HashMap::from ([(1,0), (2,4), (3,1), (4,-1)]));
+        assert_eq! (paths[&3], vec![1,2,3]);
+        assert_eq! (paths[&1], vec![1]);
+        let r = super::multi_source_bellman_ford (&g, &collections::HashSet::from ([1,4])).expect ("Failed bellman ford");
+        assert_eq! (r[&4].0, collections::HashMap::from ([(4,0), (3,7)]));
+        assert_eq! (r[&1].1[&4], vec![1,4]);
+        assert! (super::bellman_ford (&g, 6).is_err ());
+
+        let r = super::johnson (&g).expect ("Failed johnson");
+        for source in g.vertices ()
+        {
+            assert_eq! (r[source], super::bellman_ford (&g, *source).expect ("Failed bellman ford"));
+        }
+
+        // a negative cycle only matters where it is reachable
+        g.add_edge_raw (5,6,1).expect ("Failed to add edge 5 -> 6");
+        g.add_edge_raw (6,5,-2).expect ("Failed to add edge 6 -> 5");
+        assert_eq! (super::bellman_ford (&g, 1).expect ("Failed bellman ford").0[&3], 1);
+        assert_eq! (super::bellman_ford (&g, 6).unwrap_err ().to_string (), "Edge error: Graph contains a negative cycle: 5 -> 6 -> 5");
+        assert_eq! (super::johnson (&g).unwrap_err ().to_string (), "Edge error: Graph contains a negative cycle: 5 -> 6 -> 5");
+
+        // undirected, a negative edge is a negative cycle on its own
+        let mut ug = graph::UGraph::new ();
+        ug.add_edge_raw (1,2,3).expect ("Failed to add edge 1 -- 2");
+        ug.add_edge_raw (2,3,4).expect ("Failed to add edge 2 -- 3");
+        assert_eq! (super::johnson (&ug).expect ("Failed johnson")[&3].0[&1], 7);
+        ug.add_edge_raw (3,4,-1).expect ("Failed to add edge 3 -- 4");
+        assert_eq! (super::bellman_ford (&ug, 1).unwrap_err ().to_string (), "Edge error: Graph contains a negative cycle: 3 -> 4 -> 3");
+    }
+
+    #[test]
+    fn test_point_to_point ()
+    {
+        init ();
+        // a 5 x 5 grid, vertex 5 * row + column, with edges to the right and
+        // down costing 1 + row + column
+        let mut g = graph::Graph::new ();
+        let mut ug = graph::UGraph::new ();
+        for row in 0..5
+        {
+            for column in 0..5
+            {
+                let v = 5 * row + column;
+                let cost = (1 + row + column) as i64;
+                if column < 4
+                {
+                    g.add_edge_raw (v, v + 1, cost).expect ("Failed to add edge");
+                    ug.add_edge_raw (v, v + 1, cost).expect ("Failed to add edge");
+                }
+                if row < 4
+                {
+                    g.add_edge_raw (v, v + 5, cost).expect ("Failed to add edge");
+                    ug.add_edge_raw (v, v + 5, cost).expect ("Failed to add edge");
+                }
+            }
+        }
+        g.add_vertex_raw (25).expect ("Failed to add vertex 25");
+        let manhattan = |target: usize| move |v: usize| ((target / 5).abs_diff (v / 5) + (target % 5).abs_diff (v % 5)) as i64;
+
+        for (source, target) in [(0, 24), (0, 4), (6, 18), (7, 7)]
+        {
+            let r = super::multi_source_dijkstra (&g, &collections::HashSet::from ([source])).expect ("Failed dijkstra");
+            let expected = Some ( (r[&source].0[&target], r[&source].1[&target].clone ()) );
+            let astar = super::astar (&g, source, target, manhattan (target)).expect ("Failed A*");
+            assert_eq! (astar.as_ref ().map (|x| x.0), expected.as_ref ().map (|x| x.0));
+            assert! (astar.is_some_and (|(_, path)| path.first () == Some (&source) && path.last () == Some (&target)));
+            let bidirectional = super::bidirectional_dijkstra (&g, source, target).expect ("Failed bidirectional dijkstra");
+            assert_eq! (bidirectional.as_ref ().map (|x| x.0), expected.as_ref ().map (|x| x.0));
+            let (cost, path) = bidirectional.unwrap ();
+            assert_eq! (path.windows (2).map (|e| g.edges ()[&(e[0], e[1])]).sum::<i64> (), cost);
+        }
+        assert_eq! (super::astar (&g, 24, 0, |_| 0).unwrap (), None);
+        assert_eq! (super::bidirectional_dijkstra (&g, 0, 25).unwrap (), None);
+        assert! (super::bidirectional_dijkstra (&g, 0, 26).is_err ());
+
+        // undirected the grid can be crossed from the bottom right too
+        let r = super::multi_source_dijkstra (&ug, &collections::HashSet::from ([24])).expect ("Failed dijkstra");
+        let (cost, path) = super::bidirectional_dijkstra_undirected (&ug, 24, 0).unwrap ().expect ("No path from 24 to 0");
+        assert_eq! (cost, r[&24].0[&0]);
+        assert_eq! ( (path[0], path[path.len () - 1]), (24, 0) );
+        assert_eq! (super::astar (&ug, 24, 0, manhattan (0)).unwrap ().map (|x| x.0), Some (cost));
+
+        // on a directed graph edges are walked against their direction too
+        let mut g = graph::Graph::new ();
+        g.add_edge_raw (1, 2, 1).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2, 3, 1).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (4, 3, 1).expect ("Failed to add edge 4 -> 3");
+        g.add_edge_raw (3, 4, 5).expect ("Failed to add edge 3 -> 4");
+        assert_eq! (super::bidirectional_dijkstra_undirected (&g, 1, 3).unwrap (), Some ( (2, vec![1, 2, 3]) ));
+        assert_eq! (super::bidirectional_dijkstra_undirected (&g, 3, 1).unwrap (), Some ( (2, vec![3, 2, 1]) ));
+        assert_eq! (super::bidirectional_dijkstra_undirected (&g, 1, 4).unwrap (), Some ( (3, vec![1, 2, 3, 4]) ));
+        assert_eq! (super::bidirectional_dijkstra (&g, 3, 1).unwrap (), None);
+    }
+
+    #[test]
+    fn test_dijkstra_with_options ()
+    {
+        init ();
+        let mut g = graph::Graph::new ();
+        // 1 -> 2 -> 3 -> 4 -> 5 costing 1 each, 1 -> 5 costing 10, 1 -> 3
+        // costing 5, 6 apart
+        g.add_edge_raw (1,2,1).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,1).expect ("Failed to add edge 2 -> 3");
+        g.add_edge_raw (3,4,1).expect ("Failed to add edge 3 -> 4");
+        g.add_edge_raw (4,5,1).expect ("Failed to add edge 4 -> 5");
+        g.add_edge_raw (1,5,10).expect ("Failed to add edge 1 -> 5");
+        g.add_edge_raw (1,3,5).expect ("Failed to add edge 1 -> 3");
+        g.add_vertex_raw (6).expect ("Failed to add vertex 6");
+        let sources = collections::HashSet::from ([1]);
+
+        let r = super::dijkstra_with_options (&g, &sources, &super::DijkstraOptions::new ()).expect ("Failed dijkstra");
+        let expected = super::multi_source_dijkstra (&g, &sources).expect ("Failed dijkstra");
+        assert_eq! (r[&1].0, expected[&1].0);
+        for v in [1,2,3,4,5]
+        {
+            assert_eq! (super::reconstruct_path (&r[&1].1, 1, v).as_ref (), expected[&1].1.get (&v));
+        }
+        assert_eq! (super::reconstruct_path (&r[&1].1, 1, 6), None);
+        assert_eq! (r[&1].1.len (), 4);
+
+        let options = super::DijkstraOptions::new ().with_targets (collections::HashSet::from ([3]));
+        let r = super::dijkstra_with_options (&g, &sources, &options).expect ("Failed dijkstra");
+        assert_eq! (r[&1].0, collections::HashMap::from ([(1,0), (2,1), (3,2)]));
+
+        let options = super::DijkstraOptions::new ().with_max_cost (3);
+        let r = super::dijkstra_with_options (&g, &sources, &options).expect ("Failed dijkstra");
+        assert_eq! (r[&1].0, collections::HashMap::from ([(1,0), (2,1), (3,2), (4,3)]));
+
+        // within two hops 3 is still cheapest through 2, but 4 is only
+        // reached through the dearer 1 -> 3 and 5 along the direct edge
+        let options = super::DijkstraOptions::new ().with_max_hops (2);
+        let r = super::dijkstra_with_options (&g, &sources, &options).expect ("Failed dijkstra");
+        assert_eq! (r[&1].0, collections::HashMap::from ([(1,0), (2,1), (3,2), (4,6), (5,10)]));
+        assert_eq! (super::reconstruct_path (&r[&1].1, 1, 3), Some (vec![1,2,3]));
+        assert_eq! (super::reconstruct_path (&r[&1].1, 1, 4), Some (vec![1,3,4]));
+        assert_eq! (super::reconstruct_path (&r[&1].1, 1, 5), Some (vec![1,5]));
+        assert_eq! (super::reconstruct_path (&r[&1].1, 2, 5), None);
+
+        let options = super::DijkstraOptions::new ().with_max_hops (1);
+        let r = super::dijkstra_with_options (&g, &sources, &options).expect ("Failed dijkstra");
+        assert_eq! (r[&1].0, collections::HashMap::from ([(1,0), (2,1), (3,5), (5,10)]));
+
+        assert! (super::dijkstra_with_options (&g, &collections::HashSet::from ([7]), &options).is_err ());
+    }
+
     #[test]
     fn test_overlapping_components ()
     {
@@ -1976,5 +3550,73 @@ mod tests
         let r = super::single_shortest_path (&g, 1).unwrap ();
         assert_eq! (r,solution);
     }
-}
 
+    #[test]
+    fn test_labelled ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        // c     d
+        //  \   /
+        //   * *
+        //    b
+        //    |
+        //    * 5
+        //    a
+        g.add_edge_weighted (String::from ("b"), String::from ("a"), None, 5).expect ("Failed to add edge b -> a");
+        g.add_edge (String::from ("c"), String::from ("b"), None).expect ("Failed to add edge c -> b");
+        g.add_edge (String::from ("d"), String::from ("b"), None).expect ("Failed to add edge d -> b");
+
+        assert_eq! (super::bfs_edges_labelled (&g, "c").unwrap (), vec![ (String::from ("c"), String::from ("b")), (String::from ("b"), String::from ("a")) ]);
+        assert_eq! (super::single_shortest_path_labelled (&g, "d").unwrap ()["a"], vec![String::from ("d"), String::from ("b"), String::from ("a")]);
+
+        let r = super::multi_source_dijkstra_labelled (&g, &collections::HashSet::from ([String::from ("c")])).unwrap ();
+        assert_eq! (r["c"].0["a"], 5);
+        assert_eq! (r["c"].1["a"], vec![String::from ("c"), String::from ("b"), String::from ("a")]);
+
+        let r = super::topological_sort_labelled (&g).unwrap ();
+        assert_eq! (r.len (), 4);
+        assert_eq! (&r[2..], &[String::from ("b"), String::from ("a")]);
+
+        assert_eq! (super::bfs_edges_labelled (&g, "z").unwrap_err ().to_string (), "Vertex error: Failed to find vertex: \"z\"");
+    }
+
+    #[test]
+    fn test_labelled_integers ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::<i64, graph::Attributes, graph::Attributes, u32>::new_weighted ();
+        g.add_edge_weighted (2, 1, None, 5).expect ("Failed to add edge 2 -> 1");
+        g.add_edge (3, 2, None).expect ("Failed to add edge 3 -> 2");
+        g.add_edge (4, 2, None).expect ("Failed to add edge 4 -> 2");
+
+        assert_eq! (super::bfs_edges_labelled (&g, &3).unwrap (), vec![ (3, 2), (2, 1) ]);
+        assert_eq! (super::single_shortest_path_labelled (&g, &4).unwrap ()[&1], vec![4, 2, 1]);
+        let r = super::multi_source_dijkstra_labelled (&g, &collections::HashSet::from ([3])).unwrap ();
+        assert_eq! (r[&3].0[&1], 5);
+        assert_eq! (&super::topological_sort_labelled (&g).unwrap ()[2..], &[2, 1]);
+
+        g.add_edge (1, 4, None).expect ("Failed to add edge 1 -> 4");
+        assert_eq! (super::topological_sort_labelled (&g).unwrap_err ().to_string (), "Edge error: Graph contains at least one cycle: 1 -> 4 -> 2 -> 1");
+        assert_eq! (super::strongly_connected_components_labelled (&g).unwrap ().len (), 2);
+        assert_eq! (super::bfs_edges_labelled (&g, &9).unwrap_err ().to_string (), "Vertex error: Failed to find vertex: 9");
+    }
+
+    #[test]
+    fn test_connected_components_labelled ()
+    {
+        init ();
+        let mut g = graph::LabelledUGraph::new ();
+        g.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -- b");
+        g.add_edge (String::from ("c"), String::from ("b"), None).expect ("Failed to add edge c -- b");
+        g.add_edge (String::from ("d"), String::from ("e"), None).expect ("Failed to add edge d -- e");
+
+        let mut r = super::connected_components_labelled (&g).unwrap ();
+        r.sort_by_key (|x| x.len ());
+        assert_eq! (r, vec![
+            collections::HashSet::from ([String::from ("d"), String::from ("e")]),
+            collections::HashSet::from ([String::from ("a"), String::from ("b"), String::from ("c")])
+        ]);
+        assert_eq! (super::single_shortest_path_labelled (&g, "a").unwrap ()["c"], vec![String::from ("a"), String::from ("b"), String::from ("c")]);
+    }
+}
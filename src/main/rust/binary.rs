@@ -0,0 +1,869 @@
+use crate::error;
+use crate::graph;
+use std::collections;
+use std::io;
+
+// Binary snapshot format, all integers little endian
+//
+//   magic    4 bytes "GRPH"
+//   version  u16
+//   kind     u8   0 Graph, 1 UGraph, 2 LabelledGraph, 3 LabelledUGraph
+//   flags    u8   bit 0 set when self loops are allowed
+//   name     string
+//   vertices u64 count, then per vertex: id u64 [, label string, attrs]
+//   edges    u64 count, then per edge: a u64, b u64, weight i64 [, attrs]
+//   checksum u64 FNV-1a of every preceding byte
+//
+// string: u64 byte length then UTF-8 bytes
+// attrs:  u64 count, then per attribute: name string, tag u8, value
+//   0 BooleanLiteral  u8
+//   1 BooleanMap      u64 count, (string, u8)*
+//   2 IntegerLiteral  i64
+//   3 FloatLiteral    f64 bits as u64
+//   4 StringLiteral   string
+//   5 StringArray     u64 count, string*
+//   6 StringMap       u64 count, (string, string)*
+//   7 StringSet       u64 count, string*
+//   8 Bytes           u64 count, u8*
+//   9 FloatArray      u64 count, (f64 bits as u64)*
+//  10 IntegerArray    u64 count, i64*
+//  11 List            u64 count, (tag u8, value)*
+//  12 Map             u64 count, (string, tag u8, value)*
+//  13 Null
+//
+// Records are written straight from the graph maps and read straight into
+// the graph, so neither side holds a second copy. Callers should pass a
+// BufWriter / BufReader.
+//
+// The checksum can only be compared once every record has been read, so a
+// corrupt snapshot whose damage shows up as a structural error (a missing
+// vertex, an unknown tag, truncation) reports that error rather than the
+// checksum mismatch.
+//
+// Weights are fixed at i64 by the format, so only graphs with the default
+// i64 weights can be written and read; the interchange JSON carries other
+// weight types.
+
+const MAGIC: &[u8; 4] = b"GRPH";
+pub const VERSION: u16 = 1;
+
+const FLAG_SELF_LOOPS: u8 = 1;
+
+const KIND_GRAPH: u8 = 0;
+const KIND_UGRAPH: u8 = 1;
+const KIND_LABELLED_GRAPH: u8 = 2;
+const KIND_LABELLED_UGRAPH: u8 = 3;
+
+// Cap on capacity reserved from a length read from the input
+const MAX_RESERVE: usize = 1 << 16;
+
+// Cap on List / Map nesting so corrupt input cannot exhaust the stack
+const MAX_DEPTH: usize = 64;
+
+struct Fnv1a (u64);
+
+impl Fnv1a
+{
+    fn new () -> Self
+    {
+        Self (0xcbf29ce484222325)
+    }
+
+    fn update (&mut self, bytes: &[u8])
+    {
+        for b in bytes
+        {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul (0x100000001b3);
+        }
+    }
+}
+
+struct Encoder<'a, W>
+{
+    writer: &'a mut W,
+    hash: Fnv1a
+}
+
+impl<'a, W> Encoder<'a, W>
+    where
+        W: io::Write
+{
+    fn new (writer: &'a mut W, kind: u8, name: &str, self_loops: bool)
+        -> Result<Self, error::GraphError>
+    {
+        let mut r = Self { writer, hash: Fnv1a::new () };
+        r.bytes (MAGIC)?;
+        r.bytes (&VERSION.to_le_bytes ())?;
+        r.bytes (&[kind, if self_loops { FLAG_SELF_LOOPS } else { 0 }])?;
+        r.string (name)?;
+        Ok (r)
+    }
+
+    fn bytes (&mut self, bytes: &[u8])
+        -> Result<(), error::GraphError>
+    {
+        self.hash.update (bytes);
+        self.writer.write_all (bytes)?;
+        Ok (())
+    }
+
+    fn u64 (&mut self, v: u64)
+        -> Result<(), error::GraphError>
+    {
+        self.bytes (&v.to_le_bytes ())
+    }
+
+    fn usize (&mut self, v: usize)
+        -> Result<(), error::GraphError>
+    {
+        self.u64 (u64::try_from (v)?)
+    }
+
+    fn string (&mut self, s: &str)
+        -> Result<(), error::GraphError>
+    {
+        self.usize (s.len ())?;
+        self.bytes (s.as_bytes ())
+    }
+
+    fn attrs (&mut self, attrs: &collections::HashMap<String, graph::AttributeValue>)
+        -> Result<(), error::GraphError>
+    {
+        self.usize (attrs.len ())?;
+        for (k, v) in attrs
+        {
+            self.string (k)?;
+            self.value (v, 0)?;
+        }
+        Ok (())
+    }
+
+    fn value (&mut self, v: &graph::AttributeValue, depth: usize)
+        -> Result<(), error::GraphError>
+    {
+        if depth > MAX_DEPTH
+        {
+            return Err (error::GraphError::DataError (format! ("Attribute values nested deeper than {} are not supported", MAX_DEPTH)));
+        }
+        match v
+        {
+            graph::AttributeValue::BooleanLiteral (b) => {
+                self.bytes (&[0, *b as u8])?;
+            },
+            graph::AttributeValue::BooleanMap (m) => {
+                self.bytes (&[1])?;
+                self.usize (m.len ())?;
+                for (mk, mv) in m
+                {
+                    self.string (mk)?;
+                    self.bytes (&[*mv as u8])?;
+                }
+            },
+            graph::AttributeValue::IntegerLiteral (i) => {
+                self.bytes (&[2])?;
+                self.bytes (&i.to_le_bytes ())?;
+            },
+            graph::AttributeValue::FloatLiteral (f) => {
+                self.bytes (&[3])?;
+                self.u64 (f.to_bits ())?;
+            },
+            graph::AttributeValue::StringLiteral (s) => {
+                self.bytes (&[4])?;
+                self.string (s)?;
+            },
+            graph::AttributeValue::StringArray (a) => {
+                self.bytes (&[5])?;
+                self.usize (a.len ())?;
+                for s in a
+                {
+                    self.string (s)?;
+                }
+            },
+            graph::AttributeValue::StringMap (m) => {
+                self.bytes (&[6])?;
+                self.usize (m.len ())?;
+                for (mk, mv) in m
+                {
+                    self.string (mk)?;
+                    self.string (mv)?;
+                }
+            },
+            graph::AttributeValue::StringSet (s) => {
+                self.bytes (&[7])?;
+                self.usize (s.len ())?;
+                for v in s
+                {
+                    self.string (v)?;
+                }
+            },
+            graph::AttributeValue::Bytes (b) => {
+                self.bytes (&[8])?;
+                self.usize (b.len ())?;
+                self.bytes (b)?;
+            },
+            graph::AttributeValue::FloatArray (a) => {
+                self.bytes (&[9])?;
+                self.usize (a.len ())?;
+                for f in a
+                {
+                    self.u64 (f.to_bits ())?;
+                }
+            },
+            graph::AttributeValue::IntegerArray (a) => {
+                self.bytes (&[10])?;
+                self.usize (a.len ())?;
+                for i in a
+                {
+                    self.bytes (&i.to_le_bytes ())?;
+                }
+            },
+            graph::AttributeValue::List (a) => {
+                self.bytes (&[11])?;
+                self.usize (a.len ())?;
+                for v in a
+                {
+                    self.value (v, depth + 1)?;
+                }
+            },
+            graph::AttributeValue::Map (m) => {
+                self.bytes (&[12])?;
+                self.usize (m.len ())?;
+                for (mk, mv) in m
+                {
+                    self.string (mk)?;
+                    self.value (mv, depth + 1)?;
+                }
+            },
+            graph::AttributeValue::Null => {
+                self.bytes (&[13])?;
+            }
+        }
+        Ok (())
+    }
+
+    fn finish (self)
+        -> Result<(), error::GraphError>
+    {
+        self.writer.write_all (&self.hash.0.to_le_bytes ())?;
+        self.writer.flush ()?;
+        Ok (())
+    }
+}
+
+struct Decoder<'a, R>
+{
+    reader: &'a mut R,
+    hash: Fnv1a
+}
+
+impl<'a, R> Decoder<'a, R>
+    where
+        R: io::Read
+{
+    fn new (reader: &'a mut R, kind: u8)
+        -> Result<(Self, String, bool), error::GraphError>
+    {
+        let mut r = Self { reader, hash: Fnv1a::new () };
+        if r.array::<4> ()? != *MAGIC
+        {
+            return Err (error::GraphError::DataError (String::from ("Not a binary graph snapshot")));
+        }
+        let version = u16::from_le_bytes (r.array::<2> ()?);
+        if version != VERSION
+        {
+            return Err (error::GraphError::DataError (format! ("Unsupported binary snapshot version {}, expected {}", version, VERSION)));
+        }
+        let found = r.u8 ()?;
+        if found != kind
+        {
+            return Err (error::GraphError::DataError (format! ("Binary snapshot kind {} cannot be loaded as kind {}", found, kind)));
+        }
+        let flags = r.u8 ()?;
+        if flags & !FLAG_SELF_LOOPS != 0
+        {
+            return Err (error::GraphError::DataError (format! ("Unknown binary snapshot flags {:#04x}", flags)));
+        }
+        let name = r.string ()?;
+        Ok ( (r, name, flags & FLAG_SELF_LOOPS != 0) )
+    }
+
+    fn fill (&mut self, buf: &mut [u8])
+        -> Result<(), error::GraphError>
+    {
+        self.reader.read_exact (buf).map_err (|e| {
+            match e.kind ()
+            {
+                io::ErrorKind::UnexpectedEof => error::GraphError::DataError (String::from ("Binary snapshot is truncated")),
+                _ => error::GraphError::from (e)
+            }
+        })
+    }
+
+    fn array<const N: usize> (&mut self)
+        -> Result<[u8; N], error::GraphError>
+    {
+        let mut r = [0u8; N];
+        self.fill (&mut r)?;
+        self.hash.update (&r);
+        Ok (r)
+    }
+
+    fn u8 (&mut self)
+        -> Result<u8, error::GraphError>
+    {
+        Ok (self.array::<1> ()?[0])
+    }
+
+    fn bool (&mut self)
+        -> Result<bool, error::GraphError>
+    {
+        match self.u8 ()?
+        {
+            0 => Ok (false),
+            1 => Ok (true),
+            b => Err (error::GraphError::DataError (format! ("Invalid boolean byte {}", b)))
+        }
+    }
+
+    fn u64 (&mut self)
+        -> Result<u64, error::GraphError>
+    {
+        Ok (u64::from_le_bytes (self.array::<8> ()?))
+    }
+
+    fn i64 (&mut self)
+        -> Result<i64, error::GraphError>
+    {
+        Ok (i64::from_le_bytes (self.array::<8> ()?))
+    }
+
+    fn usize (&mut self)
+        -> Result<usize, error::GraphError>
+    {
+        let v = self.u64 ()?;
+        usize::try_from (v).map_err (|_| error::GraphError::DataError (format! ("Value {} does not fit in usize", v)))
+    }
+
+    fn byte_vec (&mut self)
+        -> Result<Vec<u8>, error::GraphError>
+    {
+        let len = self.usize ()?;
+        // Read in bounded chunks so a corrupt length fails on EOF rather than on allocation
+        let mut r = Vec::<u8>::with_capacity (len.min (MAX_RESERVE));
+        let mut chunk = [0u8; 4096];
+        while r.len () < len
+        {
+            let n = (len - r.len ()).min (chunk.len ());
+            self.fill (&mut chunk[..n])?;
+            r.extend_from_slice (&chunk[..n]);
+        }
+        self.hash.update (&r);
+        Ok (r)
+    }
+
+    fn string (&mut self)
+        -> Result<String, error::GraphError>
+    {
+        String::from_utf8 (self.byte_vec ()?).map_err (|e| error::GraphError::DataError (format! ("Invalid UTF-8 string: {}", e)))
+    }
+
+    fn attrs (&mut self)
+        -> Result<collections::HashMap<String, graph::AttributeValue>, error::GraphError>
+    {
+        let len = self.usize ()?;
+        let mut r = collections::HashMap::<String, graph::AttributeValue>::with_capacity (len.min (MAX_RESERVE));
+        for _ in 0..len
+        {
+            let k = self.string ()?;
+            let v = self.value (&k, 0)?;
+            r.insert (k, v);
+        }
+        Ok (r)
+    }
+
+    fn value (&mut self, k: &str, depth: usize)
+        -> Result<graph::AttributeValue, error::GraphError>
+    {
+        if depth > MAX_DEPTH
+        {
+            return Err (error::GraphError::DataError (format! ("Attribute {} is nested deeper than {}", k, MAX_DEPTH)));
+        }
+        let v = match self.u8 ()?
+        {
+            0 => graph::AttributeValue::BooleanLiteral (self.bool ()?),
+            1 => {
+                let n = self.usize ()?;
+                let mut m = collections::HashMap::<String, bool>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    let mk = self.string ()?;
+                    m.insert (mk, self.bool ()?);
+                }
+                graph::AttributeValue::BooleanMap (m)
+            },
+            2 => graph::AttributeValue::IntegerLiteral (self.i64 ()?),
+            3 => graph::AttributeValue::FloatLiteral (f64::from_bits (self.u64 ()?)),
+            4 => graph::AttributeValue::StringLiteral (self.string ()?),
+            5 => {
+                let n = self.usize ()?;
+                let mut a = Vec::<String>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    a.push (self.string ()?);
+                }
+                graph::AttributeValue::StringArray (a)
+            },
+            6 => {
+                let n = self.usize ()?;
+                let mut m = collections::HashMap::<String, String>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    let mk = self.string ()?;
+                    m.insert (mk, self.string ()?);
+                }
+                graph::AttributeValue::StringMap (m)
+            },
+            7 => {
+                let n = self.usize ()?;
+                let mut s = collections::HashSet::<String>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    s.insert (self.string ()?);
+                }
+                graph::AttributeValue::StringSet (s)
+            },
+            8 => graph::AttributeValue::Bytes (self.byte_vec ()?),
+            9 => {
+                let n = self.usize ()?;
+                let mut a = Vec::<f64>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    a.push (f64::from_bits (self.u64 ()?));
+                }
+                graph::AttributeValue::FloatArray (a)
+            },
+            10 => {
+                let n = self.usize ()?;
+                let mut a = Vec::<i64>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    a.push (self.i64 ()?);
+                }
+                graph::AttributeValue::IntegerArray (a)
+            },
+            11 => {
+                let n = self.usize ()?;
+                let mut a = Vec::<graph::AttributeValue>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    a.push (self.value (k, depth + 1)?);
+                }
+                graph::AttributeValue::List (a)
+            },
+            12 => {
+                let n = self.usize ()?;
+                let mut m = collections::HashMap::<String, graph::AttributeValue>::with_capacity (n.min (MAX_RESERVE));
+                for _ in 0..n
+                {
+                    let mk = self.string ()?;
+                    m.insert (mk, self.value (k, depth + 1)?);
+                }
+                graph::AttributeValue::Map (m)
+            },
+            13 => graph::AttributeValue::Null,
+            tag => return Err (error::GraphError::DataError (format! ("Unknown attribute tag {} for {}", tag, k)))
+        };
+        Ok (v)
+    }
+
+    fn finish (mut self)
+        -> Result<(), error::GraphError>
+    {
+        let mut checksum = [0u8; 8];
+        self.fill (&mut checksum)?;
+        if u64::from_le_bytes (checksum) == self.hash.0
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (error::GraphError::DataError (String::from ("Binary snapshot checksum mismatch")))
+        }
+    }
+}
+
+// Structural errors while rebuilding mean the snapshot is corrupt
+fn corrupt (e: error::GraphError)
+    -> error::GraphError
+{
+    match e
+    {
+        error::GraphError::DataError (_) | error::GraphError::IOError (_) => e,
+        _ => error::GraphError::DataError (format! ("Invalid binary snapshot: {}", e))
+    }
+}
+
+pub fn write_graph<W> (g: &graph::Graph<i64>, writer: &mut W)
+    -> Result<(), error::GraphError>
+    where
+        W: io::Write
+{
+    let mut e = Encoder::new (writer, KIND_GRAPH, &g.name (), g.self_loops ())?;
+    e.usize (g.vertices ().len ())?;
+    for v in g.vertices ()
+    {
+        e.usize (*v)?;
+    }
+    e.usize (g.edges ().len ())?;
+    for ((a, b), w) in g.edges ()
+    {
+        e.usize (*a)?;
+        e.usize (*b)?;
+        e.bytes (&w.to_le_bytes ())?;
+    }
+    e.finish ()
+}
+
+pub fn write_ugraph<W> (g: &graph::UGraph<i64>, writer: &mut W)
+    -> Result<(), error::GraphError>
+    where
+        W: io::Write
+{
+    let mut e = Encoder::new (writer, KIND_UGRAPH, &g.name (), g.self_loops ())?;
+    e.usize (g.vertices ().len ())?;
+    for v in g.vertices ()
+    {
+        e.usize (*v)?;
+    }
+    e.usize (g.edges ().len ())?;
+    for ((a, b), w) in g.edges ()
+    {
+        e.usize (*a)?;
+        e.usize (*b)?;
+        e.bytes (&w.to_le_bytes ())?;
+    }
+    e.finish ()
+}
+
+pub fn write_labelled_graph<W> (g: &graph::LabelledGraph<i64>, writer: &mut W)
+    -> Result<(), error::GraphError>
+    where
+        W: io::Write
+{
+    let mut e = Encoder::new (writer, KIND_LABELLED_GRAPH, &g.graph ().name (), g.self_loops ())?;
+    e.usize (g.vertices ().len ())?;
+    for v in g.vertices ()
+    {
+        e.usize (*v)?;
+        e.string (&g.vertex_label (v)?)?;
+        e.attrs (g.vertex_attrs_raw (v)?)?;
+    }
+    e.usize (g.edges ().len ())?;
+    for (ev @ (a, b), w) in g.edges ()
+    {
+        e.usize (*a)?;
+        e.usize (*b)?;
+        e.bytes (&w.to_le_bytes ())?;
+        e.attrs (g.edge_attrs_raw (ev)?)?;
+    }
+    e.finish ()
+}
+
+pub fn write_labelled_ugraph<W> (g: &graph::LabelledUGraph<i64>, writer: &mut W)
+    -> Result<(), error::GraphError>
+    where
+        W: io::Write
+{
+    let mut e = Encoder::new (writer, KIND_LABELLED_UGRAPH, &g.graph ().name (), g.self_loops ())?;
+    e.usize (g.vertices ().len ())?;
+    for v in g.vertices ()
+    {
+        e.usize (*v)?;
+        e.string (&g.vertex_label (v)?)?;
+        e.attrs (g.vertex_attrs_raw (v)?)?;
+    }
+    e.usize (g.edges ().len ())?;
+    for (ev @ (a, b), w) in g.edges ()
+    {
+        e.usize (*a)?;
+        e.usize (*b)?;
+        e.bytes (&w.to_le_bytes ())?;
+        e.attrs (g.edge_attrs_raw (ev)?)?;
+    }
+    e.finish ()
+}
+
+pub fn read_graph<R> (reader: &mut R)
+    -> Result<graph::Graph<i64>, error::GraphError>
+    where
+        R: io::Read
+{
+    let (mut d, name, self_loops) = Decoder::new (reader, KIND_GRAPH)?;
+    let mut g = graph::Graph::new_with_name (&name);
+    g.set_self_loops (self_loops)?;
+    for _ in 0..d.usize ()?
+    {
+        g.add_vertex_raw (d.usize ()?).map_err (corrupt)?;
+    }
+    for _ in 0..d.usize ()?
+    {
+        let (a, b, w) = (d.usize ()?, d.usize ()?, d.i64 ()?);
+        if !g.has_vertex_raw (&a) || !g.has_vertex_raw (&b)
+        {
+            return Err (error::GraphError::DataError (format! ("Edge ({},{}) refers to a missing vertex", a, b)));
+        }
+        g.add_edge_raw (a, b, w).map_err (corrupt)?;
+    }
+    d.finish ()?;
+    Ok (g)
+}
+
+pub fn read_ugraph<R> (reader: &mut R)
+    -> Result<graph::UGraph<i64>, error::GraphError>
+    where
+        R: io::Read
+{
+    let (mut d, name, self_loops) = Decoder::new (reader, KIND_UGRAPH)?;
+    let mut g = graph::UGraph::new_with_name (&name);
+    g.set_self_loops (self_loops)?;
+    for _ in 0..d.usize ()?
+    {
+        g.add_vertex_raw (d.usize ()?).map_err (corrupt)?;
+    }
+    for _ in 0..d.usize ()?
+    {
+        let (a, b, w) = (d.usize ()?, d.usize ()?, d.i64 ()?);
+        if !g.vertices ().contains (&a) || !g.vertices ().contains (&b)
+        {
+            return Err (error::GraphError::DataError (format! ("Edge ({},{}) refers to a missing vertex", a, b)));
+        }
+        g.add_edge_raw (a, b, w).map_err (corrupt)?;
+    }
+    d.finish ()?;
+    Ok (g)
+}
+
+pub fn read_labelled_graph<R> (reader: &mut R)
+    -> Result<graph::LabelledGraph<i64>, error::GraphError>
+    where
+        R: io::Read
+{
+    let (mut d, name, self_loops) = Decoder::new (reader, KIND_LABELLED_GRAPH)?;
+    let mut g = graph::LabelledGraph::new_with_name (&name);
+    g.set_self_loops (self_loops)?;
+    for _ in 0..d.usize ()?
+    {
+        let (v, label, attrs) = (d.usize ()?, d.string ()?, d.attrs ()?);
+        g.add_vertex_raw (v, label, Some (attrs)).map_err (corrupt)?;
+    }
+    for _ in 0..d.usize ()?
+    {
+        let (a, b, w, attrs) = (d.usize ()?, d.usize ()?, d.i64 ()?, d.attrs ()?);
+        let (a_label, b_label) = (g.vertex_label (&a).map_err (corrupt)?, g.vertex_label (&b).map_err (corrupt)?);
+        g.add_edge_raw (a, a_label, b, b_label, Some (attrs), w).map_err (corrupt)?;
+    }
+    d.finish ()?;
+    Ok (g)
+}
+
+pub fn read_labelled_ugraph<R> (reader: &mut R)
+    -> Result<graph::LabelledUGraph<i64>, error::GraphError>
+    where
+        R: io::Read
+{
+    let (mut d, name, self_loops) = Decoder::new (reader, KIND_LABELLED_UGRAPH)?;
+    let mut g = graph::LabelledUGraph::new_with_name (&name);
+    g.set_self_loops (self_loops)?;
+    for _ in 0..d.usize ()?
+    {
+        let (v, label, attrs) = (d.usize ()?, d.string ()?, d.attrs ()?);
+        g.add_vertex_raw (v, label, Some (attrs)).map_err (corrupt)?;
+    }
+    for _ in 0..d.usize ()?
+    {
+        let (a, b, w, attrs) = (d.usize ()?, d.usize ()?, d.i64 ()?, d.attrs ()?);
+        let (a_label, b_label) = (g.vertex_label (&a).map_err (corrupt)?, g.vertex_label (&b).map_err (corrupt)?);
+        g.add_edge_raw (a, a_label, b, b_label, Some (attrs), w).map_err (corrupt)?;
+    }
+    d.finish ()?;
+    Ok (g)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::graph;
+    use std::collections;
+    use std::sync;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| {
+            let _ = env_logger::try_init ();
+        });
+    }
+
+    fn attrs ()
+        -> collections::HashMap<String, graph::AttributeValue>
+    {
+        collections::HashMap::<String, graph::AttributeValue>::from ([
+            ( String::from ("b"), graph::AttributeValue::from (true) ),
+            ( String::from ("bm"), graph::AttributeValue::BooleanMap (collections::HashMap::<String, bool>::from ([ (String::from ("x"), false) ])) ),
+            ( String::from ("i"), graph::AttributeValue::from (-3i64) ),
+            ( String::from ("f"), graph::AttributeValue::from (1.5f64) ),
+            ( String::from ("s"), graph::AttributeValue::from ("é") ),
+            ( String::from ("sa"), graph::AttributeValue::from (vec![String::from ("p"), String::from ("q")]) ),
+            ( String::from ("sm"), graph::AttributeValue::from (collections::HashMap::<String, String>::from ([ (String::from ("k"), String::from ("v")) ])) ),
+            ( String::from ("ss"), graph::AttributeValue::from (collections::HashSet::<String>::from ([ String::from ("t") ])) ),
+            ( String::from ("by"), graph::AttributeValue::from (vec![0u8, 255]) ),
+            ( String::from ("fa"), graph::AttributeValue::from (vec![0.5f64, -2.0]) ),
+            ( String::from ("ia"), graph::AttributeValue::from (vec![1i64, i64::MIN]) ),
+            ( String::from ("l"), graph::AttributeValue::from (vec![graph::AttributeValue::Null, graph::AttributeValue::from (vec![graph::AttributeValue::from ("x")])]) ),
+            ( String::from ("m"), graph::AttributeValue::from (collections::HashMap::<String, graph::AttributeValue>::from ([ (String::from ("k"), graph::AttributeValue::from (2i64)) ])) ),
+            ( String::from ("n"), graph::AttributeValue::Null )
+        ])
+    }
+
+    #[test]
+    fn test_graph ()
+    {
+        init ();
+        let mut g = graph::Graph::new_with_name ("g");
+        g.add_edge_raw (1, 2, 7).expect ("Failed to add edge");
+        g.add_edge_raw (2, 1, -7).expect ("Failed to add edge");
+        g.add_vertex_raw (5).expect ("Failed to add vertex");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_graph (&g, &mut out).expect ("Failed to write graph");
+        assert_eq! (super::read_graph (&mut out.as_slice ()).expect ("Failed to read graph"), g);
+        assert_eq! (super::read_ugraph (&mut out.as_slice ()).unwrap_err ().to_string (), "Data error: Binary snapshot kind 0 cannot be loaded as kind 1");
+    }
+
+    #[test]
+    fn test_ugraph ()
+    {
+        init ();
+        let mut g = graph::UGraph::new ();
+        g.add_edge_raw (3, 1, 2).expect ("Failed to add edge");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_ugraph (&g, &mut out).expect ("Failed to write graph");
+        assert_eq! (super::read_ugraph (&mut out.as_slice ()).expect ("Failed to read graph"), g);
+    }
+
+    #[test]
+    fn test_labelled_graph ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new_with_name ("lg");
+        g.add_vertex (String::from ("a"), Some (attrs ())).expect ("Failed to add vertex");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (attrs ()), 9).expect ("Failed to add edge");
+        g.add_vertex (String::from ("c"), None).expect ("Failed to add vertex");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out).expect ("Failed to write graph");
+        let h = super::read_labelled_graph (&mut out.as_slice ()).expect ("Failed to read graph");
+
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph");
+        assert_eq! (h.vertex ("a").expect ("Failed to find a"), g.vertex ("a").expect ("Failed to find a"));
+        assert_eq! (h.graph ().name (), "lg");
+    }
+
+    #[test]
+    fn test_labelled_ugraph ()
+    {
+        init ();
+        let mut g = graph::LabelledUGraph::new ();
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (attrs ()), 9).expect ("Failed to add edge");
+        g.vertex_attrs_mut ("b").expect ("Failed to find b").1.extend (attrs ());
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&g, &mut out).expect ("Failed to write graph");
+        let h = super::read_labelled_ugraph (&mut out.as_slice ()).expect ("Failed to read graph");
+
+        assert_eq! (h, g);
+        assert_eq! (h.vertex_attrs ("b").expect ("Failed to find b").1, &attrs ());
+        let e = (String::from ("b"), String::from ("a"));
+        assert_eq! (h.edge_attrs (&e).expect ("Failed to find edge").1, g.edge_attrs (&e).expect ("Failed to find edge").1);
+    }
+
+    #[test]
+    fn test_self_loops ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge (String::from ("a"), String::from ("a"), None).expect ("Failed to add edge");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out).expect ("Failed to write graph");
+        let h = super::read_labelled_graph (&mut out.as_slice ()).expect ("Failed to read graph");
+        assert! (h.self_loops ());
+        assert_eq! (h, g);
+    }
+
+    #[test]
+    fn test_corruption ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (attrs ()), 9).expect ("Failed to add edge");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out).expect ("Failed to write graph");
+
+        let read = |bytes: &[u8]| super::read_labelled_graph (&mut &bytes[..]).unwrap_err ().to_string ();
+
+        assert_eq! (read (&out[..out.len () - 3]), "Data error: Binary snapshot is truncated");
+        assert_eq! (read (b"JUNK"), "Data error: Not a binary graph snapshot");
+
+        let mut bad = out.clone ();
+        bad[4] = 9;
+        assert_eq! (read (&bad), "Data error: Unsupported binary snapshot version 9, expected 1");
+
+        // Flip the last payload byte
+        let mut bad = out.clone ();
+        let n = bad.len ();
+        bad[n - 9] ^= 0xff;
+        let r = read (&bad);
+        assert! (r.starts_with ("Data error:"), "Unexpected error {}", r);
+
+        let mut bad = out.clone ();
+        let n = bad.len ();
+        bad[n - 1] ^= 0xff;
+        assert_eq! (read (&bad), "Data error: Binary snapshot checksum mismatch");
+
+        // A self loop in a raw graph whose flags disallow them
+        let mut g = graph::Graph::new ();
+        g.add_edge_raw (1, 1, 2).expect ("Failed to add edge 1 -> 1");
+        let mut out = Vec::<u8>::new ();
+        super::write_graph (&g, &mut out).expect ("Failed to write graph");
+        let mut bad = out[..out.len () - 8].to_vec ();
+        bad[7] = 0;
+        let mut hash = super::Fnv1a::new ();
+        hash.update (&bad);
+        bad.extend (hash.0.to_le_bytes ());
+        assert_eq! (super::read_graph (&mut bad.as_slice ()).unwrap_err ().to_string (), "Data error: Invalid binary snapshot: Edge error: edge vertices must be distinct");
+    }
+
+    #[test]
+    fn test_nesting_limit ()
+    {
+        init ();
+        let nest = |depth: usize| (0..depth).fold (graph::AttributeValue::Null, |v, _| graph::AttributeValue::from (vec![v]));
+
+        let mut g = graph::LabelledGraph::new ();
+        g.add_vertex (String::from ("a"), Some (collections::HashMap::from ([ (String::from ("deep"), nest (super::MAX_DEPTH)) ]))).expect ("Failed to add vertex");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out).expect ("Failed to write graph");
+        let h = super::read_labelled_graph (&mut out.as_slice ()).expect ("Failed to read graph");
+        assert_eq! (h.vertex_attrs ("a").expect ("Failed to find a").1, g.vertex_attrs ("a").expect ("Failed to find a").1);
+
+        let mut g = graph::LabelledGraph::new ();
+        g.add_vertex (String::from ("a"), Some (collections::HashMap::from ([ (String::from ("deep"), nest (super::MAX_DEPTH + 1)) ]))).expect ("Failed to add vertex");
+        let mut out = Vec::<u8>::new ();
+        let r = super::write_labelled_graph (&g, &mut out).unwrap_err ().to_string ();
+        assert! (r.contains ("nested deeper than 64"), "Unexpected error {}", r);
+    }
+}
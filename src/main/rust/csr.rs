@@ -0,0 +1,653 @@
+// Compressed sparse row snapshots of the graph types
+//
+// Vertices are renumbered densely 0..n in ascending order of their
+// original ids; `id` and `dense` translate between the two and, for
+// snapshots of labelled graphs, `label` and `LabelledGraphAny::vertex`
+// translate between dense ids and labels. Algorithms run over the dense
+// ids, so their results are translated back afterwards.
+//
+// The adjacency of vertex v is targets[offsets[v]..offsets[v+1]] with the
+// matching weights; each row is sorted by target so edge lookups are a
+// binary search. CsrGraph keeps both outbound and inbound rows, CsrUGraph
+// stores every edge in both endpoint rows.
+//
+// Snapshots take the weight type of the graph they were built from;
+// labelled snapshots are only built from graphs with String labels.
+use crate::error;
+use crate::graph;
+use std::borrow;
+use std::collections;
+use std::fmt;
+use std::hash;
+
+#[derive(Clone,Debug,PartialEq)]
+struct Adjacency<W>
+{
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+    weights: Vec<W>
+}
+
+impl<W> Adjacency<W>
+    where
+        W: graph::Weight
+{
+    fn new (n: usize, mut edges: Vec<(usize, usize, W)>)
+        -> Self
+    {
+        edges.sort_unstable_by_key (|(a, b, _)| (*a, *b));
+        let mut offsets = vec![0; n + 1];
+        for (a, _, _) in &edges
+        {
+            offsets[a + 1] += 1;
+        }
+        for i in 0..n
+        {
+            offsets[i + 1] += offsets[i];
+        }
+        let (targets, weights) = edges.into_iter ()
+            .map (|(_, b, w)| (b, w))
+            .unzip ();
+        Adjacency { offsets, targets, weights }
+    }
+
+    fn row (&self, a: &usize)
+        -> Result<(&[usize], &[W]), error::GraphError>
+    {
+        if *a + 1 < self.offsets.len ()
+        {
+            let range = self.offsets[*a]..self.offsets[a + 1];
+            Ok ( (&self.targets[range.clone ()], &self.weights[range]) )
+        }
+        else
+        {
+            Err (error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    fn weight (&self, ev: &(usize, usize))
+        -> Option<W>
+    {
+        let (targets, weights) = self.row (&ev.0).ok ()?;
+        targets.binary_search (&ev.1).ok ().map (|i| weights[i])
+    }
+
+    fn iter (&self)
+        -> impl Iterator<Item = ((usize, usize), W)> + '_
+    {
+        self.offsets.windows (2)
+            .enumerate ()
+            .flat_map (move |(a, w)| (w[0]..w[1]).map (move |i| ( (a, self.targets[i]), self.weights[i] )))
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+struct Index
+{
+    name: String,
+    vertices: collections::HashSet<usize>,
+    ids: Vec<usize>,
+    lookup: collections::HashMap<usize, usize>,
+    labels: Option<Vec<String>>,
+    label_lookup: collections::HashMap<String, usize>
+}
+
+impl Index
+{
+    fn new (name: String, original: &collections::HashSet<usize>)
+        -> Self
+    {
+        let mut ids = original.iter ().copied ().collect::<Vec<_>> ();
+        ids.sort_unstable ();
+        let lookup = ids.iter ().enumerate ().map (|(i, v)| (*v, i)).collect ();
+        Index {
+            name,
+            vertices: (0..ids.len ()).collect (),
+            ids,
+            lookup,
+            labels: None,
+            label_lookup: collections::HashMap::new ()
+        }
+    }
+
+    fn with_labels<F> (mut self, f: F)
+        -> Result<Self, error::GraphError>
+        where
+            F: Fn(&usize) -> Result<String, error::GraphError>
+    {
+        let labels = self.ids.iter ().map (f).collect::<Result<Vec<_>, _>> ()?;
+        self.label_lookup = labels.iter ().enumerate ().map (|(i, l)| (l.clone (), i)).collect ();
+        self.labels = Some (labels);
+        Ok (self)
+    }
+
+    fn dense_edges<'a, W, I> (&self, edges: I)
+        -> Result<Vec<(usize, usize, W)>, error::GraphError>
+        where
+            W: graph::Weight + 'a,
+            I: Iterator<Item = (&'a (usize, usize), &'a W)>
+    {
+        edges.map (|((a, b), w)| Ok ( (self.dense (a)?, self.dense (b)?, *w) ))
+            .collect ()
+    }
+
+    fn dense (&self, a: &usize)
+        -> Result<usize, error::GraphError>
+    {
+        self.lookup.get (a).copied ().ok_or (error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+    }
+
+    fn id (&self, a: &usize)
+        -> Result<usize, error::GraphError>
+    {
+        self.ids.get (*a).copied ().ok_or (error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+    }
+
+    fn label (&self, a: &usize)
+        -> Result<String, error::GraphError>
+    {
+        self.labels.as_ref ()
+            .ok_or (error::GraphError::VertexError (String::from ("Graph has no vertex labels")))?
+            .get (*a)
+            .cloned ()
+            .ok_or (error::GraphError::VertexError (format! ("Failed to find vertex with id: {}", a)))
+    }
+
+    fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, error::GraphError>
+        where
+            String: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        self.label_lookup.get (a).copied ().ok_or (error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct CsrGraph<W = i64>
+{
+    index: Index,
+    outbound: Adjacency<W>,
+    inbound: Adjacency<W>
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct CsrUGraph<W = i64>
+{
+    index: Index,
+    connected: Adjacency<W>
+}
+
+impl<W> CsrGraph<W>
+    where
+        W: graph::Weight
+{
+    fn new (index: Index, edges: Vec<(usize, usize, W)>)
+        -> Self
+    {
+        let n = index.ids.len ();
+        let reversed = edges.iter ().map (|(a, b, w)| (*b, *a, *w)).collect ();
+        CsrGraph {
+            outbound: Adjacency::new (n, edges),
+            inbound: Adjacency::new (n, reversed),
+            index
+        }
+    }
+
+    pub fn name (&self)
+        -> String
+    {
+        self.index.name.clone ()
+    }
+
+    // original id for a dense id
+    pub fn id (&self, a: &usize)
+        -> Result<usize, error::GraphError>
+    {
+        self.index.id (a)
+    }
+
+    // dense id for an original id
+    pub fn dense (&self, a: &usize)
+        -> Result<usize, error::GraphError>
+    {
+        self.index.dense (a)
+    }
+
+    pub fn label (&self, a: &usize)
+        -> Result<String, error::GraphError>
+    {
+        self.index.label (a)
+    }
+
+    pub fn is_labelled (&self)
+        -> bool
+    {
+        self.index.labels.is_some ()
+    }
+}
+
+impl<W> graph::LabelledGraphAny for CsrGraph<W>
+    where
+        W: graph::Weight
+{
+    type Label = String;
+
+    fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, error::GraphError>
+        where
+            String: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        self.index.vertex (a)
+    }
+
+    fn vertex_label (&self, a_id: &usize)
+        -> Result<String, error::GraphError>
+    {
+        self.index.label (a_id)
+    }
+}
+
+impl<W> CsrUGraph<W>
+    where
+        W: graph::Weight
+{
+    fn new (index: Index, edges: Vec<(usize, usize, W)>)
+        -> Self
+    {
+        let n = index.ids.len ();
+        let mut both = Vec::with_capacity (edges.len () * 2);
+        for (a, b, w) in edges
+        {
+            both.push ( (a, b, w) );
+            if a != b
+            {
+                both.push ( (b, a, w) );
+            }
+        }
+        CsrUGraph {
+            connected: Adjacency::new (n, both),
+            index
+        }
+    }
+
+    pub fn name (&self)
+        -> String
+    {
+        self.index.name.clone ()
+    }
+
+    // original id for a dense id
+    pub fn id (&self, a: &usize)
+        -> Result<usize, error::GraphError>
+    {
+        self.index.id (a)
+    }
+
+    // dense id for an original id
+    pub fn dense (&self, a: &usize)
+        -> Result<usize, error::GraphError>
+    {
+        self.index.dense (a)
+    }
+
+    pub fn label (&self, a: &usize)
+        -> Result<String, error::GraphError>
+    {
+        self.index.label (a)
+    }
+
+    pub fn is_labelled (&self)
+        -> bool
+    {
+        self.index.labels.is_some ()
+    }
+}
+
+impl<W> graph::LabelledGraphAny for CsrUGraph<W>
+    where
+        W: graph::Weight
+{
+    type Label = String;
+
+    fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, error::GraphError>
+        where
+            String: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        self.index.vertex (a)
+    }
+
+    fn vertex_label (&self, a_id: &usize)
+        -> Result<String, error::GraphError>
+    {
+        self.index.label (a_id)
+    }
+}
+
+pub fn from_graph<W> (g: &graph::Graph<W>)
+    -> Result<CsrGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let index = Index::new (g.name (), g.vertices ());
+    let edges = index.dense_edges (g.edges ().iter ())?;
+    Ok (CsrGraph::new (index, edges))
+}
+
+pub fn from_labelled_graph<W, V, E> (g: &graph::LabelledGraph<W, V, E>)
+    -> Result<CsrGraph<W>, error::GraphError>
+    where
+        W: graph::Weight,
+        V: graph::Payload,
+        E: graph::Payload
+{
+    let index = Index::new (g.graph ().name (), g.graph ().vertices ())
+        .with_labels (|v| g.vertex_label (v))?;
+    let edges = index.dense_edges (g.edges ().iter ())?;
+    Ok (CsrGraph::new (index, edges))
+}
+
+pub fn from_ugraph<W> (g: &graph::UGraph<W>)
+    -> Result<CsrUGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let index = Index::new (g.name (), g.vertices ());
+    let edges = index.dense_edges (g.edges ().iter ())?;
+    Ok (CsrUGraph::new (index, edges))
+}
+
+pub fn from_labelled_ugraph<W, V, E> (g: &graph::LabelledUGraph<W, V, E>)
+    -> Result<CsrUGraph<W>, error::GraphError>
+    where
+        W: graph::Weight,
+        V: graph::Payload,
+        E: graph::Payload
+{
+    let index = Index::new (g.graph ().name (), g.graph ().vertices ())
+        .with_labels (|v| g.vertex_label (v))?;
+    let edges = index.dense_edges (g.edges ().iter ())?;
+    Ok (CsrUGraph::new (index, edges))
+}
+
+impl<W> graph::GraphAny for CsrGraph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        Ok (self.outbound.row (a)?.0.iter ().copied ().collect ())
+    }
+
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        Ok (Box::new (self.outbound.row (a)?.0.iter ().copied ()))
+    }
+
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
+    {
+        Box::new (self.outbound.iter ())
+    }
+
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
+    {
+        self.outbound.weight (ev).is_some ()
+    }
+
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        Ok (graph::GraphAny::neighbours_iter (self, a)?.collect ())
+    }
+
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        let outbound = self.outbound.row (a)?.0;
+        let inbound = self.inbound.row (a)?.0.iter ()
+            .filter (move |x| outbound.binary_search (x).is_err ());
+        Ok (Box::new (outbound.iter ().chain (inbound).copied ()))
+    }
+
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
+    {
+        &self.index.vertices
+    }
+
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, error::GraphError>
+    {
+        self.outbound.weight (ev).ok_or (error::GraphError::EdgeError (format! ("No edge found for {:?}", ev)))
+    }
+}
+
+impl<W> graph::DiGraphAny for CsrGraph<W>
+    where
+        W: graph::Weight
+{
+    fn inbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        Ok (self.inbound.row (a)?.0.iter ().copied ().collect ())
+    }
+
+    fn inbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        Ok (Box::new (self.inbound.row (a)?.0.iter ().copied ()))
+    }
+
+    fn is_sink (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        Ok (self.outbound.row (a)?.0.is_empty ())
+    }
+
+    fn is_source (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        Ok (self.inbound.row (a)?.0.is_empty ())
+    }
+
+    fn outbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::GraphAny::adjacent (self, a)
+    }
+
+    fn outbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::GraphAny::adjacent_iter (self, a)
+    }
+
+    fn sinks (&self)
+        -> collections::HashSet<usize>
+    {
+        self.index.vertices.iter ().filter (|v| self.outbound.row (v).is_ok_and (|r| r.0.is_empty ())).copied ().collect ()
+    }
+
+    fn sources (&self)
+        -> collections::HashSet<usize>
+    {
+        self.index.vertices.iter ().filter (|v| self.inbound.row (v).is_ok_and (|r| r.0.is_empty ())).copied ().collect ()
+    }
+}
+
+impl<W> graph::GraphAny for CsrUGraph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        Ok (self.connected.row (a)?.0.iter ().copied ().collect ())
+    }
+
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        Ok (Box::new (self.connected.row (a)?.0.iter ().copied ()))
+    }
+
+    // each edge once, as (min, max) like UGraph::edges
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
+    {
+        Box::new (self.connected.iter ().filter (|((a, b), _)| a <= b))
+    }
+
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
+    {
+        self.connected.weight (ev).is_some ()
+    }
+
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::GraphAny::adjacent (self, a)
+    }
+
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::GraphAny::adjacent_iter (self, a)
+    }
+
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
+    {
+        &self.index.vertices
+    }
+
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, error::GraphError>
+    {
+        self.connected.weight (ev).ok_or (error::GraphError::EdgeError (format! ("No edge found for {:?}", ev)))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::algo;
+    use crate::graph;
+    use crate::graph::{DiGraphAny, GraphAny};
+    use std::collections;
+    use std::sync;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| { let _ = env_logger::try_init (); });
+    }
+
+    #[test]
+    fn test_from_graph ()
+    {
+        init ();
+        let mut g = graph::Graph::new ();
+        g.add_edge_raw (10,30,2).expect ("Failed to add edge 10 -> 30");
+        g.add_edge_raw (10,20,1).expect ("Failed to add edge 10 -> 20");
+        g.add_edge_raw (20,30,4).expect ("Failed to add edge 20 -> 30");
+        g.add_edge_raw (30,20,5).expect ("Failed to add edge 30 -> 20");
+        g.add_vertex_raw (40).expect ("Failed to add vertex 40");
+
+        let c = super::from_graph (&g).expect ("Failed to build csr");
+        assert_eq! (c.vertices (), &collections::HashSet::from ([0,1,2,3]));
+        assert_eq! ((0..4).map (|v| c.id (&v).unwrap ()).collect::<Vec<_>> (), vec![10,20,30,40]);
+        assert_eq! (c.dense (&30).unwrap (), 2);
+        assert! (c.dense (&50).is_err ());
+        assert! (!c.is_labelled ());
+
+        assert_eq! (c.adjacent_iter (&0).unwrap ().collect::<Vec<_>> (), vec![1,2]);
+        assert_eq! (c.inbound (&2).unwrap (), collections::HashSet::from ([0,1]));
+        assert_eq! (c.neighbours_iter (&1).unwrap ().collect::<Vec<_>> (), vec![2,0]);
+        assert_eq! (c.weight (&(2,1)).unwrap (), 5);
+        assert! (c.weight (&(1,0)).is_err ());
+        assert! (c.adjacent (&3).unwrap ().is_empty ());
+        assert! (c.adjacent (&4).is_err ());
+        assert_eq! (c.sources (), collections::HashSet::from ([0,3]));
+        assert_eq! (c.sinks (), collections::HashSet::from ([3]));
+
+        let edges = c.edges_iter ()
+            .map (|((a, b), w)| ( (c.id (&a).unwrap (), c.id (&b).unwrap ()), w ))
+            .collect::<collections::HashMap<_,_>> ();
+        assert_eq! (&edges, g.edges ());
+    }
+
+    #[test]
+    fn test_from_ugraph ()
+    {
+        init ();
+        let mut g = graph::UGraph::new ();
+        g.add_edge_raw (1,2,3).expect ("Failed to add edge 1 -- 2");
+        g.add_edge_raw (3,2,4).expect ("Failed to add edge 3 -- 2");
+        g.add_edge_raw (5,6,1).expect ("Failed to add edge 5 -- 6");
+
+        let c = super::from_ugraph (&g).expect ("Failed to build csr");
+        assert_eq! (c.weight (&(2,1)).unwrap (), 4);
+        assert_eq! (c.weight (&(1,2)).unwrap (), 4);
+        assert_eq! (c.edges_iter ().count (), 3);
+
+        let components = algo::connected_components (&g).expect ("Failed connected_components");
+        let (_, groups) = algo::fast_label_propagation (&c, &mut 7).expect ("Failed fast_label_propagation");
+        let groups = groups.values ()
+            .map (|x| x.iter ().map (|v| c.id (v).unwrap ()).collect::<collections::HashSet<_>> ())
+            .collect::<Vec<_>> ();
+        assert_eq! (groups.len (), components.len ());
+        assert! (groups.iter ().all (|x| components.contains (x)));
+    }
+
+    #[test]
+    fn test_algorithms_labelled ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), None, 1).expect ("Failed to add edge a -> b");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), None, 1).expect ("Failed to add edge b -> c");
+        g.add_edge_weighted (String::from ("a"), String::from ("c"), None, 5).expect ("Failed to add edge a -> c");
+
+        let c = super::from_labelled_graph (&g).expect ("Failed to build csr");
+        assert! (c.is_labelled ());
+        let a = graph::LabelledGraphAny::vertex (&c, "a").expect ("Failed to find a");
+        assert_eq! (c.label (&a).unwrap (), "a");
+
+        let r = algo::multi_source_dijkstra_labelled (&c, &collections::HashSet::from ([String::from ("a")])).expect ("Failed dijkstra");
+        assert_eq! (r["a"].0["c"], 2);
+        let expected = algo::bfs_edges_labelled (&g, "a").unwrap ().into_iter ().collect::<collections::HashSet<_>> ();
+        assert_eq! (algo::bfs_edges_labelled (&c, "a").unwrap ().into_iter ().collect::<collections::HashSet<_>> (), expected);
+        assert_eq! (algo::topological_sort (&c).unwrap ().iter ().map (|v| c.label (v).unwrap ()).collect::<Vec<_>> (), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_weighted ()
+    {
+        init ();
+        let mut g = graph::Graph::<f64>::new_weighted ();
+        g.add_edge_raw (10,20,0.5).expect ("Failed to add edge 10 -> 20");
+        g.add_edge_raw (20,10,1.5).expect ("Failed to add edge 20 -> 10");
+        let c = super::from_graph (&g).expect ("Failed to build csr");
+        assert_eq! (c.weight (&(0,1)).unwrap (), 0.5);
+        assert_eq! (c.inbound (&0).unwrap (), collections::HashSet::from ([1]));
+
+        let mut u = graph::LabelledUGraph::<u32>::new_weighted ();
+        u.add_edge_weighted (String::from ("a"), String::from ("b"), None, 7).expect ("Failed to add edge a -- b");
+        let c = super::from_labelled_ugraph (&u).expect ("Failed to build csr");
+        assert_eq! (c.edges_iter ().collect::<Vec<_>> (), vec![((0,1), 7u32)]);
+    }
+}
@@ -0,0 +1,1189 @@
+
+use crate::error;
+use crate::graph;
+use std::any;
+use std::collections;
+use std::fmt;
+use std::io;
+use std::str;
+
+// Edge weights are written with Display and read back with FromStr, so any
+// weight type whose text form round trips can be used.
+
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions
+{
+    // Attribute names copied from vertex_attrs / edge_attrs onto the dot statements
+    pub vertex_attrs: collections::HashSet<String>,
+    pub edge_attrs: collections::HashSet<String>,
+    // Dot attribute to write the edge weight to, e.g. "weight" or "label"
+    pub weight_attr: Option<String>
+}
+
+impl DotOptions
+{
+    pub fn new () -> Self
+    {
+        Self::default ()
+    }
+
+    pub fn with_vertex_attrs (mut self, names: &[&str]) -> Self
+    {
+        self.vertex_attrs.extend (names.iter ().map (|x| x.to_string ()));
+        self
+    }
+
+    pub fn with_edge_attrs (mut self, names: &[&str]) -> Self
+    {
+        self.edge_attrs.extend (names.iter ().map (|x| x.to_string ()));
+        self
+    }
+
+    pub fn with_weight_attr (mut self, name: &str) -> Self
+    {
+        self.weight_attr = Some (name.to_string ());
+        self
+    }
+}
+
+pub fn escape (s: &str)
+    -> String
+{
+    let mut r = String::with_capacity (s.len () + 2);
+    r.push ('"');
+    for c in s.chars ()
+    {
+        match c
+        {
+            '"' => r.push_str ("\\\""),
+            '\\' => r.push_str ("\\\\"),
+            '\n' => r.push_str ("\\n"),
+            '\r' => r.push_str ("\\r"),
+            _ => r.push (c)
+        }
+    }
+    r.push ('"');
+    r
+}
+
+// Booleans and numbers are written as bare IDs so the reader infers their
+// type again, everything else is quoted and read back as a string
+fn value_id (value: &graph::AttributeValue)
+    -> String
+{
+    let text = match value
+    {
+        graph::AttributeValue::FloatLiteral (f) => format! ("{:?}", f),
+        _ => value.to_string ()
+    };
+    let numeral = text.strip_prefix ('-').unwrap_or (&text).chars ().all (|c| c.is_ascii_digit () || c == '.') && text.chars ().any (|c| c.is_ascii_digit ());
+    match value
+    {
+        graph::AttributeValue::BooleanLiteral (_) => text,
+        graph::AttributeValue::IntegerLiteral (_) | graph::AttributeValue::FloatLiteral (_) if numeral => text,
+        _ => escape (&text)
+    }
+}
+
+fn attr_list (selected: &collections::HashSet<String>, attrs: Option<&collections::HashMap<String, graph::AttributeValue>>, weight: Option<(&str, String)>)
+    -> String
+{
+    let mut pairs = Vec::<(String, String)>::new ();
+    if let Some ((name, w)) = weight
+    {
+        pairs.push ( (name.to_string (), escape (&w)) );
+    }
+    if let Some (attrs) = attrs
+    {
+        let mut names = attrs.keys ().filter (|k| selected.contains (*k)).collect::<Vec<_>> ();
+        names.sort ();
+        for name in names
+        {
+            pairs.push ( (name.clone (), value_id (&attrs[name])) );
+        }
+    }
+
+    if pairs.is_empty ()
+    {
+        String::new ()
+    }
+    else
+    {
+        format! (" [{}]", pairs.iter ().map (|(k, v)| format! ("{}={}", escape (k), v)).collect::<Vec<_>> ().join (", "))
+    }
+}
+
+fn write_header<T> (writer: &mut T, directed: bool, name: &str)
+    -> Result<(), error::GraphError>
+    where
+        T: io::Write
+{
+    let kind = if directed { "digraph" } else { "graph" };
+    if name.is_empty ()
+    {
+        writeln! (writer, "{} {{", kind)?;
+    }
+    else
+    {
+        writeln! (writer, "{} {} {{", kind, escape (name))?;
+    }
+    Ok (())
+}
+
+fn sorted_vertices (vertices: &collections::HashSet<usize>)
+    -> Vec<usize>
+{
+    let mut r = vertices.iter ().copied ().collect::<Vec<_>> ();
+    r.sort ();
+    r
+}
+
+fn sorted_edges<W> (edges: &collections::HashMap<(usize, usize), W>)
+    -> Vec<((usize, usize), W)>
+    where
+        W: graph::Weight
+{
+    let mut r = edges.iter ().map (|(e, w)| (*e, *w)).collect::<Vec<_>> ();
+    r.sort_unstable_by_key (|x| x.0);
+    r
+}
+
+pub fn write_graph<W, T> (g: &graph::Graph<W>, writer: &mut T, options: &DotOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + fmt::Display,
+        T: io::Write
+{
+    write_header (writer, true, &g.name ())?;
+    for v in sorted_vertices (g.vertices ())
+    {
+        writeln! (writer, "\t{};", v)?;
+    }
+    for (e, w) in sorted_edges (g.edges ())
+    {
+        let weight = options.weight_attr.as_deref ().map (|x| (x, w.to_string ()));
+        writeln! (writer, "\t{} -> {}{};", e.0, e.1, attr_list (&options.edge_attrs, None, weight))?;
+    }
+    writeln! (writer, "}}")?;
+    Ok (())
+}
+
+pub fn write_ugraph<W, T> (g: &graph::UGraph<W>, writer: &mut T, options: &DotOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + fmt::Display,
+        T: io::Write
+{
+    write_header (writer, false, &g.name ())?;
+    for v in sorted_vertices (g.vertices ())
+    {
+        writeln! (writer, "\t{};", v)?;
+    }
+    for (e, w) in sorted_edges (g.edges ())
+    {
+        let weight = options.weight_attr.as_deref ().map (|x| (x, w.to_string ()));
+        writeln! (writer, "\t{} -- {}{};", e.0, e.1, attr_list (&options.edge_attrs, None, weight))?;
+    }
+    writeln! (writer, "}}")?;
+    Ok (())
+}
+
+pub fn write_labelled_graph<W, T> (g: &graph::LabelledGraph<W>, writer: &mut T, options: &DotOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + fmt::Display,
+        T: io::Write
+{
+    write_header (writer, true, &g.graph ().name ())?;
+    for v in sorted_vertices (g.vertices ())
+    {
+        let attrs = attr_list (&options.vertex_attrs, Some (g.vertex_attrs_raw (&v)?), None);
+        writeln! (writer, "\t{}{};", escape (&g.vertex_label (&v)?), attrs)?;
+    }
+    for (e, w) in sorted_edges (g.edges ())
+    {
+        let (a, b) = g.edge_label (&e)?;
+        let weight = options.weight_attr.as_deref ().map (|x| (x, w.to_string ()));
+        let attrs = attr_list (&options.edge_attrs, Some (g.edge_attrs_raw (&e)?), weight);
+        writeln! (writer, "\t{} -> {}{};", escape (&a), escape (&b), attrs)?;
+    }
+    writeln! (writer, "}}")?;
+    Ok (())
+}
+
+pub fn write_labelled_ugraph<W, T> (g: &graph::LabelledUGraph<W>, writer: &mut T, options: &DotOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + fmt::Display,
+        T: io::Write
+{
+    write_header (writer, false, &g.graph ().name ())?;
+    for v in sorted_vertices (g.vertices ())
+    {
+        let attrs = attr_list (&options.vertex_attrs, Some (g.vertex_attrs_raw (&v)?), None);
+        writeln! (writer, "\t{}{};", escape (&g.vertex_label (&v)?), attrs)?;
+    }
+    for (e, w) in sorted_edges (g.edges ())
+    {
+        let (a, b) = g.edge_label (&e)?;
+        let weight = options.weight_attr.as_deref ().map (|x| (x, w.to_string ()));
+        let attrs = attr_list (&options.edge_attrs, Some (g.edge_attrs_raw (&e)?), weight);
+        writeln! (writer, "\t{} -- {}{};", escape (&a), escape (&b), attrs)?;
+    }
+    writeln! (writer, "}}")?;
+    Ok (())
+}
+
+#[derive(Clone, Debug)]
+pub struct DotReadOptions
+{
+    // Dot attribute holding the edge weight, removed from the edge attributes when found
+    pub weight_attr: Option<String>,
+    // Convert unquoted values to BooleanLiteral / IntegerLiteral / FloatLiteral
+    // where they parse as such, quoted values always stay strings
+    pub infer_types: bool,
+    // Allow self loops on the graphs read, dot does not record the policy
+    pub self_loops: bool
+}
+
+impl Default for DotReadOptions
+{
+    fn default () -> Self
+    {
+        Self { weight_attr: None, infer_types: true, self_loops: false }
+    }
+}
+
+impl DotReadOptions
+{
+    pub fn new () -> Self
+    {
+        Self::default ()
+    }
+
+    pub fn with_weight_attr (mut self, name: &str) -> Self
+    {
+        self.weight_attr = Some (name.to_string ());
+        self
+    }
+
+    pub fn with_infer_types (mut self, infer_types: bool) -> Self
+    {
+        self.infer_types = infer_types;
+        self
+    }
+
+    pub fn with_self_loops (mut self, self_loops: bool) -> Self
+    {
+        self.self_loops = self_loops;
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token
+{
+    Id (String, bool),
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Semicolon,
+    Comma,
+    Equals,
+    Colon,
+    Plus,
+    EdgeOp (bool)
+}
+
+type PositionedToken = (Token, usize, usize);
+
+fn data_error (line: usize, column: usize, message: &str)
+    -> error::GraphError
+{
+    error::GraphError::DataError (format! ("line {}, column {}: {}", line, column, message))
+}
+
+struct Lexer
+{
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize
+}
+
+impl Lexer
+{
+    fn new (input: &str) -> Self
+    {
+        Self { chars: input.chars ().collect (), pos: 0, line: 1, column: 1 }
+    }
+
+    fn peek (&self, offset: usize)
+        -> Option<char>
+    {
+        self.chars.get (self.pos + offset).copied ()
+    }
+
+    fn bump (&mut self)
+        -> Option<char>
+    {
+        let c = self.chars.get (self.pos).copied ()?;
+        self.pos += 1;
+        if c == '\n'
+        {
+            self.line += 1;
+            self.column = 1;
+        }
+        else
+        {
+            self.column += 1;
+        }
+        Some (c)
+    }
+
+    fn at_line_start (&self)
+        -> bool
+    {
+        self.chars[..self.pos].iter ().rev ().take_while (|c| **c != '\n').all (|c| c.is_whitespace ())
+    }
+
+    fn skip_trivia (&mut self)
+        -> Result<(), error::GraphError>
+    {
+        loop
+        {
+            match ( self.peek (0), self.peek (1) )
+            {
+                (Some (c), _) if c.is_whitespace () => { self.bump (); },
+                (Some ('/'), Some ('/')) => {
+                    while let Some (c) = self.peek (0) && c != '\n' { self.bump (); }
+                },
+                (Some ('/'), Some ('*')) => {
+                    let (line, column) = (self.line, self.column);
+                    self.bump ();
+                    self.bump ();
+                    loop
+                    {
+                        match ( self.peek (0), self.peek (1) )
+                        {
+                            (Some ('*'), Some ('/')) => { self.bump (); self.bump (); break; },
+                            (Some (_), _) => { self.bump (); },
+                            (None, _) => return Err (data_error (line, column, "Unterminated comment"))
+                        }
+                    }
+                },
+                (Some ('#'), _) if self.at_line_start () => {
+                    while let Some (c) = self.peek (0) && c != '\n' { self.bump (); }
+                },
+                _ => return Ok (())
+            }
+        }
+    }
+
+    fn quoted (&mut self, line: usize, column: usize)
+        -> Result<Token, error::GraphError>
+    {
+        let mut r = String::new ();
+        self.bump ();
+        loop
+        {
+            match self.bump ()
+            {
+                Some ('"') => return Ok (Token::Id (r, true)),
+                Some ('\\') => {
+                    match self.bump ()
+                    {
+                        Some ('"') => r.push ('"'),
+                        Some ('\\') => r.push ('\\'),
+                        Some ('n') => r.push ('\n'),
+                        Some ('r') => r.push ('\r'),
+                        Some ('\n') => {},
+                        Some (c) => { r.push ('\\'); r.push (c); },
+                        None => return Err (data_error (line, column, "Unterminated string"))
+                    }
+                },
+                Some (c) => r.push (c),
+                None => return Err (data_error (line, column, "Unterminated string"))
+            }
+        }
+    }
+
+    fn html (&mut self, line: usize, column: usize)
+        -> Result<Token, error::GraphError>
+    {
+        let mut r = String::new ();
+        let mut depth = 0usize;
+        self.bump ();
+        loop
+        {
+            match self.bump ()
+            {
+                Some ('<') => { depth += 1; r.push ('<'); },
+                Some ('>') if depth == 0 => return Ok (Token::Id (r, true)),
+                Some ('>') => { depth -= 1; r.push ('>'); },
+                Some (c) => r.push (c),
+                None => return Err (data_error (line, column, "Unterminated HTML string"))
+            }
+        }
+    }
+
+    fn numeral (&mut self)
+        -> Token
+    {
+        let mut r = String::new ();
+        if self.peek (0) == Some ('-')
+        {
+            r.push ('-');
+            self.bump ();
+        }
+        while let Some (c) = self.peek (0) && ( c.is_ascii_digit () || c == '.' )
+        {
+            r.push (c);
+            self.bump ();
+        }
+        Token::Id (r, false)
+    }
+
+    fn identifier (&mut self)
+        -> Token
+    {
+        let mut r = String::new ();
+        while let Some (c) = self.peek (0) && ( c.is_alphanumeric () || c == '_' || !c.is_ascii () )
+        {
+            r.push (c);
+            self.bump ();
+        }
+        Token::Id (r, false)
+    }
+
+    fn tokens (mut self)
+        -> Result<(Vec<PositionedToken>, (usize, usize)), error::GraphError>
+    {
+        let mut r = Vec::<PositionedToken>::new ();
+        loop
+        {
+            self.skip_trivia ()?;
+            let (line, column) = (self.line, self.column);
+            let token = match ( self.peek (0), self.peek (1) )
+            {
+                (None, _) => return Ok ( (r, (line, column)) ),
+                (Some ('{'), _) => { self.bump (); Token::LBrace },
+                (Some ('}'), _) => { self.bump (); Token::RBrace },
+                (Some ('['), _) => { self.bump (); Token::LBracket },
+                (Some (']'), _) => { self.bump (); Token::RBracket },
+                (Some (';'), _) => { self.bump (); Token::Semicolon },
+                (Some (','), _) => { self.bump (); Token::Comma },
+                (Some ('='), _) => { self.bump (); Token::Equals },
+                (Some (':'), _) => { self.bump (); Token::Colon },
+                (Some ('+'), _) => { self.bump (); Token::Plus },
+                (Some ('-'), Some ('>')) => { self.bump (); self.bump (); Token::EdgeOp (true) },
+                (Some ('-'), Some ('-')) => { self.bump (); self.bump (); Token::EdgeOp (false) },
+                (Some ('"'), _) => self.quoted (line, column)?,
+                (Some ('<'), _) => self.html (line, column)?,
+                (Some (c), _) if c.is_ascii_digit () || c == '.' || c == '-' => self.numeral (),
+                (Some (c), _) if c.is_alphabetic () || c == '_' || !c.is_ascii () => self.identifier (),
+                (Some (c), _) => return Err (data_error (line, column, &format! ("Unexpected character '{}'", c)))
+            };
+            r.push ( (token, line, column) );
+        }
+    }
+}
+
+type DotAttrs = collections::HashMap<String, graph::AttributeValue>;
+
+#[derive(Clone, Default)]
+struct DotScope
+{
+    node: DotAttrs,
+    edge: DotAttrs
+}
+
+struct DotDocument<W>
+{
+    directed: bool,
+    name: String,
+    vertices: Vec<String>,
+    vertex_attrs: collections::HashMap<String, DotAttrs>,
+    edges: Vec<(String, String, DotAttrs, W)>
+}
+
+struct Parser<'a, W>
+{
+    tokens: Vec<PositionedToken>,
+    pos: usize,
+    end: (usize, usize),
+    options: &'a DotReadOptions,
+    document: DotDocument<W>
+}
+
+impl<'a, W> Parser<'a, W>
+    where
+        W: graph::Weight + str::FromStr
+{
+    fn position (&self)
+        -> (usize, usize)
+    {
+        self.tokens.get (self.pos).map (|x| (x.1, x.2)).unwrap_or (self.end)
+    }
+
+    fn error (&self, message: &str)
+        -> error::GraphError
+    {
+        let (line, column) = self.position ();
+        data_error (line, column, message)
+    }
+
+    fn peek (&self, offset: usize)
+        -> Option<&Token>
+    {
+        self.tokens.get (self.pos + offset).map (|x| &x.0)
+    }
+
+    fn peek_keyword (&self, offset: usize, keyword: &str)
+        -> bool
+    {
+        matches! (self.peek (offset), Some (Token::Id (text, false)) if text.eq_ignore_ascii_case (keyword))
+    }
+
+    fn expect (&mut self, token: Token, description: &str)
+        -> Result<(), error::GraphError>
+    {
+        if self.peek (0) == Some (&token)
+        {
+            self.pos += 1;
+            Ok (())
+        }
+        else
+        {
+            Err (self.error (&format! ("Expected {}", description)))
+        }
+    }
+
+    fn accept (&mut self, token: Token)
+        -> bool
+    {
+        if self.peek (0) == Some (&token)
+        {
+            self.pos += 1;
+            true
+        }
+        else
+        {
+            false
+        }
+    }
+
+    fn id (&mut self)
+        -> Result<(String, bool), error::GraphError>
+    {
+        if let Some (Token::Id (text, quoted)) = self.peek (0).cloned ()
+        {
+            self.pos += 1;
+            let mut r = text;
+            // "a" + "b" concatenation of quoted strings
+            while quoted && self.peek (0) == Some (&Token::Plus)
+            {
+                self.pos += 1;
+                match self.peek (0).cloned ()
+                {
+                    Some (Token::Id (next, true)) => { r.push_str (&next); self.pos += 1; },
+                    _ => return Err (self.error ("Expected quoted string after '+'"))
+                }
+            }
+            Ok ( (r, quoted) )
+        }
+        else
+        {
+            Err (self.error ("Expected identifier"))
+        }
+    }
+
+    fn value (&self, text: String, quoted: bool)
+        -> graph::AttributeValue
+    {
+        if self.options.infer_types && !quoted
+        {
+            let numeric = text.chars ().all (|c| c.is_ascii_digit () || "+-.eE".contains (c));
+            if text == "true" || text == "false"
+            {
+                graph::AttributeValue::BooleanLiteral (text == "true")
+            }
+            else if let Ok (i) = text.parse::<i64> () && numeric
+            {
+                graph::AttributeValue::IntegerLiteral (i)
+            }
+            else if let Ok (f) = text.parse::<f64> () && numeric
+            {
+                graph::AttributeValue::FloatLiteral (f)
+            }
+            else
+            {
+                graph::AttributeValue::StringLiteral (text)
+            }
+        }
+        else
+        {
+            graph::AttributeValue::StringLiteral (text)
+        }
+    }
+
+    fn attr_list (&mut self)
+        -> Result<DotAttrs, error::GraphError>
+    {
+        let mut r = DotAttrs::new ();
+        while self.accept (Token::LBracket)
+        {
+            while !self.accept (Token::RBracket)
+            {
+                let (key, _) = self.id ()?;
+                self.expect (Token::Equals, "'=' in attribute list")?;
+                let (text, quoted) = self.id ()?;
+                r.insert (key, self.value (text, quoted));
+                if !self.accept (Token::Comma)
+                {
+                    self.accept (Token::Semicolon);
+                }
+            }
+        }
+        Ok (r)
+    }
+
+    fn touch_vertex (&mut self, label: &str, scope: &DotScope)
+    {
+        if !self.document.vertex_attrs.contains_key (label)
+        {
+            self.document.vertices.push (label.to_string ());
+            self.document.vertex_attrs.insert (label.to_string (), scope.node.clone ());
+        }
+    }
+
+    fn node_id (&mut self)
+        -> Result<String, error::GraphError>
+    {
+        let (label, _) = self.id ()?;
+        // Ports and compass points have no equivalent and are dropped
+        if self.accept (Token::Colon)
+        {
+            self.id ()?;
+            if self.accept (Token::Colon)
+            {
+                self.id ()?;
+            }
+        }
+        Ok (label)
+    }
+
+    fn operand (&mut self, scope: &DotScope, members: &mut Vec<String>)
+        -> Result<Vec<String>, error::GraphError>
+    {
+        if self.peek_keyword (0, "subgraph") || self.peek (0) == Some (&Token::LBrace)
+        {
+            let r = self.subgraph (scope)?;
+            members.extend (r.iter ().cloned ());
+            Ok (r)
+        }
+        else
+        {
+            let label = self.node_id ()?;
+            self.touch_vertex (&label, scope);
+            members.push (label.clone ());
+            Ok (vec![label])
+        }
+    }
+
+    fn subgraph (&mut self, scope: &DotScope)
+        -> Result<Vec<String>, error::GraphError>
+    {
+        if self.peek_keyword (0, "subgraph")
+        {
+            self.pos += 1;
+            if let Some (Token::Id (_, _)) = self.peek (0)
+            {
+                self.id ()?;
+            }
+        }
+        self.expect (Token::LBrace, "'{'")?;
+        let mut inner = scope.clone ();
+        let r = self.stmt_list (&mut inner)?;
+        self.expect (Token::RBrace, "'}'")?;
+        Ok (r)
+    }
+
+    // Typed values print back to the text they were inferred from
+    fn edge_weight (&self, attrs: &mut DotAttrs, line: usize, column: usize)
+        -> Result<W, error::GraphError>
+    {
+        if let Some (weight_attr) = &self.options.weight_attr && let Some (w) = attrs.remove (weight_attr)
+        {
+            let text = w.to_string ();
+            text.parse::<W> ()
+                .map_err (|_| data_error (line, column, &format! ("Edge weight '{}' is not a valid {}", text, any::type_name::<W> ())))
+        }
+        else
+        {
+            Ok (W::zero ())
+        }
+    }
+
+    fn stmt (&mut self, scope: &mut DotScope, members: &mut Vec<String>)
+        -> Result<(), error::GraphError>
+    {
+        let (line, column) = self.position ();
+        if ( self.peek_keyword (0, "graph") || self.peek_keyword (0, "node") || self.peek_keyword (0, "edge") ) && self.peek (1) == Some (&Token::LBracket)
+        {
+            let (kind, _) = self.id ()?;
+            let attrs = self.attr_list ()?;
+            match kind.to_ascii_lowercase ().as_str ()
+            {
+                "node" => scope.node.extend (attrs),
+                "edge" => scope.edge.extend (attrs),
+                // Graph attributes have no equivalent
+                _ => {}
+            }
+            Ok (())
+        }
+        else if matches! (self.peek (0), Some (Token::Id (_, _))) && self.peek (1) == Some (&Token::Equals)
+        {
+            self.id ()?;
+            self.pos += 1;
+            self.id ()?;
+            Ok (())
+        }
+        else
+        {
+            let mut operands = vec![self.operand (scope, members)?];
+            let is_subgraph = !matches! (self.tokens.get (self.pos - 1).map (|x| &x.0), Some (Token::Id (_, _)));
+            while let Some (Token::EdgeOp (directed)) = self.peek (0).cloned ()
+            {
+                if directed != self.document.directed
+                {
+                    return Err (self.error (if directed { "'->' used in an undirected graph" } else { "'--' used in a directed graph" }));
+                }
+                self.pos += 1;
+                operands.push (self.operand (scope, members)?);
+            }
+
+            if operands.len () > 1
+            {
+                let mut attrs = scope.edge.clone ();
+                attrs.extend (self.attr_list ()?);
+                let weight = self.edge_weight (&mut attrs, line, column)?;
+                for pair in operands.windows (2)
+                {
+                    for a in &pair[0]
+                    {
+                        for b in &pair[1]
+                        {
+                            self.document.edges.push ( (a.clone (), b.clone (), attrs.clone (), weight) );
+                        }
+                    }
+                }
+            }
+            else if !is_subgraph
+            {
+                let attrs = self.attr_list ()?;
+                let label = &operands[0][0];
+                self.document.vertex_attrs.get_mut (label)
+                    .ok_or (data_error (line, column, &format! ("Vertex {} not found", label)))?
+                    .extend (attrs);
+            }
+            Ok (())
+        }
+    }
+
+    fn stmt_list (&mut self, scope: &mut DotScope)
+        -> Result<Vec<String>, error::GraphError>
+    {
+        let mut members = Vec::<String>::new ();
+        while self.peek (0).is_some () && self.peek (0) != Some (&Token::RBrace)
+        {
+            self.stmt (scope, &mut members)?;
+            self.accept (Token::Semicolon);
+        }
+        Ok (members)
+    }
+
+    fn graph (&mut self)
+        -> Result<(), error::GraphError>
+    {
+        if self.peek_keyword (0, "strict")
+        {
+            self.pos += 1;
+        }
+        if self.peek_keyword (0, "digraph")
+        {
+            self.document.directed = true;
+        }
+        else if !self.peek_keyword (0, "graph")
+        {
+            return Err (self.error ("Expected 'graph' or 'digraph'"));
+        }
+        self.pos += 1;
+        if let Some (Token::Id (_, _)) = self.peek (0)
+        {
+            self.document.name = self.id ()?.0;
+        }
+        self.expect (Token::LBrace, "'{'")?;
+        self.stmt_list (&mut DotScope::default ())?;
+        self.expect (Token::RBrace, "'}'")?;
+        if self.peek (0).is_some ()
+        {
+            Err (self.error ("Unexpected content after graph"))
+        }
+        else
+        {
+            Ok (())
+        }
+    }
+}
+
+fn parse<W, R> (mut reader: R, options: &DotReadOptions)
+    -> Result<DotDocument<W>, error::GraphError>
+    where
+        W: graph::Weight + str::FromStr,
+        R: io::Read
+{
+    let mut input = String::new ();
+    reader.read_to_string (&mut input)?;
+    let (tokens, end) = Lexer::new (&input).tokens ()?;
+    let document = DotDocument { directed: false, name: String::new (), vertices: Vec::new (), vertex_attrs: collections::HashMap::new (), edges: Vec::new () };
+    let mut parser = Parser { tokens, pos: 0, end, options, document };
+    parser.graph ()?;
+    Ok (parser.document)
+}
+
+pub fn read_labelled_graph<R> (reader: R, options: &DotReadOptions)
+    -> Result<graph::LabelledGraph, error::GraphError>
+    where
+        R: io::Read
+{
+    read_labelled_graph_weighted (reader, options)
+}
+
+pub fn read_labelled_graph_weighted<W, R> (reader: R, options: &DotReadOptions)
+    -> Result<graph::LabelledGraph<W>, error::GraphError>
+    where
+        W: graph::Weight + str::FromStr,
+        R: io::Read
+{
+    let mut document = parse (reader, options)?;
+    if document.directed
+    {
+        let mut g = graph::LabelledGraph::new_weighted_with_name (&document.name);
+        g.set_self_loops (options.self_loops)?;
+        for v in document.vertices
+        {
+            let attrs = document.vertex_attrs.remove (&v);
+            g.add_vertex (v, attrs)?;
+        }
+        for (a, b, attrs, weight) in document.edges
+        {
+            g.add_edge_weighted (a, b, Some (attrs), weight)?;
+        }
+        Ok (g)
+    }
+    else
+    {
+        Err (error::GraphError::DataError (String::from ("Expected a digraph but found an undirected graph")))
+    }
+}
+
+pub fn read_labelled_ugraph<R> (reader: R, options: &DotReadOptions)
+    -> Result<graph::LabelledUGraph, error::GraphError>
+    where
+        R: io::Read
+{
+    read_labelled_ugraph_weighted (reader, options)
+}
+
+pub fn read_labelled_ugraph_weighted<W, R> (reader: R, options: &DotReadOptions)
+    -> Result<graph::LabelledUGraph<W>, error::GraphError>
+    where
+        W: graph::Weight + str::FromStr,
+        R: io::Read
+{
+    let mut document = parse (reader, options)?;
+    if document.directed
+    {
+        Err (error::GraphError::DataError (String::from ("Expected an undirected graph but found a digraph")))
+    }
+    else
+    {
+        let mut g = graph::LabelledUGraph::new_weighted_with_name (&document.name);
+        g.set_self_loops (options.self_loops)?;
+        for v in document.vertices
+        {
+            let attrs = document.vertex_attrs.remove (&v);
+            g.add_vertex (v, attrs)?;
+        }
+        for (a, b, attrs, weight) in document.edges
+        {
+            g.add_edge_weighted (a, b, Some (attrs), weight)?;
+        }
+        Ok (g)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::graph;
+    use std::collections;
+    use std::sync;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| { let _ = env_logger::try_init (); });
+    }
+
+    #[test]
+    fn test_escape ()
+    {
+        init ();
+        assert_eq! (super::escape ("a"), "\"a\"");
+        assert_eq! (super::escape ("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq! (super::escape ("back\\slash"), "\"back\\\\slash\"");
+        assert_eq! (super::escape ("two\nlines"), "\"two\\nlines\"");
+    }
+
+    #[test]
+    fn test_write_graph ()
+    {
+        init ();
+        let mut g = graph::Graph::new_with_name ("g");
+        g.add_edge_raw (1, 2, 3).expect ("Failed to add edge 1 -> 2");
+        g.add_vertex_raw (4).expect ("Failed to add vertex 4");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_graph (&g, &mut out, &super::DotOptions::new ().with_weight_attr ("weight")).expect ("Failed to write dot");
+
+        let expected = "digraph \"g\" {\n\t1;\n\t2;\n\t4;\n\t1 -> 2 [\"weight\"=\"3\"];\n}\n";
+        assert_eq! (String::from_utf8 (out).expect ("Invalid utf8"), expected);
+    }
+
+    #[test]
+    fn test_write_ugraph ()
+    {
+        init ();
+        let mut g = graph::UGraph::new ();
+        g.add_edge_raw (2, 1, 0).expect ("Failed to add edge 2 -- 1");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_ugraph (&g, &mut out, &super::DotOptions::new ()).expect ("Failed to write dot");
+
+        let expected = "graph {\n\t1;\n\t2;\n\t1 -- 2;\n}\n";
+        assert_eq! (String::from_utf8 (out).expect ("Invalid utf8"), expected);
+    }
+
+    #[test]
+    fn test_write_labelled_graph ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        let vertex_attrs = collections::HashMap::<String, graph::AttributeValue>::from ([
+            ( String::from ("color"), graph::AttributeValue::from ("red") ),
+            ( String::from ("hidden"), graph::AttributeValue::from (true) )
+        ]);
+        let edge_attrs = collections::HashMap::<String, graph::AttributeValue>::from ([
+            ( String::from ("style"), graph::AttributeValue::from ("dashed") )
+        ]);
+        g.add_vertex (String::from ("a \"quoted\""), Some (vertex_attrs)).expect ("Failed to add vertex");
+        g.add_edge_weighted (String::from ("a \"quoted\""), String::from ("b"), Some (edge_attrs), 7).expect ("Failed to add edge");
+
+        let options = super::DotOptions::new ()
+            .with_vertex_attrs (&["color"])
+            .with_edge_attrs (&["style"])
+            .with_weight_attr ("label");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &options).expect ("Failed to write dot");
+
+        let expected = concat! (
+            "digraph {\n",
+            "\t\"a \\\"quoted\\\"\" [\"color\"=\"red\"];\n",
+            "\t\"b\";\n",
+            "\t\"a \\\"quoted\\\"\" -> \"b\" [\"label\"=\"7\", \"style\"=\"dashed\"];\n",
+            "}\n"
+        );
+        assert_eq! (String::from_utf8 (out).expect ("Invalid utf8"), expected);
+    }
+
+    #[test]
+    fn test_write_labelled_ugraph ()
+    {
+        init ();
+        let mut g = graph::LabelledUGraph::new_with_name ("u");
+        g.add_edge_weighted (String::from ("b"), String::from ("a"), None, 2).expect ("Failed to add edge");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&g, &mut out, &super::DotOptions::new ().with_weight_attr ("weight")).expect ("Failed to write dot");
+
+        let expected = "graph \"u\" {\n\t\"b\";\n\t\"a\";\n\t\"b\" -- \"a\" [\"weight\"=\"2\"];\n}\n";
+        assert_eq! (String::from_utf8 (out).expect ("Invalid utf8"), expected);
+    }
+
+    #[test]
+    fn test_read_labelled_graph ()
+    {
+        init ();
+        let input = r#"
+            /* a comment */
+            strict digraph "deps" {
+                node [shape=box];
+                a [color="red", size=2];
+                a -> b -> c [weight=3, style=dashed];
+                // line comment
+                b -> { d; e } [ratio=0.5, hidden=true];
+                subgraph cluster_x { edge [label="x"]; f -> g }
+            }
+        "#;
+
+        let g = super::read_labelled_graph (input.as_bytes (), &super::DotReadOptions::new ().with_weight_attr ("weight")).expect ("Failed to read dot");
+
+        assert_eq! (g.graph ().name (), "deps");
+        assert_eq! (g.vertex_labels (), ["a","b","c","d","e","f","g"].iter ().map (|x| x.to_string ()).collect::<collections::HashSet<_>> ());
+        assert_eq! (g.edges ().len (), 5);
+
+        let a_attrs = g.vertex_attrs ("a").expect ("Failed to find a").1;
+        assert_eq! (a_attrs["color"], graph::AttributeValue::from ("red"));
+        assert_eq! (a_attrs["size"], graph::AttributeValue::IntegerLiteral (2));
+        assert_eq! (a_attrs["shape"], graph::AttributeValue::from ("box"));
+
+        let e_ab = g.edge ( &(String::from ("a"), String::from ("b")) ).expect ("Failed to find a -> b");
+        assert_eq! (g.edges ()[&e_ab], 3);
+        let ab_attrs = g.edge_attrs ( &(String::from ("a"), String::from ("b")) ).expect ("Failed to find a -> b").1;
+        assert_eq! (ab_attrs.get ("weight"), None);
+        assert_eq! (ab_attrs["style"], graph::AttributeValue::from ("dashed"));
+
+        let bd_attrs = g.edge_attrs ( &(String::from ("b"), String::from ("d")) ).expect ("Failed to find b -> d").1;
+        assert_eq! (bd_attrs["ratio"], graph::AttributeValue::FloatLiteral (0.5));
+        assert_eq! (bd_attrs["hidden"], graph::AttributeValue::BooleanLiteral (true));
+        assert! (g.has_edge ( &(String::from ("b"), String::from ("e")) ));
+
+        let fg_attrs = g.edge_attrs ( &(String::from ("f"), String::from ("g")) ).expect ("Failed to find f -> g").1;
+        assert_eq! (fg_attrs["label"], graph::AttributeValue::from ("x"));
+    }
+
+    #[test]
+    fn test_read_labelled_ugraph ()
+    {
+        init ();
+        let input = "graph { a -- b; \"c \\\"q\\\"\" -- a [w=2] }";
+        let g = super::read_labelled_ugraph (input.as_bytes (), &super::DotReadOptions::new ().with_weight_attr ("w")).expect ("Failed to read dot");
+
+        assert! (g.has_edge ( &(String::from ("b"), String::from ("a")) ));
+        assert! (g.has_edge ( &(String::from ("a"), String::from ("c \"q\"")) ));
+        assert_eq! (super::read_labelled_graph (input.as_bytes (), &super::DotReadOptions::new ()).unwrap_err ().to_string (), "Data error: Expected a digraph but found an undirected graph");
+    }
+
+    #[test]
+    fn test_read_untyped ()
+    {
+        init ();
+        let input = "digraph { a [n=1] }";
+        let g = super::read_labelled_graph (input.as_bytes (), &super::DotReadOptions::new ().with_infer_types (false)).expect ("Failed to read dot");
+
+        assert_eq! (g.vertex_attrs ("a").expect ("Failed to find a").1["n"], graph::AttributeValue::from ("1"));
+    }
+
+    #[test]
+    fn test_read_errors ()
+    {
+        init ();
+        let cases = [
+            ( "digraph { a -- b }", "Data error: line 1, column 13: '--' used in a directed graph" ),
+            ( "graph {\n  a -> b\n}", "Data error: line 2, column 5: '->' used in an undirected graph" ),
+            ( "digraph {\n  a [color=]\n}", "Data error: line 2, column 12: Expected identifier" ),
+            ( "digraph { a -> b [weight=x] }", "Data error: line 1, column 11: Edge weight 'x' is not a valid i64" ),
+            ( "digraph { \"a }", "Data error: line 1, column 11: Unterminated string" ),
+            ( "tree { }", "Data error: line 1, column 1: Expected 'graph' or 'digraph'" ),
+            ( "digraph { a ", "Data error: line 1, column 13: Expected '}'" ),
+        ];
+
+        for (input, expected) in cases
+        {
+            let r = super::read_labelled_graph (input.as_bytes (), &super::DotReadOptions::new ().with_weight_attr ("weight"));
+            assert_eq! (r.unwrap_err ().to_string (), expected, "Unexpected error for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_round_trip ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new_with_name ("rt");
+        let attrs = collections::HashMap::<String, graph::AttributeValue>::from ([
+            ( String::from ("color"), graph::AttributeValue::from ("blue \\ \"x\"") ),
+            ( String::from ("rank"), graph::AttributeValue::from (2i64) ),
+            ( String::from ("code"), graph::AttributeValue::from ("123") ),
+            ( String::from ("flag"), graph::AttributeValue::from ("true") ),
+            ( String::from ("hidden"), graph::AttributeValue::from (false) ),
+            ( String::from ("ratio"), graph::AttributeValue::from (2.0f64) )
+        ]);
+        g.add_vertex (String::from ("a"), Some (attrs.clone ())).expect ("Failed to add vertex");
+        g.add_edge_weighted (String::from ("a"), String::from ("b\nc"), Some (attrs), -4).expect ("Failed to add edge");
+
+        let names = ["color", "rank", "code", "flag", "hidden", "ratio"];
+        let options = super::DotOptions::new ()
+            .with_vertex_attrs (&names)
+            .with_edge_attrs (&names)
+            .with_weight_attr ("weight");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &options).expect ("Failed to write dot");
+
+        let h = super::read_labelled_graph (out.as_slice (), &super::DotReadOptions::new ().with_weight_attr ("weight")).expect ("Failed to read dot");
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph");
+        let a_attrs = h.vertex_attrs ("a").expect ("Failed to find a").1;
+        assert_eq! (a_attrs["code"], graph::AttributeValue::from ("123"));
+        assert_eq! (a_attrs["flag"], graph::AttributeValue::from ("true"));
+        assert_eq! (a_attrs["ratio"], graph::AttributeValue::FloatLiteral (2.0));
+    }
+
+    #[test]
+    fn test_round_trip_self_loops ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new_with_name ("rt");
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge_weighted (String::from ("a"), String::from ("a"), None, 3).expect ("Failed to add edge a -> a");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), None, 1).expect ("Failed to add edge a -> b");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::DotOptions::new ().with_weight_attr ("weight")).expect ("Failed to write dot");
+        assert! (super::read_labelled_graph (out.as_slice (), &super::DotReadOptions::new ().with_weight_attr ("weight")).is_err ());
+        let options = super::DotReadOptions::new ().with_weight_attr ("weight").with_self_loops (true);
+        let h = super::read_labelled_graph (out.as_slice (), &options).expect ("Failed to read dot");
+        assert! (h.self_loops ());
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph");
+
+        let mut u = graph::LabelledUGraph::new ();
+        u.set_self_loops (true).expect ("Failed to allow self loops");
+        u.add_edge_weighted (String::from ("a"), String::from ("a"), None, 2).expect ("Failed to add edge a - a");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&u, &mut out, &super::DotOptions::new ().with_weight_attr ("weight")).expect ("Failed to write dot");
+        let h = super::read_labelled_ugraph (out.as_slice (), &options).expect ("Failed to read dot");
+        assert_eq! (h.edges ().values ().copied ().collect::<Vec<_>> (), vec![2]);
+    }
+
+    #[test]
+    fn test_round_trip_weighted ()
+    {
+        init ();
+        let mut g = graph::LabelledUGraph::<f64>::new_weighted_with_name ("w");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), None, 0.75).expect ("Failed to add edge a -- b");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), None, 2.0).expect ("Failed to add edge b -- c");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&g, &mut out, &super::DotOptions::new ().with_weight_attr ("weight")).expect ("Failed to write dot");
+        let options = super::DotReadOptions::new ().with_weight_attr ("weight");
+        let h = super::read_labelled_ugraph_weighted::<f64, _> (out.as_slice (), &options).expect ("Failed to read dot");
+        for (a, b, w) in [("a", "b", 0.75), ("b", "c", 2.0)]
+        {
+            let e = (h.vertex (a).expect ("Failed to find vertex"), h.vertex (b).expect ("Failed to find vertex"));
+            assert_eq! (graph::GraphAny::weight (&h, &e).expect ("Failed to find edge"), w);
+        }
+        assert_eq! (super::read_labelled_ugraph (out.as_slice (), &options).unwrap_err ().to_string (), "Data error: line 5, column 2: Edge weight '0.75' is not a valid i64");
+    }
+}
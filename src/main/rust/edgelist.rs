@@ -0,0 +1,538 @@
+use crate::error;
+use crate::graph;
+use std::collections;
+use std::fmt;
+use std::io;
+use std::str;
+
+// One edge per line: source, target, weight and then any attributes, split on
+// the delimiter. Without a header the attribute columns are name=value pairs;
+// with a header the columns after the weight are named by the header and hold
+// bare values, an empty value meaning the attribute is absent, so empty strings
+// can't be written with a header. Vertices without edges and vertex attributes
+// have no representation.
+//
+// Weights are written with Display and read back with FromStr, so any weight
+// type whose text form round trips can be used.
+
+#[derive(Clone, Debug)]
+pub struct EdgeListOptions
+{
+    pub delimiter: char,
+    pub header: bool,
+    // Lines starting with this character are skipped
+    pub comment: Option<char>,
+    // Whether the third column holds the edge weight
+    pub weight: bool,
+    pub attr_types: collections::HashMap<String, graph::AttributeType>,
+    // Type for attributes missing from attr_types
+    pub default_type: graph::AttributeType,
+    // Allow self loops on the graphs read
+    pub self_loops: bool
+}
+
+impl Default for EdgeListOptions
+{
+    fn default () -> Self
+    {
+        Self {
+            delimiter: '\t',
+            header: false,
+            comment: Some ('#'),
+            weight: true,
+            attr_types: collections::HashMap::new (),
+            default_type: graph::AttributeType::StringLiteral,
+            self_loops: false
+        }
+    }
+}
+
+impl EdgeListOptions
+{
+    pub fn new () -> Self
+    {
+        Self::default ()
+    }
+
+    pub fn with_delimiter (mut self, delimiter: char) -> Self
+    {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_header (mut self, header: bool) -> Self
+    {
+        self.header = header;
+        self
+    }
+
+    pub fn with_comment (mut self, comment: Option<char>) -> Self
+    {
+        self.comment = comment;
+        self
+    }
+
+    pub fn with_weight (mut self, weight: bool) -> Self
+    {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_attr_type (mut self, name: &str, attr_type: graph::AttributeType) -> Self
+    {
+        self.attr_types.insert (name.to_string (), attr_type);
+        self
+    }
+
+    pub fn with_default_type (mut self, attr_type: graph::AttributeType) -> Self
+    {
+        self.default_type = attr_type;
+        self
+    }
+
+    pub fn with_self_loops (mut self, self_loops: bool) -> Self
+    {
+        self.self_loops = self_loops;
+        self
+    }
+
+    fn attr_columns (&self)
+        -> usize
+    {
+        if self.weight { 3 } else { 2 }
+    }
+}
+
+type Attrs = collections::HashMap<String, graph::AttributeValue>;
+
+fn line_error (line: usize, message: &str)
+    -> error::GraphError
+{
+    error::GraphError::DataError (format! ("line {}: {}", line, message))
+}
+
+fn read_edges<W, R, F> (mut reader: R, options: &EdgeListOptions, mut add: F)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + str::FromStr,
+        W::Err: fmt::Display,
+        R: io::BufRead,
+        F: FnMut (String, String, Attrs, W) -> Result<(), error::GraphError>
+{
+    let mut buf = String::new ();
+    let mut line = 0usize;
+    let mut columns: Option<Vec<String>> = None;
+
+    loop
+    {
+        buf.clear ();
+        if reader.read_line (&mut buf)? == 0
+        {
+            return Ok (());
+        }
+        line += 1;
+        let text = buf.trim_end_matches (['\n', '\r']);
+        if text.is_empty () || options.comment.is_some_and (|c| text.starts_with (c))
+        {
+            continue;
+        }
+
+        let mut fields = text.split (options.delimiter);
+        if options.header && columns.is_none ()
+        {
+            columns = Some (fields.skip (options.attr_columns ()).map (|x| x.to_string ()).collect ());
+            continue;
+        }
+
+        let (a, b) = match (fields.next (), fields.next ())
+        {
+            (Some (a), Some (b)) => (a.to_string (), b.to_string ()),
+            _ => return Err (line_error (line, "expected at least source and target columns"))
+        };
+        let weight = if options.weight
+        {
+            match fields.next ()
+            {
+                None | Some ("") => W::zero (),
+                Some (w) => w.parse::<W> ().map_err (|e| line_error (line, &format! ("invalid weight '{}': {}", w, e)))?
+            }
+        }
+        else
+        {
+            W::zero ()
+        };
+
+        let mut attrs = Attrs::new ();
+        for (i, field) in fields.enumerate ()
+        {
+            let (name, value) = match &columns
+            {
+                Some (names) => {
+                    let name = names.get (i).ok_or (line_error (line, &format! ("column {} has no header", i + options.attr_columns () + 1)))?;
+                    (name.as_str (), field)
+                },
+                None => field.split_once ('=').ok_or (line_error (line, &format! ("expected name=value but found '{}'", field)))?
+            };
+            if columns.is_some () && value.is_empty ()
+            {
+                continue;
+            }
+            let attr_type = options.attr_types.get (name).unwrap_or (&options.default_type);
+            let value = attr_type.parse (value).map_err (|e| line_error (line, &e.to_string ()))?;
+            attrs.insert (name.to_string (), value);
+        }
+        add (a, b, attrs, weight).map_err (|e| line_error (line, &e.to_string ()))?;
+    }
+}
+
+pub fn read_labelled_graph<R> (reader: R, options: &EdgeListOptions)
+    -> Result<graph::LabelledGraph, error::GraphError>
+    where
+        R: io::BufRead
+{
+    read_labelled_graph_weighted (reader, options)
+}
+
+pub fn read_labelled_graph_weighted<W, R> (reader: R, options: &EdgeListOptions)
+    -> Result<graph::LabelledGraph<W>, error::GraphError>
+    where
+        W: graph::Weight + str::FromStr,
+        W::Err: fmt::Display,
+        R: io::BufRead
+{
+    let mut g = graph::LabelledGraph::new_weighted ();
+    g.set_self_loops (options.self_loops)?;
+    read_edges (reader, options, |a, b, attrs, weight| {
+        g.add_edge_weighted (a, b, Some (attrs), weight)?;
+        Ok (())
+    })?;
+    Ok (g)
+}
+
+pub fn read_labelled_ugraph<R> (reader: R, options: &EdgeListOptions)
+    -> Result<graph::LabelledUGraph, error::GraphError>
+    where
+        R: io::BufRead
+{
+    read_labelled_ugraph_weighted (reader, options)
+}
+
+pub fn read_labelled_ugraph_weighted<W, R> (reader: R, options: &EdgeListOptions)
+    -> Result<graph::LabelledUGraph<W>, error::GraphError>
+    where
+        W: graph::Weight + str::FromStr,
+        W::Err: fmt::Display,
+        R: io::BufRead
+{
+    let mut g = graph::LabelledUGraph::new_weighted ();
+    g.set_self_loops (options.self_loops)?;
+    read_edges (reader, options, |a, b, attrs, weight| {
+        g.add_edge_weighted (a, b, Some (attrs), weight)?;
+        Ok (())
+    })?;
+    Ok (g)
+}
+
+fn check_field (field: &str, options: &EdgeListOptions)
+    -> Result<(), error::GraphError>
+{
+    if field.contains (options.delimiter) || field.contains (['\n', '\r'])
+    {
+        Err (error::GraphError::ConversionError (format! ("'{}' contains the delimiter or a line break", field)))
+    }
+    else
+    {
+        Ok (())
+    }
+}
+
+fn write_edges<W, T> (writer: &mut T, edges: &[(String, String, &Attrs, W)], options: &EdgeListOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: fmt::Display,
+        T: io::Write
+{
+    let delimiter = options.delimiter.to_string ();
+    let columns = if options.header
+    {
+        let names = edges.iter ().flat_map (|e| e.2.keys ().cloned ()).collect::<collections::BTreeSet<_>> ();
+        let mut header = vec![String::from ("source"), String::from ("target")];
+        if options.weight
+        {
+            header.push (String::from ("weight"));
+        }
+        header.extend (names.iter ().cloned ());
+        for h in &header
+        {
+            check_field (h, options)?;
+        }
+        writeln! (writer, "{}", header.join (&delimiter))?;
+        Some (names.into_iter ().collect::<Vec<_>> ())
+    }
+    else
+    {
+        None
+    };
+
+    for (a, b, attrs, w) in edges
+    {
+        if options.comment.is_some_and (|c| a.starts_with (c))
+        {
+            return Err (error::GraphError::ConversionError (format! ("Vertex '{}' would be read back as a comment", a)));
+        }
+        check_field (a, options)?;
+        check_field (b, options)?;
+        let mut fields = vec![a.clone (), b.clone ()];
+        if options.weight
+        {
+            fields.push (w.to_string ());
+        }
+
+        let value = |name: &str, v: &graph::AttributeValue| {
+            match v
+            {
+                graph::AttributeValue::BooleanLiteral (_) | graph::AttributeValue::IntegerLiteral (_) | graph::AttributeValue::FloatLiteral (_) | graph::AttributeValue::StringLiteral (_) => {
+                    let r = v.to_string ();
+                    check_field (&r, options)?;
+                    Ok (r)
+                },
+                _ => Err (error::GraphError::ConversionError (format! ("Attribute {} of edge ({}, {}) is not a scalar", name, a, b)))
+            }
+        };
+        match &columns
+        {
+            Some (names) => {
+                for name in names
+                {
+                    let r = attrs.get (name).map (|v| value (name, v)).transpose ()?;
+                    if r.as_deref () == Some ("")
+                    {
+                        return Err (error::GraphError::ConversionError (format! ("Attribute {} of edge ({}, {}) is empty and would be read back as absent", name, a, b)));
+                    }
+                    fields.push (r.unwrap_or_default ());
+                }
+            },
+            None => {
+                let mut names = attrs.keys ().collect::<Vec<_>> ();
+                names.sort ();
+                for name in names
+                {
+                    if name.contains ('=')
+                    {
+                        return Err (error::GraphError::ConversionError (format! ("Attribute name {} contains '='", name)));
+                    }
+                    check_field (name, options)?;
+                    fields.push (format! ("{}={}", name, value (name, &attrs[name])?));
+                }
+            }
+        }
+        writeln! (writer, "{}", fields.join (&delimiter))?;
+    }
+    Ok (())
+}
+
+fn sorted_edges<W> (edges: &collections::HashMap<(usize, usize), W>)
+    -> Vec<((usize, usize), W)>
+    where
+        W: graph::Weight
+{
+    let mut r = edges.iter ().map (|(e, w)| (*e, *w)).collect::<Vec<_>> ();
+    r.sort_unstable_by_key (|x| x.0);
+    r
+}
+
+pub fn write_labelled_graph<W, T> (g: &graph::LabelledGraph<W>, writer: &mut T, options: &EdgeListOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + fmt::Display,
+        T: io::Write
+{
+    let edges = sorted_edges (g.edges ()).into_iter ()
+        .map (|(e, w)| {
+            let (a, b) = g.edge_label (&e)?;
+            Ok ( (a, b, g.edge_attrs_raw (&e)?, w) )
+        })
+        .collect::<Result<Vec<_>, error::GraphError>> ()?;
+    write_edges (writer, &edges, options)
+}
+
+pub fn write_labelled_ugraph<W, T> (g: &graph::LabelledUGraph<W>, writer: &mut T, options: &EdgeListOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + fmt::Display,
+        T: io::Write
+{
+    let edges = sorted_edges (g.edges ()).into_iter ()
+        .map (|(e, w)| {
+            let (a, b) = g.edge_label (&e)?;
+            Ok ( (a, b, g.edge_attrs_raw (&e)?, w) )
+        })
+        .collect::<Result<Vec<_>, error::GraphError>> ()?;
+    write_edges (writer, &edges, options)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::graph;
+    use std::collections;
+    use std::sync;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| {
+            let _ = env_logger::try_init ();
+        });
+    }
+
+    #[test]
+    fn test_read_labelled_graph ()
+    {
+        init ();
+        let input = "# comment\na\tb\t3\tcolour=red\tsize=2\r\n\nb\tc\t-1\nc\td\n";
+        let options = super::EdgeListOptions::new ().with_attr_type ("size", graph::AttributeType::IntegerLiteral);
+        let g = super::read_labelled_graph (input.as_bytes (), &options).expect ("Failed to read edge list");
+
+        assert_eq! (g.edges ().len (), 3);
+        let ab = (String::from ("a"), String::from ("b"));
+        assert_eq! (g.edges ()[&g.edge (&ab).expect ("Failed to find a -> b")], 3);
+        assert_eq! (g.edge_attrs (&ab).expect ("Failed to find a -> b").1, &collections::HashMap::<String, graph::AttributeValue>::from ([
+            ( String::from ("colour"), graph::AttributeValue::from ("red") ),
+            ( String::from ("size"), graph::AttributeValue::from (2i64) )
+        ]));
+        assert_eq! (g.edges ()[&g.edge ( &(String::from ("b"), String::from ("c")) ).expect ("Failed to find b -> c")], -1);
+        assert_eq! (g.edges ()[&g.edge ( &(String::from ("c"), String::from ("d")) ).expect ("Failed to find c -> d")], 0);
+    }
+
+    #[test]
+    fn test_read_header ()
+    {
+        init ();
+        let input = "src,dst,score,ok\n// skipped\nx,y,0.5,true\ny,z,,\n";
+        let options = super::EdgeListOptions::new ()
+            .with_delimiter (',')
+            .with_header (true)
+            .with_comment (Some ('/'))
+            .with_weight (false)
+            .with_default_type (graph::AttributeType::FloatLiteral)
+            .with_attr_type ("ok", graph::AttributeType::BooleanLiteral);
+        let g = super::read_labelled_ugraph (input.as_bytes (), &options).expect ("Failed to read edge list");
+
+        let xy = g.edge_attrs ( &(String::from ("y"), String::from ("x")) ).expect ("Failed to find x -- y").1;
+        assert_eq! (xy["score"], graph::AttributeValue::FloatLiteral (0.5));
+        assert_eq! (xy["ok"], graph::AttributeValue::BooleanLiteral (true));
+        assert! (g.edge_attrs ( &(String::from ("y"), String::from ("z")) ).expect ("Failed to find y -- z").1.is_empty ());
+    }
+
+    #[test]
+    fn test_read_errors ()
+    {
+        init ();
+        let options = super::EdgeListOptions::new ().with_attr_type ("n", graph::AttributeType::IntegerLiteral);
+        let cases = [
+            ( "a\tb\t1\na", "Data error: line 2: expected at least source and target columns" ),
+            ( "a\tb\tx", "Data error: line 1: invalid weight 'x': invalid digit found in string" ),
+            ( "a\tb\t1\tcolour", "Data error: line 1: expected name=value but found 'colour'" ),
+            ( "a\tb\t1\tn=x", "Data error: line 1: Conversion error: Failed to parse 'x' as an IntegerLiteral: invalid digit found in string" ),
+            ( "a\ta\t1", "Data error: line 1: Edge error: edge vertices must be distinct" )
+        ];
+
+        for (input, expected) in cases
+        {
+            let r = super::read_labelled_graph (input.as_bytes (), &options);
+            assert_eq! (r.unwrap_err ().to_string (), expected, "Unexpected error for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_round_trip ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        let attrs = collections::HashMap::<String, graph::AttributeValue>::from ([
+            ( String::from ("colour"), graph::AttributeValue::from ("red") ),
+            ( String::from ("size"), graph::AttributeValue::from (2i64) )
+        ]);
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (attrs), 3).expect ("Failed to add edge");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), None, 4).expect ("Failed to add edge");
+
+        for header in [false, true]
+        {
+            let options = super::EdgeListOptions::new ().with_header (header).with_attr_type ("size", graph::AttributeType::IntegerLiteral);
+            let mut out = Vec::<u8>::new ();
+            super::write_labelled_graph (&g, &mut out, &options).expect ("Failed to write edge list");
+            let h = super::read_labelled_graph (out.as_slice (), &options).expect ("Failed to read edge list");
+            assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph with header {}", header);
+        }
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::EdgeListOptions::new ().with_header (true)).expect ("Failed to write edge list");
+        assert_eq! (String::from_utf8 (out).expect ("Invalid utf8").lines ().next (), Some ("source\ttarget\tweight\tcolour\tsize"));
+
+        g.add_edge (String::from ("#c"), String::from ("a"), None).expect ("Failed to add edge");
+        assert! (super::write_labelled_graph (&g, &mut Vec::<u8>::new (), &super::EdgeListOptions::new ()).is_err ());
+    }
+
+    #[test]
+    fn test_round_trip_empty_string ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        let attrs = collections::HashMap::<String, graph::AttributeValue>::from ([ ( String::from ("colour"), graph::AttributeValue::from ("") ) ]);
+        g.add_edge (String::from ("a"), String::from ("b"), Some (attrs)).expect ("Failed to add edge");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::EdgeListOptions::new ()).expect ("Failed to write edge list");
+        let h = super::read_labelled_graph (out.as_slice (), &super::EdgeListOptions::new ()).expect ("Failed to read edge list");
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"));
+
+        assert! (super::write_labelled_graph (&g, &mut Vec::<u8>::new (), &super::EdgeListOptions::new ().with_header (true)).is_err ());
+    }
+
+    #[test]
+    fn test_round_trip_self_loops ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new ();
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge_weighted (String::from ("a"), String::from ("a"), None, 3).expect ("Failed to add edge a -> a");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), None, 1).expect ("Failed to add edge a -> b");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::EdgeListOptions::new ()).expect ("Failed to write edge list");
+        assert! (super::read_labelled_graph (out.as_slice (), &super::EdgeListOptions::new ()).is_err ());
+        let options = super::EdgeListOptions::new ().with_self_loops (true);
+        let h = super::read_labelled_graph (out.as_slice (), &options).expect ("Failed to read edge list");
+        assert! (h.self_loops ());
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph");
+
+        let mut u = graph::LabelledUGraph::new ();
+        u.set_self_loops (true).expect ("Failed to allow self loops");
+        u.add_edge_weighted (String::from ("a"), String::from ("a"), None, 2).expect ("Failed to add edge a - a");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&u, &mut out, &options).expect ("Failed to write edge list");
+        let h = super::read_labelled_ugraph (out.as_slice (), &options).expect ("Failed to read edge list");
+        assert_eq! (h.edges ().values ().copied ().collect::<Vec<_>> (), vec![2]);
+    }
+
+    #[test]
+    fn test_round_trip_weighted ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::<f64>::new_weighted ();
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), None, 0.1).expect ("Failed to add edge a -> b");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), None, -2.5e-7).expect ("Failed to add edge b -> c");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::EdgeListOptions::new ()).expect ("Failed to write edge list");
+        let h = super::read_labelled_graph_weighted::<f64, _> (out.as_slice (), &super::EdgeListOptions::new ()).expect ("Failed to read edge list");
+        for (a, b, w) in [("a", "b", 0.1), ("b", "c", -2.5e-7)]
+        {
+            let e = h.edge ( &(String::from (a), String::from (b)) ).expect ("Failed to find edge");
+            assert_eq! (h.edges ()[&e], w);
+        }
+        assert! (super::read_labelled_ugraph_weighted::<u32, _> (out.as_slice (), &super::EdgeListOptions::new ()).is_err ());
+    }
+}
@@ -3,8 +3,11 @@ use crate::error;
 use crate::graph;
 use std::collections;
 
-pub fn labels_and_attrs_eq (a: &graph::LabelledGraph, b: &graph::LabelledGraph)
+pub fn labels_and_attrs_eq<V, E> (a: &graph::LabelledGraph<i64, V, E>, b: &graph::LabelledGraph<i64, V, E>)
     -> Result<bool, error::GraphError>
+    where
+        V: graph::Payload + PartialEq,
+        E: graph::Payload + PartialEq
 {
     if a.graph ().vertices ().len () == b.graph ().vertices ().len () && a.graph ().edges ().len () == b.graph ().edges ().len ()
     {
@@ -15,7 +18,7 @@ pub fn labels_and_attrs_eq (a: &graph::LabelledGraph, b: &graph::LabelledGraph)
 
             if a_edges == b_edges
             {
-                let vertex_attrs_eq = a.vertex_labels ().iter ().map (|vl| Ok (a.vertex_attrs (&vl)?.1 == b.vertex_attrs (&vl)?.1) ).collect::<Result<Vec<_>, error::GraphError>> ()?;
+                let vertex_attrs_eq = a.vertex_labels ().iter ().map (|vl| Ok (a.vertex_attrs (vl)?.1 == b.vertex_attrs (vl)?.1) ).collect::<Result<Vec<_>, error::GraphError>> ()?;
                 if vertex_attrs_eq.iter ().all (|&x| x)
                 {
                     let edge_attrs_eq = a.edge_labels ()?.iter ().map (|el| Ok (a.edge_attrs (el)?.1 == b.edge_attrs (el)?.1) ).collect::<Result<Vec<_>, error::GraphError>> ()?;
@@ -66,8 +69,8 @@ pub fn labels_and_attrs_retain_eq (a: &graph::LabelledGraph, b: &graph::Labelled
                     .map (|vl| {
                         if let Some (rv) = &retain_vertex_attrs
                         {
-                            let mut vaa = a.vertex_attrs (&vl)?.1.clone ();
-                            let mut vab = b.vertex_attrs (&vl)?.1.clone ();
+                            let mut vaa = a.vertex_attrs (vl)?.1.clone ();
+                            let mut vab = b.vertex_attrs (vl)?.1.clone ();
 
                             vaa.retain (|k, _| rv.contains (k.as_str ()));
                             vab.retain (|k, _| rv.contains (k.as_str ()));
@@ -75,8 +78,8 @@ pub fn labels_and_attrs_retain_eq (a: &graph::LabelledGraph, b: &graph::Labelled
                         }
                         else
                         {
-                            let vaa = a.vertex_attrs (&vl)?.1;
-                            let vab = b.vertex_attrs (&vl)?.1;
+                            let vaa = a.vertex_attrs (vl)?.1;
+                            let vab = b.vertex_attrs (vl)?.1;
                             Ok (vaa == vab)
                         }
                     }).collect::<Result<Vec<_>, error::GraphError>> ()?;
@@ -146,7 +149,7 @@ mod tests
 
     fn init ()
     {
-        INIT.call_once (env_logger::init);
+        INIT.call_once (|| { let _ = env_logger::try_init (); });
     }
 
     fn attrs_a ()
@@ -454,5 +457,22 @@ mod tests
         assert! (!super::labels_and_attrs_eq (&a, &b).expect ("Failed eq check"), "Should not be equal");
         assert! (super::labels_and_attrs_retain_eq (&a, &b, None, Some (&keep)).expect ("Failed eq check"), "Should be equal");
     }
+
+    #[test]
+    fn test_typed_payloads ()
+    {
+        init ();
+        let mut a = graph::LabelledGraph::<i64, u32, String>::new_weighted ();
+        let mut b = graph::LabelledGraph::<i64, u32, String>::new_weighted ();
+
+        a.add_vertex (String::from ("vertex_one"), Some (1)).expect ("Failed to add vertex");
+        b.add_vertex (String::from ("vertex_one"), Some (1)).expect ("Failed to add vertex");
+        a.add_edge (String::from ("vertex_one"), String::from ("vertex_two"), Some (String::from ("e"))).expect ("Failed to add edge");
+        b.add_edge (String::from ("vertex_one"), String::from ("vertex_two"), Some (String::from ("e"))).expect ("Failed to add edge");
+        assert! (super::labels_and_attrs_eq (&a, &b).expect ("Failed eq check"), "Should be equal");
+
+        *b.vertex_attrs_mut ("vertex_two").expect ("Failed to find vertex").1 = 2;
+        assert! (!super::labels_and_attrs_eq (&a, &b).expect ("Failed eq check"), "Should not be equal");
+    }
 }
 
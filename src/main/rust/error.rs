@@ -22,6 +22,12 @@ pub enum GraphError
     #[error("IO error: {0}")]
     IOError (String),
 
+    #[error("Schema error: {0}")]
+    SchemaError (String),
+
+    #[error("Validation error: {}", .0.iter ().map (|e| e.to_string ()).collect::<Vec<_>> ().join ("; "))]
+    ValidationError (Vec<GraphError>),
+
     #[error("Vertex error: {0}")]
     VertexError (String)
 }
@@ -1,8 +1,11 @@
 
 //use log::debug;
 use serde::{Serialize,Deserialize};
+use std::borrow;
+use std::cmp;
 use std::collections;
 use std::fmt;
+use std::hash;
 use std::sync;
 
 #[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
@@ -15,7 +18,13 @@ pub enum AttributeValue
     StringLiteral (String),
     StringArray (Vec<String>),
     StringMap (collections::HashMap<String,String>),
-    StringSet (collections::HashSet<String>)
+    StringSet (collections::HashSet<String>),
+    Bytes (Vec<u8>),
+    FloatArray (Vec<f64>),
+    IntegerArray (Vec<i64>),
+    List (Vec<AttributeValue>),
+    Map (collections::HashMap<String,AttributeValue>),
+    Null
 }
 
 impl AttributeValue
@@ -135,6 +144,184 @@ impl AttributeValue
             unreachable! ("Must only be called with StringSet");
         }
     }
+
+    // The try_ getters, one per variant, return an error rather than panicking
+    // on the wrong variant
+
+    pub fn try_bool_literal (&self)
+        -> Result<bool, crate::error::GraphError>
+    {
+        if let AttributeValue::BooleanLiteral (val) = *self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a BooleanLiteral")))
+        }
+    }
+
+    pub fn try_boolean_map (&self)
+        -> Result<&collections::HashMap<String,bool>, crate::error::GraphError>
+    {
+        if let AttributeValue::BooleanMap (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a BooleanMap")))
+        }
+    }
+
+    pub fn try_integer_literal (&self)
+        -> Result<i64, crate::error::GraphError>
+    {
+        if let AttributeValue::IntegerLiteral (val) = *self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not an IntegerLiteral")))
+        }
+    }
+
+    pub fn try_float_literal (&self)
+        -> Result<f64, crate::error::GraphError>
+    {
+        if let AttributeValue::FloatLiteral (val) = *self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a FloatLiteral")))
+        }
+    }
+
+    pub fn try_string_literal (&self)
+        -> Result<&String, crate::error::GraphError>
+    {
+        if let AttributeValue::StringLiteral (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a StringLiteral")))
+        }
+    }
+
+    pub fn try_string_array (&self)
+        -> Result<&Vec<String>, crate::error::GraphError>
+    {
+        if let AttributeValue::StringArray (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a StringArray")))
+        }
+    }
+
+    pub fn try_string_map (&self)
+        -> Result<&collections::HashMap<String,String>, crate::error::GraphError>
+    {
+        if let AttributeValue::StringMap (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a StringMap")))
+        }
+    }
+
+    pub fn try_string_set (&self)
+        -> Result<&collections::HashSet<String>, crate::error::GraphError>
+    {
+        if let AttributeValue::StringSet (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a StringSet")))
+        }
+    }
+
+    pub fn try_bytes (&self)
+        -> Result<&Vec<u8>, crate::error::GraphError>
+    {
+        if let AttributeValue::Bytes (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a Bytes")))
+        }
+    }
+
+    pub fn try_float_array (&self)
+        -> Result<&Vec<f64>, crate::error::GraphError>
+    {
+        if let AttributeValue::FloatArray (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a FloatArray")))
+        }
+    }
+
+    pub fn try_integer_array (&self)
+        -> Result<&Vec<i64>, crate::error::GraphError>
+    {
+        if let AttributeValue::IntegerArray (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not an IntegerArray")))
+        }
+    }
+
+    pub fn try_list (&self)
+        -> Result<&Vec<AttributeValue>, crate::error::GraphError>
+    {
+        if let AttributeValue::List (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a List")))
+        }
+    }
+
+    pub fn try_map (&self)
+        -> Result<&collections::HashMap<String,AttributeValue>, crate::error::GraphError>
+    {
+        if let AttributeValue::Map (val) = self
+        {
+            Ok (val)
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Not a Map")))
+        }
+    }
+
+    pub fn is_null (&self)
+        -> bool
+    {
+        matches! (self, AttributeValue::Null)
+    }
 }
 
 impl From<bool> for AttributeValue
@@ -164,6 +351,15 @@ impl From<i64> for AttributeValue
     }
 }
 
+impl From<u32> for AttributeValue
+{
+    fn from (value: u32)
+        -> Self
+    {
+        AttributeValue::IntegerLiteral (value.into ())
+    }
+}
+
 impl From<String> for AttributeValue
 {
     fn from (value: String)
@@ -209,824 +405,2493 @@ impl From<collections::HashSet<String>> for AttributeValue
     }
 }
 
-impl fmt::Display for AttributeValue
+impl From<Vec<u8>> for AttributeValue
 {
-    fn fmt (&self, f: &mut std::fmt::Formatter<'_>)
-        -> fmt::Result
+    fn from (value: Vec<u8>)
+        -> Self
     {
-        match self {
-            AttributeValue::BooleanLiteral (boolean) => write!(f, "{}", boolean),
-            AttributeValue::BooleanMap (boolean_map) => f.pad(&boolean_map.iter ().map (|(k,v)| format! ("{}:{}", k,v)).collect::<Vec<String>> ().join (",")),
-            AttributeValue::FloatLiteral (float) => write!(f, "{}", float),
-            AttributeValue::IntegerLiteral (integer) => write!(f, "{}", integer),
-            AttributeValue::StringLiteral (string) => f.pad(string),
-            AttributeValue::StringArray (string_vec) => f.pad(&Vec::from_iter (string_vec.iter ().cloned ()).join (",")),
-            AttributeValue::StringMap (string_map) => f.pad(&string_map.iter ().map (|(k,v)| format! ("{}:{}", k,v)).collect::<Vec<String>> ().join (",")),
-            AttributeValue::StringSet (string_set) => f.pad(&Vec::from_iter (string_set.iter ().cloned ()).join (","))
-        }
+        AttributeValue::Bytes (value)
     }
 }
 
-pub trait MaybeAttributeValue
+impl From<Vec<f64>> for AttributeValue
 {
-    fn maybe_string_literal (&self) -> Result<Option<String>, crate::error::GraphError>;
+    fn from (value: Vec<f64>)
+        -> Self
+    {
+        AttributeValue::FloatArray (value)
+    }
 }
 
-impl MaybeAttributeValue for Option<AttributeValue>
+impl From<Vec<i64>> for AttributeValue
 {
-    fn maybe_string_literal (&self) -> Result<Option<String>, crate::error::GraphError>
+    fn from (value: Vec<i64>)
+        -> Self
     {
-        match self
-        {
-            None => Ok (None),
-            Some (AttributeValue::StringLiteral (val)) => Ok (Some (val.to_string ())),
-            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a StringLiteral")))
-        }
+        AttributeValue::IntegerArray (value)
     }
 }
 
-#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
-pub struct Graph
+impl From<Vec<AttributeValue>> for AttributeValue
 {
-    name: String,
-    vertices: collections::HashSet <usize>,
-    #[serde(with="crate::sd::key_pair_usize")]
-    edges: collections::HashMap <(usize,usize), i64>,
-    #[serde(with="crate::sd::key_usize")]
-    inbound: collections::HashMap <usize, collections::HashSet <usize>>,
-    #[serde(with="crate::sd::key_usize")]
-    outbound: collections::HashMap <usize, collections::HashSet <usize>>
+    fn from (value: Vec<AttributeValue>)
+        -> Self
+    {
+        AttributeValue::List (value)
+    }
 }
 
-#[derive(Clone,Debug,Deserialize,Serialize)]
-pub struct LabelledGraph
+impl From<collections::HashMap<String,AttributeValue>> for AttributeValue
 {
-    vertex_id: sync::Arc::<sync::atomic::AtomicUsize>,
-    #[serde(with="crate::sd::key_pair_usize")]
-    edge_attrs: collections::HashMap<(usize,usize), collections::HashMap<String, AttributeValue>>,
-    graph: Graph,
-    #[serde(with="crate::sd::key_usize")]
-    vertex_attrs: collections::HashMap<usize, collections::HashMap<String, AttributeValue>>,
-    #[serde(with="crate::sd::key_usize")]
-    vertex_label: collections::HashMap<usize, String>,
-    vertex_lookup: collections::HashMap<String, usize>
+    fn from (value: collections::HashMap<String,AttributeValue>)
+        -> Self
+    {
+        AttributeValue::Map (value)
+    }
 }
 
-#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
-pub struct UGraph
+// Composite variants become arrays and objects, StringSet sorted. Non finite
+// floats have no JSON form and become null, as with serde_json::to_value
+impl From<&AttributeValue> for serde_json::Value
 {
-    pub (crate) name: String,
-    pub (crate) vertices: collections::HashSet <usize>,
-    #[serde(with="crate::sd::key_pair_usize")]
-    pub (crate) edges: collections::HashMap <(usize,usize), i64>,
-    #[serde(with="crate::sd::key_usize")]
-    pub (crate) connected: collections::HashMap <usize, collections::HashSet <usize>>,
+    fn from (value: &AttributeValue)
+        -> Self
+    {
+        let float = |f: &f64| serde_json::Number::from_f64 (*f).map (serde_json::Value::Number).unwrap_or (serde_json::Value::Null);
+        match value
+        {
+            AttributeValue::BooleanLiteral (b) => serde_json::Value::Bool (*b),
+            AttributeValue::BooleanMap (m) => serde_json::Value::Object (m.iter ().map (|(k, v)| (k.clone (), serde_json::Value::Bool (*v))).collect ()),
+            AttributeValue::IntegerLiteral (i) => serde_json::Value::from (*i),
+            AttributeValue::FloatLiteral (f) => float (f),
+            AttributeValue::StringLiteral (s) => serde_json::Value::String (s.clone ()),
+            AttributeValue::StringArray (a) => serde_json::Value::Array (a.iter ().map (|v| serde_json::Value::String (v.clone ())).collect ()),
+            AttributeValue::StringMap (m) => serde_json::Value::Object (m.iter ().map (|(k, v)| (k.clone (), serde_json::Value::String (v.clone ()))).collect ()),
+            AttributeValue::StringSet (s) => serde_json::Value::Array (s.iter ().collect::<collections::BTreeSet<_>> ().into_iter ().map (|v| serde_json::Value::String (v.clone ())).collect ()),
+            AttributeValue::Bytes (b) => serde_json::Value::Array (b.iter ().map (|v| serde_json::Value::from (*v)).collect ()),
+            AttributeValue::FloatArray (a) => serde_json::Value::Array (a.iter ().map (float).collect ()),
+            AttributeValue::IntegerArray (a) => serde_json::Value::Array (a.iter ().map (|v| serde_json::Value::from (*v)).collect ()),
+            AttributeValue::List (a) => serde_json::Value::Array (a.iter ().map (serde_json::Value::from).collect ()),
+            AttributeValue::Map (m) => serde_json::Value::Object (m.iter ().map (|(k, v)| (k.clone (), serde_json::Value::from (v))).collect ()),
+            AttributeValue::Null => serde_json::Value::Null
+        }
+    }
 }
 
-#[derive(Clone,Debug,Deserialize,Serialize)]
-pub struct LabelledUGraph
+// JSON only distinguishes scalars, arrays and objects, so arrays become List
+// and objects Map. Numbers that fit an i64 become IntegerLiteral
+impl From<serde_json::Value> for AttributeValue
 {
-    vertex_id: sync::Arc::<sync::atomic::AtomicUsize>,
-    #[serde(with="crate::sd::key_pair_usize")]
-    edge_attrs: collections::HashMap<(usize,usize), collections::HashMap<String, AttributeValue>>,
-    graph: UGraph,
-    #[serde(with="crate::sd::key_usize")]
-    vertex_attrs: collections::HashMap<usize, collections::HashMap<String, AttributeValue>>,
-    #[serde(with="crate::sd::key_usize")]
-    vertex_label: collections::HashMap<usize, String>,
-    vertex_lookup: collections::HashMap<String, usize>
+    fn from (value: serde_json::Value)
+        -> Self
+    {
+        match value
+        {
+            serde_json::Value::Null => AttributeValue::Null,
+            serde_json::Value::Bool (b) => AttributeValue::BooleanLiteral (b),
+            serde_json::Value::Number (n) => {
+                match n.as_i64 ()
+                {
+                    Some (i) => AttributeValue::IntegerLiteral (i),
+                    None => AttributeValue::FloatLiteral (n.as_f64 ().unwrap_or (f64::NAN))
+                }
+            },
+            serde_json::Value::String (s) => AttributeValue::StringLiteral (s),
+            serde_json::Value::Array (a) => AttributeValue::List (a.into_iter ().map (AttributeValue::from).collect ()),
+            serde_json::Value::Object (m) => AttributeValue::Map (m.into_iter ().map (|(k, v)| (k, AttributeValue::from (v))).collect ())
+        }
+    }
 }
 
-impl Graph
+// Weights stored as attribute values, e.g. by the GraphML weight key. Float
+// weights also take integers, as a file may hold whole numbers only
+impl TryFrom<AttributeValue> for i64
 {
-    pub fn new () -> Self
-    {
-        Self { name: String::from (""), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), i64>::new (), inbound: collections::HashMap::<usize, collections::HashSet<usize>>::new (), outbound: collections::HashMap::<usize, collections::HashSet<usize>>::new () }
-    }
+    type Error = crate::error::GraphError;
 
-    pub fn new_with_name (name: &str) -> Self
+    fn try_from (value: AttributeValue)
+        -> Result<Self, Self::Error>
     {
-        Self { name: name.to_string (), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), i64>::new (), inbound: collections::HashMap::<usize, collections::HashSet<usize>>::new (), outbound: collections::HashMap::<usize, collections::HashSet<usize>>::new () }
+        match value
+        {
+            AttributeValue::IntegerLiteral (i) => Ok (i),
+            other => Err (crate::error::GraphError::ConversionError (format! ("{} is not an integer", other)))
+        }
     }
+}
 
-    pub fn add_edge_raw (&mut self, a: usize, b: usize, weight: i64)
-        -> Result<(), crate::error::GraphError>
+impl TryFrom<AttributeValue> for u32
+{
+    type Error = crate::error::GraphError;
+
+    fn try_from (value: AttributeValue)
+        -> Result<Self, Self::Error>
     {
-        self.vertices.insert (a);
-        self.vertices.insert (b);
-        self.edges.insert ( (a, b), weight);
-        self.inbound.entry ( b ).or_insert (collections::HashSet::<usize>::new ()).insert (a);
-        self.outbound.entry ( a ).or_insert (collections::HashSet::<usize>::new ()).insert (b);
-        Ok (())
+        match value
+        {
+            AttributeValue::IntegerLiteral (i) => u32::try_from (i).map_err (|e| crate::error::GraphError::ConversionError (format! ("{} is not a u32: {}", i, e))),
+            other => Err (crate::error::GraphError::ConversionError (format! ("{} is not an integer", other)))
+        }
     }
+}
 
-    pub fn add_vertex_raw (&mut self, a: usize)
-        -> Result<(), crate::error::GraphError>
+impl TryFrom<AttributeValue> for f64
+{
+    type Error = crate::error::GraphError;
+
+    fn try_from (value: AttributeValue)
+        -> Result<Self, Self::Error>
     {
-        self.vertices.insert (a);
-        Ok (())
+        match value
+        {
+            AttributeValue::FloatLiteral (f) => Ok (f),
+            AttributeValue::IntegerLiteral (i) => Ok (i as f64),
+            other => Err (crate::error::GraphError::ConversionError (format! ("{} is not a number", other)))
+        }
     }
+}
 
-    pub fn has_vertex_raw (&self, a: &usize)
-        -> bool
+impl fmt::Display for AttributeValue
+{
+    fn fmt (&self, f: &mut std::fmt::Formatter<'_>)
+        -> fmt::Result
     {
-        self.vertices.contains (a)
+        match self {
+            AttributeValue::BooleanLiteral (boolean) => write!(f, "{}", boolean),
+            AttributeValue::BooleanMap (boolean_map) => f.pad(&boolean_map.iter ().map (|(k,v)| format! ("{}:{}", k,v)).collect::<Vec<String>> ().join (",")),
+            AttributeValue::FloatLiteral (float) => write!(f, "{}", float),
+            AttributeValue::IntegerLiteral (integer) => write!(f, "{}", integer),
+            AttributeValue::StringLiteral (string) => f.pad(string),
+            AttributeValue::StringArray (string_vec) => f.pad(&Vec::from_iter (string_vec.iter ().cloned ()).join (",")),
+            AttributeValue::StringMap (string_map) => f.pad(&string_map.iter ().map (|(k,v)| format! ("{}:{}", k,v)).collect::<Vec<String>> ().join (",")),
+            AttributeValue::StringSet (string_set) => f.pad(&Vec::from_iter (string_set.iter ().cloned ()).join (",")),
+            AttributeValue::Bytes (bytes) => f.pad(&bytes.iter ().map (|b| format! ("{:02x}", b)).collect::<String> ()),
+            AttributeValue::FloatArray (float_vec) => f.pad(&float_vec.iter ().map (|v| v.to_string ()).collect::<Vec<String>> ().join (",")),
+            AttributeValue::IntegerArray (integer_vec) => f.pad(&integer_vec.iter ().map (|v| v.to_string ()).collect::<Vec<String>> ().join (",")),
+            AttributeValue::List (list) => f.pad(&list.iter ().map (|v| v.to_string ()).collect::<Vec<String>> ().join (",")),
+            AttributeValue::Map (map) => f.pad(&map.iter ().map (|(k,v)| format! ("{}:{}", k,v)).collect::<Vec<String>> ().join (",")),
+            AttributeValue::Null => f.pad("null")
+        }
     }
+}
 
-    pub fn has_edge_raw (&self, t: &(usize, usize))
-        -> bool
+pub trait MaybeAttributeValue
+{
+    fn maybe_string_literal (&self) -> Result<Option<String>, crate::error::GraphError>;
+    fn maybe_bool_literal (&self) -> Result<Option<bool>, crate::error::GraphError>;
+    fn maybe_integer_literal (&self) -> Result<Option<i64>, crate::error::GraphError>;
+    fn maybe_float_literal (&self) -> Result<Option<f64>, crate::error::GraphError>;
+    fn maybe_boolean_map (&self) -> Result<Option<&collections::HashMap<String,bool>>, crate::error::GraphError>;
+    fn maybe_string_array (&self) -> Result<Option<&Vec<String>>, crate::error::GraphError>;
+    fn maybe_string_map (&self) -> Result<Option<&collections::HashMap<String,String>>, crate::error::GraphError>;
+    fn maybe_string_set (&self) -> Result<Option<&collections::HashSet<String>>, crate::error::GraphError>;
+    fn maybe_bytes (&self) -> Result<Option<&Vec<u8>>, crate::error::GraphError>;
+    fn maybe_float_array (&self) -> Result<Option<&Vec<f64>>, crate::error::GraphError>;
+    fn maybe_integer_array (&self) -> Result<Option<&Vec<i64>>, crate::error::GraphError>;
+    fn maybe_list (&self) -> Result<Option<&Vec<AttributeValue>>, crate::error::GraphError>;
+    fn maybe_map (&self) -> Result<Option<&collections::HashMap<String,AttributeValue>>, crate::error::GraphError>;
+}
+
+impl MaybeAttributeValue for Option<AttributeValue>
+{
+    fn maybe_string_literal (&self) -> Result<Option<String>, crate::error::GraphError>
     {
-        self.edges.contains_key ( t )
+        match self
+        {
+            None => Ok (None),
+            Some (AttributeValue::StringLiteral (val)) => Ok (Some (val.to_string ())),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a StringLiteral")))
+        }
     }
 
-    pub fn inbound (&self, a: &usize)
-        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    fn maybe_bool_literal (&self) -> Result<Option<bool>, crate::error::GraphError>
     {
-        if self.vertices.contains (a)
+        match self
         {
-            let mut r = collections::HashSet::<usize>::new ();
-            if let Some (children) = self.inbound.get (a)
-            {
-                r.extend (children);
-            }
-            Ok (r)
-        }
-        else
-        {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+            None => Ok (None),
+            Some (AttributeValue::BooleanLiteral (val)) => Ok (Some (*val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a BooleanLiteral")))
         }
     }
 
-    pub fn incident (&self, a: &usize)
-        -> Result<collections::HashSet<(usize, usize)>, crate::error::GraphError>
+    fn maybe_integer_literal (&self) -> Result<Option<i64>, crate::error::GraphError>
     {
-        if self.vertices.contains (a)
+        match self
         {
-            let mut r = collections::HashSet::<(usize,usize)>::new ();
-            if let Some (children) = self.inbound.get (a)
-            {
-                r.extend (children.iter ().map (|&x| (x, *a)));
-            }
-            if let Some (parents) = self.outbound.get (a)
-            {
-                r.extend (parents.iter ().map (|&x| (*a, x)));
-            }
-            Ok (r)
+            None => Ok (None),
+            Some (AttributeValue::IntegerLiteral (val)) => Ok (Some (*val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a IntegerLiteral")))
         }
-        else
+    }
+
+    fn maybe_float_literal (&self) -> Result<Option<f64>, crate::error::GraphError>
+    {
+        match self
         {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+            None => Ok (None),
+            Some (AttributeValue::FloatLiteral (val)) => Ok (Some (*val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a FloatLiteral")))
         }
     }
 
-    pub fn vertex_identification (&mut self, m: &collections::BTreeSet<usize>)
-        -> Result<(), crate::error::GraphError>
+    fn maybe_boolean_map (&self) -> Result<Option<&collections::HashMap<String,bool>>, crate::error::GraphError>
     {
-        let mut it = m.iter ();
-        if let Some (tv) = it.next ()
+        match self
         {
-            while let Some (mv) = it.next ()
-            {
-                for mvi in self.inbound (mv)?
-                {
-                    let me = (mvi, *mv);
-                    let te = (mvi, *tv);
-                    let w = self.edges.get (&me)
-                        .copied ()
-                        .ok_or (crate::error::GraphError::DataError (format! ("Failed to find inbound merging edge: {:?}", me)))?;
-                    self.remove_edge_raw (&me.0, &me.1)?;
-                    if !self.has_edge_raw (&te)
-                    {
-                        self.add_edge_raw (te.0, te.1, w)?;
-                    }
-                }
-
-                for mvo in self.outbound (mv)?
-                {
-                    let me = (*mv, mvo);
-                    let te = (*tv, mvo);
-                    let w = self.edges.get (&me)
-                        .copied ()
-                        .ok_or (crate::error::GraphError::DataError (format! ("Failed to find outbound merging edge: {:?}", me)))?;
-                    self.remove_edge_raw (&me.0, &me.1)?;
-                    if !self.has_edge_raw (&te)
-                    {
-                        self.add_edge_raw (te.0, te.1, w)?;
-                    }
-                }
-
-                self.remove_vertex_raw (mv)?;
-            }
-            Ok (())
+            None => Ok (None),
+            Some (AttributeValue::BooleanMap (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a BooleanMap")))
         }
-        else
+    }
+
+    fn maybe_string_array (&self) -> Result<Option<&Vec<String>>, crate::error::GraphError>
+    {
+        match self
         {
-            Err (crate::error::GraphError::DataError (String::from ("No vertices to merge")))
+            None => Ok (None),
+            Some (AttributeValue::StringArray (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a StringArray")))
         }
     }
 
-    pub fn neighbours (&self, a: &usize)
-        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    fn maybe_string_map (&self) -> Result<Option<&collections::HashMap<String,String>>, crate::error::GraphError>
     {
-        if self.vertices.contains (a)
+        match self
         {
-            let mut r = collections::HashSet::<usize>::new ();
-            if let Some (children) = self.inbound.get (a)
-            {
-                r.extend (children);
-            }
-            if let Some (parents) = self.outbound.get (a)
-            {
-                r.extend (parents);
-            }
-            Ok (r)
+            None => Ok (None),
+            Some (AttributeValue::StringMap (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a StringMap")))
         }
-        else
+    }
+
+    fn maybe_string_set (&self) -> Result<Option<&collections::HashSet<String>>, crate::error::GraphError>
+    {
+        match self
         {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+            None => Ok (None),
+            Some (AttributeValue::StringSet (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a StringSet")))
         }
     }
 
-    pub fn outbound (&self, a: &usize)
-        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    fn maybe_bytes (&self) -> Result<Option<&Vec<u8>>, crate::error::GraphError>
     {
-        if self.vertices.contains (a)
+        match self
         {
-            let mut r = collections::HashSet::<usize>::new ();
-            if let Some (parents) = self.outbound.get (a)
-            {
-                r.extend (parents);
-            }
-            Ok (r)   
+            None => Ok (None),
+            Some (AttributeValue::Bytes (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a Bytes")))
         }
-        else
+    }
+
+    fn maybe_float_array (&self) -> Result<Option<&Vec<f64>>, crate::error::GraphError>
+    {
+        match self
         {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+            None => Ok (None),
+            Some (AttributeValue::FloatArray (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a FloatArray")))
         }
     }
 
-    pub fn endpoints (&self)
-        -> collections::HashSet<usize>
+    fn maybe_integer_array (&self) -> Result<Option<&Vec<i64>>, crate::error::GraphError>
     {
-        let mut r = collections::HashSet::<usize>::with_capacity (self.vertices.len ());
-        r.extend (self.inbound.keys ().copied ());
-        r.extend (self.outbound.keys ().copied ());
-        r
+        match self
+        {
+            None => Ok (None),
+            Some (AttributeValue::IntegerArray (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not an IntegerArray")))
+        }
     }
 
-    pub fn edges (&self)
-        -> &collections::HashMap <(usize,usize), i64>
+    fn maybe_list (&self) -> Result<Option<&Vec<AttributeValue>>, crate::error::GraphError>
     {
-        &self.edges
+        match self
+        {
+            None => Ok (None),
+            Some (AttributeValue::List (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a List")))
+        }
     }
 
-    pub fn is_source (&self, a: &usize)
-        -> Result<bool, crate::error::GraphError>
+    fn maybe_map (&self) -> Result<Option<&collections::HashMap<String,AttributeValue>>, crate::error::GraphError>
     {
-        if self.vertices.contains (a)
+        match self
         {
-            if let Some (inbound_a) = self.inbound.get (a)
-            {
-                Ok (inbound_a.is_empty ())
-            }
-            else
-            {
-                Ok (true)
-            }
+            None => Ok (None),
+            Some (AttributeValue::Map (val)) => Ok (Some (val)),
+            Some (_) => Err (crate::error::GraphError::DataError (String::from ("Not a Map")))
         }
-        else
+    }
+}
+
+// Scalar AttributeValue variants that can be parsed from text
+#[derive(Clone,Copy,Debug,Deserialize,Serialize,PartialEq)]
+pub enum AttributeType
+{
+    BooleanLiteral,
+    IntegerLiteral,
+    FloatLiteral,
+    StringLiteral
+}
+
+impl AttributeType
+{
+    pub fn parse (&self, s: &str)
+        -> Result<AttributeValue, crate::error::GraphError>
+    {
+        match self
         {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+            AttributeType::BooleanLiteral => s.parse::<bool> ().map (AttributeValue::BooleanLiteral)
+                .map_err (|e| crate::error::GraphError::ConversionError (format! ("Failed to parse '{}' as a BooleanLiteral: {}", s, e))),
+            AttributeType::IntegerLiteral => s.parse::<i64> ().map (AttributeValue::IntegerLiteral)
+                .map_err (|e| crate::error::GraphError::ConversionError (format! ("Failed to parse '{}' as an IntegerLiteral: {}", s, e))),
+            AttributeType::FloatLiteral => s.parse::<f64> ().map (AttributeValue::FloatLiteral)
+                .map_err (|e| crate::error::GraphError::ConversionError (format! ("Failed to parse '{}' as a FloatLiteral: {}", s, e))),
+            AttributeType::StringLiteral => Ok (AttributeValue::StringLiteral (s.to_string ()))
         }
     }
+}
 
-    pub fn is_sink (&self, a: &usize)
-        -> Result<bool, crate::error::GraphError>
+// Default vertex and edge payload of the labelled graphs, which also accept
+// any other type with a Default for vertices and edges added without one
+pub type Attributes = collections::HashMap<String, AttributeValue>;
+
+// Vertex and edge payloads of the labelled graphs. Attributes exposes itself to
+// the attribute schema, other payload types have nothing for it to check and
+// only need an empty impl
+pub trait Payload: Default
+{
+    fn attributes (&self)
+        -> Option<&Attributes>
     {
-        if self.vertices.contains (a)
+        None
+    }
+
+    fn attributes_mut (&mut self)
+        -> Option<&mut Attributes>
+    {
+        None
+    }
+}
+
+impl Payload for Attributes
+{
+    fn attributes (&self)
+        -> Option<&Attributes>
+    {
+        Some (self)
+    }
+
+    fn attributes_mut (&mut self)
+        -> Option<&mut Attributes>
+    {
+        Some (self)
+    }
+}
+
+impl Payload for () {}
+impl Payload for bool {}
+impl Payload for i64 {}
+impl Payload for u32 {}
+impl Payload for u64 {}
+impl Payload for f64 {}
+impl Payload for String {}
+
+// Mutable access to a payload, checked against the schema and brought into the
+// attribute index by commit or, failing that, when dropped. A change that
+// breaks the schema is rolled back. Dropping can only log the rollback, commit
+// returns it as an error
+#[must_use = "changes that break the schema are rolled back, call commit to find out"]
+pub struct AttrsMut<'a, T, K = usize>
+    where
+        T: Payload,
+        K: Copy + Eq + hash::Hash
+{
+    payload: &'a mut T,
+    schema: Option<&'a crate::schema::AttributeSchema>,
+    index: Option<(&'a mut crate::index::AttributeIndex<K>, K)>,
+    context: String,
+    snapshot: Option<Attributes>,
+    committed: bool
+}
+
+pub type EdgeAttrsMut<'a, E> = AttrsMut<'a, E, (usize, usize)>;
+
+impl<'a, T, K> AttrsMut<'a, T, K>
+    where
+        T: Payload,
+        K: Copy + Eq + hash::Hash
+{
+    fn new<F> (payload: &'a mut T, schema: Option<&'a crate::schema::AttributeSchema>, index: Option<(&'a mut crate::index::AttributeIndex<K>, K)>, context: F)
+        -> Self
+        where
+            F: FnOnce () -> String
+    {
+        // Only pay for the copy when there is a schema to roll back for or an
+        // index to update
+        let snapshot = (schema.is_some () || index.is_some ()).then (|| payload.attributes ().cloned ()).flatten ();
+        let context = if schema.is_some () { context () } else { String::new () };
+        Self { payload, schema, index, context, snapshot, committed: false }
+    }
+
+    pub fn commit (mut self)
+        -> Result<(), crate::error::GraphError>
+    {
+        self.committed = true;
+        self.check ()
+    }
+
+    fn check (&mut self)
+        -> Result<(), crate::error::GraphError>
+    {
+        let r = match self.schema
         {
-            if let Some (outbound_a) = self.outbound.get (a)
+            Some (schema) => schema.conform (self.payload, &self.context),
+            None => Ok (())
+        };
+        if let Some (snapshot) = self.snapshot.take ()
+        {
+            if r.is_err ()
             {
-                Ok (outbound_a.is_empty ())
+                if let Some (attrs) = self.payload.attributes_mut ()
+                {
+                    *attrs = snapshot;
+                }
             }
-            else
+            else if let Some ((index, id)) = &mut self.index
             {
-                Ok (true)
+                index.remove_attrs (id, &snapshot);
+                index.insert (*id, self.payload);
             }
         }
-        else
-        {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
-        }
+        r
     }
+}
 
-    pub fn name (&self)
-        -> String
+impl<T, K> std::ops::Deref for AttrsMut<'_, T, K>
+    where
+        T: Payload,
+        K: Copy + Eq + hash::Hash
+{
+    type Target = T;
+
+    fn deref (&self)
+        -> &T
     {
-        self.name.clone ()
+        self.payload
     }
+}
 
-    pub fn sources (&self)
-        -> collections::HashSet::<usize>
+impl<T, K> std::ops::DerefMut for AttrsMut<'_, T, K>
+    where
+        T: Payload,
+        K: Copy + Eq + hash::Hash
+{
+    fn deref_mut (&mut self)
+        -> &mut T
     {
-        self.vertices.iter ().filter (|x| !self.inbound.contains_key (x)).copied ().collect::<collections::HashSet::<usize>> ()
+        self.payload
     }
+}
 
-    pub fn parent (&self, a: &usize)
-        -> Result<Option<usize>, crate::error::GraphError>
+impl<T, K> Drop for AttrsMut<'_, T, K>
+    where
+        T: Payload,
+        K: Copy + Eq + hash::Hash
+{
+    fn drop (&mut self)
     {
-        if self.vertices.contains (a)
-        {
-            let inbound = self.inbound (&a)?;
-            match inbound.len ()
-            {
-                0 | 1 => Ok (inbound.into_iter ().next ()),
-                _ => Err (crate::error::GraphError::VertexError (format! ("Vertex: {} has more than one parent", a)))
-            }
-        }
-        else
+        if !self.committed && let Err (e) = self.check ()
         {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+            log::warn! ("Rolled back uncommitted attribute changes: {}", e);
         }
     }
+}
 
-    pub fn remove_edge_raw (&mut self, a: &usize, b: &usize)
+// Vertex labels of the labelled graphs, e.g. String, integers, UUIDs or tuples.
+// Debug is only used to name vertices in errors
+pub trait Label: Clone + Eq + hash::Hash + fmt::Debug
+{
+}
+
+impl<T> Label for T
+    where
+        T: Clone + Eq + hash::Hash + fmt::Debug
+{
+}
+
+// Edge weights form an ordered monoid: combine accumulates path costs starting
+// from zero and compare must be a total order. check rejects values that have
+// no place in that order, e.g. NaN for f64
+pub trait Weight: Copy + fmt::Debug + PartialEq
+{
+    fn zero () -> Self;
+    fn combine (&self, other: &Self) -> Self;
+    fn compare (&self, other: &Self) -> cmp::Ordering;
+
+    fn check (&self)
         -> Result<(), crate::error::GraphError>
     {
-        //debug! ("remove_edge_raw: self: {:?}", self);
-        //debug! ("remove_edge_raw: a: {} b: {}", a, b);
-        if self.vertices.contains (a) && self.vertices.contains (b)
-        {
-            let mut errors = Vec::<String>::new ();
-            if self.edges.contains_key ( &(*a ,*b) )
-            {
-                self.edges.remove ( &(*a ,*b) );
-            }
-            else
-            {
-                errors.push (format! ("remove_edge_raw: Could not find edge {} -> {}", a, b));
-            }
+        Ok (())
+    }
 
-            if let Some (inbound_b) = self.inbound.get_mut (b)
+    // None when combining overflows
+    fn checked_combine (&self, other: &Self)
+        -> Option<Self>
+    {
+        Some (self.combine (other))
+    }
+}
+
+impl Weight for i64
+{
+    fn zero ()
+        -> Self
+    {
+        0
+    }
+
+    fn combine (&self, other: &Self)
+        -> Self
+    {
+        self + other
+    }
+
+    fn compare (&self, other: &Self)
+        -> cmp::Ordering
+    {
+        self.cmp (other)
+    }
+
+    fn checked_combine (&self, other: &Self)
+        -> Option<Self>
+    {
+        self.checked_add (*other)
+    }
+}
+
+impl Weight for u32
+{
+    fn zero ()
+        -> Self
+    {
+        0
+    }
+
+    fn combine (&self, other: &Self)
+        -> Self
+    {
+        // path costs go through checked_combine, this only has to not panic
+        self.saturating_add (*other)
+    }
+
+    fn compare (&self, other: &Self)
+        -> cmp::Ordering
+    {
+        self.cmp (other)
+    }
+
+    fn checked_combine (&self, other: &Self)
+        -> Option<Self>
+    {
+        self.checked_add (*other)
+    }
+}
+
+impl Weight for f64
+{
+    fn zero ()
+        -> Self
+    {
+        0.0
+    }
+
+    fn combine (&self, other: &Self)
+        -> Self
+    {
+        self + other
+    }
+
+    fn compare (&self, other: &Self)
+        -> cmp::Ordering
+    {
+        self.total_cmp (other)
+    }
+
+    fn check (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        if self.is_nan ()
+        {
+            Err (crate::error::GraphError::DataError (String::from ("NaN is not a valid edge weight")))
+        }
+        else
+        {
+            Ok (())
+        }
+    }
+}
+
+// Graph and UGraph allow self loops by default, both from new and when
+// deserialized without a self_loops field, as they always have. The labelled
+// graphs keep their policy in the same flag of their inner graph but create
+// it with self loops off, so for them loops are opt-in.
+fn default_self_loops ()
+    -> bool
+{
+    true
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
+#[serde(bound(serialize = "W: Serialize", deserialize = "W: Deserialize<'de>"))]
+pub struct Graph<W = i64>
+{
+    name: String,
+    vertices: collections::HashSet <usize>,
+    #[serde(with="crate::sd::key_pair_usize")]
+    edges: collections::HashMap <(usize,usize), W>,
+    #[serde(with="crate::sd::key_usize")]
+    inbound: collections::HashMap <usize, collections::HashSet <usize>>,
+    #[serde(with="crate::sd::key_usize")]
+    outbound: collections::HashMap <usize, collections::HashSet <usize>>,
+    #[serde(default = "default_self_loops")]
+    self_loops: bool
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize)]
+#[serde(remote = "Self", bound(serialize = "W: Serialize, V: Serialize, E: Serialize, L: Serialize", deserialize = "W: Deserialize<'de>, V: Deserialize<'de>, E: Deserialize<'de>, L: Deserialize<'de> + Label"))]
+pub struct LabelledGraph<W = i64, V = Attributes, E = Attributes, L = String>
+{
+    vertex_id: sync::Arc::<sync::atomic::AtomicUsize>,
+    #[serde(with="crate::sd::key_pair_usize")]
+    edge_attrs: collections::HashMap<(usize,usize), E>,
+    graph: Graph<W>,
+    #[serde(with="crate::sd::key_usize")]
+    vertex_attrs: collections::HashMap<usize, V>,
+    #[serde(with="crate::sd::key_usize")]
+    vertex_label: collections::HashMap<usize, L>,
+    #[serde(with="crate::sd::key_any")]
+    vertex_lookup: collections::HashMap<L, usize>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    schema: Option<crate::schema::GraphSchema>,
+    #[serde(skip)]
+    vertex_index: crate::index::AttributeIndex<usize>,
+    #[serde(skip)]
+    edge_index: crate::index::AttributeIndex<(usize,usize)>
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
+#[serde(bound(serialize = "W: Serialize", deserialize = "W: Deserialize<'de>"))]
+pub struct UGraph<W = i64>
+{
+    pub (crate) name: String,
+    pub (crate) vertices: collections::HashSet <usize>,
+    #[serde(with="crate::sd::key_pair_usize")]
+    pub (crate) edges: collections::HashMap <(usize,usize), W>,
+    #[serde(with="crate::sd::key_usize")]
+    pub (crate) connected: collections::HashMap <usize, collections::HashSet <usize>>,
+    #[serde(default = "default_self_loops")]
+    pub (crate) self_loops: bool
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize)]
+#[serde(remote = "Self", bound(serialize = "W: Serialize, V: Serialize, E: Serialize, L: Serialize", deserialize = "W: Deserialize<'de>, V: Deserialize<'de>, E: Deserialize<'de>, L: Deserialize<'de> + Label"))]
+pub struct LabelledUGraph<W = i64, V = Attributes, E = Attributes, L = String>
+{
+    vertex_id: sync::Arc::<sync::atomic::AtomicUsize>,
+    #[serde(with="crate::sd::key_pair_usize")]
+    edge_attrs: collections::HashMap<(usize,usize), E>,
+    graph: UGraph<W>,
+    #[serde(with="crate::sd::key_usize")]
+    vertex_attrs: collections::HashMap<usize, V>,
+    #[serde(with="crate::sd::key_usize")]
+    vertex_label: collections::HashMap<usize, L>,
+    #[serde(with="crate::sd::key_any")]
+    vertex_lookup: collections::HashMap<L, usize>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    schema: Option<crate::schema::GraphSchema>,
+    #[serde(skip)]
+    vertex_index: crate::index::AttributeIndex<usize>,
+    #[serde(skip)]
+    edge_index: crate::index::AttributeIndex<(usize,usize)>
+}
+
+impl Graph
+{
+    pub fn new () -> Self
+    {
+        Self::new_weighted ()
+    }
+
+    pub fn new_with_name (name: &str) -> Self
+    {
+        Self::new_weighted_with_name (name)
+    }
+}
+
+impl<W> Graph<W>
+    where
+        W: Weight
+{
+    pub fn new_weighted () -> Self
+    {
+        Self { name: String::from (""), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), W>::new (), inbound: collections::HashMap::<usize, collections::HashSet<usize>>::new (), outbound: collections::HashMap::<usize, collections::HashSet<usize>>::new (), self_loops: true }
+    }
+
+    pub fn new_weighted_with_name (name: &str) -> Self
+    {
+        Self { name: name.to_string (), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), W>::new (), inbound: collections::HashMap::<usize, collections::HashSet<usize>>::new (), outbound: collections::HashMap::<usize, collections::HashSet<usize>>::new (), self_loops: true }
+    }
+
+    pub fn add_edge_raw (&mut self, a: usize, b: usize, weight: W)
+        -> Result<(), crate::error::GraphError>
+    {
+        if a == b && !self.self_loops
+        {
+            return Err (crate::error::GraphError::EdgeError (String::from ("edge vertices must be distinct")));
+        }
+        weight.check ()?;
+        self.vertices.insert (a);
+        self.vertices.insert (b);
+        self.edges.insert ( (a, b), weight);
+        self.inbound.entry ( b ).or_insert (collections::HashSet::<usize>::new ()).insert (a);
+        self.outbound.entry ( a ).or_insert (collections::HashSet::<usize>::new ()).insert (b);
+        Ok (())
+    }
+
+    pub fn add_vertex_raw (&mut self, a: usize)
+        -> Result<(), crate::error::GraphError>
+    {
+        self.vertices.insert (a);
+        Ok (())
+    }
+
+    pub fn has_vertex_raw (&self, a: &usize)
+        -> bool
+    {
+        self.vertices.contains (a)
+    }
+
+    pub fn has_edge_raw (&self, t: &(usize, usize))
+        -> bool
+    {
+        self.edges.contains_key ( t )
+    }
+
+    pub fn inbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            let mut r = collections::HashSet::<usize>::new ();
+            if let Some (children) = self.inbound.get (a)
+            {
+                r.extend (children);
+            }
+            Ok (r)
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn inbound_iter (&self, a: &usize)
+        -> Result<impl Iterator<Item = usize> + '_, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            Ok (self.inbound.get (a).into_iter ().flatten ().copied ())
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn incident (&self, a: &usize)
+        -> Result<collections::HashSet<(usize, usize)>, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            let mut r = collections::HashSet::<(usize,usize)>::new ();
+            if let Some (children) = self.inbound.get (a)
+            {
+                r.extend (children.iter ().map (|&x| (x, *a)));
+            }
+            if let Some (parents) = self.outbound.get (a)
+            {
+                r.extend (parents.iter ().map (|&x| (*a, x)));
+            }
+            Ok (r)
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn vertex_identification (&mut self, m: &collections::BTreeSet<usize>)
+        -> Result<(), crate::error::GraphError>
+    {
+        let mut it = m.iter ();
+        if let Some (tv) = it.next ()
+        {
+            while let Some (mv) = it.next ()
+            {
+                for mvi in self.inbound (mv)?
+                {
+                    let me = (mvi, *mv);
+                    let te = (mvi, *tv);
+                    let w = self.edges.get (&me)
+                        .copied ()
+                        .ok_or (crate::error::GraphError::DataError (format! ("Failed to find inbound merging edge: {:?}", me)))?;
+                    self.remove_edge_raw (&me.0, &me.1)?;
+                    // an edge between merged vertices becomes a loop, dropped unless loops are allowed
+                    if !self.has_edge_raw (&te) && (te.0 != te.1 || self.self_loops)
+                    {
+                        self.add_edge_raw (te.0, te.1, w)?;
+                    }
+                }
+
+                for mvo in self.outbound (mv)?
+                {
+                    let me = (*mv, mvo);
+                    let te = (*tv, mvo);
+                    let w = self.edges.get (&me)
+                        .copied ()
+                        .ok_or (crate::error::GraphError::DataError (format! ("Failed to find outbound merging edge: {:?}", me)))?;
+                    self.remove_edge_raw (&me.0, &me.1)?;
+                    // an edge between merged vertices becomes a loop, dropped unless loops are allowed
+                    if !self.has_edge_raw (&te) && (te.0 != te.1 || self.self_loops)
+                    {
+                        self.add_edge_raw (te.0, te.1, w)?;
+                    }
+                }
+
+                self.remove_vertex_raw (mv)?;
+            }
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("No vertices to merge")))
+        }
+    }
+
+    pub fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            let mut r = collections::HashSet::<usize>::new ();
+            if let Some (children) = self.inbound.get (a)
+            {
+                r.extend (children);
+            }
+            if let Some (parents) = self.outbound.get (a)
+            {
+                r.extend (parents);
+            }
+            Ok (r)
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn neighbours_iter (&self, a: &usize)
+        -> Result<impl Iterator<Item = usize> + '_, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            let outbound = self.outbound.get (a);
+            let inbound = self.inbound.get (a).into_iter ().flatten ()
+                .filter (move |x| !outbound.is_some_and (|o| o.contains (x)));
+            Ok (outbound.into_iter ().flatten ().chain (inbound).copied ())
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn outbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            let mut r = collections::HashSet::<usize>::new ();
+            if let Some (parents) = self.outbound.get (a)
+            {
+                r.extend (parents);
+            }
+            Ok (r)   
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn outbound_iter (&self, a: &usize)
+        -> Result<impl Iterator<Item = usize> + '_, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            Ok (self.outbound.get (a).into_iter ().flatten ().copied ())
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn endpoints (&self)
+        -> collections::HashSet<usize>
+    {
+        let mut r = collections::HashSet::<usize>::with_capacity (self.vertices.len ());
+        r.extend (self.inbound.keys ().copied ());
+        r.extend (self.outbound.keys ().copied ());
+        r
+    }
+
+    pub fn edges (&self)
+        -> &collections::HashMap <(usize,usize), W>
+    {
+        &self.edges
+    }
+
+    pub fn edges_iter (&self)
+        -> impl Iterator<Item = ((usize, usize), W)> + '_
+    {
+        self.edges.iter ().map (|(ev, w)| (*ev, *w))
+    }
+
+    pub fn is_source (&self, a: &usize)
+        -> Result<bool, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            if let Some (inbound_a) = self.inbound.get (a)
+            {
+                Ok (inbound_a.is_empty ())
+            }
+            else
+            {
+                Ok (true)
+            }
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn is_sink (&self, a: &usize)
+        -> Result<bool, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            if let Some (outbound_a) = self.outbound.get (a)
+            {
+                Ok (outbound_a.is_empty ())
+            }
+            else
+            {
+                Ok (true)
+            }
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn name (&self)
+        -> String
+    {
+        self.name.clone ()
+    }
+
+    pub fn self_loops (&self)
+        -> bool
+    {
+        self.self_loops
+    }
+
+    // Loops may only be disallowed again once the graph has none
+    pub fn set_self_loops (&mut self, allow: bool)
+        -> Result<(), crate::error::GraphError>
+    {
+        if !allow && self.edges.keys ().any (|(a, b)| a == b)
+        {
+            Err (crate::error::GraphError::EdgeError (String::from ("graph contains self loops")))
+        }
+        else
+        {
+            self.self_loops = allow;
+            Ok (())
+        }
+    }
+
+    pub fn sources (&self)
+        -> collections::HashSet::<usize>
+    {
+        self.vertices.iter ().filter (|x| !self.inbound.contains_key (x)).copied ().collect::<collections::HashSet::<usize>> ()
+    }
+
+    pub fn parent (&self, a: &usize)
+        -> Result<Option<usize>, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            let inbound = self.inbound (&a)?;
+            match inbound.len ()
+            {
+                0 | 1 => Ok (inbound.into_iter ().next ()),
+                _ => Err (crate::error::GraphError::VertexError (format! ("Vertex: {} has more than one parent", a)))
+            }
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn remove_edge_raw (&mut self, a: &usize, b: &usize)
+        -> Result<(), crate::error::GraphError>
+    {
+        //debug! ("remove_edge_raw: self: {:?}", self);
+        //debug! ("remove_edge_raw: a: {} b: {}", a, b);
+        if self.vertices.contains (a) && self.vertices.contains (b)
+        {
+            let mut errors = Vec::<String>::new ();
+            if self.edges.contains_key ( &(*a ,*b) )
+            {
+                self.edges.remove ( &(*a ,*b) );
+            }
+            else
+            {
+                errors.push (format! ("remove_edge_raw: Could not find edge {} -> {}", a, b));
+            }
+
+            if let Some (inbound_b) = self.inbound.get_mut (b)
+            {
+                if inbound_b.contains (a)
+                {
+                    inbound_b.remove (a);
+                }
+                else
+                {
+                    errors.push (format! ("remove_edge_raw: {} had no inbound edge from {}", b, a));
+                }
+                if inbound_b.is_empty ()
+                {
+                    self.inbound.remove (b);
+                }
+            }
+            else
+            {
+                errors.push (format! ("remove_edge_raw: {} has no inbound edges", b));
+            }
+
+            if let Some (outbound_a) = self.outbound.get_mut (a)
+            {
+                if outbound_a.contains (b)
+                {
+                    outbound_a.remove (b);
+                }
+                else
+                {
+                    errors.push (format! ("remove_edge_raw: {} had no outbound edge to {}", a, b));
+                }
+                if outbound_a.is_empty ()
+                {
+                    self.outbound.remove (a);
+                }
+            }
+            else
+            {
+                errors.push (format! ("remove_edge_raw: {} has no outbound edges", a));
+            }
+
+            if errors.is_empty ()
+            {
+                Ok (())
+            }
+            else
+            {
+                Err (crate::error::GraphError::EdgeError (errors.join ("\n\t")))
+            }
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("remove_edge_raw: Failed to find all vertices {}:{} {}:{}", a, self.vertices.contains (a), b, self.vertices.contains (b))))
+        }
+    }
+
+    pub fn remove_vertex_raw (&mut self, a: &usize)
+        -> Result<(), crate::error::GraphError>
+    {
+        for v in self.outbound (a)?
+        {
+            //debug! ("rvr a -> v: {} -> {}", a, v);
+            self.remove_edge_raw (a, &v)?;
+        }
+        for v in self.inbound (a)?
+        {
+            //debug! ("rvr v -> a: {} -> {}", v, a);
+            self.remove_edge_raw (&v, a)?;
+        }
+        self.vertices.remove (a);
+        self.inbound.remove (a);
+        self.outbound.remove (a);
+        Ok (())
+    }
+
+    pub fn rename (&mut self, name: String)
+    {
+        self.name = name;
+    }
+
+    pub fn sinks (&self)
+        -> collections::HashSet <usize>
+    {
+        self.vertices.iter ().filter (|x| !self.outbound.contains_key (x)).copied ().collect::<collections::HashSet::<usize>> ()
+    }
+
+    pub fn remap (&mut self, map: &collections::HashMap<usize, usize>)
+        -> Result<(), crate::error::GraphError>
+    {
+        let ks = map.keys ().copied ().collect::<collections::HashSet<_>> ();
+        if ks.is_subset (&self.vertices)
+        {
+            let vs = map.values ().copied ().collect::<collections::HashSet<_>> ();
+            if ks.len () == vs.len ()
+            {
+                let rvs = vs.intersection (&self.vertices).copied ().collect::<collections::HashSet<_>> ();
+                if rvs.is_subset (&ks)
+                {
+                    let edges_new = self.edges.drain ()
+                        .map (|(k,v)| {
+                            (
+                                (
+                                    map.get (&k.0).copied ().unwrap_or (k.0),
+                                    map.get (&k.1).copied ().unwrap_or (k.1),
+                                )
+                                , v
+                            )
+                        }).collect::<Vec<_>> ();
+                    self.edges.extend (edges_new);
+                    let inbound_new = self.inbound.drain ()
+                        .map (|(k,v)| {
+                            (
+                                map.get (&k).copied ().unwrap_or (k),
+                                v.iter ().map (|x| map.get (x).copied ().unwrap_or (*x) ).collect::<collections::HashSet::<_>> ()
+                            )
+                        })
+                        .collect::<Vec<_>> ();
+                    self.inbound.extend (inbound_new);
+                    let outbound_new = self.outbound.drain ()
+                        .map (|(k,v)| {
+                            (
+                                map.get (&k).copied ().unwrap_or (k),
+                                v.iter ().map (|x| map.get (x).copied ().unwrap_or (*x) ).collect::<collections::HashSet::<_>> ()
+                            )
+                        })
+                        .collect::<Vec<_>> ();
+                    self.outbound.extend (outbound_new);
+                    let vertices_new = self.vertices.drain ()
+                        .map (|x| map.get (&x).copied ().unwrap_or (x))
+                        .collect::<Vec<_>> ();
+                    self.vertices.extend (vertices_new);
+                    Ok (())
+                }
+                else
+                {
+                    Err (crate::error::GraphError::DataError (String::from ("Both directions are required when swapping existing vertices")))
+                }
+            }
+            else
+            {
+                Err (crate::error::GraphError::DataError (String::from ("Every key must have a unique value")))
+            }
+        }
+        else
+        {
+            Err (crate::error::GraphError::DataError (String::from ("Remap keys must be a subset of graph vertices")))
+        }
+    }
+
+    pub fn retain (&mut self, vertices_retain: &collections::HashSet<usize>)
+        -> Result<(), crate::error::GraphError>
+    {
+        for vd in &self.vertices - vertices_retain
+        {
+            self.remove_vertex_raw (&vd)?;
+        }
+        Ok (())
+    }
+
+    pub fn retain_edges (&mut self, edges_retain: &collections::HashSet<(usize,usize)>)
+        -> Result<(), crate::error::GraphError>
+    {
+        for e in &self.edges.keys ().cloned ().collect::<collections::HashSet<_>> () - edges_retain
+        {
+            self.remove_edge_raw (&e.0, &e.1)?;
+        }
+        Ok (())
+    }
+
+    pub fn validate (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        let mut errors = Vec::<crate::error::GraphError>::new ();
+        let mut edges = self.edges.keys ().copied ().collect::<Vec<_>> ();
+        edges.sort ();
+        for (a, b) in edges
+        {
+            if a == b && !self.self_loops
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) is a self loop but self loops are not allowed", a, b)));
+            }
+            if let Err (e) = self.edges[&(a, b)].check ()
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) has an invalid weight: {}", a, b, e)));
+            }
+            for v in [a, b].iter ().filter (|v| !self.vertices.contains (v))
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) refers to missing vertex {}", a, b, v)));
+            }
+            if !self.outbound.get (&a).is_some_and (|x| x.contains (&b))
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) is missing from outbound of {}", a, b, a)));
+            }
+            if !self.inbound.get (&b).is_some_and (|x| x.contains (&a))
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) is missing from inbound of {}", a, b, b)));
+            }
+        }
+        for (direction, adjacency) in [("outbound", &self.outbound), ("inbound", &self.inbound)]
+        {
+            let mut entries = adjacency.iter ().flat_map (|(a, vs)| vs.iter ().map (move |b| (*a, *b))).collect::<Vec<_>> ();
+            entries.sort ();
+            for (a, b) in entries
+            {
+                let e = if direction == "outbound" { (a, b) } else { (b, a) };
+                if !self.edges.contains_key (&e)
+                {
+                    errors.push (crate::error::GraphError::EdgeError (format! ("{} of {} lists {} but there is no edge ({},{})", direction, a, b, e.0, e.1)));
+                }
+            }
+        }
+        if errors.is_empty ()
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::ValidationError (errors))
+        }
+    }
+
+    pub fn vertices (&self)
+        -> &collections::HashSet <usize>
+    {
+        &self.vertices
+    }
+}
+
+impl UGraph
+{
+    pub fn new () -> Self
+    {
+        Self::new_weighted ()
+    }
+
+    pub fn new_with_name (name: &str) -> Self
+    {
+        Self::new_weighted_with_name (name)
+    }
+}
+
+impl<W> UGraph<W>
+    where
+        W: Weight
+{
+    pub fn new_weighted () -> Self
+    {
+        Self { name: String::from (""), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), W>::new (), connected: collections::HashMap::<usize, collections::HashSet<usize>>::new (), self_loops: true }
+    }
+
+    pub fn new_weighted_with_name (name: &str) -> Self
+    {
+        Self { name: name.to_string (), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), W>::new (), connected: collections::HashMap::<usize, collections::HashSet<usize>>::new (), self_loops: true }
+    }
+
+    pub fn add_edge_raw (&mut self, a: usize, b: usize, weight: W)
+        -> Result<(), crate::error::GraphError>
+    {
+        if a == b && !self.self_loops
+        {
+            return Err (crate::error::GraphError::EdgeError (String::from ("edge vertices must be distinct")));
+        }
+        weight.check ()?;
+        self.vertices.insert (a);
+        self.vertices.insert (b);
+        if a < b
+        {
+            self.edges.insert ( (a, b), weight);
+        }
+        else
+        {
+            self.edges.insert ( (b, a), weight);
+        }
+        self.connected.entry ( b ).or_insert (collections::HashSet::<usize>::new ()).insert (a);
+        self.connected.entry ( a ).or_insert (collections::HashSet::<usize>::new ()).insert (b);
+        Ok (())
+    }
+
+    pub fn add_vertex_raw (&mut self, a: usize)
+        -> Result<(), crate::error::GraphError>
+    {
+        self.vertices.insert (a);
+        Ok (())
+    }
+
+    pub fn has_edge_raw (&self, t: &(usize, usize))
+        -> bool
+    {
+        if t.0 < t.1
+        {
+            self.edges.contains_key ( t )
+        }
+        else
+        {
+            self.edges.contains_key ( &(t.1, t.0) )
+        }
+    }
+
+    pub fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            let mut r = collections::HashSet::<usize>::new ();
+            if let Some (children) = self.connected.get (a)
+            {
+                r.extend (children);
+            }
+            Ok (r)
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn neighbours_iter (&self, a: &usize)
+        -> Result<impl Iterator<Item = usize> + '_, crate::error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            Ok (self.connected.get (a).into_iter ().flatten ().copied ())
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+        }
+    }
+
+    pub fn edges (&self)
+        -> &collections::HashMap <(usize,usize), W>
+    {
+        &self.edges
+    }
+
+    pub fn edges_iter (&self)
+        -> impl Iterator<Item = ((usize, usize), W)> + '_
+    {
+        self.edges.iter ().map (|(ev, w)| (*ev, *w))
+    }
+
+    pub fn name (&self)
+        -> String
+    {
+        self.name.clone ()
+    }
+
+    pub fn self_loops (&self)
+        -> bool
+    {
+        self.self_loops
+    }
+
+    // Loops may only be disallowed again once the graph has none
+    pub fn set_self_loops (&mut self, allow: bool)
+        -> Result<(), crate::error::GraphError>
+    {
+        if !allow && self.edges.keys ().any (|(a, b)| a == b)
+        {
+            Err (crate::error::GraphError::EdgeError (String::from ("graph contains self loops")))
+        }
+        else
+        {
+            self.self_loops = allow;
+            Ok (())
+        }
+    }
+
+    pub fn remove_edge_raw (&mut self, a: &usize, b: &usize)
+        -> Result<(), crate::error::GraphError>
+    {
+        //debug! ("remove_edge_raw: self: {:?}", self);
+        //debug! ("remove_edge_raw: a: {} b: {}", a, b);
+        if self.vertices.contains (a) && self.vertices.contains (b)
+        {
+            let mut errors = Vec::<String>::new ();
+            if a < b
+            {
+                if self.edges.contains_key ( &(*a ,*b) )
+                {
+                    self.edges.remove ( &(*a ,*b) );
+                }
+                else
+                {
+                    errors.push (format! ("remove_edge_raw: Could not find edge {} -> {}", a, b));
+                }
+            }
+            else
+            {
+                if self.edges.contains_key ( &(*b ,*a) )
+                {
+                    self.edges.remove ( &(*b ,*a) );
+                }
+                else
+                {
+                    errors.push (format! ("remove_edge_raw: Could not find edge {} -> {}", b, a));
+                }
+            }
+
+            if let Some (connected_b) = self.connected.get_mut (b)
+            {
+                if connected_b.contains (a)
+                {
+                    connected_b.remove (a);
+                }
+                else
+                {
+                    errors.push (format! ("remove_edge_raw: {} had no edge from {}", b, a));
+                }
+            }
+            else
+            {
+                errors.push (format! ("remove_edge_raw: {} has no edges", b));
+            }
+
+            // a loop is listed once in connected
+            if a != b
+            {
+                if let Some (connected_a) = self.connected.get_mut (a)
+                {
+                    if connected_a.contains (b)
+                    {
+                        connected_a.remove (b);
+                    }
+                    else
+                    {
+                        errors.push (format! ("remove_edge_raw: {} had no edge to {}", a, b));
+                    }
+                }
+                else
+                {
+                    errors.push (format! ("remove_edge_raw: {} has no edges", a));
+                }
+            }
+
+            if errors.is_empty ()
+            {
+                Ok (())
+            }
+            else
+            {
+                Err (crate::error::GraphError::EdgeError (errors.join ("\n\t")))
+            }
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("remove_edge_raw: Failed to find all vertices {}:{} {}:{}", a, self.vertices.contains (a), b, self.vertices.contains (b))))
+        }
+    }
+
+    pub fn remove_vertex_raw (&mut self, a: &usize)
+        -> Result<(), crate::error::GraphError>
+    {
+        let vertices = self.connected.get (a).ok_or (crate::error::GraphError::VertexError (format! ("Failed to find vertex {}", a)))?.clone ();
+        for v in vertices
+        {
+            //debug! ("rvr a -> v: {} -> {}", a, v);
+            self.remove_edge_raw (a, &v)?;
+        }
+        self.vertices.remove (a);
+        self.connected.remove (a);
+        Ok (())
+    }
+
+    pub fn validate (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        let mut errors = Vec::<crate::error::GraphError>::new ();
+        let mut edges = self.edges.keys ().copied ().collect::<Vec<_>> ();
+        edges.sort ();
+        for (a, b) in edges
+        {
+            if a > b
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) is not stored with the smaller vertex first", a, b)));
+            }
+            if a == b && !self.self_loops
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) is a self loop but self loops are not allowed", a, b)));
+            }
+            if let Err (e) = self.edges[&(a, b)].check ()
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) has an invalid weight: {}", a, b, e)));
+            }
+            for v in [a, b].iter ().filter (|v| !self.vertices.contains (v))
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) refers to missing vertex {}", a, b, v)));
+            }
+            if !self.connected.get (&a).is_some_and (|x| x.contains (&b)) || !self.connected.get (&b).is_some_and (|x| x.contains (&a))
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) is missing from connected", a, b)));
+            }
+        }
+        let mut entries = self.connected.iter ().flat_map (|(a, vs)| vs.iter ().map (move |b| (*a, *b))).collect::<Vec<_>> ();
+        entries.sort ();
+        for (a, b) in entries
+        {
+            if !self.has_edge_raw ( &(a, b) )
+            {
+                errors.push (crate::error::GraphError::EdgeError (format! ("connected of {} lists {} but there is no edge between them", a, b)));
+            }
+        }
+        if errors.is_empty ()
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::ValidationError (errors))
+        }
+    }
+
+    pub fn vertices (&self)
+        -> &collections::HashSet <usize>
+    {
+        &self.vertices
+    }
+}
+
+impl LabelledGraph
+{
+    pub fn new () -> Self
+    {
+        Self::new_weighted ()
+    }
+
+    pub fn new_with_name (name: &str) -> Self
+    {
+        Self::new_weighted_with_name (name)
+    }
+}
+
+impl<W, V, E, L> LabelledGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    pub fn new_weighted () -> Self
+    {
+        Self {
+            vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (1)),
+            edge_attrs: collections::HashMap::<(usize,usize), E>::new (),
+            graph: Graph { self_loops: false, ..Graph::new_weighted () },
+            vertex_attrs: collections::HashMap::<usize, V>::new (),
+            vertex_label: collections::HashMap::<usize, L>::new (),
+            vertex_lookup: collections::HashMap::<L, usize>::new (),
+            schema: None,
+            vertex_index: crate::index::AttributeIndex::default (),
+            edge_index: crate::index::AttributeIndex::default ()
+        }
+    }
+
+    pub fn new_weighted_with_name (name: &str) -> Self
+    {
+        Self {
+            vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (1)),
+            edge_attrs: collections::HashMap::<(usize,usize), E>::new (),
+            graph: Graph { self_loops: false, ..Graph::new_weighted_with_name (name) },
+            vertex_attrs: collections::HashMap::<usize, V>::new (),
+            vertex_label: collections::HashMap::<usize, L>::new (),
+            vertex_lookup: collections::HashMap::<L, usize>::new (),
+            schema: None,
+            vertex_index: crate::index::AttributeIndex::default (),
+            edge_index: crate::index::AttributeIndex::default ()
+        }
+    }
+
+    pub fn new_without_attributes (g: &LabelledGraph<W, V, E, L>)
+        -> Self
+    {
+        Self {
+            vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (g.vertices ().iter ().max ().cloned ().unwrap_or (1))),
+            edge_attrs: collections::HashMap::<(usize,usize), E>::new (),
+            graph: g.graph ().clone (),
+            vertex_attrs: g.graph ().vertices ().iter ().fold (collections::HashMap::<usize, V>::new (), |mut acc, item| {
+                acc.insert (*item, V::default ());
+                acc
+            }),
+            vertex_label: g.vertex_label.clone (),
+            vertex_lookup: g.vertex_lookup.clone (),
+            schema: None,
+            vertex_index: crate::index::AttributeIndex::default (),
+            edge_index: crate::index::AttributeIndex::default ()
+        }
+    }
+
+    pub fn add_edge (&mut self, a: L, b: L, attrs: Option<E>)
+        -> Result<(usize, usize), crate::error::GraphError>
+    {
+        self.add_edge_weighted (a, b, attrs, W::zero ())
+    }
+
+    pub fn add_edge_weighted (&mut self, a: L, b: L, attrs: Option<E>, weight: W)
+        -> Result<(usize, usize), crate::error::GraphError>
+    {
+        //debug! ("add {} to {}", a , b);
+        if a == b && !self.graph.self_loops
+        {
+            Err (crate::error::GraphError::EdgeError (String::from ("edge vertices must be distinct")))
+        }
+        else
+        {
+            weight.check ()?;
+            let edge_attrs = self.conform_edge (&a, &b, attrs)?;
+            // Check that we didn't already add the vertices
+            // create b before a for nice vertex ids
+            let b_id = if self.vertex_lookup.contains_key (&b) { self.vertex_lookup[&b] } else { self.add_vertex (b, None)? };
+            let a_id = if self.vertex_lookup.contains_key (&a) { self.vertex_lookup[&a] } else { self.add_vertex (a, None)? };
+            self.graph.add_edge_raw (a_id, b_id, weight)?;
+            self.insert_edge_attrs ((a_id, b_id), edge_attrs);
+            Ok ((a_id, b_id))
+        }
+    }
+
+    pub fn add_edge_raw (&mut self, a_id: usize, a: L, b_id: usize, b: L, attrs: Option<E>, weight: W)
+        -> Result<(usize, usize), crate::error::GraphError>
+    {
+        if a == b && !self.graph.self_loops
+        {
+            Err (crate::error::GraphError::EdgeError (String::from ("edge vertices must be distinct")))
+        }
+        else if a != b && a_id == b_id
+        {
+            Err (crate::error::GraphError::EdgeError (String::from ("edge vertex ids must be distinct")))
+        }
+        else if a == b && a_id != b_id
+        {
+            Err (crate::error::GraphError::EdgeError (String::from ("self loop vertex ids must be equal")))
+        }
+        else
+        {
+            weight.check ()?;
+            let edge_attrs = self.conform_edge (&a, &b, attrs)?;
+            // Keep the attributes of vertices that already exist
+            if self.vertex_label.get (&a_id) != Some (&a)
             {
-                if inbound_b.contains (a)
-                {
-                    inbound_b.remove (a);
-                }
-                else
-                {
-                    errors.push (format! ("remove_edge_raw: {} had no inbound edge from {}", b, a));
-                }
-                if inbound_b.is_empty ()
-                {
-                    self.inbound.remove (b);
-                }
+                self.add_vertex_raw (a_id, a, None)?;
             }
-            else
+            if self.vertex_label.get (&b_id) != Some (&b)
             {
-                errors.push (format! ("remove_edge_raw: {} has no inbound edges", b));
+                self.add_vertex_raw (b_id, b, None)?;
             }
+            self.graph.add_edge_raw (a_id, b_id, weight)?;
+            self.insert_edge_attrs ((a_id, b_id), edge_attrs);
+            Ok ((a_id, b_id))
+        }
+    }
 
-            if let Some (outbound_a) = self.outbound.get_mut (a)
+    pub fn add_vertex (&mut self, a: L, attrs: Option<V>)
+        -> Result<usize, crate::error::GraphError>
+    {
+        let vertex_attrs = self.conform_vertex (&a, attrs)?;
+        let vertex_exists = self.vertex_lookup.contains_key (&a);
+        let a_id = *self.vertex_lookup.entry (a.clone ()).or_insert_with (|| self.vertex_id.fetch_add (1, sync::atomic::Ordering::Relaxed));
+
+        if ! vertex_exists
+        {
+            self.graph.add_vertex_raw (a_id)?;
+            self.vertex_label.insert (a_id, a.clone ());
+        }
+        self.insert_vertex_attrs (a_id, vertex_attrs);
+        Ok (a_id)
+    }
+
+    pub fn add_vertex_raw (&mut self, a_id: usize, a: L, attrs: Option<V>)
+        -> Result<usize, crate::error::GraphError>
+    {
+        let vertex_attrs = self.conform_vertex (&a, attrs)?;
+        // If the vertices already exist, check the the ids are consistent
+        if let Some (a_id_e) = self.vertex_lookup.get (&a)
+        {
+            if *a_id_e == a_id
             {
-                if outbound_a.contains (b)
+                self.insert_vertex_attrs (a_id, vertex_attrs);
+
+                Ok (a_id)
+            }
+            else
+            {
+                Err (crate::error::GraphError::VertexError (format! ("vertex already exists but has a different id {} != {}", a_id, a_id_e)))
+            }
+        }
+        else
+        {
+                if let Some (a_e) = self.vertex_label.get (&a_id)
                 {
-                    outbound_a.remove (b);
+                    if *a_e == a
+                    {
+                        unreachable! ("Recovered vertex label for source id but it should have been found in the vertex lookup");
+                    }
+                    else
+                    {
+                        Err (crate::error::GraphError::VertexError (format! ("vertex id {} already exists but has a different label", a_id)))
+                    }
                 }
                 else
                 {
-                    errors.push (format! ("remove_edge_raw: {} had no outbound edge to {}", a, b));
+                    self.vertex_id.fetch_max (a_id + 1, sync::atomic::Ordering::Relaxed);
+                    self.vertex_lookup.insert (a.clone (), a_id);
+                    self.graph.add_vertex_raw (a_id)?;
+                    self.vertex_label.insert (a_id, a.clone ());
+                    self.insert_vertex_attrs (a_id, vertex_attrs);
+
+                    Ok (a_id)
                 }
-                if outbound_a.is_empty ()
+        }
+    }
+
+    pub fn edge (&self, (a, b): &(L, L))
+        -> Result<(usize, usize), crate::error::GraphError>
+    {
+        match ( self.vertex_lookup.get (a), self.vertex_lookup.get (b) )
+        {
+            (Some (a_id), Some (b_id)) => {
+                if self.edge_attrs.contains_key ( &(*a_id, *b_id) )
                 {
-                    self.outbound.remove (a);
+                    Ok ( (*a_id, *b_id) )
                 }
-            }
-            else
-            {
-                errors.push (format! ("remove_edge_raw: {} has no outbound edges", a));
-            }
+                else
+                {
+                    Err (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{:?},{}:{:?})", *a_id, a, *b_id, b)))
+                }
+            },
+            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{:?}. Failed to find vertex b: {:?}", a_id, a, b))),
+            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {:?}. Found vertex b: {}:{:?}", a, b_id, b))),
+            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {:?} {:?}", a, b)))
+        }
+    }
 
-            if errors.is_empty ()
+    pub fn graph (&self)
+        -> &Graph<W>
+    {
+        &self.graph
+    }
+
+    pub fn schema (&self)
+        -> Option<&crate::schema::GraphSchema>
+    {
+        self.schema.as_ref ()
+    }
+
+    // Rejects a schema the current payloads violate, otherwise fills in its
+    // defaults and enforces it from then on. None removes the schema
+    pub fn set_schema (&mut self, schema: Option<crate::schema::GraphSchema>)
+        -> Result<(), crate::error::GraphError>
+    {
+        if let Some (schema) = &schema
+        {
+            let errors = crate::schema::check_payloads (schema, &self.vertex_label, &self.vertex_attrs, &self.edge_attrs);
+            if !errors.is_empty ()
             {
-                Ok (())
+                return Err (crate::error::GraphError::ValidationError (errors));
             }
-            else
+            self.vertex_attrs.values_mut ().filter_map (|v| v.attributes_mut ()).for_each (|attrs| schema.vertex.apply_defaults (attrs));
+            self.edge_attrs.values_mut ().filter_map (|e| e.attributes_mut ()).for_each (|attrs| schema.edge.apply_defaults (attrs));
+            self.vertex_index.rebuild (self.vertex_attrs.iter ());
+            self.edge_index.rebuild (self.edge_attrs.iter ());
+        }
+        self.schema = schema;
+        Ok (())
+    }
+
+    // Reports every payload that violates the schema, not just the first
+    pub fn check_schema (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        let errors = match &self.schema
+        {
+            Some (schema) => crate::schema::check_payloads (schema, &self.vertex_label, &self.vertex_attrs, &self.edge_attrs),
+            None => Vec::new ()
+        };
+        if errors.is_empty ()
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::ValidationError (errors))
+        }
+    }
+
+    fn conform_vertex (&self, a: &L, attrs: Option<V>)
+        -> Result<V, crate::error::GraphError>
+    {
+        let mut vertex_attrs = attrs.unwrap_or_default ();
+        if let Some (schema) = &self.schema
+        {
+            schema.vertex.conform (&mut vertex_attrs, &format! ("vertex {:?}", a))?;
+        }
+        Ok (vertex_attrs)
+    }
+
+    fn conform_edge (&self, a: &L, b: &L, attrs: Option<E>)
+        -> Result<E, crate::error::GraphError>
+    {
+        let mut edge_attrs = attrs.unwrap_or_default ();
+        if let Some (schema) = &self.schema
+        {
+            schema.edge.conform (&mut edge_attrs, &format! ("edge ({:?},{:?})", a, b))?;
+        }
+        Ok (edge_attrs)
+    }
+
+    pub fn create_vertex_index (&mut self, name: &str)
+    {
+        self.vertex_index.create (name, self.vertex_attrs.iter ());
+    }
+
+    pub fn drop_vertex_index (&mut self, name: &str)
+        -> bool
+    {
+        self.vertex_index.remove_index (name)
+    }
+
+    pub fn create_edge_index (&mut self, name: &str)
+    {
+        self.edge_index.create (name, self.edge_attrs.iter ());
+    }
+
+    pub fn drop_edge_index (&mut self, name: &str)
+        -> bool
+    {
+        self.edge_index.remove_index (name)
+    }
+
+    // Vertices whose attribute name equals value, from the index on name
+    pub fn vertices_where (&self, name: &str, value: &AttributeValue)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        self.vertex_index.get (name, value)
+    }
+
+    // Vertices whose StringSet attribute name contains member
+    pub fn vertices_with_member (&self, name: &str, member: &str)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        self.vertex_index.get_member (name, member)
+    }
+
+    pub fn edges_where (&self, name: &str, value: &AttributeValue)
+        -> Result<collections::HashSet<(usize,usize)>, crate::error::GraphError>
+    {
+        self.edge_index.get (name, value)
+    }
+
+    pub fn edges_with_member (&self, name: &str, member: &str)
+        -> Result<collections::HashSet<(usize,usize)>, crate::error::GraphError>
+    {
+        self.edge_index.get_member (name, member)
+    }
+
+    fn insert_vertex_attrs (&mut self, a_id: usize, vertex_attrs: V)
+    {
+        if let Some (vertex_attrs_prev) = self.vertex_attrs.insert (a_id, vertex_attrs)
+        {
+            self.vertex_index.remove (&a_id, &vertex_attrs_prev);
+        }
+        self.vertex_index.insert (a_id, &self.vertex_attrs[&a_id]);
+    }
+
+    fn remove_vertex_attrs (&mut self, a_id: &usize)
+    {
+        if let Some (vertex_attrs) = self.vertex_attrs.remove (a_id)
+        {
+            self.vertex_index.remove (a_id, &vertex_attrs);
+        }
+    }
+
+    fn insert_edge_attrs (&mut self, e: (usize, usize), edge_attrs: E)
+    {
+        if let Some (edge_attrs_prev) = self.edge_attrs.insert (e, edge_attrs)
+        {
+            self.edge_index.remove (&e, &edge_attrs_prev);
+        }
+        self.edge_index.insert (e, &self.edge_attrs[&e]);
+    }
+
+    fn remove_edge_attrs (&mut self, e: &(usize, usize))
+    {
+        if let Some (edge_attrs) = self.edge_attrs.remove (e)
+        {
+            self.edge_index.remove (e, &edge_attrs);
+        }
+    }
+
+    pub fn self_loops (&self)
+        -> bool
+    {
+        self.graph.self_loops ()
+    }
+
+    // The policy lives on the inner graph, which new creates with self loops
+    // off, so labelled graphs only take them once allowed
+    pub fn set_self_loops (&mut self, allow: bool)
+        -> Result<(), crate::error::GraphError>
+    {
+        self.graph.set_self_loops (allow)
+    }
+
+    pub fn vertices (&self)
+        -> &collections::HashSet <usize>
+    {
+        self.graph.vertices ()
+    }
+
+    pub fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        if let Some (a_id) = self.vertex_lookup.get (a)
+        {
+            Ok (*a_id)
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
+        }
+    }
+
+    pub fn vertex_attrs<Q> (&self, a: &Q)
+        -> Result<(usize, &V), crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        if let Some (a_id) = self.vertex_lookup.get (a)
+        {
+            match self.vertex_attrs.get (a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{:?}", a_id, a)))
             {
-                Err (crate::error::GraphError::EdgeError (errors.join ("\n\t")))
+                Ok (vertex_attrs) => {
+                    Ok ( (*a_id, vertex_attrs ) )
+                },
+                Err (e) => Err (e)
             }
         }
         else
         {
-            Err (crate::error::GraphError::VertexError (format! ("remove_edge_raw: Failed to find all vertices {}:{} {}:{}", a, self.vertices.contains (a), b, self.vertices.contains (b))))
+            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
         }
     }
 
-    pub fn remove_vertex_raw (&mut self, a: &usize)
-        -> Result<(), crate::error::GraphError>
+    pub fn vertex_attrs_mut<Q> (&mut self, a: &Q)
+        -> Result<(usize, AttrsMut<'_, V>), crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
-        for v in self.outbound (a)?
+        if let Some (a_id) = self.vertex_lookup.get (a)
         {
-            //debug! ("rvr a -> v: {} -> {}", a, v);
-            self.remove_edge_raw (a, &v)?;
+            match self.vertex_attrs.get_mut (a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{:?}", a_id, a)))
+            {
+                Ok (vertex_attrs) => {
+                    Ok ( (*a_id, AttrsMut::new (vertex_attrs, self.schema.as_ref ().map (|s| &s.vertex), (!self.vertex_index.is_empty ()).then_some ((&mut self.vertex_index, *a_id)), || format! ("vertex {:?}", a))) )
+                },
+                Err (e) => Err (e)
+            }
         }
-        for v in self.inbound (a)?
+        else
         {
-            //debug! ("rvr v -> a: {} -> {}", v, a);
-            self.remove_edge_raw (&v, a)?;
+            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
         }
-        self.vertices.remove (a);
-        self.inbound.remove (a);
-        self.outbound.remove (a);
-        Ok (())
     }
 
-    pub fn rename (&mut self, name: String)
+    pub fn vertex_attrs_raw (&self, a_id: &usize)
+        -> Result<&V, crate::error::GraphError>
     {
-        self.name = name;
+        self.vertex_attrs.get (a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
     }
 
-    pub fn sinks (&self)
-        -> collections::HashSet <usize>
+    pub fn vertex_attrs_raw_mut (&mut self, a_id: &usize)
+        -> Result<AttrsMut<'_, V>, crate::error::GraphError>
     {
-        self.vertices.iter ().filter (|x| !self.outbound.contains_key (x)).copied ().collect::<collections::HashSet::<usize>> ()
+        self.vertex_attrs.get_mut (a_id)
+            .map (|vertex_attrs| AttrsMut::new (vertex_attrs, self.schema.as_ref ().map (|s| &s.vertex), (!self.vertex_index.is_empty ()).then_some ((&mut self.vertex_index, *a_id)), || format! ("vertex {}", a_id)))
+            .ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
     }
 
-    pub fn remap (&mut self, map: &collections::HashMap<usize, usize>)
-        -> Result<(), crate::error::GraphError>
+    pub fn vertex_label (&self, a_id: &usize)
+        -> Result<L, crate::error::GraphError>
     {
-        let ks = map.keys ().copied ().collect::<collections::HashSet<_>> ();
-        if ks.is_subset (&self.vertices)
+        self.vertex_label.get (a_id).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", a_id)))
+    }
+
+    pub fn vertex_labels (&self)
+        -> collections::HashSet<L>
+    {
+        self.vertex_lookup.keys ().cloned ().collect ()
+    }
+
+    pub fn edges (&self)
+        -> &collections::HashMap <(usize,usize), W>
+    {
+        self.graph.edges ()
+    }
+
+    pub fn edge_attrs (&self, (a, b): &(L, L))
+        -> Result<( (usize, usize), &E), crate::error::GraphError>
+    {
+        match ( self.vertex_lookup.get (a), self.vertex_lookup.get (b) )
         {
-            let vs = map.values ().copied ().collect::<collections::HashSet<_>> ();
-            if ks.len () == vs.len ()
-            {
-                let rvs = vs.intersection (&self.vertices).copied ().collect::<collections::HashSet<_>> ();
-                if rvs.is_subset (&ks)
-                {
-                    let edges_new = self.edges.drain ()
-                        .map (|(k,v)| {
-                            (
-                                (
-                                    map.get (&k.0).copied ().unwrap_or (k.0),
-                                    map.get (&k.1).copied ().unwrap_or (k.1),
-                                )
-                                , v
-                            )
-                        }).collect::<Vec<_>> ();
-                    self.edges.extend (edges_new);
-                    let inbound_new = self.inbound.drain ()
-                        .map (|(k,v)| {
-                            (
-                                map.get (&k).copied ().unwrap_or (k),
-                                v.iter ().map (|x| map.get (x).copied ().unwrap_or (*x) ).collect::<collections::HashSet::<_>> ()
-                            )
-                        })
-                        .collect::<Vec<_>> ();
-                    self.inbound.extend (inbound_new);
-                    let outbound_new = self.outbound.drain ()
-                        .map (|(k,v)| {
-                            (
-                                map.get (&k).copied ().unwrap_or (k),
-                                v.iter ().map (|x| map.get (x).copied ().unwrap_or (*x) ).collect::<collections::HashSet::<_>> ()
-                            )
-                        })
-                        .collect::<Vec<_>> ();
-                    self.outbound.extend (outbound_new);
-                    let vertices_new = self.vertices.drain ()
-                        .map (|x| map.get (&x).copied ().unwrap_or (x))
-                        .collect::<Vec<_>> ();
-                    self.vertices.extend (vertices_new);
-                    Ok (())
-                }
-                else
+            (Some (a_id), Some (b_id)) => {
+                match self.edge_attrs.get ( &(*a_id, *b_id) ).ok_or (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{:?},{}:{:?})", *a_id, a, *b_id, b)))
                 {
-                    Err (crate::error::GraphError::DataError (String::from ("Both directions are required when swapping existing vertices")))
+                    Ok (edge_attrs) => {
+                        Ok ( ( (*a_id, *b_id), edge_attrs ) )
+                    },
+                    Err (e) => Err (e)
                 }
-            }
-            else
-            {
-                Err (crate::error::GraphError::DataError (String::from ("Every key must have a unique value")))
-            }
+            },
+            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{:?}. Failed to find vertex b: {:?}", a_id, a, b))),
+            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {:?}. Found vertex b: {}:{:?}", a, b_id, b))),
+            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {:?} {:?}", a, b)))
+        }
+    }
+
+    pub fn edge_attrs_raw (&self, ev @ (a_id, b_id): &(usize, usize))
+        -> Result<&E, crate::error::GraphError>
+    {
+        if let Some (ea) = self.edge_attrs.get (ev)
+        {
+            Ok (ea)
         }
         else
         {
-            Err (crate::error::GraphError::DataError (String::from ("Remap keys must be a subset of graph vertices")))
+            match ( self.vertex_label.get (a_id), self.vertex_label.get (b_id) )
+            {
+                (Some (a), Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("Found both vertices {}:{:?} {}:{:?} but no edge", a_id, a, b_id, b))),
+                (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{:?}. Failed to find vertex b: {}", a_id, a, b_id))),
+                (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found vertex b: {}:{:?}", a_id, b_id, b))),
+                _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a_id, b_id)))
+            }
         }
     }
 
-    pub fn retain (&mut self, vertices_retain: &collections::HashSet<usize>)
-        -> Result<(), crate::error::GraphError>
+    pub fn edge_attrs_mut (&mut self, (a, b): &(L, L))
+        -> Result<( (usize, usize), EdgeAttrsMut<'_, E>), crate::error::GraphError>
     {
-        for vd in &self.vertices - vertices_retain
+        match ( self.vertex_lookup.get (a), self.vertex_lookup.get (b) )
         {
-            self.remove_vertex_raw (&vd)?;
+            (Some (a_id), Some (b_id)) => {
+                match self.edge_attrs.get_mut ( &(*a_id, *b_id) ).ok_or (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{:?},{}:{:?})", *a_id, a, *b_id, b)))
+                {
+                    Ok (edge_attrs) => {
+                        Ok ( ( (*a_id, *b_id), AttrsMut::new (edge_attrs, self.schema.as_ref ().map (|s| &s.edge), (!self.edge_index.is_empty ()).then_some ((&mut self.edge_index, (*a_id, *b_id))), || format! ("edge ({:?},{:?})", a, b)) ) )
+                    },
+                    Err (e) => Err (e)
+                }
+            },
+            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{:?}. Failed to find vertex b: {:?}", a_id, a, b))),
+            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {:?}. Found vertex b: {}:{:?}", a, b_id, b))),
+            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {:?} {:?}", a, b)))
         }
-        Ok (())
     }
 
-    pub fn retain_edges (&mut self, edges_retain: &collections::HashSet<(usize,usize)>)
-        -> Result<(), crate::error::GraphError>
+    pub fn edge_label (&self, (a_id, b_id): &(usize, usize))
+        -> Result<(L, L), crate::error::GraphError>
     {
-        for e in &self.edges.keys ().cloned ().collect::<collections::HashSet<_>> () - edges_retain
+        match ( self.vertex_label.get (&a_id), self.vertex_label.get (&b_id) )
         {
-            self.remove_edge_raw (&e.0, &e.1)?;
+            (Some (a), Some (b)) => {
+                if self.graph.has_edge_raw ( &(*a_id, *b_id) )
+                {
+                    Ok ( (a.clone (), b.clone ()) )
+                }
+                else
+                {
+                    Err (crate::error::GraphError::EdgeError (format! ("No edge found between {} and {}", a_id, b_id)))
+                }
+            },
+            (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {:?}. Failed to find vertex b: {}", a_id, a, b_id))),
+            (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {:?}", a_id, b_id, b))),
+            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a_id, b_id)))
         }
-        Ok (())
     }
 
-    pub fn vertices (&self)
-        -> &collections::HashSet <usize>
+    pub fn edge_labels (&self)
+        -> Result<collections::HashSet<(L, L)>, crate::error::GraphError>
     {
-        &self.vertices
+        self.graph ().edges ().keys ().map (|x| {
+            match ( self.vertex_label.get (&x.0), self.vertex_label.get (&x.1) )
+            {
+                (Some (a), Some (b)) => Ok ( (a.clone (), b.clone ()) ),
+                (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {:?}. Failed to find vertex b: {}", x.0, a, x.1))),
+                (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {:?}", x.0, x.1, b))),
+                _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", x.0, x.1)))
+            }
+        }).collect::<Result<collections::HashSet<_>, crate::error::GraphError>> ()
     }
-}
 
-impl UGraph
-{
-    pub fn new () -> Self
+    pub fn has_edge (&self, (a, b): &(L, L))
+        -> bool
     {
-        Self { name: String::from (""), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), i64>::new (), connected: collections::HashMap::<usize, collections::HashSet<usize>>::new () }
+        self.vertex_lookup.contains_key (a) && self.vertex_lookup.contains_key (b) && self.graph.edges.contains_key ( &(self.vertex_lookup[a], self.vertex_lookup[b]) )
     }
 
-    pub fn new_with_name (name: &str) -> Self
+    pub fn has_vertex<Q> (&self, a: &Q)
+        -> bool
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
-        Self { name: name.to_string (), vertices: collections::HashSet::<usize>::new (), edges: collections::HashMap::<(usize,usize), i64>::new (), connected: collections::HashMap::<usize, collections::HashSet<usize>>::new () }
+        self.vertex_lookup.contains_key (a)
     }
 
-    pub fn add_edge_raw (&mut self, a: usize, b: usize, weight: i64)
+    pub fn relabel_vertex (&mut self, a_id: &usize, vertex_label_next: L)
         -> Result<(), crate::error::GraphError>
     {
-        self.vertices.insert (a);
-        self.vertices.insert (b);
-        if a < b
+        let vertex_label_prev = self.vertex_label.get (a_id).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", a_id)))?;
+
+        if let Some (_) = self.vertex_lookup.get (&vertex_label_next)
         {
-            self.edges.insert ( (a, b), weight);
+            Err (crate::error::GraphError::VertexError (format! ("vertex {:?} already in graph, duplicate nodes are forbidden", vertex_label_next)))
         }
         else
         {
-            self.edges.insert ( (b, a), weight);
+            self.vertex_lookup.remove (&vertex_label_prev);
+            self.vertex_lookup.insert (vertex_label_next.clone (), *a_id);
+            self.vertex_label.insert (*a_id, vertex_label_next);
+            Ok (())
         }
-        self.connected.entry ( b ).or_insert (collections::HashSet::<usize>::new ()).insert (a);
-        self.connected.entry ( a ).or_insert (collections::HashSet::<usize>::new ()).insert (b);
-        Ok (())
     }
 
-    pub fn add_vertex_raw (&mut self, a: usize)
+    pub fn remap_raw (&mut self, map: &collections::HashMap<usize, usize>)
         -> Result<(), crate::error::GraphError>
     {
-        self.vertices.insert (a);
+        self.graph.remap (map)?;
+        let edge_attrs_new = self.edge_attrs.drain ()
+            .map (|(k,v)| {
+                (
+                    (
+                        map.get (&k.0).copied ().unwrap_or (k.0),
+                        map.get (&k.1).copied ().unwrap_or (k.1),
+                    ),
+                    v
+                )
+            })
+            .collect::<Vec<_>> ();
+        self.edge_attrs.extend (edge_attrs_new);
+        let vertex_attrs_new = self.vertex_attrs.drain ()
+            .map (|(k,v)| {
+                (
+                    map.get (&k).copied ().unwrap_or (k),
+                    v
+                )
+            })
+            .collect::<Vec<_>> ();
+        self.vertex_attrs.extend (vertex_attrs_new);
+        let vertex_label_new = self.vertex_label.drain ()
+            .map (|(k,v)| {
+                (
+                    map.get (&k).copied ().unwrap_or (k),
+                    v
+                )
+            })
+            .collect::<Vec<_>> ();
+        self.vertex_label.extend (vertex_label_new);
+        for val in self.vertex_lookup.values_mut ()
+        {
+            *val = map.get (val).copied ().unwrap_or (*val);
+        }
+        self.vertex_index.rebuild (self.vertex_attrs.iter ());
+        self.edge_index.rebuild (self.edge_attrs.iter ());
         Ok (())
     }
 
-    pub fn has_edge_raw (&self, t: &(usize, usize))
-        -> bool
+    pub fn remove_edge (&mut self, (a, b): &(L, L))
+        -> Result<(), crate::error::GraphError>
     {
-        if t.0 < t.1
-        {
-            self.edges.contains_key ( t )
-        }
-        else
+        match ( self.has_vertex (&a), self.has_vertex (&b) )
         {
-            self.edges.contains_key ( &(t.1, t.0) )
+            (true, true) => {
+                let er = ( self.vertex (&a)?, self.vertex (&b)? );
+                self.remove_edge_attrs (&er);
+                self.graph.remove_edge_raw (&er.0, &er.1)?;
+                Ok (())
+            },
+            (true, false) => Err (crate::error::GraphError::EdgeError (format! ("Found {:?}. Failed to find vertex b: {:?}", a, b))),
+            (false, true) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {:?}. Found {:?}", a, b))),
+            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {:?} {:?}", a, b)))
         }
     }
 
-    pub fn neighbours (&self, a: &usize)
-        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    pub fn remove_vertex<Q> (&mut self, a: &Q)
+        -> Result<(), crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
-        if self.vertices.contains (a)
+        let a_id = self.vertex (a)?;
+        if self.graph.neighbours (&a_id)?.is_empty ()
         {
-            let mut r = collections::HashSet::<usize>::new ();
-            if let Some (children) = self.connected.get (a)
-            {
-                r.extend (children);
-            }
-            Ok (r)
+            self.vertex_lookup.remove (a);
+            self.vertex_label.remove (&a_id);
+            self.remove_vertex_attrs (&a_id);
+            self.graph.remove_vertex_raw (&a_id)?;
+            Ok (())
         }
         else
         {
-            Err (crate::error::GraphError::VertexError (format! ("Vertex: {} not found in graph", a)))
+            Err (crate::error::GraphError::VertexError (format! ("Cannot delete vertex {:?} with edges", a)))
         }
     }
 
-    pub fn edges (&self)
-        -> &collections::HashMap <(usize,usize), i64>
-    {
-        &self.edges
-    }
-
-    pub fn name (&self)
-        -> String
+    pub fn rename (&mut self, name: String)
     {
-        self.name.clone ()
+        self.graph.rename (name);
     }
 
-    pub fn remove_edge_raw (&mut self, a: &usize, b: &usize)
+    pub fn retain (&mut self, vertices_retain: &collections::HashSet<usize>)
         -> Result<(), crate::error::GraphError>
     {
-        //debug! ("remove_edge_raw: self: {:?}", self);
-        //debug! ("remove_edge_raw: a: {} b: {}", a, b);
-        if self.vertices.contains (a) && self.vertices.contains (b)
+        for vd in self.graph.vertices () - vertices_retain
         {
-            let mut errors = Vec::<String>::new ();
-            if a < b
-            {
-                if self.edges.contains_key ( &(*a ,*b) )
-                {
-                    self.edges.remove ( &(*a ,*b) );
-                }
-                else
-                {
-                    errors.push (format! ("remove_edge_raw: Could not find edge {} -> {}", a, b));
-                }
-            }
-            else
-            {
-                if self.edges.contains_key ( &(*b ,*a) )
-                {
-                    self.edges.remove ( &(*b ,*a) );
-                }
-                else
-                {
-                    errors.push (format! ("remove_edge_raw: Could not find edge {} -> {}", b, a));
-                }
-            }
-
-            if let Some (connected_b) = self.connected.get_mut (b)
-            {
-                if connected_b.contains (a)
-                {
-                    connected_b.remove (a);
-                }
-                else
-                {
-                    errors.push (format! ("remove_edge_raw: {} had no edge from {}", b, a));
-                }
-            }
-            else
-            {
-                errors.push (format! ("remove_edge_raw: {} has no edges", b));
-            }
-
-            if let Some (connected_a) = self.connected.get_mut (a)
-            {
-                if connected_a.contains (b)
-                {
-                    connected_a.remove (b);
-                }
-                else
-                {
-                    errors.push (format! ("remove_edge_raw: {} had no edge to {}", a, b));
-                }
-            }
-            else
-            {
-                errors.push (format! ("remove_edge_raw: {} has no edges", a));
-            }
+            let vdl = self.vertex_label.get (&vd).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", vd)))?;
+            self.vertex_lookup.remove (&vdl);
+            self.vertex_label.remove (&vd);
+            self.remove_vertex_attrs (&vd);
 
-            if errors.is_empty ()
+            for vdi in self.graph.inbound (&vd)?
             {
-                Ok (())
+                self.remove_edge_attrs ( &(vdi, vd) );
             }
-            else
+            for vdo in self.graph.outbound (&vd)?
             {
-                Err (crate::error::GraphError::EdgeError (errors.join ("\n\t")))
+                self.remove_edge_attrs ( &(vd, vdo) );
             }
         }
-        else
+        self.graph.retain (vertices_retain)
+    }
+
+    pub fn retain_edges (&mut self, edges_retain: &collections::HashSet<(usize,usize)>)
+        -> Result<(), crate::error::GraphError>
+    {
+        for e in &self.graph.edges.keys ().cloned ().collect::<collections::HashSet<_>> () - &edges_retain
         {
-            Err (crate::error::GraphError::VertexError (format! ("remove_edge_raw: Failed to find all vertices {}:{} {}:{}", a, self.vertices.contains (a), b, self.vertices.contains (b))))
+            self.remove_edge_attrs (&e);
         }
-    }
 
-    pub fn remove_vertex_raw (&mut self, a: &usize)
+        self.graph.retain_edges (edges_retain)
+    }
+    pub fn validate (&self)
         -> Result<(), crate::error::GraphError>
     {
-        let vertices = self.connected.get (a).ok_or (crate::error::GraphError::VertexError (format! ("Failed to find vertex {}", a)))?.clone ();
-        for v in vertices
+        let mut errors = match self.graph.validate ()
         {
-            //debug! ("rvr a -> v: {} -> {}", a, v);
-            self.remove_edge_raw (a, &v)?;
+            Err (crate::error::GraphError::ValidationError (errors)) => errors,
+            Err (e) => vec![e],
+            Ok (()) => Vec::new ()
+        };
+        errors.extend (validate_labels (self.graph.vertices (), self.graph.edges (), &self.vertex_id, &self.vertex_label, &self.vertex_lookup, &self.vertex_attrs, &self.edge_attrs));
+        if let Some (schema) = &self.schema
+        {
+            errors.extend (crate::schema::check_payloads (schema, &self.vertex_label, &self.vertex_attrs, &self.edge_attrs));
+        }
+        if errors.is_empty ()
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::ValidationError (errors))
         }
-        self.vertices.remove (a);
-        self.connected.remove (a);
-        Ok (())
     }
+}
 
-    pub fn vertices (&self)
-        -> &collections::HashSet <usize>
+impl<W, V, E, L> PartialEq for LabelledGraph<W, V, E, L>
+    where
+        W: Weight
     {
-        &self.vertices
+    fn eq(&self, other: &Self) -> bool {
+        // TODO compare vertex labels
+        self.graph == other.graph
     }
 }
 
-impl LabelledGraph
+impl LabelledUGraph
 {
     pub fn new () -> Self
+    {
+        Self::new_weighted ()
+    }
+
+    pub fn new_with_name (name: &str) -> Self
+    {
+        Self::new_weighted_with_name (name)
+    }
+}
+
+impl<W, V, E, L> LabelledUGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    pub fn new_weighted () -> Self
     {
         Self {
             vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (1)),
-            edge_attrs: collections::HashMap::<(usize,usize), collections::HashMap::<String, AttributeValue>>::new (),
-            graph: Graph::new (),
-            vertex_attrs: collections::HashMap::<usize, collections::HashMap::<String, AttributeValue>>::new (),
-            vertex_label: collections::HashMap::<usize, String>::new (),
-            vertex_lookup: collections::HashMap::<String, usize>::new ()
+            edge_attrs: collections::HashMap::<(usize,usize), E>::new (),
+            graph: UGraph { self_loops: false, ..UGraph::new_weighted () },
+            vertex_attrs: collections::HashMap::<usize, V>::new (),
+            vertex_label: collections::HashMap::<usize, L>::new (),
+            vertex_lookup: collections::HashMap::<L, usize>::new (),
+            schema: None,
+            vertex_index: crate::index::AttributeIndex::default (),
+            edge_index: crate::index::AttributeIndex::default ()
         }
     }
 
-    pub fn new_with_name (name: &str) -> Self
+    pub fn new_weighted_with_name (name: &str) -> Self
     {
         Self {
             vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (1)),
-            edge_attrs: collections::HashMap::<(usize,usize), collections::HashMap::<String, AttributeValue>>::new (),
-            graph: Graph::new_with_name (name),
-            vertex_attrs: collections::HashMap::<usize, collections::HashMap::<String, AttributeValue>>::new (),
-            vertex_label: collections::HashMap::<usize, String>::new (),
-            vertex_lookup: collections::HashMap::<String, usize>::new ()
+            edge_attrs: collections::HashMap::<(usize,usize), E>::new (),
+            graph: UGraph { self_loops: false, ..UGraph::new_weighted_with_name (name) },
+            vertex_attrs: collections::HashMap::<usize, V>::new (),
+            vertex_label: collections::HashMap::<usize, L>::new (),
+            vertex_lookup: collections::HashMap::<L, usize>::new (),
+            schema: None,
+            vertex_index: crate::index::AttributeIndex::default (),
+            edge_index: crate::index::AttributeIndex::default ()
         }
     }
 
-    pub fn new_without_attributes (g: &LabelledGraph)
+    pub fn new_without_attributes (g: &LabelledUGraph<W, V, E, L>)
         -> Self
     {
         Self {
             vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (g.vertices ().iter ().max ().cloned ().unwrap_or (1))),
-            edge_attrs: collections::HashMap::<(usize,usize), collections::HashMap::<String, AttributeValue>>::new (),
+            edge_attrs: collections::HashMap::<(usize,usize), E>::new (),
             graph: g.graph ().clone (),
-            vertex_attrs: g.graph ().vertices ().iter ().fold (collections::HashMap::<usize, collections::HashMap::<String, AttributeValue>>::new (), |mut acc, item| {
-                acc.insert (*item, collections::HashMap::<String, AttributeValue>::new ());
+            vertex_attrs: g.graph ().vertices ().iter ().fold (collections::HashMap::<usize, V>::new (), |mut acc, item| {
+                acc.insert (*item, V::default ());
                 acc
             }),
             vertex_label: g.vertex_label.clone (),
-            vertex_lookup: g.vertex_lookup.clone ()
+            vertex_lookup: g.vertex_lookup.clone (),
+            schema: None,
+            vertex_index: crate::index::AttributeIndex::default (),
+            edge_index: crate::index::AttributeIndex::default ()
         }
     }
 
-    pub fn add_edge (&mut self, a: String, b: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
+    pub fn add_edge (&mut self, a: L, b: L, attrs: Option<E>)
         -> Result<(usize, usize), crate::error::GraphError>
     {
-        self.add_edge_weighted (a, b, attrs, 0)
+        self.add_edge_weighted (a, b, attrs, W::zero ())
     }
 
-    pub fn add_edge_weighted (&mut self, a: String, b: String, attrs: Option<collections::HashMap::<String, AttributeValue>>, weight: i64)
+    pub fn add_edge_weighted (&mut self, a: L, b: L, attrs: Option<E>, weight: W)
         -> Result<(usize, usize), crate::error::GraphError>
     {
         //debug! ("add {} to {}", a , b);
-        if a == b
+        if a == b && !self.graph.self_loops
         {
             Err (crate::error::GraphError::EdgeError (String::from ("edge vertices must be distinct")))
         }
         else
         {
+            weight.check ()?;
+            let edge_attrs = self.conform_edge (&a, &b, attrs)?;
             // Check that we didn't already add the vertices
-            // create b before a for nice vertex ids
-            let b_id = if self.vertex_lookup.contains_key (&b) { self.vertex_lookup[&b] } else { self.add_vertex (b, None)? };
             let a_id = if self.vertex_lookup.contains_key (&a) { self.vertex_lookup[&a] } else { self.add_vertex (a, None)? };
+            let b_id = if self.vertex_lookup.contains_key (&b) { self.vertex_lookup[&b] } else { self.add_vertex (b, None)? };
             self.graph.add_edge_raw (a_id, b_id, weight)?;
-            if let Some (edge_attrs) = attrs
-            {
-                self.edge_attrs.insert ((a_id, b_id), edge_attrs);
-            }
-            else
-            {
-                self.edge_attrs.insert ((a_id, b_id), collections::HashMap::<String, AttributeValue>::new ());
-            }
-            Ok ((a_id, b_id))
+            let t = if a_id < b_id { (a_id, b_id) } else { (b_id, a_id) };
+            self.insert_edge_attrs (t, edge_attrs);
+            Ok (t)
         }
     }
 
-    pub fn add_edge_raw (&mut self, a_id: usize, a: String, b_id: usize, b: String, attrs: Option<collections::HashMap::<String, AttributeValue>>, weight: i64)
+    pub fn add_edge_raw (&mut self, a_id: usize, a: L, b_id: usize, b: L, attrs: Option<E>, weight: W)
         -> Result<(usize, usize), crate::error::GraphError>
     {
-        if a == b
+        if a == b && !self.graph.self_loops
         {
             Err (crate::error::GraphError::EdgeError (String::from ("edge vertices must be distinct")))
         }
-        else if a_id == b_id
+        else if a != b && a_id == b_id
         {
             Err (crate::error::GraphError::EdgeError (String::from ("edge vertex ids must be distinct")))
         }
+        else if a == b && a_id != b_id
+        {
+            Err (crate::error::GraphError::EdgeError (String::from ("self loop vertex ids must be equal")))
+        }
         else
         {
-            self.add_vertex_raw (a_id, a, None)?;
-            self.add_vertex_raw (b_id, b, None)?;
-            self.graph.add_edge_raw (a_id, b_id, weight)?;
-            if let Some (edge_attrs) = attrs
+            weight.check ()?;
+            let edge_attrs = self.conform_edge (&a, &b, attrs)?;
+            // Keep the attributes of vertices that already exist
+            if self.vertex_label.get (&a_id) != Some (&a)
             {
-                self.edge_attrs.insert ((a_id, b_id), edge_attrs);
+                self.add_vertex_raw (a_id, a, None)?;
             }
-            else
+            if self.vertex_label.get (&b_id) != Some (&b)
             {
-                self.edge_attrs.insert ((a_id, b_id), collections::HashMap::<String, AttributeValue>::new ());
+                self.add_vertex_raw (b_id, b, None)?;
             }
-            Ok ((a_id, b_id))
+            self.graph.add_edge_raw (a_id, b_id, weight)?;
+            let t = if a_id < b_id { (a_id, b_id) } else { (b_id, a_id) };
+            self.insert_edge_attrs (t, edge_attrs);
+            Ok (t)
         }
     }
 
-    pub fn add_vertex (&mut self, a: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
+    pub fn add_vertex (&mut self, a: L, attrs: Option<V>)
         -> Result<usize, crate::error::GraphError>
     {
+        let vertex_attrs = self.conform_vertex (&a, attrs)?;
         let vertex_exists = self.vertex_lookup.contains_key (&a);
         let a_id = *self.vertex_lookup.entry (a.clone ()).or_insert_with (|| self.vertex_id.fetch_add (1, sync::atomic::Ordering::Relaxed));
 
@@ -1035,99 +2900,211 @@ impl LabelledGraph
             self.graph.add_vertex_raw (a_id)?;
             self.vertex_label.insert (a_id, a.clone ());
         }
-        if let Some (vertex_attrs) = attrs
-        {
-            self.vertex_attrs.insert (a_id, vertex_attrs);
-        }
-        else
-        {
-            self.vertex_attrs.insert (a_id, collections::HashMap::<String, AttributeValue>::new ());
-        }
+        self.insert_vertex_attrs (a_id, vertex_attrs);
         Ok (a_id)
     }
 
-    pub fn add_vertex_raw (&mut self, a_id: usize, a: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
+    pub fn add_vertex_raw (&mut self, a_id: usize, a: L, attrs: Option<V>)
         -> Result<usize, crate::error::GraphError>
     {
+        let vertex_attrs = self.conform_vertex (&a, attrs)?;
         // If the vertices already exist, check the the ids are consistent
         if let Some (a_id_e) = self.vertex_lookup.get (&a)
         {
             if *a_id_e == a_id
             {
-                if let Some (vertex_attrs) = attrs
-                {
-                    self.vertex_attrs.insert (a_id, vertex_attrs);
-                }
-                else
-                {
-                    self.vertex_attrs.insert (a_id, collections::HashMap::<String, AttributeValue>::new ());
-                }
+                self.insert_vertex_attrs (a_id, vertex_attrs);
 
-                Ok (*a_id_e)
+                Ok (a_id)
             }
             else
             {
                 Err (crate::error::GraphError::VertexError (format! ("vertex already exists but has a different id {} != {}", a_id, a_id_e)))
             }
         }
+        else if self.vertex_label.contains_key (&a_id)
+        {
+            Err (crate::error::GraphError::VertexError (format! ("vertex id {} already exists but has a different label", a_id)))
+        }
         else
         {
-                if let Some (a_e) = self.vertex_label.get (&a_id)
-                {
-                    if *a_e == a
-                    {
-                        unreachable! ("Recovered vertex label for source id but it should have been found in the vertex lookup");
-                    }
-                    else
-                    {
-                        Err (crate::error::GraphError::VertexError (format! ("vertex id {} already exists but has a different label", a_id)))
-                    }
-                }
-                else
-                {
-                    self.vertex_id.fetch_max (a_id + 1, sync::atomic::Ordering::Relaxed);
-                    self.vertex_lookup.insert (a.clone (), a_id);
-                    self.graph.add_vertex_raw (a_id)?;
-                    self.vertex_label.insert (a_id, a.clone ());
-                    if let Some (vertex_attrs) = attrs
-                    {
-                        self.vertex_attrs.insert (a_id, vertex_attrs);
-                    }
-                    else
-                    {
-                        self.vertex_attrs.insert (a_id, collections::HashMap::<String, AttributeValue>::new ());
-                    }
+            self.vertex_id.fetch_max (a_id + 1, sync::atomic::Ordering::Relaxed);
+            self.vertex_lookup.insert (a.clone (), a_id);
+            self.graph.add_vertex_raw (a_id)?;
+            self.vertex_label.insert (a_id, a);
+            self.insert_vertex_attrs (a_id, vertex_attrs);
+            Ok (a_id)
+        }
+    }
 
-                    Ok (a_id)
-                }
+    pub fn graph (&self)
+        -> &UGraph<W>
+    {
+        &self.graph
+    }
+
+    pub fn schema (&self)
+        -> Option<&crate::schema::GraphSchema>
+    {
+        self.schema.as_ref ()
+    }
+
+    // Rejects a schema the current payloads violate, otherwise fills in its
+    // defaults and enforces it from then on. None removes the schema
+    pub fn set_schema (&mut self, schema: Option<crate::schema::GraphSchema>)
+        -> Result<(), crate::error::GraphError>
+    {
+        if let Some (schema) = &schema
+        {
+            let errors = crate::schema::check_payloads (schema, &self.vertex_label, &self.vertex_attrs, &self.edge_attrs);
+            if !errors.is_empty ()
+            {
+                return Err (crate::error::GraphError::ValidationError (errors));
+            }
+            self.vertex_attrs.values_mut ().filter_map (|v| v.attributes_mut ()).for_each (|attrs| schema.vertex.apply_defaults (attrs));
+            self.edge_attrs.values_mut ().filter_map (|e| e.attributes_mut ()).for_each (|attrs| schema.edge.apply_defaults (attrs));
+            self.vertex_index.rebuild (self.vertex_attrs.iter ());
+            self.edge_index.rebuild (self.edge_attrs.iter ());
+        }
+        self.schema = schema;
+        Ok (())
+    }
+
+    // Reports every payload that violates the schema, not just the first
+    pub fn check_schema (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        let errors = match &self.schema
+        {
+            Some (schema) => crate::schema::check_payloads (schema, &self.vertex_label, &self.vertex_attrs, &self.edge_attrs),
+            None => Vec::new ()
+        };
+        if errors.is_empty ()
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::ValidationError (errors))
+        }
+    }
+
+    fn conform_vertex (&self, a: &L, attrs: Option<V>)
+        -> Result<V, crate::error::GraphError>
+    {
+        let mut vertex_attrs = attrs.unwrap_or_default ();
+        if let Some (schema) = &self.schema
+        {
+            schema.vertex.conform (&mut vertex_attrs, &format! ("vertex {:?}", a))?;
+        }
+        Ok (vertex_attrs)
+    }
+
+    fn conform_edge (&self, a: &L, b: &L, attrs: Option<E>)
+        -> Result<E, crate::error::GraphError>
+    {
+        let mut edge_attrs = attrs.unwrap_or_default ();
+        if let Some (schema) = &self.schema
+        {
+            schema.edge.conform (&mut edge_attrs, &format! ("edge ({:?},{:?})", a, b))?;
+        }
+        Ok (edge_attrs)
+    }
+
+    pub fn create_vertex_index (&mut self, name: &str)
+    {
+        self.vertex_index.create (name, self.vertex_attrs.iter ());
+    }
+
+    pub fn drop_vertex_index (&mut self, name: &str)
+        -> bool
+    {
+        self.vertex_index.remove_index (name)
+    }
+
+    pub fn create_edge_index (&mut self, name: &str)
+    {
+        self.edge_index.create (name, self.edge_attrs.iter ());
+    }
+
+    pub fn drop_edge_index (&mut self, name: &str)
+        -> bool
+    {
+        self.edge_index.remove_index (name)
+    }
+
+    // Vertices whose attribute name equals value, from the index on name
+    pub fn vertices_where (&self, name: &str, value: &AttributeValue)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        self.vertex_index.get (name, value)
+    }
+
+    // Vertices whose StringSet attribute name contains member
+    pub fn vertices_with_member (&self, name: &str, member: &str)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        self.vertex_index.get_member (name, member)
+    }
+
+    pub fn edges_where (&self, name: &str, value: &AttributeValue)
+        -> Result<collections::HashSet<(usize,usize)>, crate::error::GraphError>
+    {
+        self.edge_index.get (name, value)
+    }
+
+    pub fn edges_with_member (&self, name: &str, member: &str)
+        -> Result<collections::HashSet<(usize,usize)>, crate::error::GraphError>
+    {
+        self.edge_index.get_member (name, member)
+    }
+
+    fn insert_vertex_attrs (&mut self, a_id: usize, vertex_attrs: V)
+    {
+        if let Some (vertex_attrs_prev) = self.vertex_attrs.insert (a_id, vertex_attrs)
+        {
+            self.vertex_index.remove (&a_id, &vertex_attrs_prev);
+        }
+        self.vertex_index.insert (a_id, &self.vertex_attrs[&a_id]);
+    }
+
+    fn remove_vertex_attrs (&mut self, a_id: &usize)
+    {
+        if let Some (vertex_attrs) = self.vertex_attrs.remove (a_id)
+        {
+            self.vertex_index.remove (a_id, &vertex_attrs);
+        }
+    }
+
+    fn insert_edge_attrs (&mut self, e: (usize, usize), edge_attrs: E)
+    {
+        if let Some (edge_attrs_prev) = self.edge_attrs.insert (e, edge_attrs)
+        {
+            self.edge_index.remove (&e, &edge_attrs_prev);
         }
+        self.edge_index.insert (e, &self.edge_attrs[&e]);
     }
 
-    pub fn edge (&self, (a, b): &(String, String))
-        -> Result<(usize, usize), crate::error::GraphError>
+    fn remove_edge_attrs (&mut self, e: &(usize, usize))
     {
-        match ( self.vertex_lookup.get (a), self.vertex_lookup.get (b) )
+        if let Some (edge_attrs) = self.edge_attrs.remove (e)
         {
-            (Some (a_id), Some (b_id)) => {
-                if self.edge_attrs.contains_key ( &(*a_id, *b_id) )
-                {
-                    Ok ( (*a_id, *b_id) )
-                }
-                else
-                {
-                    Err (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{},{}:{})", *a_id, a, *b_id, b)))
-                }
-            },
-            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{}. Failed to find vertex b: {}", a_id, a, b))),
-            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found vertex b: {}:{}", a, b_id, b))),
-            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a, b)))
+            self.edge_index.remove (e, &edge_attrs);
         }
     }
 
-    pub fn graph (&self)
-        -> &Graph
+    pub fn self_loops (&self)
+        -> bool
     {
-        &self.graph
+        self.graph.self_loops ()
+    }
+
+    // The policy lives on the inner graph, which new creates with self loops
+    // off, so labelled graphs only take them once allowed
+    pub fn set_self_loops (&mut self, allow: bool)
+        -> Result<(), crate::error::GraphError>
+    {
+        self.graph.set_self_loops (allow)
     }
 
     pub fn vertices (&self)
@@ -1136,8 +3113,11 @@ impl LabelledGraph
         self.graph.vertices ()
     }
 
-    pub fn vertex (&self, a: &str)
+    pub fn vertex<Q> (&self, a: &Q)
         -> Result<usize, crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
         if let Some (a_id) = self.vertex_lookup.get (a)
         {
@@ -1145,16 +3125,19 @@ impl LabelledGraph
         }
         else
         {
-            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {}", a)))
+            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
         }
     }
 
-    pub fn vertex_attrs (&self, a: &str)
-        -> Result<(usize, &collections::HashMap::<String, AttributeValue>), crate::error::GraphError>
+    pub fn vertex_attrs<Q> (&self, a: &Q)
+        -> Result<(usize, &V), crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
         if let Some (a_id) = self.vertex_lookup.get (a)
         {
-            match self.vertex_attrs.get (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{}", a_id, a)))
+            match self.vertex_attrs.get (a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{:?}", a_id, a)))
             {
                 Ok (vertex_attrs) => {
                     Ok ( (*a_id, vertex_attrs ) )
@@ -1164,83 +3147,90 @@ impl LabelledGraph
         }
         else
         {
-            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {}", a)))
+            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
         }
     }
 
-    pub fn vertex_attrs_mut (&mut self, a: &str)
-        -> Result<(usize, &mut collections::HashMap::<String, AttributeValue>), crate::error::GraphError>
+    pub fn vertex_attrs_mut<Q> (&mut self, a: &Q)
+        -> Result<(usize, AttrsMut<'_, V>), crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
         if let Some (a_id) = self.vertex_lookup.get (a)
         {
-            match self.vertex_attrs.get_mut (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{}", a_id, a)))
+            match self.vertex_attrs.get_mut (a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{:?}", a_id, a)))
             {
                 Ok (vertex_attrs) => {
-                    Ok ( (*a_id, vertex_attrs) )
+                    Ok ( (*a_id, AttrsMut::new (vertex_attrs, self.schema.as_ref ().map (|s| &s.vertex), (!self.vertex_index.is_empty ()).then_some ((&mut self.vertex_index, *a_id)), || format! ("vertex {:?}", a))) )
                 },
                 Err (e) => Err (e)
             }
         }
         else
         {
-            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {}", a)))
+            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
         }
     }
 
     pub fn vertex_attrs_raw (&self, a_id: &usize)
-        -> Result<&collections::HashMap::<String, AttributeValue>, crate::error::GraphError>
+        -> Result<&V, crate::error::GraphError>
     {
-        self.vertex_attrs.get (a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
+        self.vertex_attrs.get (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
     }
 
     pub fn vertex_attrs_raw_mut (&mut self, a_id: &usize)
-        -> Result<&mut collections::HashMap::<String, AttributeValue>, crate::error::GraphError>
+        -> Result<AttrsMut<'_, V>, crate::error::GraphError>
     {
-        self.vertex_attrs.get_mut (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
+        self.vertex_attrs.get_mut (a_id)
+            .map (|vertex_attrs| AttrsMut::new (vertex_attrs, self.schema.as_ref ().map (|s| &s.vertex), (!self.vertex_index.is_empty ()).then_some ((&mut self.vertex_index, *a_id)), || format! ("vertex {}", a_id)))
+            .ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
     }
 
     pub fn vertex_label (&self, a_id: &usize)
-        -> Result<String, crate::error::GraphError>
+        -> Result<L, crate::error::GraphError>
     {
         self.vertex_label.get (a_id).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", a_id)))
     }
 
     pub fn vertex_labels (&self)
-        -> collections::HashSet<String>
+        -> collections::HashSet<L>
     {
         self.vertex_lookup.keys ().cloned ().collect ()
     }
 
     pub fn edges (&self)
-        -> &collections::HashMap <(usize,usize), i64>
+        -> &collections::HashMap <(usize,usize), W>
     {
         self.graph.edges ()
     }
 
-    pub fn edge_attrs (&self, (a, b): &(String, String))
-        -> Result<( (usize, usize), &collections::HashMap::<String, AttributeValue>), crate::error::GraphError>
+    pub fn edge_attrs (&self, (a, b): &(L, L))
+        -> Result<( (usize, usize), &E), crate::error::GraphError>
     {
         match ( self.vertex_lookup.get (a), self.vertex_lookup.get (b) )
         {
             (Some (a_id), Some (b_id)) => {
-                match self.edge_attrs.get ( &(*a_id, *b_id) ).ok_or (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{},{}:{})", *a_id, a, *b_id, b)))
+                let t = if a_id < b_id { (*a_id, *b_id) } else { (*b_id, *a_id) };
+                match self.edge_attrs.get ( &t ).ok_or (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{:?},{}:{:?})", *a_id, a, *b_id, b)))
                 {
                     Ok (edge_attrs) => {
-                        Ok ( ( (*a_id, *b_id), edge_attrs ) )
+                        Ok ( (t, edge_attrs) )
                     },
                     Err (e) => Err (e)
                 }
             },
-            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{}. Failed to find vertex b: {}", a_id, a, b))),
-            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found vertex b: {}:{}", a, b_id, b))),
-            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a, b)))
+            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{:?}. Failed to find vertex b: {:?}", a_id, a, b))),
+            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {:?}. Found vertex b: {}:{:?}", a, b_id, b))),
+            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {:?} {:?}", a, b)))
         }
     }
 
-    pub fn edge_attrs_raw (&self, ev @ (a_id, b_id): &(usize, usize))
-        -> Result<&collections::HashMap::<String, AttributeValue>, crate::error::GraphError>
+    pub fn edge_attrs_raw (&self, (a_id, b_id): &(usize, usize))
+        -> Result<&E, crate::error::GraphError>
     {
-        if let Some (ea) = self.edge_attrs.get (ev)
+        let t = if a_id < b_id { (*a_id, *b_id) } else { (*b_id, *a_id) };
+        if let Some (ea) = self.edge_attrs.get (&t)
         {
             Ok (ea)
         }
@@ -1248,89 +3238,71 @@ impl LabelledGraph
         {
             match ( self.vertex_label.get (a_id), self.vertex_label.get (b_id) )
             {
-                (Some (a), Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("Found both vertices {}:{} {}:{} but no edge", a_id, a, b_id, b))),
-                (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{}. Failed to find vertex b: {}", a_id, a, b_id))),
-                (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found vertex b: {}:{}", a_id, b_id, b))),
+                (Some (a), Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("Found both vertices {}:{:?} {}:{:?} but no edge", a_id, a, b_id, b))),
+                (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{:?}. Failed to find vertex b: {}", a_id, a, b_id))),
+                (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found vertex b: {}:{:?}", a_id, b_id, b))),
                 _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a_id, b_id)))
             }
         }
     }
 
-    pub fn edge_attrs_mut (&mut self, (a, b): &(String, String))
-        -> Result<( (usize, usize), &mut collections::HashMap::<String, AttributeValue>), crate::error::GraphError>
-    {
-        match ( self.vertex_lookup.get (a), self.vertex_lookup.get (b) )
-        {
-            (Some (a_id), Some (b_id)) => {
-                match self.edge_attrs.get_mut ( &(*a_id, *b_id) ).ok_or (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{},{}:{})", *a_id, a, *b_id, b)))
-                {
-                    Ok (edge_attrs) => {
-                        Ok ( ( (*a_id, *b_id), edge_attrs ) )
-                    },
-                    Err (e) => Err (e)
-                }
-            },
-            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{}. Failed to find vertex b: {}", a_id, a, b))),
-            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found vertex b: {}:{}", a, b_id, b))),
-            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a, b)))
-        }
-    }
-
     pub fn edge_label (&self, (a_id, b_id): &(usize, usize))
-        -> Result<(String, String), crate::error::GraphError>
+        -> Result<(L, L), crate::error::GraphError>
     {
         match ( self.vertex_label.get (&a_id), self.vertex_label.get (&b_id) )
         {
-            (Some (a), Some (b)) => {
-                if self.graph.has_edge_raw ( &(*a_id, *b_id) )
-                {
-                    Ok ( (a.clone (), b.clone ()) )
-                }
-                else
-                {
-                    Err (crate::error::GraphError::EdgeError (format! ("No edge found between {} and {}", a_id, b_id)))
-                }
-            },
-            (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {}. Failed to find vertex b: {}", a_id, a, b_id))),
-            (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {}", a_id, b_id, b))),
+            (Some (a), Some (b)) => Ok ( (a.clone (), b.clone ()) ),
+            (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {:?}. Failed to find vertex b: {}", a_id, a, b_id))),
+            (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {:?}", a_id, b_id, b))),
             _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a_id, b_id)))
         }
     }
 
     pub fn edge_labels (&self)
-        -> Result<collections::HashSet<(String, String)>, crate::error::GraphError>
+        -> Result<collections::HashSet<(L, L)>, crate::error::GraphError>
     {
         self.graph ().edges ().keys ().map (|x| {
-            match ( self.vertex_label.get (&x.0), self.vertex_label.get (&x.1) )
+            match ( self.vertex_label.get (&x.0), self.vertex_label.get (&x.0) )
             {
                 (Some (a), Some (b)) => Ok ( (a.clone (), b.clone ()) ),
-                (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {}. Failed to find vertex b: {}", x.0, a, x.1))),
-                (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {}", x.0, x.1, b))),
+                (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {:?}. Failed to find vertex b: {}", x.0, a, x.1))),
+                (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {:?}", x.0, x.1, b))),
                 _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", x.0, x.1)))
             }
         }).collect::<Result<collections::HashSet<_>, crate::error::GraphError>> ()
     }
 
-    pub fn has_edge (&self, (a, b): &(String, String))
+    pub fn has_edge (&self, (a, b): &(L, L))
         -> bool
     {
-        self.vertex_lookup.contains_key (a) && self.vertex_lookup.contains_key (b) && self.graph.edges.contains_key ( &(self.vertex_lookup[a], self.vertex_lookup[b]) )
+        if self.vertex_lookup.contains_key (a) && self.vertex_lookup.contains_key (b)
+        {
+            let t = if self.vertex_lookup[a] < self.vertex_lookup[b] { (self.vertex_lookup[a], self.vertex_lookup[b]) } else { (self.vertex_lookup[b], self.vertex_lookup[a]) };
+            self.graph.edges.contains_key ( &t )
+        }
+        else
+        {
+            false
+        }
     }
 
-    pub fn has_vertex (&self, a: &str)
+    pub fn has_vertex<Q> (&self, a: &Q)
         -> bool
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
         self.vertex_lookup.contains_key (a)
     }
 
-    pub fn relabel_vertex (&mut self, a_id: &usize, vertex_label_next: String)
+    pub fn relabel_vertex (&mut self, a_id: &usize, vertex_label_next: L)
         -> Result<(), crate::error::GraphError>
     {
         let vertex_label_prev = self.vertex_label.get (a_id).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", a_id)))?;
 
         if let Some (_) = self.vertex_lookup.get (&vertex_label_next)
         {
-            Err (crate::error::GraphError::VertexError (format! ("vertex {} already in graph, duplicate nodes are forbidden", vertex_label_next)))
+            Err (crate::error::GraphError::VertexError (format! ("vertex {:?} already in graph, duplicate nodes are forbidden", vertex_label_next)))
         }
         else
         {
@@ -1341,528 +3313,740 @@ impl LabelledGraph
         }
     }
 
-    pub fn remap_raw (&mut self, map: &collections::HashMap<usize, usize>)
+    pub fn remove_edge (&mut self, (a, b): &(L, L))
         -> Result<(), crate::error::GraphError>
     {
-        self.graph.remap (map)?;
-        let edge_attrs_new = self.edge_attrs.drain ()
-            .map (|(k,v)| {
-                (
-                    (
-                        map.get (&k.0).copied ().unwrap_or (k.0),
-                        map.get (&k.1).copied ().unwrap_or (k.1),
-                    ),
-                    v
-                )
-            })
-            .collect::<Vec<_>> ();
-        self.edge_attrs.extend (edge_attrs_new);
-        let vertex_attrs_new = self.vertex_attrs.drain ()
-            .map (|(k,v)| {
-                (
-                    map.get (&k).copied ().unwrap_or (k),
-                    v
-                )
-            })
-            .collect::<Vec<_>> ();
-        self.vertex_attrs.extend (vertex_attrs_new);
-        let vertex_label_new = self.vertex_label.drain ()
-            .map (|(k,v)| {
-                (
-                    map.get (&k).copied ().unwrap_or (k),
-                    v
-                )
-            })
-            .collect::<Vec<_>> ();
-        self.vertex_label.extend (vertex_label_new);
-        for val in self.vertex_lookup.values_mut ()
+        match ( self.has_vertex (&a), self.has_vertex (&b) )
         {
-            *val = map.get (val).copied ().unwrap_or (*val);
+            (true, true) => {
+                let er = ( self.vertex (&a)?, self.vertex (&b)? );
+                self.remove_edge_attrs (&if er.0 < er.1 { er } else { (er.1, er.0) });
+                self.graph.remove_edge_raw (&er.0, &er.1)?;
+                Ok (())
+            },
+            (true, false) => Err (crate::error::GraphError::EdgeError (format! ("Found {:?}. Failed to find vertex b: {:?}", a, b))),
+            (false, true) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {:?}. Found {:?}", a, b))),
+            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {:?} {:?}", a, b)))
         }
-        Ok (())
     }
 
-    pub fn remove_edge (&mut self, (a, b): &(String, String))
-        -> Result<(), crate::error::GraphError>
+    pub fn remove_vertex<Q> (&mut self, a: &Q)
+        -> Result<(), crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        let a_id = self.vertex (a)?;
+
+        if self.graph.neighbours (&a_id)?.is_empty ()
+        {
+            self.vertex_lookup.remove (a);
+            self.vertex_label.remove (&a_id);
+            self.remove_vertex_attrs (&a_id);
+            self.graph.remove_vertex_raw (&a_id)?;
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::VertexError (format! ("Cannot delete vertex {} with edges", a_id)))
+        }
+    }
+    pub fn validate (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        let mut errors = match self.graph.validate ()
+        {
+            Err (crate::error::GraphError::ValidationError (errors)) => errors,
+            Err (e) => vec![e],
+            Ok (()) => Vec::new ()
+        };
+        errors.extend (validate_labels (self.graph.vertices (), self.graph.edges (), &self.vertex_id, &self.vertex_label, &self.vertex_lookup, &self.vertex_attrs, &self.edge_attrs));
+        if let Some (schema) = &self.schema
+        {
+            errors.extend (crate::schema::check_payloads (schema, &self.vertex_label, &self.vertex_attrs, &self.edge_attrs));
+        }
+        if errors.is_empty ()
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (crate::error::GraphError::ValidationError (errors))
+        }
+    }
+}
+
+impl<W, V, E, L> PartialEq for LabelledUGraph<W, V, E, L>
+    where
+        W: Weight
+    {
+    fn eq(&self, other: &Self) -> bool {
+        // TODO compare vertex labels
+        self.graph == other.graph
+    }
+}
+
+// Checks the label and attribute maps of the labelled graphs against their inner graph
+fn validate_labels<W, V, E, L: Label> (vertices: &collections::HashSet<usize>, edges: &collections::HashMap<(usize,usize), W>, vertex_id: &sync::atomic::AtomicUsize, vertex_label: &collections::HashMap<usize, L>, vertex_lookup: &collections::HashMap<L, usize>, vertex_attrs: &collections::HashMap<usize, V>, edge_attrs: &collections::HashMap<(usize,usize), E>)
+    -> Vec<crate::error::GraphError>
+{
+    let mut errors = Vec::<crate::error::GraphError>::new ();
+    let mut vs = vertices.iter ().copied ().collect::<Vec<_>> ();
+    vs.sort ();
+    for a_id in &vs
+    {
+        if !vertex_label.contains_key (a_id)
+        {
+            errors.push (crate::error::GraphError::VertexError (format! ("vertex {} has no label", a_id)));
+        }
+        if !vertex_attrs.contains_key (a_id)
+        {
+            errors.push (crate::error::GraphError::VertexError (format! ("vertex {} has no attributes", a_id)));
+        }
+    }
+    let next_id = vertex_id.load (sync::atomic::Ordering::Relaxed);
+    if let Some (a_id) = vs.last () && *a_id >= next_id
+    {
+        errors.push (crate::error::GraphError::VertexError (format! ("vertex id counter {} would reuse vertex {}", next_id, a_id)));
+    }
+
+    let mut labels = vertex_label.iter ().collect::<Vec<_>> ();
+    labels.sort_by_key (|(a_id, _)| **a_id);
+    for (a_id, a) in labels
+    {
+        if !vertices.contains (a_id)
+        {
+            errors.push (crate::error::GraphError::VertexError (format! ("label {:?} refers to missing vertex {}", a, a_id)));
+        }
+        match vertex_lookup.get (a)
+        {
+            Some (b_id) if b_id == a_id => {},
+            Some (b_id) => errors.push (crate::error::GraphError::VertexError (format! ("duplicate label {:?} for vertices {} and {}", a, a_id, b_id))),
+            None => errors.push (crate::error::GraphError::VertexError (format! ("label {:?} of vertex {} is missing from the lookup", a, a_id)))
+        }
+    }
+    // Labels need not be Ord, so order the report by id then label
+    let mut lookup = vertex_lookup.iter ().collect::<Vec<_>> ();
+    lookup.sort_by_cached_key (|(a, a_id)| (**a_id, format! ("{:?}", a)));
+    for (a, a_id) in lookup
+    {
+        if vertex_label.get (a_id) != Some (a)
+        {
+            errors.push (crate::error::GraphError::VertexError (format! ("lookup maps {:?} to vertex {} which has a different label", a, a_id)));
+        }
+    }
+
+    let mut attrs = vertex_attrs.keys ().copied ().collect::<Vec<_>> ();
+    attrs.sort ();
+    for a_id in attrs.iter ().filter (|x| !vertices.contains (x))
+    {
+        errors.push (crate::error::GraphError::VertexError (format! ("attributes for missing vertex {}", a_id)));
+    }
+
+    let mut es = edges.keys ().copied ().collect::<Vec<_>> ();
+    es.sort ();
+    for (a_id, b_id) in es.iter ().filter (|x| !edge_attrs.contains_key (x))
+    {
+        errors.push (crate::error::GraphError::EdgeError (format! ("edge ({},{}) has no attributes", a_id, b_id)));
+    }
+    let mut attrs = edge_attrs.keys ().copied ().collect::<Vec<_>> ();
+    attrs.sort ();
+    for (a_id, b_id) in attrs.iter ().filter (|x| !edges.contains_key (x))
+    {
+        errors.push (crate::error::GraphError::EdgeError (format! ("attributes for missing edge ({},{})", a_id, b_id)));
+    }
+    errors
+}
+
+// The labelled graphs derive their serde impls with remote = "Self", which
+// makes them inherent functions. These wrap them so a schema serialized with
+// a graph is checked whenever it is deserialized.
+impl<W, V, E, L> Serialize for LabelledGraph<W, V, E, L>
+    where
+        W: Serialize,
+        V: Serialize,
+        E: Serialize,
+        L: Serialize
+{
+    fn serialize<S> (&self, serializer: S)
+        -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer
+    {
+        LabelledGraph::serialize (self, serializer)
+    }
+}
+
+impl<'de, W, V, E, L> Deserialize<'de> for LabelledGraph<W, V, E, L>
+    where
+        W: Deserialize<'de> + Weight,
+        V: Deserialize<'de> + Payload,
+        E: Deserialize<'de> + Payload,
+        L: Deserialize<'de> + Label
+{
+    fn deserialize<D> (deserializer: D)
+        -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>
+    {
+        let g = LabelledGraph::deserialize (deserializer)?;
+        g.check_schema ().map_err (serde::de::Error::custom)?;
+        Ok (g)
+    }
+}
+
+impl<W, V, E, L> Serialize for LabelledUGraph<W, V, E, L>
+    where
+        W: Serialize,
+        V: Serialize,
+        E: Serialize,
+        L: Serialize
+{
+    fn serialize<S> (&self, serializer: S)
+        -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer
+    {
+        LabelledUGraph::serialize (self, serializer)
+    }
+}
+
+impl<'de, W, V, E, L> Deserialize<'de> for LabelledUGraph<W, V, E, L>
+    where
+        W: Deserialize<'de> + Weight,
+        V: Deserialize<'de> + Payload,
+        E: Deserialize<'de> + Payload,
+        L: Deserialize<'de> + Label
+{
+    fn deserialize<D> (deserializer: D)
+        -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>
+    {
+        let g = LabelledUGraph::deserialize (deserializer)?;
+        g.check_schema ().map_err (serde::de::Error::custom)?;
+        Ok (g)
+    }
+}
+
+pub trait Validate
+{
+    fn validate (&self) -> Result<(), crate::error::GraphError>;
+}
+
+impl<W> Validate for Graph<W>
+    where
+        W: Weight
+{
+    fn validate (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        Graph::validate (self)
+    }
+}
+
+impl<W> Validate for UGraph<W>
+    where
+        W: Weight
+{
+    fn validate (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        UGraph::validate (self)
+    }
+}
+
+impl<W, V, E, L> Validate for LabelledGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    fn validate (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        LabelledGraph::validate (self)
+    }
+}
+
+impl<W, V, E, L> Validate for LabelledUGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    fn validate (&self)
+        -> Result<(), crate::error::GraphError>
+    {
+        LabelledUGraph::validate (self)
+    }
+}
+
+// Deserializes G and rejects it unless validate passes, e.g.
+// serde_json::from_str::<Validated<LabelledGraph>> (s)?.into_inner ()
+#[derive(Clone,Debug,Serialize)]
+#[serde(transparent)]
+pub struct Validated<G> (G);
+
+impl<G> Validated<G>
+{
+    pub fn into_inner (self)
+        -> G
+    {
+        self.0
+    }
+}
+
+impl<G> std::ops::Deref for Validated<G>
+{
+    type Target = G;
+
+    fn deref (&self)
+        -> &G
+    {
+        &self.0
+    }
+}
+
+impl<'de, G> Deserialize<'de> for Validated<G>
+    where
+        G: Deserialize<'de> + Validate
+{
+    fn deserialize<D> (deserializer: D)
+        -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>
+    {
+        let g = G::deserialize (deserializer)?;
+        g.validate ().map_err (serde::de::Error::custom)?;
+        Ok (Validated (g))
+    }
+}
+
+pub trait GraphAny
+{
+    type Weight: Weight;
+
+    fn adjacent (&self, a: &usize) -> Result<collections::HashSet<usize>, crate::error::GraphError>;
+    fn adjacent_iter (&self, a: &usize) -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>;
+    fn edges_iter (&self) -> Box<dyn Iterator<Item = ((usize, usize), Self::Weight)> + '_>;
+    fn has_edge_raw (&self, ev: &(usize, usize)) -> bool;
+    fn neighbours (&self, a: &usize) -> Result<collections::HashSet<usize>, crate::error::GraphError>;
+    fn neighbours_iter (&self, a: &usize) -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>;
+    fn vertices (&self) -> &collections::HashSet <usize>;
+    fn weight (&self, ev: &(usize, usize)) -> Result<Self::Weight, crate::error::GraphError>;
+}
+
+impl<W> GraphAny for Graph<W>
+    where
+        W: Weight
+{
+    type Weight = W;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        self.outbound (a)
+    }
+
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
+    {
+        Ok (Box::new (self.outbound_iter (a)?))
+    }
+
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
     {
-        match ( self.has_vertex (&a), self.has_vertex (&b) )
-        {
-            (true, true) => {
-                let er = ( self.vertex (&a)?, self.vertex (&b)? );
-                self.edge_attrs.remove (&er);
-                self.graph.remove_edge_raw (&er.0, &er.1)?;
-                Ok (())
-            },
-            (true, false) => Err (crate::error::GraphError::EdgeError (format! ("Found {}. Failed to find vertex b: {}", a, b))),
-            (false, true) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {}", a, b))),
-            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a, b)))
-        }
+        Box::new (self.edges_iter ())
     }
 
-    pub fn remove_vertex (&mut self, a: &str)
-        -> Result<(), crate::error::GraphError>
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
     {
-        let a_id = self.vertex (a)?;
-        if self.graph.neighbours (&a_id)?.is_empty ()
-        {
-            self.vertex_lookup.remove (a);
-            self.vertex_label.remove (&a_id);
-            self.vertex_attrs.remove (&a_id);
-            self.graph.remove_vertex_raw (&a_id)?;
-            Ok (())
-        }
-        else
-        {
-            Err (crate::error::GraphError::VertexError (format! ("Cannot delete vertex {} with edges", a)))
-        }
+        self.has_edge_raw (ev)
     }
 
-    pub fn rename (&mut self, name: String)
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        self.graph.rename (name);
+        self.neighbours (a)
     }
 
-    pub fn retain (&mut self, vertices_retain: &collections::HashSet<usize>)
-        -> Result<(), crate::error::GraphError>
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        for vd in self.graph.vertices () - vertices_retain
-        {
-            let vdl = self.vertex_label.get (&vd).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", vd)))?;
-            self.vertex_lookup.remove (&vdl);
-            self.vertex_label.remove (&vd);
-            self.vertex_attrs.remove (&vd);
-
-            for vdi in self.graph.inbound (&vd)?
-            {
-                self.edge_attrs.remove ( &(vdi, vd) );
-            }
-            for vdo in self.graph.outbound (&vd)?
-            {
-                self.edge_attrs.remove ( &(vd, vdo) );
-            }
-        }
-        self.graph.retain (vertices_retain)
+        Ok (Box::new (self.neighbours_iter (a)?))
     }
 
-    pub fn retain_edges (&mut self, edges_retain: &collections::HashSet<(usize,usize)>)
-        -> Result<(), crate::error::GraphError>
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
     {
-        for e in &self.graph.edges.keys ().cloned ().collect::<collections::HashSet<_>> () - &edges_retain
-        {
-            self.edge_attrs.remove (&e);
-        }
-
-        self.graph.retain_edges (edges_retain)
+        self.vertices ()
     }
-}
 
-impl PartialEq for LabelledGraph
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, crate::error::GraphError>
     {
-    fn eq(&self, other: &Self) -> bool {
-        // TODO compare vertex labels
-        self.graph == other.graph
+        self.edges ().get (ev).copied ().ok_or (crate::error::GraphError::EdgeError (format! ("No edge found for {:?}", ev)))
     }
 }
 
-impl LabelledUGraph
+impl<W> GraphAny for UGraph<W>
+    where
+        W: Weight
 {
-    pub fn new () -> Self
+    type Weight = W;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        Self {
-            vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (1)),
-            edge_attrs: collections::HashMap::<(usize,usize), collections::HashMap::<String, AttributeValue>>::new (),
-            graph: UGraph::new (),
-            vertex_attrs: collections::HashMap::<usize, collections::HashMap::<String, AttributeValue>>::new (),
-            vertex_label: collections::HashMap::<usize, String>::new (),
-            vertex_lookup: collections::HashMap::<String, usize>::new ()
-        }
+        self.neighbours (a)
     }
 
-    pub fn new_with_name (name: &str) -> Self
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        Self {
-            vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (1)),
-            edge_attrs: collections::HashMap::<(usize,usize), collections::HashMap::<String, AttributeValue>>::new (),
-            graph: UGraph::new_with_name (name),
-            vertex_attrs: collections::HashMap::<usize, collections::HashMap::<String, AttributeValue>>::new (),
-            vertex_label: collections::HashMap::<usize, String>::new (),
-            vertex_lookup: collections::HashMap::<String, usize>::new ()
-        }
+        Ok (Box::new (self.neighbours_iter (a)?))
     }
 
-    pub fn new_without_attributes (g: &LabelledUGraph)
-        -> Self
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
     {
-        Self {
-            vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (g.vertices ().iter ().max ().cloned ().unwrap_or (1))),
-            edge_attrs: collections::HashMap::<(usize,usize), collections::HashMap::<String, AttributeValue>>::new (),
-            graph: g.graph ().clone (),
-            vertex_attrs: g.graph ().vertices ().iter ().fold (collections::HashMap::<usize, collections::HashMap::<String, AttributeValue>>::new (), |mut acc, item| {
-                acc.insert (*item, collections::HashMap::<String, AttributeValue>::new ());
-                acc
-            }),
-            vertex_label: g.vertex_label.clone (),
-            vertex_lookup: g.vertex_lookup.clone ()
-        }
+        Box::new (self.edges_iter ())
     }
 
-    pub fn add_edge (&mut self, a: String, b: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
-        -> Result<(usize, usize), crate::error::GraphError>
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
     {
-        self.add_edge_weighted (a, b, attrs, 0)
+        self.has_edge_raw (ev)
     }
 
-    pub fn add_edge_weighted (&mut self, a: String, b: String, attrs: Option<collections::HashMap::<String, AttributeValue>>, weight: i64)
-        -> Result<(usize, usize), crate::error::GraphError>
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        //debug! ("add {} to {}", a , b);
-        if a == b
-        {
-            Err (crate::error::GraphError::EdgeError (String::from ("edge vertices must be distinct")))
-        }
-        else
-        {
-            // Check that we didn't already add the vertices
-            let a_id = if self.vertex_lookup.contains_key (&a) { self.vertex_lookup[&a] } else { self.add_vertex (a, None)? };
-            let b_id = if self.vertex_lookup.contains_key (&b) { self.vertex_lookup[&b] } else { self.add_vertex (b, None)? };
-            self.graph.add_edge_raw (a_id, b_id, weight)?;
-            if let Some (edge_attrs) = attrs
-            {
-                self.edge_attrs.insert ((a_id, b_id), edge_attrs);
-            }
-            else
-            {
-                self.edge_attrs.insert ((a_id, b_id), collections::HashMap::<String, AttributeValue>::new ());
-            }
-            if a_id < b_id
-            {
-                Ok ((a_id, b_id))
-            }
-            else
-            {
-                Ok ((b_id, a_id))
-            }
-        }
+        self.neighbours (a)
     }
 
-    pub fn add_vertex (&mut self, a: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
-        -> Result<usize, crate::error::GraphError>
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        let vertex_exists = self.vertex_lookup.contains_key (&a);
-        let a_id = *self.vertex_lookup.entry (a.clone ()).or_insert_with (|| self.vertex_id.fetch_add (1, sync::atomic::Ordering::Relaxed));
-
-        if ! vertex_exists
-        {
-            self.graph.add_vertex_raw (a_id)?;
-            self.vertex_label.insert (a_id, a.clone ());
-        }
-        if let Some (vertex_attrs) = attrs
-        {
-            self.vertex_attrs.insert (a_id, vertex_attrs);
-        }
-        else
-        {
-            self.vertex_attrs.insert (a_id, collections::HashMap::<String, AttributeValue>::new ());
-        }
-        Ok (a_id)
+        Ok (Box::new (self.neighbours_iter (a)?))
     }
 
-    pub fn graph (&self)
-        -> &UGraph
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
     {
-        &self.graph
+        self.vertices ()
     }
 
-    pub fn vertices (&self)
-        -> &collections::HashSet <usize>
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, crate::error::GraphError>
     {
-        self.graph.vertices ()
+        let t = if ev.0 < ev.1 { (ev.0, ev.1) } else { (ev.1, ev.0) };
+        self.edges ().get (&t).copied ().ok_or (crate::error::GraphError::EdgeError (format! ("No edge found for {:?}", ev)))
     }
+}
 
-    pub fn vertex (&self, a: &str)
-        -> Result<usize, crate::error::GraphError>
+impl<W, V, E, L> GraphAny for LabelledGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    type Weight = W;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        if let Some (a_id) = self.vertex_lookup.get (a)
-        {
-            Ok (*a_id)
-        }
-        else
-        {
-            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {}", a)))
-        }
+        GraphAny::adjacent (&self.graph, a)
     }
 
-    pub fn vertex_attrs (&self, a: &str)
-        -> Result<(usize, &collections::HashMap::<String, AttributeValue>), crate::error::GraphError>
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        if let Some (a_id) = self.vertex_lookup.get (a)
-        {
-            match self.vertex_attrs.get (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{}", a_id, a)))
-            {
-                Ok (vertex_attrs) => {
-                    Ok ( (*a_id, vertex_attrs ) )
-                },
-                Err (e) => Err (e)
-            }
-        }
-        else
-        {
-            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {}", a)))
-        }
+        GraphAny::adjacent_iter (&self.graph, a)
     }
 
-    pub fn vertex_attrs_mut (&mut self, a: &str)
-        -> Result<(usize, &mut collections::HashMap::<String, AttributeValue>), crate::error::GraphError>
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
     {
-        if let Some (a_id) = self.vertex_lookup.get (a)
-        {
-            match self.vertex_attrs.get_mut (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}:{}", a_id, a)))
-            {
-                Ok (vertex_attrs) => {
-                    Ok ( (*a_id, vertex_attrs) )
-                },
-                Err (e) => Err (e)
-            }
-        }
-        else
-        {
-            Err (crate::error::GraphError::VertexError (format! ("Failed to find vertex: {}", a)))
-        }
+        GraphAny::edges_iter (&self.graph)
     }
 
-    pub fn vertex_attrs_raw (&self, a_id: &usize)
-        -> Result<&collections::HashMap::<String, AttributeValue>, crate::error::GraphError>
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
     {
-        self.vertex_attrs.get (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
+        self.graph.has_edge_raw (ev)
     }
 
-    pub fn vertex_attrs_raw_mut (&mut self, a_id: &usize)
-        -> Result<&mut collections::HashMap::<String, AttributeValue>, crate::error::GraphError>
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        self.vertex_attrs.get_mut (&a_id).ok_or (crate::error::GraphError::VertexError (format! ("Could not find vertex attributes for {}", a_id)))
+        self.graph.neighbours (a)
     }
 
-    pub fn vertex_label (&self, a_id: &usize)
-        -> Result<String, crate::error::GraphError>
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        self.vertex_label.get (a_id).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", a_id)))
+        GraphAny::neighbours_iter (&self.graph, a)
     }
 
-    pub fn vertex_labels (&self)
-        -> collections::HashSet<String>
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
     {
-        self.vertex_lookup.keys ().cloned ().collect ()
+        self.graph.vertices ()
     }
 
-    pub fn edges (&self)
-        -> &collections::HashMap <(usize,usize), i64>
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, crate::error::GraphError>
     {
-        self.graph.edges ()
+        GraphAny::weight (&self.graph, ev)
     }
+}
+
+impl<W, V, E, L> GraphAny for LabelledUGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    type Weight = W;
 
-    pub fn edge_attrs (&self, (a, b): &(String, String))
-        -> Result<( (usize, usize), &collections::HashMap::<String, AttributeValue>), crate::error::GraphError>
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        match ( self.vertex_lookup.get (a), self.vertex_lookup.get (b) )
-        {
-            (Some (a_id), Some (b_id)) => {
-                let t = if a_id < b_id { (*a_id, *b_id) } else { (*b_id, *a_id) };
-                match self.edge_attrs.get ( &t ).ok_or (crate::error::GraphError::EdgeError (format! ("Could not find edge attributes for ({}:{},{}:{})", *a_id, a, *b_id, b)))
-                {
-                    Ok (edge_attrs) => {
-                        Ok ( (t, edge_attrs) )
-                    },
-                    Err (e) => Err (e)
-                }
-            },
-            (Some (a_id), None) => Err (crate::error::GraphError::EdgeError (format! ("Found vertex a: {}:{}. Failed to find vertex b: {}", a_id, a, b))),
-            (None, Some (b_id)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found vertex b: {}:{}", a, b_id, b))),
-            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a, b)))
-        }
+        GraphAny::adjacent (&self.graph, a)
     }
 
-    pub fn edge_label (&self, (a_id, b_id): &(usize, usize))
-        -> Result<(String, String), crate::error::GraphError>
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        match ( self.vertex_label.get (&a_id), self.vertex_label.get (&b_id) )
-        {
-            (Some (a), Some (b)) => Ok ( (a.clone (), b.clone ()) ),
-            (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {}. Failed to find vertex b: {}", a_id, a, b_id))),
-            (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {}", a_id, b_id, b))),
-            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a_id, b_id)))
-        }
+        GraphAny::adjacent_iter (&self.graph, a)
     }
 
-    pub fn edge_labels (&self)
-        -> Result<collections::HashSet<(String, String)>, crate::error::GraphError>
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
     {
-        self.graph ().edges ().keys ().map (|x| {
-            match ( self.vertex_label.get (&x.0), self.vertex_label.get (&x.0) )
-            {
-                (Some (a), Some (b)) => Ok ( (a.clone (), b.clone ()) ),
-                (Some (a), None) => Err (crate::error::GraphError::EdgeError (format! ("Found {} {}. Failed to find vertex b: {}", x.0, a, x.1))),
-                (None, Some (b)) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {} {}", x.0, x.1, b))),
-                _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", x.0, x.1)))
-            }
-        }).collect::<Result<collections::HashSet<_>, crate::error::GraphError>> ()
+        GraphAny::edges_iter (&self.graph)
     }
 
-    pub fn has_edge (&self, (a, b): &(String, String))
+    fn has_edge_raw (&self, ev: &(usize, usize))
         -> bool
     {
-        if self.vertex_lookup.contains_key (a) && self.vertex_lookup.contains_key (b)
-        {
-            let t = if self.vertex_lookup[a] < self.vertex_lookup[b] { (self.vertex_lookup[a], self.vertex_lookup[b]) } else { (self.vertex_lookup[b], self.vertex_lookup[a]) };
-            self.graph.edges.contains_key ( &t )
-        }
-        else
-        {
-            false
-        }
+        self.graph.has_edge_raw (ev)
+    }
+
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        self.graph.neighbours (a)
+    }
+
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
+    {
+        GraphAny::neighbours_iter (&self.graph, a)
+    }
+
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
+    {
+        self.graph.vertices ()
+    }
+
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, crate::error::GraphError>
+    {
+        GraphAny::weight (&self.graph, ev)
+    }
+}
+
+// Directed queries shared by Graph and LabelledGraph
+pub trait DiGraphAny: GraphAny
+{
+    fn inbound (&self, a: &usize) -> Result<collections::HashSet<usize>, crate::error::GraphError>;
+    fn inbound_iter (&self, a: &usize) -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>;
+    fn is_sink (&self, a: &usize) -> Result<bool, crate::error::GraphError>;
+    fn is_source (&self, a: &usize) -> Result<bool, crate::error::GraphError>;
+    fn outbound (&self, a: &usize) -> Result<collections::HashSet<usize>, crate::error::GraphError>;
+    fn outbound_iter (&self, a: &usize) -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>;
+    fn sinks (&self) -> collections::HashSet<usize>;
+    fn sources (&self) -> collections::HashSet<usize>;
+}
+
+impl<W> DiGraphAny for Graph<W>
+    where
+        W: Weight
+{
+    fn inbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    {
+        self.inbound (a)
     }
 
-    pub fn has_vertex (&self, a: &str)
-        -> bool
+    fn inbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        self.vertex_lookup.contains_key (a)
+        Ok (Box::new (self.inbound_iter (a)?))
     }
 
-    pub fn relabel_vertex (&mut self, a_id: &usize, vertex_label_next: String)
-        -> Result<(), crate::error::GraphError>
+    fn is_sink (&self, a: &usize)
+        -> Result<bool, crate::error::GraphError>
     {
-        let vertex_label_prev = self.vertex_label.get (a_id).cloned ().ok_or (crate::error::GraphError::VertexError (format! ("vertex {} not found in graph", a_id)))?;
-
-        if let Some (_) = self.vertex_lookup.get (&vertex_label_next)
-        {
-            Err (crate::error::GraphError::VertexError (format! ("vertex {} already in graph, duplicate nodes are forbidden", vertex_label_next)))
-        }
-        else
-        {
-            self.vertex_lookup.remove (&vertex_label_prev);
-            self.vertex_lookup.insert (vertex_label_next.clone (), *a_id);
-            self.vertex_label.insert (*a_id, vertex_label_next);
-            Ok (())
-        }
+        self.is_sink (a)
     }
 
-    pub fn remove_edge (&mut self, (a, b): &(String, String))
-        -> Result<(), crate::error::GraphError>
+    fn is_source (&self, a: &usize)
+        -> Result<bool, crate::error::GraphError>
     {
-        match ( self.has_vertex (&a), self.has_vertex (&b) )
-        {
-            (true, true) => {
-                let er = ( self.vertex (&a)?, self.vertex (&b)? );
-                self.edge_attrs.remove (&er);
-                self.graph.remove_edge_raw (&er.0, &er.1)?;
-                Ok (())
-            },
-            (true, false) => Err (crate::error::GraphError::EdgeError (format! ("Found {}. Failed to find vertex b: {}", a, b))),
-            (false, true) => Err (crate::error::GraphError::EdgeError (format! ("failed to find vertex a: {}. Found {}", a, b))),
-            _ => Err (crate::error::GraphError::EdgeError (format! ("failed to find both vertices: {} {}", a, b)))
-        }
+        self.is_source (a)
     }
 
-    pub fn remove_vertex (&mut self, a: &str)
-        -> Result<(), crate::error::GraphError>
+    fn outbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        let a_id = self.vertex (a)?;
+        self.outbound (a)
+    }
 
-        if self.graph.neighbours (&a_id)?.is_empty ()
-        {
-            self.vertex_lookup.remove (a);
-            self.vertex_label.remove (&a_id);
-            self.vertex_attrs.remove (&a_id);
-            self.graph.remove_vertex_raw (&a_id)?;
-            Ok (())
-        }
-        else
-        {
-            Err (crate::error::GraphError::VertexError (format! ("Cannot delete vertex {} with edges", a_id)))
-        }
+    fn outbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
+    {
+        Ok (Box::new (self.outbound_iter (a)?))
     }
-}
 
-impl PartialEq for LabelledUGraph
+    fn sinks (&self)
+        -> collections::HashSet<usize>
     {
-    fn eq(&self, other: &Self) -> bool {
-        // TODO compare vertex labels
-        self.graph == other.graph
+        self.sinks ()
     }
-}
 
-pub trait GraphAny
-{
-    fn adjacent (&self, a: &usize) -> Result<collections::HashSet<usize>, crate::error::GraphError>;
-    fn has_edge_raw (&self, ev: &(usize, usize)) -> bool;
-    fn neighbours (&self, a: &usize) -> Result<collections::HashSet<usize>, crate::error::GraphError>;
-    fn vertices (&self) -> &collections::HashSet <usize>;
-    fn weight (&self, ev: &(usize, usize)) -> Result<i64, crate::error::GraphError>;
+    fn sources (&self)
+        -> collections::HashSet<usize>
+    {
+        self.sources ()
+    }
 }
 
-impl GraphAny for Graph
+impl<W, V, E, L> DiGraphAny for LabelledGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
 {
-    fn adjacent (&self, a: &usize)
+    fn inbound (&self, a: &usize)
         -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        self.outbound (a)
+        self.graph.inbound (a)
     }
 
-    fn has_edge_raw (&self, ev: &(usize, usize))
-        -> bool
+    fn inbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        self.has_edge_raw (ev)
+        DiGraphAny::inbound_iter (&self.graph, a)
     }
 
-    fn neighbours (&self, a: &usize)
+    fn is_sink (&self, a: &usize)
+        -> Result<bool, crate::error::GraphError>
+    {
+        self.graph.is_sink (a)
+    }
+
+    fn is_source (&self, a: &usize)
+        -> Result<bool, crate::error::GraphError>
+    {
+        self.graph.is_source (a)
+    }
+
+    fn outbound (&self, a: &usize)
         -> Result<collections::HashSet<usize>, crate::error::GraphError>
     {
-        self.neighbours (a)
+        self.graph.outbound (a)
     }
 
-    fn vertices (&self)
-        -> &collections::HashSet<usize>
+    fn outbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, crate::error::GraphError>
     {
-        self.vertices ()
+        DiGraphAny::outbound_iter (&self.graph, a)
     }
 
-    fn weight (&self, ev: &(usize, usize))
-        -> Result<i64, crate::error::GraphError>
+    fn sinks (&self)
+        -> collections::HashSet<usize>
     {
-        self.edges ().get (ev).copied ().ok_or (crate::error::GraphError::EdgeError (format! ("No edge found for {:?}", ev)))
+        self.graph.sinks ()
     }
-}
 
-impl GraphAny for UGraph
-{
-    fn adjacent (&self, a: &usize)
-        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    fn sources (&self)
+        -> collections::HashSet<usize>
     {
-        self.neighbours (a)
+        self.graph.sources ()
     }
+}
 
-    fn has_edge_raw (&self, ev: &(usize, usize))
-        -> bool
+// Translation between vertex ids and labels for the labelled graphs
+pub trait LabelledGraphAny: GraphAny
+{
+    type Label: Label;
+
+    fn vertex<Q> (&self, a: &Q) -> Result<usize, crate::error::GraphError>
+        where
+            Self::Label: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized;
+    fn vertex_label (&self, a_id: &usize) -> Result<Self::Label, crate::error::GraphError>;
+}
+
+impl<W, V, E, L> LabelledGraphAny for LabelledGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    type Label = L;
+
+    fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
-        self.has_edge_raw (ev)
+        self.vertex (a)
     }
 
-    fn neighbours (&self, a: &usize)
-        -> Result<collections::HashSet<usize>, crate::error::GraphError>
+    fn vertex_label (&self, a_id: &usize)
+        -> Result<L, crate::error::GraphError>
     {
-        self.neighbours (a)
+        self.vertex_label (a_id)
     }
+}
 
-    fn vertices (&self)
-        -> &collections::HashSet<usize>
+impl<W, V, E, L> LabelledGraphAny for LabelledUGraph<W, V, E, L>
+    where
+        V: Payload,
+        E: Payload,
+        W: Weight,
+        L: Label
+{
+    type Label = L;
+
+    fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, crate::error::GraphError>
+        where
+            L: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
     {
-        self.vertices ()
+        self.vertex (a)
     }
 
-    fn weight (&self, ev: &(usize, usize))
-        -> Result<i64, crate::error::GraphError>
+    fn vertex_label (&self, a_id: &usize)
+        -> Result<L, crate::error::GraphError>
     {
-        let t = if ev.0 < ev.1 { (ev.0, ev.1) } else { (ev.1, ev.0) };
-        self.edges ().get (&t).copied ().ok_or (crate::error::GraphError::EdgeError (format! ("No edge found for {:?}", ev)))
+        self.vertex_label (a_id)
     }
 }
 
@@ -1878,9 +4062,10 @@ mod tests
 
     static INIT: sync::Once = sync::Once::new ();
 
+    // Every test module has its own INIT, so the logger may already be set
     fn init ()
     {
-        INIT.call_once (env_logger::init);
+        INIT.call_once (|| { let _ = env_logger::try_init (); });
     }
 
     #[test]
@@ -1901,6 +4086,35 @@ mod tests
 
         let c_id = g.add_vertex (String::from ("c"), None).expect ("Failed to add vertex 'c'");
         assert_eq! (c_id, b_id + 1);
+
+        let attrs = collections::HashMap::<String, AttributeValue>::from ([ (String::from ("k"), AttributeValue::from ("v")) ]);
+        g.add_vertex_raw (c_id, String::from ("c"), Some (attrs.clone ())).expect ("Failed to add_vertex_raw");
+        g.add_edge_raw (c_id, String::from ("c"), a_id, String::from ("a"), None, 0).expect ("Failed to add_edge_raw");
+        assert_eq! (g.vertex_attrs ("c").expect ("Failed to get vertex attrs").1, &attrs);
+    }
+
+    #[test]
+    fn test_add_vertex_raw_labelled_u ()
+    {
+        init ();
+        let mut g = LabelledUGraph::new ();
+        let a_id = g.add_vertex (String::from ("a"), None).expect ("Failed to add vertex 'a'");
+
+        assert_eq! (g.add_vertex_raw (a_id, String::from ("b"), None).unwrap_err ().to_string (), "Vertex error: vertex id 1 already exists but has a different label");
+        assert_eq! (g.add_vertex_raw (a_id + 10, String::from ("a"), None).unwrap_err ().to_string (), "Vertex error: vertex already exists but has a different id 11 != 1");
+
+        let b_id = g.add_vertex_raw (a_id + 10, String::from ("b"), None).expect ("Failed to add_vertex_raw");
+
+        assert_eq! (b_id, a_id + 10);
+        assert! (g.has_vertex ("b"));
+        assert_eq! (g.vertex_label (&b_id).expect ("Failed to get vertex label"), String::from ("b"));
+
+        let c_id = g.add_vertex (String::from ("c"), None).expect ("Failed to add vertex 'c'");
+        assert_eq! (c_id, b_id + 1);
+
+        let e = g.add_edge_raw (c_id + 5, String::from ("d"), a_id, String::from ("a"), None, 3).expect ("Failed to add_edge_raw");
+        assert_eq! (e, (a_id, c_id + 5));
+        assert! (g.has_edge ( &(String::from ("a"), String::from ("d")) ));
     }
 
     #[test]
@@ -1990,6 +4204,403 @@ mod tests
         assert_eq! ((0..8).map (|x| g.is_sink (&x).expect ("Failed to call is_sink")).collect::<Vec<_>> (), expected_is_sink, "Failed is_sink");
     }
 
+    #[test]
+    fn test_iterators ()
+    {
+        init ();
+        let mut g = Graph::new ();
+        g.add_edge_raw (1,2,3).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,1,4).expect ("Failed to add edge 2 -> 1");
+        g.add_edge_raw (3,1,5).expect ("Failed to add edge 3 -> 1");
+        g.add_vertex_raw (4).expect ("Failed to add vertex 4");
+
+        for v in g.vertices ()
+        {
+            assert_eq! (g.inbound_iter (v).unwrap ().collect::<collections::HashSet<_>> (), g.inbound (v).unwrap ());
+            assert_eq! (g.outbound_iter (v).unwrap ().collect::<collections::HashSet<_>> (), g.outbound (v).unwrap ());
+            assert_eq! (g.neighbours (v).unwrap ().len (), g.neighbours_iter (v).unwrap ().count (), "Neighbours of {} repeated", v);
+        }
+        assert_eq! (g.edges_iter ().collect::<collections::HashMap<_,_>> (), *g.edges ());
+        assert_eq! (g.outbound_iter (&5).err ().unwrap ().to_string (), "Vertex error: Vertex: 5 not found in graph");
+
+        let mut ug = UGraph::new ();
+        ug.add_edge_raw (1,2,3).expect ("Failed to add edge 1 -- 2");
+        ug.add_edge_raw (3,1,5).expect ("Failed to add edge 3 -- 1");
+
+        assert_eq! (ug.neighbours_iter (&1).unwrap ().collect::<collections::HashSet<_>> (), collections::HashSet::from ([2,3]));
+        assert_eq! (ug.edges_iter ().collect::<collections::HashMap<_,_>> (), *ug.edges ());
+    }
+
+    #[test]
+    fn test_self_loops ()
+    {
+        init ();
+        let mut g = Graph::new ();
+        assert! (g.self_loops ());
+        g.set_self_loops (false).expect ("Failed to disallow self loops");
+        assert_eq! (g.add_edge_raw (1,1,0).unwrap_err ().to_string (), "Edge error: edge vertices must be distinct");
+        assert! (g.vertices ().is_empty ());
+
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge_raw (1,1,3).expect ("Failed to add edge 1 -> 1");
+        g.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -> 2");
+        assert_eq! (g.neighbours (&1).unwrap (), collections::HashSet::from ([1,2]));
+        assert_eq! (g.neighbours_iter (&1).unwrap ().count (), 2);
+        assert! (!g.is_source (&1).unwrap ());
+        assert! (!g.is_sink (&1).unwrap ());
+        assert_eq! (g.sources (), collections::HashSet::new ());
+        assert! (g.validate ().is_ok ());
+        assert_eq! (g.set_self_loops (false).unwrap_err ().to_string (), "Edge error: graph contains self loops");
+
+        g.remove_edge_raw (&1, &1).expect ("Failed to remove edge 1 -> 1");
+        assert! (g.is_source (&1).unwrap ());
+        g.set_self_loops (false).expect ("Failed to disallow self loops");
+
+        let mut ug = UGraph::new ();
+        ug.add_edge_raw (1,1,3).expect ("Failed to add edge 1 -- 1");
+        ug.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -- 2");
+        assert_eq! (ug.neighbours (&1).unwrap (), collections::HashSet::from ([1,2]));
+        assert! (ug.validate ().is_ok ());
+        ug.remove_edge_raw (&1, &1).expect ("Failed to remove edge 1 -- 1");
+        assert_eq! (ug.neighbours (&1).unwrap (), collections::HashSet::from ([2]));
+        ug.add_edge_raw (2,2,0).expect ("Failed to add edge 2 -- 2");
+        ug.remove_vertex_raw (&2).expect ("Failed to remove vertex 2");
+        assert! (ug.edges ().is_empty ());
+    }
+
+    #[test]
+    fn test_attribute_values ()
+    {
+        init ();
+        let v = AttributeValue::from (vec![1.5f64, 2.0]);
+        assert_eq! (v.try_float_array ().expect ("Failed to get float array"), &vec![1.5, 2.0]);
+        assert_eq! (v.try_list ().unwrap_err ().to_string (), "Data error: Not a List");
+        assert_eq! (v.try_integer_array ().unwrap_err ().to_string (), "Data error: Not an IntegerArray");
+        assert_eq! (AttributeValue::from (3i64).try_integer_literal ().expect ("Failed to get integer"), 3);
+        assert! (AttributeValue::from (true).try_bool_literal ().expect ("Failed to get bool"));
+        assert_eq! (AttributeValue::from ("x").try_string_literal ().expect ("Failed to get string"), "x");
+        assert_eq! (AttributeValue::from ("x").try_string_map ().unwrap_err ().to_string (), "Data error: Not a StringMap");
+        assert_eq! (v.try_boolean_map ().unwrap_err ().to_string (), "Data error: Not a BooleanMap");
+        assert_eq! (AttributeValue::from (0.5f64).try_float_literal ().expect ("Failed to get float"), 0.5);
+        assert! (AttributeValue::Null.is_null ());
+        assert_eq! (AttributeValue::from (vec![0u8, 171]).to_string (), "00ab");
+
+        let attrs = Attributes::from ([ (String::from ("n"), AttributeValue::from (vec![7i64])) ]);
+        assert_eq! (attrs.get ("n").cloned ().maybe_integer_array ().expect ("Failed to get integer array"), Some (&vec![7]));
+        assert_eq! (attrs.get ("m").cloned ().maybe_map ().expect ("Failed to get map"), None);
+        assert! (attrs.get ("n").cloned ().maybe_bytes ().is_err ());
+
+        let json = serde_json::json! ({ "a": [1, 2.5, "x", null, true], "b": { "c": -4 } });
+        let v = AttributeValue::from (json.clone ());
+        let b = v.try_map ().expect ("Failed to get map")["b"].try_map ().expect ("Failed to get map");
+        assert_eq! (b["c"], AttributeValue::from (-4i64));
+        assert_eq! (serde_json::Value::from (&v), json);
+
+        let s = AttributeValue::from (collections::HashSet::<String>::from ([ String::from ("z"), String::from ("a") ]));
+        assert_eq! (serde_json::Value::from (&s), serde_json::json! (["a", "z"]));
+        assert_eq! (serde_json::Value::from (&AttributeValue::from (f64::NAN)), serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_weights ()
+    {
+        init ();
+        let mut g = Graph::<f64>::new_weighted ();
+        g.add_edge_raw (1,2,0.5).expect ("Failed to add edge 1 -> 2");
+        assert_eq! (g.add_edge_raw (2,3,f64::NAN).unwrap_err ().to_string (), "Data error: NaN is not a valid edge weight");
+        assert! (!g.has_edge_raw (&(2,3)));
+        assert_eq! (GraphAny::weight (&g, &(1,2)).unwrap (), 0.5);
+        assert! (g.validate ().is_ok ());
+
+        let mut ug = UGraph::<u32>::new_weighted_with_name ("u");
+        ug.add_edge_raw (2,1,7).expect ("Failed to add edge 2 -- 1");
+        assert_eq! (ug.edges_iter ().collect::<Vec<_>> (), vec![((1,2), 7)]);
+
+        let mut lg = LabelledGraph::<f64>::new_weighted ();
+        lg.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -> b");
+        lg.add_edge_weighted (String::from ("b"), String::from ("c"), None, 1.5).expect ("Failed to add edge b -> c");
+        let before = lg.clone ();
+        assert! (lg.add_edge_weighted (String::from ("c"), String::from ("d"), None, f64::NAN).is_err ());
+        assert! (lg.add_edge_raw (10, String::from ("x"), 11, String::from ("y"), None, f64::NAN).is_err ());
+        assert! (!lg.has_vertex ("d") && !lg.has_vertex ("x") && !lg.has_vertex ("y"));
+        assert_eq! (lg, before);
+
+        let mut lug = LabelledUGraph::<f64>::new_weighted ();
+        lug.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -- b");
+        let before = lug.clone ();
+        assert! (lug.add_edge_weighted (String::from ("a"), String::from ("c"), None, f64::NAN).is_err ());
+        assert! (lug.add_edge_raw (10, String::from ("x"), 11, String::from ("y"), None, f64::NAN).is_err ());
+        assert! (!lug.has_vertex ("c") && !lug.has_vertex ("x"));
+        assert_eq! (lug, before);
+
+        let (b, c) = (lg.vertex ("b").unwrap (), lg.vertex ("c").unwrap ());
+        assert_eq! (GraphAny::weight (&lg, &(b, c)).unwrap (), 1.5);
+        let s = serde_json::to_string (&lg).expect ("Failed to serialize graph");
+        let lg_de = serde_json::from_str::<Validated<LabelledGraph<f64>>> (&s).expect ("Failed to deserialize graph").into_inner ();
+        assert_eq! (lg_de, lg);
+    }
+
+    #[derive(Clone,Debug,Default,Deserialize,Serialize,PartialEq)]
+    struct Person
+    {
+        age: u32
+    }
+
+    #[derive(Clone,Debug,Default,Deserialize,Serialize,PartialEq)]
+    struct Link
+    {
+        since: u32
+    }
+
+    impl Payload for Person {}
+    impl Payload for Link {}
+
+    #[test]
+    fn test_schema ()
+    {
+        use crate::schema::{AttributeKind, AttributeSpec, GraphSchema};
+        init ();
+        let schema = GraphSchema::new ()
+            .with_vertex_attribute ("kind", AttributeSpec::required (AttributeKind::StringLiteral).with_default (AttributeValue::from ("node")))
+            .with_vertex_attribute ("size", AttributeSpec::optional (AttributeKind::IntegerLiteral))
+            .with_edge_attribute ("cost", AttributeSpec::required (AttributeKind::FloatLiteral));
+
+        let mut g = LabelledGraph::new ();
+        g.add_vertex (String::from ("x"), Some (Attributes::from ([ (String::from ("size"), AttributeValue::from ("big")) ]))).expect ("Failed to add vertex");
+        g.add_edge (String::from ("x"), String::from ("y"), None).expect ("Failed to add edge");
+        assert_eq! (g.set_schema (Some (schema.clone ())).unwrap_err ().to_string (),
+            "Validation error: Schema error: vertex 1:\"x\": attribute size is StringLiteral, expected IntegerLiteral; Schema error: edge (1,2): missing required attribute cost");
+        assert! (g.schema ().is_none ());
+
+        let mut g = LabelledGraph::new ();
+        g.add_vertex (String::from ("a"), None).expect ("Failed to add vertex");
+        g.set_schema (Some (schema.clone ())).expect ("Failed to set schema");
+        assert_eq! (g.vertex_attrs ("a").expect ("Failed to find a").1["kind"], AttributeValue::from ("node"));
+
+        let bad = Attributes::from ([ (String::from ("size"), AttributeValue::from (1.5f64)) ]);
+        assert_eq! (g.add_vertex (String::from ("b"), Some (bad)).unwrap_err ().to_string (), "Schema error: vertex \"b\": attribute size is FloatLiteral, expected IntegerLiteral");
+        assert! (!g.has_vertex ("b"));
+        assert_eq! (g.add_edge (String::from ("a"), String::from ("c"), None).unwrap_err ().to_string (), "Schema error: edge (\"a\",\"c\"): missing required attribute cost");
+        assert! (!g.has_vertex ("c"));
+        let cost = Attributes::from ([ (String::from ("cost"), AttributeValue::from (2.0f64)) ]);
+        g.add_edge_weighted (String::from ("a"), String::from ("c"), Some (cost), 3).expect ("Failed to add edge");
+        assert_eq! (g.vertex_attrs ("c").expect ("Failed to find c").1["kind"], AttributeValue::from ("node"));
+
+        let mut attrs = g.vertex_attrs_mut ("a").expect ("Failed to find a").1;
+        attrs.insert (String::from ("size"), AttributeValue::from (3i64));
+        attrs.commit ().expect ("Failed to commit");
+        let mut attrs = g.vertex_attrs_mut ("a").expect ("Failed to find a").1;
+        attrs.remove ("kind");
+        attrs.insert (String::from ("size"), AttributeValue::from ("huge"));
+        assert_eq! (attrs.commit ().unwrap_err ().to_string (), "Schema error: vertex \"a\": attribute size is StringLiteral, expected IntegerLiteral");
+        assert_eq! (g.vertex_attrs ("a").expect ("Failed to find a").1["size"], AttributeValue::from (3i64));
+        // Removing an attribute with a default puts the default back
+        g.vertex_attrs_mut ("a").expect ("Failed to find a").1.insert (String::from ("kind"), AttributeValue::from ("hub"));
+        g.vertex_attrs_mut ("a").expect ("Failed to find a").1.remove ("kind");
+        assert_eq! (g.vertex_attrs ("a").expect ("Failed to find a").1["kind"], AttributeValue::from ("node"));
+        // An uncommitted change that breaks the schema is rolled back on drop
+        let e = (String::from ("a"), String::from ("c"));
+        g.edge_attrs_mut (&e).expect ("Failed to find edge").1.clear ();
+        assert_eq! (g.edge_attrs (&e).expect ("Failed to find edge").1["cost"], AttributeValue::from (2.0f64));
+        g.check_schema ().expect ("Failed schema check");
+
+        // The schema travels with the graph and is checked on deserialization
+        let s = serde_json::to_string (&g).expect ("Failed to serialize graph");
+        let g_de = serde_json::from_str::<Validated<LabelledGraph>> (&s).expect ("Failed to deserialize graph").into_inner ();
+        assert_eq! (g_de.schema (), Some (&schema));
+        let s = s.replace ("\"size\":{\"IntegerLiteral\":3}", "\"size\":{\"BooleanLiteral\":true}");
+        let r = serde_json::from_str::<Validated<LabelledGraph>> (&s).unwrap_err ().to_string ();
+        assert! (r.contains ("attribute size is BooleanLiteral, expected IntegerLiteral"), "Unexpected error {}", r);
+        let r = serde_json::from_str::<LabelledGraph> (&s).unwrap_err ().to_string ();
+        assert! (r.contains ("attribute size is BooleanLiteral, expected IntegerLiteral"), "Unexpected error {}", r);
+
+        let mut ug = LabelledUGraph::new ();
+        ug.set_schema (Some (schema)).expect ("Failed to set schema");
+        assert! (ug.add_edge (String::from ("a"), String::from ("b"), None).is_err ());
+        assert! (ug.vertices ().is_empty ());
+        ug.add_edge (String::from ("a"), String::from ("b"), Some (Attributes::from ([ (String::from ("cost"), AttributeValue::from (1.0f64)) ]))).expect ("Failed to add edge");
+        ug.check_schema ().expect ("Failed schema check");
+        let s = serde_json::to_string (&ug).expect ("Failed to serialize graph");
+        serde_json::from_str::<LabelledUGraph> (&s).expect ("Failed to deserialize graph");
+        let s = s.replace ("\"cost\":{\"FloatLiteral\":1.0}", "\"cost\":{\"StringLiteral\":\"x\"}");
+        assert! (serde_json::from_str::<LabelledUGraph> (&s).is_err ());
+    }
+
+    #[test]
+    fn test_indexes ()
+    {
+        init ();
+        let gene = AttributeValue::from ("gene");
+        let kind = |k: &str| Attributes::from ([ (String::from ("kind"), AttributeValue::from (k)) ]);
+        let mut g = LabelledGraph::new ();
+        let a = g.add_vertex (String::from ("a"), Some (kind ("gene"))).expect ("Failed to add vertex");
+        g.create_vertex_index ("kind");
+        g.create_vertex_index ("tags");
+        g.create_edge_index ("w");
+        let b = g.add_vertex (String::from ("b"), Some (kind ("gene"))).expect ("Failed to add vertex");
+        let c = g.add_vertex (String::from ("c"), Some (kind ("protein"))).expect ("Failed to add vertex");
+        assert_eq! (g.vertices_where ("kind", &gene).expect ("Failed to query index"), collections::HashSet::from ([a, b]));
+        assert! (g.vertices_where ("size", &gene).is_err ());
+        assert! (g.vertices_where ("kind", &AttributeValue::from (1.0f64)).is_err ());
+
+        // Replacing and mutating attributes moves the vertex between keys
+        g.add_vertex (String::from ("b"), Some (kind ("protein"))).expect ("Failed to add vertex");
+        g.vertex_attrs_mut ("c").expect ("Failed to find c").1.insert (String::from ("tags"), AttributeValue::from (collections::HashSet::from ([ String::from ("x") ])));
+        g.vertex_attrs_mut ("a").expect ("Failed to find a").1.insert (String::from ("kind"), AttributeValue::from ("rna"));
+        assert_eq! (g.vertices_where ("kind", &gene).expect ("Failed to query index"), collections::HashSet::new ());
+        assert_eq! (g.vertices_where ("kind", &AttributeValue::from ("protein")).expect ("Failed to query index"), collections::HashSet::from ([b, c]));
+        assert_eq! (g.vertices_with_member ("tags", "x").expect ("Failed to query index"), collections::HashSet::from ([c]));
+
+        let w = Attributes::from ([ (String::from ("w"), AttributeValue::from (true)) ]);
+        let e = g.add_edge (String::from ("a"), String::from ("b"), Some (w)).expect ("Failed to add edge");
+        assert_eq! (g.edges_where ("w", &AttributeValue::from (true)).expect ("Failed to query index"), collections::HashSet::from ([e]));
+
+        let map = collections::HashMap::from ([ (a, 10), (b, 11) ]);
+        g.remap_raw (&map).expect ("Failed to remap");
+        assert_eq! (g.vertices_where ("kind", &AttributeValue::from ("protein")).expect ("Failed to query index"), collections::HashSet::from ([11, c]));
+        assert_eq! (g.edges_where ("w", &AttributeValue::from (true)).expect ("Failed to query index"), collections::HashSet::from ([(10, 11)]));
+
+        g.retain (&collections::HashSet::from ([10, c])).expect ("Failed to retain");
+        assert_eq! (g.vertices_where ("kind", &AttributeValue::from ("protein")).expect ("Failed to query index"), collections::HashSet::from ([c]));
+        assert! (g.edges_where ("w", &AttributeValue::from (true)).expect ("Failed to query index").is_empty ());
+        g.remove_vertex ("c").expect ("Failed to remove c");
+        assert! (g.vertices_with_member ("tags", "x").expect ("Failed to query index").is_empty ());
+        assert! (g.drop_vertex_index ("tags"));
+        assert! (g.vertices_with_member ("tags", "x").is_err ());
+
+        let mut ug = LabelledUGraph::new ();
+        ug.create_edge_index ("w");
+        let e = ug.add_edge (String::from ("b"), String::from ("a"), Some (Attributes::from ([ (String::from ("w"), AttributeValue::from (1i64)) ]))).expect ("Failed to add edge");
+        assert_eq! (ug.edges_where ("w", &AttributeValue::from (1i64)).expect ("Failed to query index"), collections::HashSet::from ([e]));
+        ug.remove_edge ( &(String::from ("a"), String::from ("b")) ).expect ("Failed to remove edge");
+        assert! (ug.edges_where ("w", &AttributeValue::from (1i64)).expect ("Failed to query index").is_empty ());
+    }
+
+    #[test]
+    fn test_typed_payloads ()
+    {
+        init ();
+        let mut g = LabelledGraph::<i64, Person, Link>::new_weighted ();
+        g.add_vertex (String::from ("a"), Some (Person { age: 40 })).expect ("Failed to add vertex 'a'");
+        g.add_edge (String::from ("a"), String::from ("b"), Some (Link { since: 2001 })).expect ("Failed to add edge a -> b");
+        assert_eq! (g.vertex_attrs ("a").unwrap ().1, &Person { age: 40 });
+        assert_eq! (g.vertex_attrs ("b").unwrap ().1, &Person::default ());
+        g.vertex_attrs_mut ("b").unwrap ().1.age = 7;
+        let e = (String::from ("a"), String::from ("b"));
+        assert_eq! (g.edge_attrs (&e).unwrap ().1.since, 2001);
+        g.edge_attrs_mut (&e).unwrap ().1.since = 2002;
+        assert_eq! (g.edge_attrs_raw (&g.edge (&e).unwrap ()).unwrap (), &Link { since: 2002 });
+
+        let s = serde_json::to_string (&g).expect ("Failed to serialize graph");
+        let g_de = serde_json::from_str::<Validated<LabelledGraph<i64, Person, Link>>> (&s).expect ("Failed to deserialize graph").into_inner ();
+        assert_eq! (g_de.vertex_attrs ("b").unwrap ().1, &Person { age: 7 });
+        assert_eq! (g_de.edge_attrs (&e).unwrap ().1, &Link { since: 2002 });
+
+        let mut ug = LabelledUGraph::<i64, Person, ()>::new_weighted ();
+        ug.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -- b");
+        assert_eq! (ug.edge_attrs (&(String::from ("b"), String::from ("a"))).unwrap ().1, &());
+        assert! (ug.validate ().is_ok ());
+    }
+
+    #[test]
+    fn test_typed_labels ()
+    {
+        init ();
+        let mut g = LabelledGraph::<i64, Attributes, Attributes, (String, u32)>::new_weighted ();
+        let a = (String::from ("tenant"), 1);
+        let b = (String::from ("tenant"), 2);
+        let a_id = g.add_vertex (a.clone (), None).expect ("Failed to add vertex a");
+        let (_, b_id) = g.add_edge (a.clone (), b.clone (), None).expect ("Failed to add edge a -> b");
+        assert_eq! (g.vertex (&a).unwrap (), a_id);
+        assert_eq! (g.edge (&(a.clone (), b.clone ())).unwrap (), (a_id, b_id));
+        assert! (g.has_edge (&(a.clone (), b.clone ())));
+        assert! (!g.has_edge (&(b.clone (), a.clone ())));
+        assert_eq! (g.vertex (&(String::from ("tenant"), 3)).unwrap_err ().to_string (), "Vertex error: Failed to find vertex: (\"tenant\", 3)");
+
+        let c = (String::from ("other"), 1);
+        g.relabel_vertex (&a_id, c.clone ()).expect ("Failed to relabel vertex a");
+        assert! (!g.has_vertex (&a));
+        assert_eq! (g.vertex_label (&a_id).unwrap (), c);
+        assert! (g.relabel_vertex (&b_id, c.clone ()).is_err ());
+
+        let s = serde_json::to_string (&g).expect ("Failed to serialize graph");
+        let g_de = serde_json::from_str::<Validated<LabelledGraph<i64, Attributes, Attributes, (String, u32)>>> (&s).expect ("Failed to deserialize graph").into_inner ();
+        assert_eq! (g_de.vertex_labels (), collections::HashSet::from ([b.clone (), c.clone ()]));
+        assert! (g_de.has_edge (&(c, b)));
+
+        let mut ug = LabelledUGraph::<i64, Attributes, Attributes, u64>::new_weighted ();
+        ug.add_edge (7, 9, None).expect ("Failed to add edge 7 -- 9");
+        assert! (ug.has_edge (&(9, 7)));
+        assert_eq! (ug.vertex_label (&ug.vertex (&7).unwrap ()).unwrap (), 7);
+
+        // Lookups written as a JSON object are still read
+        let mut lg = LabelledGraph::new ();
+        lg.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -> b");
+        let mut v = serde_json::to_value (&lg).expect ("Failed to serialize graph");
+        v["vertex_lookup"] = serde_json::json! ({ "a": lg.vertex ("a").unwrap (), "b": lg.vertex ("b").unwrap () });
+        let lg_de = serde_json::from_value::<Validated<LabelledGraph>> (v).expect ("Failed to deserialize graph").into_inner ();
+        assert! (lg_de.has_edge (&(String::from ("a"), String::from ("b"))));
+    }
+
+    #[test]
+    fn test_self_loops_baseline_json ()
+    {
+        init ();
+        // Written before the self loop policy existed, so without self_loops
+        let s = r#"{"name":"g","vertices":[1,2],"edges":{"(1,1)":3,"(1,2)":0},"inbound":{"1":[1],"2":[1]},"outbound":{"1":[1,2]}}"#;
+        let g = serde_json::from_str::<Validated<Graph>> (s).expect ("Failed to deserialize graph").into_inner ();
+        assert! (g.self_loops ());
+        assert! (g.has_edge_raw (&(1,1)));
+        let s = r#"{"name":"u","vertices":[1],"edges":{"(1,1)":3},"connected":{"1":[1]}}"#;
+        let ug = serde_json::from_str::<Validated<UGraph>> (s).expect ("Failed to deserialize graph").into_inner ();
+        assert! (ug.self_loops ());
+
+        // Labelled graphs never took loops and start with them off; the
+        // policy is the flag of their inner graph
+        let mut lg = LabelledGraph::new ();
+        lg.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -> b");
+        let v = serde_json::to_value (&lg).expect ("Failed to serialize graph");
+        assert_eq! (v["graph"]["self_loops"], serde_json::Value::Bool (false));
+        let lg_de = serde_json::from_value::<Validated<LabelledGraph>> (v).expect ("Failed to deserialize graph").into_inner ();
+        assert! (!lg_de.self_loops ());
+        lg.set_self_loops (true).expect ("Failed to allow self loops");
+        assert! (lg.graph ().self_loops ());
+        lg.add_edge (String::from ("a"), String::from ("a"), None).expect ("Failed to add edge a -> a");
+        let mut v = serde_json::to_value (&lg).expect ("Failed to serialize graph");
+        v["graph"]["self_loops"] = serde_json::Value::Bool (false);
+        assert_eq! (serde_json::from_value::<Validated<LabelledGraph>> (v).unwrap_err ().to_string (), "Validation error: Edge error: edge (2,2) is a self loop but self loops are not allowed");
+    }
+
+    #[test]
+    fn test_self_loops_labelled ()
+    {
+        init ();
+        let mut g = LabelledGraph::new ();
+        assert_eq! (g.add_edge (String::from ("a"), String::from ("a"), None).unwrap_err ().to_string (), "Edge error: edge vertices must be distinct");
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        let (a, b) = g.add_edge (String::from ("a"), String::from ("a"), None).expect ("Failed to add edge a -> a");
+        assert_eq! (a, b);
+        assert! (g.has_edge (&(String::from ("a"), String::from ("a"))));
+        assert_eq! (g.add_edge_raw (a, String::from ("a"), 7, String::from ("a"), None, 0).unwrap_err ().to_string (), "Edge error: self loop vertex ids must be equal");
+        assert! (g.validate ().is_ok ());
+
+        let mut ug = LabelledUGraph::new ();
+        ug.set_self_loops (true).expect ("Failed to allow self loops");
+        ug.add_edge (String::from ("a"), String::from ("a"), None).expect ("Failed to add edge a -- a");
+        assert! (ug.validate ().is_ok ());
+
+        // Merging the endpoints of an edge drops it unless loops are allowed
+        let mut g = Graph::new ();
+        g.add_edge_raw (1,2,0).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,3,0).expect ("Failed to add edge 2 -> 3");
+        let mut gl = g.clone ();
+        g.set_self_loops (false).expect ("Failed to disallow self loops");
+        g.vertex_identification (&collections::BTreeSet::from ([1,2])).expect ("Merge failed");
+        assert_eq! (g.edges (), &collections::HashMap::from ([ ( (1,3), 0 ) ]));
+        gl.vertex_identification (&collections::BTreeSet::from ([1,2])).expect ("Merge failed");
+        assert_eq! (gl.edges (), &collections::HashMap::from ([ ( (1,1), 0 ), ( (1,3), 0 ) ]));
+    }
+
     #[test]
     fn test_parent ()
     {
@@ -2169,6 +4780,21 @@ mod tests
         assert_eq! (elr, (String::from ("a"), String::from ("b")));
     }
 
+    #[test]
+    fn test_edge_attrs_labelled_u ()
+    {
+        init ();
+        let mut g = LabelledUGraph::new ();
+        let attrs = collections::HashMap::<String, AttributeValue>::from ([ (String::from ("k"), AttributeValue::from ("v")) ]);
+        g.add_vertex (String::from ("b"), None).expect ("Failed to add vertex b");
+        let e = g.add_edge (String::from ("a"), String::from ("b"), Some (attrs.clone ())).expect ("Failed to add edge a -- b");
+
+        assert_eq! (e, (1, 2));
+        assert_eq! (g.edge_attrs ( &(String::from ("a"), String::from ("b")) ).expect ("Failed to get edge_attrs a -- b").1, &attrs);
+        assert_eq! (g.edge_attrs ( &(String::from ("b"), String::from ("a")) ).expect ("Failed to get edge_attrs b -- a").1, &attrs);
+        assert_eq! (g.edge_attrs_raw ( &(2, 1) ).expect ("Failed to get edge_attrs_raw 2 -- 1"), &attrs);
+    }
+
     #[test]
     fn test_remove_edge_labelled ()
     {
@@ -2189,6 +4815,20 @@ mod tests
         g.remove_edge (&e).expect ("Failed to remove edge");
     }
 
+    #[test]
+    fn test_remove_edge_labelled_u_reversed ()
+    {
+        init ();
+        let mut g = LabelledUGraph::new ();
+        g.add_vertex (String::from ("a"), None).expect ("Failed to add vertex a");
+        g.add_edge (String::from ("b"), String::from ("a"), None).expect ("Failed to add edge b -- a");
+
+        assert! (g.edge_attrs_raw ( &(1, 2) ).is_ok ());
+        g.remove_edge ( &(String::from ("b"), String::from ("a")) ).expect ("Failed to remove edge b -- a");
+        assert! (g.edge_attrs_raw ( &(1, 2) ).is_err ());
+        assert! (g.edge_attrs_raw ( &(2, 1) ).is_err ());
+    }
+
     #[test]
     fn test_endpoints ()
     {
@@ -2453,6 +5093,71 @@ mod tests
         // TODO: test more
     }
 
+    #[test]
+    fn test_validate ()
+    {
+        init ();
+        let mut g = Graph::new ();
+        g.add_edge_raw (2,1,0).expect ("Failed to add edge 2 -> 1");
+        g.add_edge_raw (1,0,0).expect ("Failed to add edge 1 -> 0");
+        assert_eq! (g.validate (), Ok (()));
+
+        let mut v = serde_json::to_value (g.clone ()).expect ("Failed to serialize Graph");
+        v["edges"]["(0,3)"] = serde_json::json! (1);
+        v["outbound"]["2"] = serde_json::json! ([]);
+        let h = serde_json::from_value::<Graph> (v.clone ()).expect ("Failed to deserialize Graph");
+
+        assert_eq! (h.validate (), Err (crate::error::GraphError::ValidationError (vec![
+            crate::error::GraphError::EdgeError (String::from ("edge (0,3) refers to missing vertex 3")),
+            crate::error::GraphError::EdgeError (String::from ("edge (0,3) is missing from outbound of 0")),
+            crate::error::GraphError::EdgeError (String::from ("edge (0,3) is missing from inbound of 3")),
+            crate::error::GraphError::EdgeError (String::from ("edge (2,1) is missing from outbound of 2"))
+        ])));
+        assert! (serde_json::from_value::<Validated<Graph>> (v).is_err ());
+    }
+
+    #[test]
+    fn test_validate_u ()
+    {
+        init ();
+        let mut g = UGraph::new ();
+        g.add_edge_raw (2,1,0).expect ("Failed to add edge 2 -- 1");
+        assert_eq! (g.validate (), Ok (()));
+
+        g.edges.insert ( (2, 1), 0 );
+        g.connected.get_mut (&1).expect ("Failed to find 1").insert (5);
+
+        assert_eq! (g.validate ().unwrap_err ().to_string (), "Validation error: Edge error: edge (2,1) is not stored with the smaller vertex first; Edge error: connected of 1 lists 5 but there is no edge between them");
+    }
+
+    #[test]
+    fn test_validate_labelled ()
+    {
+        init ();
+        let mut g = LabelledGraph::new ();
+        g.add_edge (String::from ("b"), String::from ("a"), None).expect ("Failed to add edge b -> a");
+        assert_eq! (g.validate (), Ok (()));
+
+        let v = serde_json::to_value (g.clone ()).expect ("Failed to serialize LabelledGraph");
+        let h = serde_json::from_value::<Validated<LabelledGraph>> (v.clone ()).expect ("Failed to deserialize LabelledGraph").into_inner ();
+        assert_eq! (g, h);
+
+        let mut v_bad = v.clone ();
+        v_bad["vertex_label"]["2"] = serde_json::json! ("a");
+        v_bad["vertex_attrs"]["7"] = serde_json::json! ({});
+        v_bad["vertex_id"] = serde_json::json! (2);
+        let h = serde_json::from_value::<LabelledGraph> (v_bad.clone ()).expect ("Failed to deserialize LabelledGraph");
+
+        assert_eq! (h.validate (), Err (crate::error::GraphError::ValidationError (vec![
+            crate::error::GraphError::VertexError (String::from ("vertex id counter 2 would reuse vertex 2")),
+            crate::error::GraphError::VertexError (String::from ("duplicate label \"a\" for vertices 2 and 1")),
+            crate::error::GraphError::VertexError (String::from ("lookup maps \"b\" to vertex 2 which has a different label")),
+            crate::error::GraphError::VertexError (String::from ("attributes for missing vertex 7"))
+        ])));
+        let e = serde_json::from_value::<Validated<LabelledGraph>> (v_bad).unwrap_err ();
+        assert! (e.to_string ().starts_with ("Validation error: Vertex error: vertex id counter 2"));
+    }
+
     #[test]
     fn test_serde_json ()
     {
@@ -0,0 +1,782 @@
+use crate::error;
+use crate::graph;
+use quick_xml::escape;
+use quick_xml::events;
+use std::collections;
+use std::io;
+
+// GraphML has no native collection types. The scalar variants map onto the
+// GraphML types boolean, long, double and string. The composite variants are
+// written as attr.type="string" keys whose <desc> is "json:<Variant>" and whose
+// data is JSON text:
+//   BooleanMap  -> {"k":true,...}   keys sorted
+//   StringArray -> ["a","b",...]    order preserved
+//   StringMap   -> {"k":"v",...}    keys sorted
+//   StringSet   -> ["a","b",...]    sorted
+//   Bytes, FloatArray, IntegerArray -> [1,2,...]
+//   List        -> [{"<Variant>":...},...]  each element tagged by variant
+//   Map         -> {"k":{"<Variant>":...},...}  keys sorted
+//   Null        -> null
+// One key is declared per (domain, attribute name, variant) so an attribute
+// holding different variants on different vertices survives a round trip.
+//
+// Edge weights go through AttributeValue as well: the weight key is a long
+// for integer weights and a double for f64 weights.
+
+const JSON_DESC_PREFIX: &str = "json:";
+
+#[derive(Clone, Debug)]
+pub struct GraphMLOptions
+{
+    // Edge key holding the edge weight, None to leave weights out
+    pub weight_attr: Option<String>,
+    // Allow self loops on the graphs read, GraphML does not record the policy
+    pub self_loops: bool
+}
+
+impl Default for GraphMLOptions
+{
+    fn default () -> Self
+    {
+        Self { weight_attr: Some (String::from ("weight")), self_loops: false }
+    }
+}
+
+impl GraphMLOptions
+{
+    pub fn new () -> Self
+    {
+        Self::default ()
+    }
+
+    pub fn with_weight_attr (mut self, name: Option<&str>) -> Self
+    {
+        self.weight_attr = name.map (|x| x.to_string ());
+        self
+    }
+
+    pub fn with_self_loops (mut self, self_loops: bool) -> Self
+    {
+        self.self_loops = self_loops;
+        self
+    }
+}
+
+type Attrs = collections::HashMap<String, graph::AttributeValue>;
+
+fn variant_name (value: &graph::AttributeValue)
+    -> &'static str
+{
+    match value
+    {
+        graph::AttributeValue::BooleanLiteral (_) => "boolean",
+        graph::AttributeValue::BooleanMap (_) => "BooleanMap",
+        graph::AttributeValue::IntegerLiteral (_) => "long",
+        graph::AttributeValue::FloatLiteral (_) => "double",
+        graph::AttributeValue::StringLiteral (_) => "string",
+        graph::AttributeValue::StringArray (_) => "StringArray",
+        graph::AttributeValue::StringMap (_) => "StringMap",
+        graph::AttributeValue::StringSet (_) => "StringSet",
+        graph::AttributeValue::Bytes (_) => "Bytes",
+        graph::AttributeValue::FloatArray (_) => "FloatArray",
+        graph::AttributeValue::IntegerArray (_) => "IntegerArray",
+        graph::AttributeValue::List (_) => "List",
+        graph::AttributeValue::Map (_) => "Map",
+        graph::AttributeValue::Null => "Null"
+    }
+}
+
+fn encode (value: &graph::AttributeValue)
+    -> Result<String, error::GraphError>
+{
+    let r = match value
+    {
+        graph::AttributeValue::BooleanLiteral (b) => Ok (b.to_string ()),
+        graph::AttributeValue::IntegerLiteral (i) => Ok (i.to_string ()),
+        graph::AttributeValue::FloatLiteral (f) => Ok (f.to_string ()),
+        graph::AttributeValue::StringLiteral (s) => Ok (s.clone ()),
+        graph::AttributeValue::BooleanMap (m) => serde_json::to_string (&m.iter ().collect::<collections::BTreeMap<_, _>> ()),
+        graph::AttributeValue::StringArray (v) => serde_json::to_string (v),
+        graph::AttributeValue::StringMap (m) => serde_json::to_string (&m.iter ().collect::<collections::BTreeMap<_, _>> ()),
+        graph::AttributeValue::StringSet (s) => serde_json::to_string (&s.iter ().collect::<collections::BTreeSet<_>> ()),
+        graph::AttributeValue::Bytes (v) => serde_json::to_string (v),
+        graph::AttributeValue::FloatArray (v) => serde_json::to_string (v),
+        graph::AttributeValue::IntegerArray (v) => serde_json::to_string (v),
+        graph::AttributeValue::List (v) => serde_json::to_string (v),
+        graph::AttributeValue::Map (m) => serde_json::to_string (&m.iter ().collect::<collections::BTreeMap<_, _>> ()),
+        graph::AttributeValue::Null => Ok (String::from ("null"))
+    };
+    r.map_err (|e| error::GraphError::ConversionError (format! ("Failed to encode attribute value {}: {}", value, e)))
+}
+
+fn decode (kind: &str, text: &str)
+    -> Result<graph::AttributeValue, error::GraphError>
+{
+    let invalid = |e: &dyn std::fmt::Display| error::GraphError::DataError (format! ("Invalid {} value '{}': {}", kind, text, e));
+    match kind
+    {
+        "boolean" => {
+            match text.trim ().to_ascii_lowercase ().as_str ()
+            {
+                "true" | "1" => Ok (graph::AttributeValue::BooleanLiteral (true)),
+                "false" | "0" => Ok (graph::AttributeValue::BooleanLiteral (false)),
+                _ => Err (invalid (&"expected true or false"))
+            }
+        },
+        "int" | "long" => text.trim ().parse::<i64> ().map (graph::AttributeValue::IntegerLiteral).map_err (|e| invalid (&e)),
+        "float" | "double" => text.trim ().parse::<f64> ().map (graph::AttributeValue::FloatLiteral).map_err (|e| invalid (&e)),
+        "string" => Ok (graph::AttributeValue::StringLiteral (text.to_string ())),
+        "BooleanMap" => serde_json::from_str (text).map (graph::AttributeValue::BooleanMap).map_err (|e| invalid (&e)),
+        "StringArray" => serde_json::from_str (text).map (graph::AttributeValue::StringArray).map_err (|e| invalid (&e)),
+        "StringMap" => serde_json::from_str (text).map (graph::AttributeValue::StringMap).map_err (|e| invalid (&e)),
+        "StringSet" => serde_json::from_str (text).map (graph::AttributeValue::StringSet).map_err (|e| invalid (&e)),
+        "Bytes" => serde_json::from_str (text).map (graph::AttributeValue::Bytes).map_err (|e| invalid (&e)),
+        "FloatArray" => serde_json::from_str (text).map (graph::AttributeValue::FloatArray).map_err (|e| invalid (&e)),
+        "IntegerArray" => serde_json::from_str (text).map (graph::AttributeValue::IntegerArray).map_err (|e| invalid (&e)),
+        "List" => serde_json::from_str (text).map (graph::AttributeValue::List).map_err (|e| invalid (&e)),
+        "Map" => serde_json::from_str (text).map (graph::AttributeValue::Map).map_err (|e| invalid (&e)),
+        "Null" => serde_json::from_str::<()> (text).map (|_| graph::AttributeValue::Null).map_err (|e| invalid (&e)),
+        _ => Err (error::GraphError::DataError (format! ("Unsupported attr.type '{}'", kind)))
+    }
+}
+
+// (domain, attribute name, variant) -> key id
+type KeyMap = collections::BTreeMap<(&'static str, String, &'static str), String>;
+
+fn collect_keys<'a> (vertex_attrs: impl Iterator<Item = &'a Attrs>, edge_attrs: impl Iterator<Item = &'a Attrs>, weight_variant: &'static str, options: &GraphMLOptions)
+    -> Result<KeyMap, error::GraphError>
+{
+    let mut names = collections::BTreeSet::<(&'static str, String, &'static str)>::new ();
+    for attrs in vertex_attrs
+    {
+        names.extend (attrs.iter ().map (|(k, v)| ("node", k.clone (), variant_name (v))));
+    }
+    for attrs in edge_attrs
+    {
+        if let Some (weight_attr) = &options.weight_attr && attrs.contains_key (weight_attr)
+        {
+            return Err (error::GraphError::ConversionError (format! ("Edge attribute {} clashes with the weight key", weight_attr)));
+        }
+        names.extend (attrs.iter ().map (|(k, v)| ("edge", k.clone (), variant_name (v))));
+    }
+    if let Some (weight_attr) = &options.weight_attr
+    {
+        names.insert ( ("edge", weight_attr.clone (), weight_variant) );
+    }
+    Ok (names.into_iter ().enumerate ().map (|(i, k)| (k, format! ("d{}", i))).collect ())
+}
+
+fn write_data<W> (writer: &mut W, domain: &'static str, attrs: &Attrs, keys: &KeyMap)
+    -> Result<(), error::GraphError>
+    where
+        W: io::Write
+{
+    let mut names = attrs.keys ().collect::<Vec<_>> ();
+    names.sort ();
+    for name in names
+    {
+        let value = &attrs[name];
+        let id = &keys[&(domain, name.clone (), variant_name (value))];
+        writeln! (writer, "      <data key=\"{}\">{}</data>", id, escape::escape (encode (value)?.as_str ()))?;
+    }
+    Ok (())
+}
+
+fn write_document<T> (writer: &mut T, directed: bool, name: &str, vertices: &[(String, &Attrs)], edges: &[(String, String, &Attrs, graph::AttributeValue)], weight_variant: &'static str, options: &GraphMLOptions)
+    -> Result<(), error::GraphError>
+    where
+        T: io::Write
+{
+    let keys = collect_keys (vertices.iter ().map (|x| x.1), edges.iter ().map (|x| x.2), weight_variant, options)?;
+
+    writeln! (writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln! (writer, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd\">")?;
+    for ((domain, attr_name, variant), id) in &keys
+    {
+        let attr_type = if variant.chars ().next ().is_some_and (|c| c.is_uppercase ()) { "string" } else { variant };
+        let head = format! ("  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"", id, domain, escape::escape (attr_name.as_str ()), attr_type);
+        if attr_type == *variant
+        {
+            writeln! (writer, "{}/>", head)?;
+        }
+        else
+        {
+            writeln! (writer, "{}>", head)?;
+            writeln! (writer, "    <desc>{}{}</desc>", JSON_DESC_PREFIX, variant)?;
+            writeln! (writer, "  </key>")?;
+        }
+    }
+
+    let edgedefault = if directed { "directed" } else { "undirected" };
+    if name.is_empty ()
+    {
+        writeln! (writer, "  <graph edgedefault=\"{}\">", edgedefault)?;
+    }
+    else
+    {
+        writeln! (writer, "  <graph id=\"{}\" edgedefault=\"{}\">", escape::escape (name), edgedefault)?;
+    }
+    for (v, attrs) in vertices
+    {
+        writeln! (writer, "    <node id=\"{}\">", escape::escape (v.as_str ()))?;
+        write_data (writer, "node", attrs, &keys)?;
+        writeln! (writer, "    </node>")?;
+    }
+    for (a, b, attrs, w) in edges
+    {
+        writeln! (writer, "    <edge source=\"{}\" target=\"{}\">", escape::escape (a.as_str ()), escape::escape (b.as_str ()))?;
+        if let Some (weight_attr) = &options.weight_attr
+        {
+            writeln! (writer, "      <data key=\"{}\">{}</data>", keys[&("edge", weight_attr.clone (), weight_variant)], escape::escape (encode (w)?.as_str ()))?;
+        }
+        write_data (writer, "edge", attrs, &keys)?;
+        writeln! (writer, "    </edge>")?;
+    }
+    writeln! (writer, "  </graph>")?;
+    writeln! (writer, "</graphml>")?;
+    Ok (())
+}
+
+fn sorted_vertices (vertices: &collections::HashSet<usize>)
+    -> Vec<usize>
+{
+    let mut r = vertices.iter ().copied ().collect::<Vec<_>> ();
+    r.sort ();
+    r
+}
+
+fn sorted_edges<W> (edges: &collections::HashMap<(usize, usize), W>)
+    -> Vec<((usize, usize), W)>
+    where
+        W: graph::Weight
+{
+    let mut r = edges.iter ().map (|(e, w)| (*e, *w)).collect::<Vec<_>> ();
+    r.sort_unstable_by_key (|x| x.0);
+    r
+}
+
+pub fn write_labelled_graph<W, T> (g: &graph::LabelledGraph<W>, writer: &mut T, options: &GraphMLOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + Into<graph::AttributeValue>,
+        T: io::Write
+{
+    let vertices = sorted_vertices (g.vertices ()).into_iter ()
+        .map (|v| Ok ( (g.vertex_label (&v)?, g.vertex_attrs_raw (&v)?) ))
+        .collect::<Result<Vec<_>, error::GraphError>> ()?;
+    let edges = sorted_edges (g.edges ()).into_iter ()
+        .map (|(e, w)| {
+            let (a, b) = g.edge_label (&e)?;
+            Ok ( (a, b, g.edge_attrs_raw (&e)?, w.into ()) )
+        })
+        .collect::<Result<Vec<_>, error::GraphError>> ()?;
+    write_document (writer, true, &g.graph ().name (), &vertices, &edges, variant_name (&W::zero ().into ()), options)
+}
+
+pub fn write_labelled_ugraph<W, T> (g: &graph::LabelledUGraph<W>, writer: &mut T, options: &GraphMLOptions)
+    -> Result<(), error::GraphError>
+    where
+        W: graph::Weight + Into<graph::AttributeValue>,
+        T: io::Write
+{
+    let vertices = sorted_vertices (g.vertices ()).into_iter ()
+        .map (|v| Ok ( (g.vertex_label (&v)?, g.vertex_attrs_raw (&v)?) ))
+        .collect::<Result<Vec<_>, error::GraphError>> ()?;
+    let edges = sorted_edges (g.edges ()).into_iter ()
+        .map (|(e, w)| {
+            let (a, b) = g.edge_label (&e)?;
+            Ok ( (a, b, g.edge_attrs_raw (&e)?, w.into ()) )
+        })
+        .collect::<Result<Vec<_>, error::GraphError>> ()?;
+    write_document (writer, false, &g.graph ().name (), &vertices, &edges, variant_name (&W::zero ().into ()), options)
+}
+
+#[derive(Default)]
+struct KeySpec
+{
+    domain: String,
+    name: String,
+    kind: String,
+    default: Option<String>
+}
+
+// (key id, text)
+type DataValue = (String, String);
+
+#[derive(Default)]
+struct GraphMLDocument
+{
+    directed: bool,
+    name: String,
+    keys: collections::HashMap<String, KeySpec>,
+    vertices: Vec<(String, Vec<DataValue>)>,
+    edges: Vec<(String, String, Vec<DataValue>)>
+}
+
+enum Element
+{
+    Key (String),
+    Node,
+    Edge,
+    Other
+}
+
+fn attributes (e: &events::BytesStart)
+    -> Result<collections::HashMap<String, String>, error::GraphError>
+{
+    e.attributes ()
+        .map (|a| {
+            let a = a.map_err (|e| error::GraphError::DataError (format! ("Invalid attribute: {}", e)))?;
+            let key = String::from_utf8_lossy (a.key.local_name ().as_ref ()).into_owned ();
+            let value = a.unescape_value ().map_err (|e| error::GraphError::DataError (format! ("Invalid attribute value: {}", e)))?;
+            Ok ( (key, value.into_owned ()) )
+        })
+        .collect ()
+}
+
+fn required (attrs: &collections::HashMap<String, String>, element: &str, name: &str)
+    -> Result<String, error::GraphError>
+{
+    attrs.get (name).cloned ().ok_or (error::GraphError::DataError (format! ("<{}> is missing the {} attribute", element, name)))
+}
+
+fn parse<R> (reader: R)
+    -> Result<GraphMLDocument, error::GraphError>
+    where
+        R: io::Read
+{
+    let mut xml = quick_xml::Reader::from_reader (io::BufReader::new (reader));
+    let mut buf = Vec::<u8>::new ();
+    let mut document = GraphMLDocument::default ();
+    let mut stack = Vec::<Element>::new ();
+    let mut graph_depth = 0usize;
+    let mut text = String::new ();
+    // data or default currently being read and its key id
+    let mut data_key: Option<String> = None;
+
+    loop
+    {
+        let position = xml.buffer_position ();
+        let event = xml.read_event_into (&mut buf)
+            .map_err (|e| error::GraphError::DataError (format! ("Invalid GraphML at byte {}: {}", position, e)))?;
+        let (start, end) = match &event
+        {
+            events::Event::Start (e) => (Some (e.clone ()), false),
+            events::Event::Empty (e) => (Some (e.clone ()), true),
+            _ => (None, false)
+        };
+
+        if let Some (e) = start
+        {
+            let attrs = attributes (&e)?;
+            let element = match e.local_name ().as_ref ()
+            {
+                b"key" => {
+                    let id = required (&attrs, "key", "id")?;
+                    document.keys.insert (id.clone (), KeySpec {
+                        domain: attrs.get ("for").cloned ().unwrap_or (String::from ("all")),
+                        name: attrs.get ("attr.name").cloned ().unwrap_or (id.clone ()),
+                        kind: attrs.get ("attr.type").cloned ().unwrap_or (String::from ("string")),
+                        default: None
+                    });
+                    Element::Key (id)
+                },
+                b"graph" => {
+                    if graph_depth > 0
+                    {
+                        return Err (error::GraphError::DataError (String::from ("Nested graphs are not supported")));
+                    }
+                    graph_depth += 1;
+                    document.directed = required (&attrs, "graph", "edgedefault")? == "directed";
+                    document.name = attrs.get ("id").cloned ().unwrap_or_default ();
+                    Element::Other
+                },
+                b"node" => {
+                    document.vertices.push ( (required (&attrs, "node", "id")?, Vec::new ()) );
+                    Element::Node
+                },
+                b"edge" => {
+                    if let Some (directed) = attrs.get ("directed") && ( directed == "true" ) != document.directed
+                    {
+                        return Err (error::GraphError::DataError (String::from ("Mixed directed and undirected edges are not supported")));
+                    }
+                    document.edges.push ( (required (&attrs, "edge", "source")?, required (&attrs, "edge", "target")?, Vec::new ()) );
+                    Element::Edge
+                },
+                b"hyperedge" => return Err (error::GraphError::DataError (String::from ("Hyperedges are not supported"))),
+                b"data" => {
+                    data_key = Some (required (&attrs, "data", "key")?);
+                    text.clear ();
+                    Element::Other
+                },
+                b"default" | b"desc" => {
+                    text.clear ();
+                    Element::Other
+                },
+                _ => Element::Other
+            };
+            if !end
+            {
+                stack.push (element);
+            }
+            else if e.local_name ().as_ref () == b"data"
+            {
+                // <data key="..."/> carries an empty value
+                let key = data_key.take ().unwrap_or_default ();
+                match stack.last_mut ()
+                {
+                    Some (Element::Node) => if let Some (v) = document.vertices.last_mut () { v.1.push ( (key, String::new ()) ); },
+                    Some (Element::Edge) => if let Some (e) = document.edges.last_mut () { e.2.push ( (key, String::new ()) ); },
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        match event
+        {
+            events::Event::Text (e) => {
+                text.push_str (&e.unescape ().map_err (|e| error::GraphError::DataError (format! ("Invalid text at byte {}: {}", position, e)))?);
+            },
+            events::Event::CData (e) => {
+                text.push_str (&String::from_utf8_lossy (&e));
+            },
+            events::Event::End (e) => {
+                stack.pop ();
+                match e.local_name ().as_ref ()
+                {
+                    b"data" => {
+                        let key = data_key.take ().unwrap_or_default ();
+                        match stack.last_mut ()
+                        {
+                            Some (Element::Node) => if let Some (v) = document.vertices.last_mut () { v.1.push ( (key, text.clone ()) ); },
+                            Some (Element::Edge) => if let Some (e) = document.edges.last_mut () { e.2.push ( (key, text.clone ()) ); },
+                            // Graph level data has no equivalent
+                            _ => {}
+                        }
+                    },
+                    b"default" => {
+                        if let Some (Element::Key (id)) = stack.last () && let Some (spec) = document.keys.get_mut (id)
+                        {
+                            spec.default = Some (text.clone ());
+                        }
+                    },
+                    b"desc" => {
+                        if let Some (Element::Key (id)) = stack.last ()
+                            && let Some (spec) = document.keys.get_mut (id)
+                            && let Some (variant) = text.trim ().strip_prefix (JSON_DESC_PREFIX)
+                        {
+                            spec.kind = variant.to_string ();
+                        }
+                    },
+                    b"graph" => graph_depth -= 1,
+                    _ => {}
+                }
+            },
+            events::Event::Eof => break,
+            _ => {}
+        }
+        buf.clear ();
+    }
+
+    if stack.is_empty ()
+    {
+        Ok (document)
+    }
+    else
+    {
+        Err (error::GraphError::DataError (String::from ("Unexpected end of GraphML document")))
+    }
+}
+
+fn resolve_attrs<W> (document: &GraphMLDocument, domain: &str, data: &[DataValue], weight_attr: Option<&str>)
+    -> Result<(Attrs, Option<W>), error::GraphError>
+    where
+        W: TryFrom<graph::AttributeValue, Error = error::GraphError>
+{
+    let mut attrs = Attrs::new ();
+    let mut weight = None;
+    let applies = |spec: &KeySpec| spec.domain == domain || spec.domain == "all";
+
+    for (id, spec) in &document.keys
+    {
+        if let Some (default) = &spec.default && applies (spec) && !data.iter ().any (|(k, _)| k == id)
+        {
+            attrs.insert (spec.name.clone (), decode (&spec.kind, default)?);
+        }
+    }
+    for (id, text) in data
+    {
+        let spec = document.keys.get (id).ok_or (error::GraphError::DataError (format! ("<data> refers to undeclared key {}", id)))?;
+        if !applies (spec)
+        {
+            return Err (error::GraphError::DataError (format! ("Key {} is declared for {} but used on {}", id, spec.domain, domain)));
+        }
+        attrs.insert (spec.name.clone (), decode (&spec.kind, text)?);
+    }
+    if let Some (weight_attr) = weight_attr && let Some (w) = attrs.remove (weight_attr)
+    {
+        weight = match W::try_from (w)
+        {
+            Ok (w) => Some (w),
+            Err (error::GraphError::ConversionError (e)) => return Err (error::GraphError::DataError (format! ("Edge weight {}", e))),
+            Err (e) => return Err (e)
+        };
+    }
+    Ok ( (attrs, weight) )
+}
+
+pub fn read_labelled_graph<R> (reader: R, options: &GraphMLOptions)
+    -> Result<graph::LabelledGraph, error::GraphError>
+    where
+        R: io::Read
+{
+    read_labelled_graph_weighted (reader, options)
+}
+
+pub fn read_labelled_graph_weighted<W, R> (reader: R, options: &GraphMLOptions)
+    -> Result<graph::LabelledGraph<W>, error::GraphError>
+    where
+        W: graph::Weight + TryFrom<graph::AttributeValue, Error = error::GraphError>,
+        R: io::Read
+{
+    let document = parse (reader)?;
+    if document.directed
+    {
+        let mut g = graph::LabelledGraph::new_weighted_with_name (&document.name);
+        g.set_self_loops (options.self_loops)?;
+        for (v, data) in &document.vertices
+        {
+            let (attrs, _) = resolve_attrs::<W> (&document, "node", data, None)?;
+            g.add_vertex (v.clone (), Some (attrs))?;
+        }
+        for (a, b, data) in &document.edges
+        {
+            if !g.has_vertex (a) || !g.has_vertex (b)
+            {
+                return Err (error::GraphError::DataError (format! ("Edge ({}, {}) refers to an undeclared node", a, b)));
+            }
+            let (attrs, weight) = resolve_attrs (&document, "edge", data, options.weight_attr.as_deref ())?;
+            g.add_edge_weighted (a.clone (), b.clone (), Some (attrs), weight.unwrap_or (W::zero ()))?;
+        }
+        Ok (g)
+    }
+    else
+    {
+        Err (error::GraphError::DataError (String::from ("Expected a directed graph but found an undirected graph")))
+    }
+}
+
+pub fn read_labelled_ugraph<R> (reader: R, options: &GraphMLOptions)
+    -> Result<graph::LabelledUGraph, error::GraphError>
+    where
+        R: io::Read
+{
+    read_labelled_ugraph_weighted (reader, options)
+}
+
+pub fn read_labelled_ugraph_weighted<W, R> (reader: R, options: &GraphMLOptions)
+    -> Result<graph::LabelledUGraph<W>, error::GraphError>
+    where
+        W: graph::Weight + TryFrom<graph::AttributeValue, Error = error::GraphError>,
+        R: io::Read
+{
+    let document = parse (reader)?;
+    if document.directed
+    {
+        Err (error::GraphError::DataError (String::from ("Expected an undirected graph but found a directed graph")))
+    }
+    else
+    {
+        let mut g = graph::LabelledUGraph::new_weighted_with_name (&document.name);
+        g.set_self_loops (options.self_loops)?;
+        for (v, data) in &document.vertices
+        {
+            let (attrs, _) = resolve_attrs::<W> (&document, "node", data, None)?;
+            g.add_vertex (v.clone (), Some (attrs))?;
+        }
+        for (a, b, data) in &document.edges
+        {
+            if !g.has_vertex (a) || !g.has_vertex (b)
+            {
+                return Err (error::GraphError::DataError (format! ("Edge ({}, {}) refers to an undeclared node", a, b)));
+            }
+            let (attrs, weight) = resolve_attrs (&document, "edge", data, options.weight_attr.as_deref ())?;
+            g.add_edge_weighted (a.clone (), b.clone (), Some (attrs), weight.unwrap_or (W::zero ()))?;
+        }
+        Ok (g)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::graph;
+    use std::collections;
+    use std::sync;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| {
+            let _ = env_logger::try_init ();
+        });
+    }
+
+    fn attrs ()
+        -> collections::HashMap<String, graph::AttributeValue>
+    {
+        collections::HashMap::<String, graph::AttributeValue>::from ([
+            ( String::from ("flag"), graph::AttributeValue::from (true) ),
+            ( String::from ("count"), graph::AttributeValue::from (3i64) ),
+            ( String::from ("ratio"), graph::AttributeValue::from (0.25f64) ),
+            ( String::from ("name"), graph::AttributeValue::from ("<a & \"b\">") ),
+            ( String::from ("path"), graph::AttributeValue::from (vec![String::from ("z"), String::from ("a")]) ),
+            ( String::from ("props"), graph::AttributeValue::from (collections::HashMap::<String, String>::from ([ (String::from ("k"), String::from ("v")) ])) ),
+            ( String::from ("tags"), graph::AttributeValue::from (collections::HashSet::<String>::from ([ String::from ("x"), String::from ("y") ])) ),
+            ( String::from ("seen"), graph::AttributeValue::BooleanMap (collections::HashMap::<String, bool>::from ([ (String::from ("q"), false) ])) ),
+            ( String::from ("raw"), graph::AttributeValue::from (vec![1u8, 2, 254]) ),
+            ( String::from ("scores"), graph::AttributeValue::from (vec![0.5f64, 1e-3]) ),
+            ( String::from ("ranks"), graph::AttributeValue::from (vec![3i64, -1]) ),
+            ( String::from ("mixed"), graph::AttributeValue::from (vec![graph::AttributeValue::from (1i64), graph::AttributeValue::from ("<1>"), graph::AttributeValue::Null]) ),
+            ( String::from ("nested"), graph::AttributeValue::from (collections::HashMap::<String, graph::AttributeValue>::from ([ (String::from ("inner"), graph::AttributeValue::from (vec![String::from ("s")])) ])) ),
+            ( String::from ("missing"), graph::AttributeValue::Null )
+        ])
+    }
+
+    #[test]
+    fn test_round_trip_labelled_graph ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new_with_name ("g");
+        g.add_vertex (String::from ("a"), Some (attrs ())).expect ("Failed to add vertex");
+        g.add_vertex (String::from ("b"), None).expect ("Failed to add vertex");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (attrs ()), -7).expect ("Failed to add edge");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), None, 2).expect ("Failed to add edge");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::GraphMLOptions::new ()).expect ("Failed to write graphml");
+        let h = super::read_labelled_graph (out.as_slice (), &super::GraphMLOptions::new ()).expect ("Failed to read graphml");
+
+        assert_eq! (h.graph ().name (), "g");
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph");
+        let e = h.edge ( &(String::from ("a"), String::from ("b")) ).expect ("Failed to find edge");
+        assert_eq! (h.edges ()[&e], -7);
+    }
+
+    #[test]
+    fn test_round_trip_labelled_ugraph ()
+    {
+        init ();
+        let mut g = graph::LabelledUGraph::new ();
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (attrs ()), 4).expect ("Failed to add edge");
+        g.vertex_attrs_mut ("b").expect ("Failed to find b").1.insert (String::from ("count"), graph::AttributeValue::from ("three"));
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&g, &mut out, &super::GraphMLOptions::new ()).expect ("Failed to write graphml");
+        let h = super::read_labelled_ugraph (out.as_slice (), &super::GraphMLOptions::new ()).expect ("Failed to read graphml");
+
+        assert_eq! (h.vertex_labels (), g.vertex_labels ());
+        for v in g.vertex_labels ()
+        {
+            assert_eq! (h.vertex_attrs (&v).expect ("Failed to find vertex").1, g.vertex_attrs (&v).expect ("Failed to find vertex").1);
+        }
+        let e = (String::from ("b"), String::from ("a"));
+        assert_eq! (h.edge_attrs (&e).expect ("Failed to find edge").1, g.edge_attrs (&e).expect ("Failed to find edge").1);
+        assert_eq! (h.vertex_attrs ("b").expect ("Failed to find b").1["count"], graph::AttributeValue::from ("three"));
+        assert! (super::read_labelled_graph (out.as_slice (), &super::GraphMLOptions::new ()).is_err ());
+    }
+
+    #[test]
+    fn test_read_defaults ()
+    {
+        init ();
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+              <key id="c" for="node" attr.name="color" attr.type="string"><default>yellow</default></key>
+              <key id="w" for="edge" attr.name="weight" attr.type="double"/>
+              <key id="n" for="all" attr.name="note" attr.type="int"/>
+              <graph id="G" edgedefault="directed">
+                <node id="n0"><data key="c">green</data></node>
+                <node id="n1"/>
+                <edge source="n0" target="n1"><data key="n">5</data></edge>
+              </graph>
+            </graphml>"#;
+
+        let g = super::read_labelled_graph (input.as_bytes (), &super::GraphMLOptions::new ().with_weight_attr (None)).expect ("Failed to read graphml");
+        assert_eq! (g.vertex_attrs ("n0").expect ("Failed to find n0").1["color"], graph::AttributeValue::from ("green"));
+        assert_eq! (g.vertex_attrs ("n1").expect ("Failed to find n1").1["color"], graph::AttributeValue::from ("yellow"));
+        assert_eq! (g.edge_attrs ( &(String::from ("n0"), String::from ("n1")) ).expect ("Failed to find edge").1["note"], graph::AttributeValue::from (5i64));
+
+        let r = super::read_labelled_graph (input.replace ("<data key=\"n\">5</data>", "<data key=\"w\">1.5</data>").as_bytes (), &super::GraphMLOptions::new ());
+        assert_eq! (r.unwrap_err ().to_string (), "Data error: Edge weight 1.5 is not an integer");
+    }
+
+    #[test]
+    fn test_read_errors ()
+    {
+        init ();
+        let cases = [
+            ( r#"<graphml><graph edgedefault="directed"><edge source="a" target="b"/></graph></graphml>"#, "Data error: Edge (a, b) refers to an undeclared node" ),
+            ( r#"<graphml><graph edgedefault="directed"><node id="a"><data key="k">1</data></node></graph></graphml>"#, "Data error: <data> refers to undeclared key k" ),
+            ( r#"<graphml><key id="k" for="node" attr.type="long"/><graph edgedefault="directed"><node id="a"><data key="k">x</data></node></graph></graphml>"#, "Data error: Invalid long value 'x': invalid digit found in string" ),
+            ( r#"<graphml><graph edgedefault="directed"><node/></graph></graphml>"#, "Data error: <node> is missing the id attribute" ),
+            ( r#"<graphml><graph edgedefault="directed"><node id="a">"#, "Data error: Unexpected end of GraphML document" )
+        ];
+
+        for (input, expected) in cases
+        {
+            let r = super::read_labelled_graph (input.as_bytes (), &super::GraphMLOptions::new ());
+            assert_eq! (r.unwrap_err ().to_string (), expected, "Unexpected error for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_self_loops ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new_with_name ("g");
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge_weighted (String::from ("a"), String::from ("a"), Some (attrs ()), 5).expect ("Failed to add edge a -> a");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), None, 1).expect ("Failed to add edge a -> b");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::GraphMLOptions::new ()).expect ("Failed to write graphml");
+        assert! (super::read_labelled_graph (out.as_slice (), &super::GraphMLOptions::new ()).is_err ());
+        let h = super::read_labelled_graph (out.as_slice (), &super::GraphMLOptions::new ().with_self_loops (true)).expect ("Failed to read graphml");
+        assert! (h.self_loops ());
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph");
+
+        let mut u = graph::LabelledUGraph::new ();
+        u.set_self_loops (true).expect ("Failed to allow self loops");
+        u.add_edge_weighted (String::from ("a"), String::from ("a"), None, 2).expect ("Failed to add edge a - a");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&u, &mut out, &super::GraphMLOptions::new ()).expect ("Failed to write graphml");
+        let h = super::read_labelled_ugraph (out.as_slice (), &super::GraphMLOptions::new ().with_self_loops (true)).expect ("Failed to read graphml");
+        assert_eq! (h.edges ().values ().copied ().collect::<Vec<_>> (), vec![2]);
+    }
+
+    #[test]
+    fn test_round_trip_weighted ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::<f64>::new_weighted_with_name ("g");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), None, 0.25).expect ("Failed to add edge a -> b");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), None, -3.0).expect ("Failed to add edge b -> c");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_graph (&g, &mut out, &super::GraphMLOptions::new ()).expect ("Failed to write graphml");
+        assert! (String::from_utf8 (out.clone ()).expect ("Invalid utf8").contains ("attr.name=\"weight\" attr.type=\"double\""));
+        let h = super::read_labelled_graph_weighted::<f64, _> (out.as_slice (), &super::GraphMLOptions::new ()).expect ("Failed to read graphml");
+        assert_eq! (h, g);
+        assert! (super::read_labelled_graph (out.as_slice (), &super::GraphMLOptions::new ()).unwrap_err ().to_string ().ends_with ("is not an integer"));
+
+        let mut u = graph::LabelledUGraph::<u32>::new_weighted ();
+        u.add_edge_weighted (String::from ("a"), String::from ("b"), None, 4).expect ("Failed to add edge a -- b");
+        let mut out = Vec::<u8>::new ();
+        super::write_labelled_ugraph (&u, &mut out, &super::GraphMLOptions::new ()).expect ("Failed to write graphml");
+        let h = super::read_labelled_ugraph_weighted::<f64, _> (out.as_slice (), &super::GraphMLOptions::new ()).expect ("Failed to read graphml");
+        assert_eq! (h.edges ().values ().copied ().collect::<Vec<_>> (), vec![4.0]);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::error;
+use crate::graph;
+use std::collections;
+use std::hash;
+
+// Opt-in secondary indexes over the attributes of labelled graph payloads.
+// An index on an attribute name maps each BooleanLiteral, IntegerLiteral and
+// StringLiteral value to the ids holding it, and each member of a StringSet
+// value to the ids whose set contains it. Other variants are not indexed.
+// Indexes are not serialized, a deserialized graph starts without any.
+
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub enum IndexKey
+{
+    Boolean (bool),
+    Integer (i64),
+    String (String)
+}
+
+impl IndexKey
+{
+    pub fn of (value: &graph::AttributeValue)
+        -> Option<Self>
+    {
+        match value
+        {
+            graph::AttributeValue::BooleanLiteral (b) => Some (IndexKey::Boolean (*b)),
+            graph::AttributeValue::IntegerLiteral (i) => Some (IndexKey::Integer (*i)),
+            graph::AttributeValue::StringLiteral (s) => Some (IndexKey::String (s.clone ())),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone,Debug)]
+struct NameIndex<K>
+{
+    values: collections::HashMap<IndexKey, collections::HashSet<K>>,
+    members: collections::HashMap<String, collections::HashSet<K>>
+}
+
+#[derive(Clone,Debug)]
+pub struct AttributeIndex<K>
+{
+    names: collections::HashMap<String, NameIndex<K>>
+}
+
+impl<K> Default for AttributeIndex<K>
+{
+    fn default () -> Self
+    {
+        Self { names: collections::HashMap::new () }
+    }
+}
+
+impl<K> AttributeIndex<K>
+    where
+        K: Copy + Eq + hash::Hash
+{
+    pub fn is_empty (&self)
+        -> bool
+    {
+        self.names.is_empty ()
+    }
+
+    pub (crate) fn create<'a, T, I> (&mut self, name: &str, payloads: I)
+        where
+            T: graph::Payload + 'a,
+            I: Iterator<Item = (&'a K, &'a T)>,
+            K: 'a
+    {
+        let mut name_index = NameIndex { values: collections::HashMap::new (), members: collections::HashMap::new () };
+        for (id, payload) in payloads
+        {
+            if let Some (value) = payload.attributes ().and_then (|attrs| attrs.get (name))
+            {
+                Self::add (&mut name_index, *id, value);
+            }
+        }
+        self.names.insert (name.to_string (), name_index);
+    }
+
+    pub (crate) fn remove_index (&mut self, name: &str)
+        -> bool
+    {
+        self.names.remove (name).is_some ()
+    }
+
+    // Rebuilds every index after ids or values changed wholesale
+    pub (crate) fn rebuild<'a, T, I> (&mut self, payloads: I)
+        where
+            T: graph::Payload + 'a,
+            I: Iterator<Item = (&'a K, &'a T)> + Clone,
+            K: 'a
+    {
+        let names = self.names.keys ().cloned ().collect::<Vec<_>> ();
+        for name in names
+        {
+            self.create (&name, payloads.clone ());
+        }
+    }
+
+    pub (crate) fn insert<T> (&mut self, id: K, payload: &T)
+        where
+            T: graph::Payload
+    {
+        if let Some (attrs) = payload.attributes ()
+        {
+            self.insert_attrs (id, attrs);
+        }
+    }
+
+    pub (crate) fn insert_attrs (&mut self, id: K, attrs: &graph::Attributes)
+    {
+        for (name, name_index) in self.names.iter_mut ()
+        {
+            if let Some (value) = attrs.get (name)
+            {
+                Self::add (name_index, id, value);
+            }
+        }
+    }
+
+    pub (crate) fn remove<T> (&mut self, id: &K, payload: &T)
+        where
+            T: graph::Payload
+    {
+        if let Some (attrs) = payload.attributes ()
+        {
+            self.remove_attrs (id, attrs);
+        }
+    }
+
+    pub (crate) fn remove_attrs (&mut self, id: &K, attrs: &graph::Attributes)
+    {
+        for (name, name_index) in self.names.iter_mut ()
+        {
+            if let Some (value) = attrs.get (name)
+            {
+                if let Some (key) = IndexKey::of (value)
+                    && let Some (ids) = name_index.values.get_mut (&key)
+                {
+                    ids.remove (id);
+                    if ids.is_empty ()
+                    {
+                        name_index.values.remove (&key);
+                    }
+                }
+                if let graph::AttributeValue::StringSet (set) = value
+                {
+                    for member in set
+                    {
+                        if let Some (ids) = name_index.members.get_mut (member)
+                        {
+                            ids.remove (id);
+                            if ids.is_empty ()
+                            {
+                                name_index.members.remove (member);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Ids whose attribute name equals value
+    pub fn get (&self, name: &str, value: &graph::AttributeValue)
+        -> Result<collections::HashSet<K>, error::GraphError>
+    {
+        let name_index = self.names.get (name).ok_or (error::GraphError::DataError (format! ("No index on attribute {}", name)))?;
+        let key = IndexKey::of (value).ok_or (error::GraphError::DataError (format! ("Attribute value {} cannot be looked up in an index", value)))?;
+        Ok (name_index.values.get (&key).cloned ().unwrap_or_default ())
+    }
+
+    // Ids whose StringSet attribute name contains member
+    pub fn get_member (&self, name: &str, member: &str)
+        -> Result<collections::HashSet<K>, error::GraphError>
+    {
+        let name_index = self.names.get (name).ok_or (error::GraphError::DataError (format! ("No index on attribute {}", name)))?;
+        Ok (name_index.members.get (member).cloned ().unwrap_or_default ())
+    }
+
+    fn add (name_index: &mut NameIndex<K>, id: K, value: &graph::AttributeValue)
+    {
+        if let Some (key) = IndexKey::of (value)
+        {
+            name_index.values.entry (key).or_default ().insert (id);
+        }
+        else if let graph::AttributeValue::StringSet (set) = value
+        {
+            for member in set
+            {
+                name_index.members.entry (member.clone ()).or_default ().insert (id);
+            }
+        }
+    }
+}
@@ -0,0 +1,515 @@
+use crate::error;
+use crate::graph;
+use serde::{Deserialize, Serialize};
+use std::collections;
+use std::io;
+
+// Interchange schema, version 1
+//
+// {
+//   "version": 1,
+//   "kind": "graph" | "ugraph" | "labelled_graph" | "labelled_ugraph",
+//   "name": "...",
+//   "self_loops": true,
+//   "vertices": [ { "id": 1, "label": "a", "attrs": { "colour": { "StringLiteral": "red" } } } ],
+//   "edges": [ { "source": 1, "target": 2, "weight": 0, "attrs": {} } ]
+// }
+//
+// Vertices are sorted by id and edges by (source, target); undirected edges are
+// written with source < target. label is only present for the labelled kinds
+// and attrs is omitted when empty. self_loops is only written when it differs
+// from the default of the kind: the raw kinds allow self loops and the
+// labelled kinds do not. Self loops are rejected unless allowed. Weights and
+// attribute values use their serde representation, so the weight type of the
+// graph carries over; labels are Strings and attributes attribute maps. The
+// internal lookups (vertex_lookup,
+// inbound, outbound, connected) are not stored; from_interchange rebuilds them
+// after checking the document for duplicate or dangling ids and labels.
+
+pub const VERSION: u32 = 1;
+
+#[derive(Clone,Copy,Debug,Deserialize,Serialize,PartialEq)]
+pub enum InterchangeKind
+{
+    #[serde(rename="graph")]
+    Graph,
+    #[serde(rename="ugraph")]
+    UGraph,
+    #[serde(rename="labelled_graph")]
+    LabelledGraph,
+    #[serde(rename="labelled_ugraph")]
+    LabelledUGraph
+}
+
+impl InterchangeKind
+{
+    fn directed (&self)
+        -> bool
+    {
+        matches! (self, InterchangeKind::Graph | InterchangeKind::LabelledGraph)
+    }
+
+    fn labelled (&self)
+        -> bool
+    {
+        matches! (self, InterchangeKind::LabelledGraph | InterchangeKind::LabelledUGraph)
+    }
+
+    fn default_self_loops (&self)
+        -> bool
+    {
+        !self.labelled ()
+    }
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
+pub struct InterchangeVertex
+{
+    pub id: usize,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if="collections::BTreeMap::is_empty")]
+    pub attrs: collections::BTreeMap<String, graph::AttributeValue>
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
+#[serde(bound(serialize = "W: Serialize", deserialize = "W: graph::Weight + Deserialize<'de>"))]
+pub struct InterchangeEdge<W = i64>
+{
+    pub source: usize,
+    pub target: usize,
+    #[serde(default = "graph::Weight::zero")]
+    pub weight: W,
+    #[serde(default, skip_serializing_if="collections::BTreeMap::is_empty")]
+    pub attrs: collections::BTreeMap<String, graph::AttributeValue>
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
+#[serde(bound(serialize = "W: Serialize", deserialize = "W: graph::Weight + Deserialize<'de>"))]
+pub struct Interchange<W = i64>
+{
+    pub version: u32,
+    pub kind: InterchangeKind,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub self_loops: Option<bool>,
+    pub vertices: Vec<InterchangeVertex>,
+    pub edges: Vec<InterchangeEdge<W>>
+}
+
+pub trait InterchangeFormat: Sized
+{
+    type Weight;
+
+    fn to_interchange (&self) -> Result<Interchange<Self::Weight>, error::GraphError>;
+    fn from_interchange (i: &Interchange<Self::Weight>) -> Result<Self, error::GraphError>;
+}
+
+fn sorted_attrs (attrs: &collections::HashMap<String, graph::AttributeValue>)
+    -> collections::BTreeMap<String, graph::AttributeValue>
+{
+    attrs.iter ().map (|(k, v)| (k.clone (), v.clone ())).collect ()
+}
+
+fn unsorted_attrs (attrs: &collections::BTreeMap<String, graph::AttributeValue>)
+    -> collections::HashMap<String, graph::AttributeValue>
+{
+    attrs.iter ().map (|(k, v)| (k.clone (), v.clone ())).collect ()
+}
+
+fn new_interchange<W> (kind: InterchangeKind, name: &str, self_loops: bool, mut vertices: Vec<InterchangeVertex>, mut edges: Vec<InterchangeEdge<W>>)
+    -> Interchange<W>
+{
+    vertices.sort_by_key (|v| v.id);
+    edges.sort_by_key (|e| (e.source, e.target));
+    let self_loops = (self_loops != kind.default_self_loops ()).then_some (self_loops);
+    Interchange { version: VERSION, kind, name: name.to_string (), self_loops, vertices, edges }
+}
+
+impl<W> Interchange<W>
+{
+    pub fn self_loops (&self)
+        -> bool
+    {
+        self.self_loops.unwrap_or (self.kind.default_self_loops ())
+    }
+}
+
+// Checks everything from_interchange relies on so that rebuilding cannot fail
+// part way or produce lookups that disagree with each other
+fn check<W> (i: &Interchange<W>, kind: InterchangeKind)
+    -> Result<collections::HashMap<usize, Option<String>>, error::GraphError>
+{
+    if i.version != VERSION
+    {
+        return Err (error::GraphError::DataError (format! ("Unsupported interchange version {}, expected {}", i.version, VERSION)));
+    }
+    if i.kind != kind
+    {
+        return Err (error::GraphError::DataError (format! ("Interchange kind {:?} cannot be loaded as {:?}", i.kind, kind)));
+    }
+
+    let mut labels = collections::HashMap::<usize, Option<String>>::new ();
+    let mut lookup = collections::HashSet::<&str>::new ();
+    for v in &i.vertices
+    {
+        if labels.contains_key (&v.id)
+        {
+            return Err (error::GraphError::DataError (format! ("Duplicate vertex id {}", v.id)));
+        }
+        match (&v.label, kind.labelled ())
+        {
+            (Some (label), true) => {
+                if !lookup.insert (label)
+                {
+                    return Err (error::GraphError::DataError (format! ("Duplicate vertex label {}", label)));
+                }
+            },
+            (None, true) => return Err (error::GraphError::DataError (format! ("Vertex {} has no label", v.id))),
+            (Some (_), false) => return Err (error::GraphError::DataError (format! ("Vertex {} has a label but {:?} is unlabelled", v.id, kind))),
+            (None, false) => {}
+        }
+        if !kind.labelled () && !v.attrs.is_empty ()
+        {
+            return Err (error::GraphError::DataError (format! ("Vertex {} has attributes but {:?} is unlabelled", v.id, kind)));
+        }
+        labels.insert (v.id, v.label.clone ());
+    }
+
+    let mut edges = collections::HashSet::<(usize, usize)>::new ();
+    for e in &i.edges
+    {
+        if !labels.contains_key (&e.source) || !labels.contains_key (&e.target)
+        {
+            return Err (error::GraphError::DataError (format! ("Edge ({}, {}) refers to a missing vertex", e.source, e.target)));
+        }
+        if e.source == e.target && !i.self_loops ()
+        {
+            return Err (error::GraphError::DataError (format! ("Edge ({}, {}) is a self loop", e.source, e.target)));
+        }
+        let t = if kind.directed () || e.source < e.target { (e.source, e.target) } else { (e.target, e.source) };
+        if !edges.insert (t)
+        {
+            return Err (error::GraphError::DataError (format! ("Duplicate edge ({}, {})", e.source, e.target)));
+        }
+        if !kind.labelled () && !e.attrs.is_empty ()
+        {
+            return Err (error::GraphError::DataError (format! ("Edge ({}, {}) has attributes but {:?} is unlabelled", e.source, e.target, kind)));
+        }
+    }
+    Ok (labels)
+}
+
+impl<W> InterchangeFormat for graph::Graph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn to_interchange (&self)
+        -> Result<Interchange<W>, error::GraphError>
+    {
+        let vertices = self.vertices ().iter ().map (|v| InterchangeVertex { id: *v, label: None, attrs: collections::BTreeMap::new () }).collect ();
+        let edges = self.edges ().iter ().map (|(e, w)| InterchangeEdge { source: e.0, target: e.1, weight: *w, attrs: collections::BTreeMap::new () }).collect ();
+        Ok (new_interchange (InterchangeKind::Graph, &self.name (), self.self_loops (), vertices, edges))
+    }
+
+    fn from_interchange (i: &Interchange<W>)
+        -> Result<Self, error::GraphError>
+    {
+        check (i, InterchangeKind::Graph)?;
+        let mut g = graph::Graph::new_weighted_with_name (&i.name);
+        g.set_self_loops (i.self_loops ())?;
+        for v in &i.vertices
+        {
+            g.add_vertex_raw (v.id)?;
+        }
+        for e in &i.edges
+        {
+            g.add_edge_raw (e.source, e.target, e.weight)?;
+        }
+        Ok (g)
+    }
+}
+
+impl<W> InterchangeFormat for graph::UGraph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn to_interchange (&self)
+        -> Result<Interchange<W>, error::GraphError>
+    {
+        let vertices = self.vertices ().iter ().map (|v| InterchangeVertex { id: *v, label: None, attrs: collections::BTreeMap::new () }).collect ();
+        let edges = self.edges ().iter ().map (|(e, w)| InterchangeEdge { source: e.0, target: e.1, weight: *w, attrs: collections::BTreeMap::new () }).collect ();
+        Ok (new_interchange (InterchangeKind::UGraph, &self.name (), self.self_loops (), vertices, edges))
+    }
+
+    fn from_interchange (i: &Interchange<W>)
+        -> Result<Self, error::GraphError>
+    {
+        check (i, InterchangeKind::UGraph)?;
+        let mut g = graph::UGraph::new_weighted_with_name (&i.name);
+        g.set_self_loops (i.self_loops ())?;
+        for v in &i.vertices
+        {
+            g.add_vertex_raw (v.id)?;
+        }
+        for e in &i.edges
+        {
+            g.add_edge_raw (e.source, e.target, e.weight)?;
+        }
+        Ok (g)
+    }
+}
+
+impl<W> InterchangeFormat for graph::LabelledGraph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn to_interchange (&self)
+        -> Result<Interchange<W>, error::GraphError>
+    {
+        let vertices = self.vertices ().iter ()
+            .map (|v| Ok (InterchangeVertex { id: *v, label: Some (self.vertex_label (v)?), attrs: sorted_attrs (self.vertex_attrs_raw (v)?) }))
+            .collect::<Result<Vec<_>, error::GraphError>> ()?;
+        let edges = self.edges ().iter ()
+            .map (|(e, w)| Ok (InterchangeEdge { source: e.0, target: e.1, weight: *w, attrs: sorted_attrs (self.edge_attrs_raw (e)?) }))
+            .collect::<Result<Vec<_>, error::GraphError>> ()?;
+        Ok (new_interchange (InterchangeKind::LabelledGraph, &self.graph ().name (), self.self_loops (), vertices, edges))
+    }
+
+    fn from_interchange (i: &Interchange<W>)
+        -> Result<Self, error::GraphError>
+    {
+        let labels = check (i, InterchangeKind::LabelledGraph)?;
+        let label = |id: &usize| labels[id].clone ().unwrap_or_default ();
+        let mut g = graph::LabelledGraph::new_weighted_with_name (&i.name);
+        g.set_self_loops (i.self_loops ())?;
+        for v in &i.vertices
+        {
+            g.add_vertex_raw (v.id, label (&v.id), Some (unsorted_attrs (&v.attrs)))?;
+        }
+        for e in &i.edges
+        {
+            g.add_edge_raw (e.source, label (&e.source), e.target, label (&e.target), Some (unsorted_attrs (&e.attrs)), e.weight)?;
+        }
+        Ok (g)
+    }
+}
+
+impl<W> InterchangeFormat for graph::LabelledUGraph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn to_interchange (&self)
+        -> Result<Interchange<W>, error::GraphError>
+    {
+        let vertices = self.vertices ().iter ()
+            .map (|v| Ok (InterchangeVertex { id: *v, label: Some (self.vertex_label (v)?), attrs: sorted_attrs (self.vertex_attrs_raw (v)?) }))
+            .collect::<Result<Vec<_>, error::GraphError>> ()?;
+        let edges = self.edges ().iter ()
+            .map (|(e, w)| Ok (InterchangeEdge { source: e.0, target: e.1, weight: *w, attrs: sorted_attrs (self.edge_attrs_raw (e)?) }))
+            .collect::<Result<Vec<_>, error::GraphError>> ()?;
+        Ok (new_interchange (InterchangeKind::LabelledUGraph, &self.graph ().name (), self.self_loops (), vertices, edges))
+    }
+
+    fn from_interchange (i: &Interchange<W>)
+        -> Result<Self, error::GraphError>
+    {
+        let labels = check (i, InterchangeKind::LabelledUGraph)?;
+        let label = |id: &usize| labels[id].clone ().unwrap_or_default ();
+        let mut g = graph::LabelledUGraph::new_weighted_with_name (&i.name);
+        g.set_self_loops (i.self_loops ())?;
+        for v in &i.vertices
+        {
+            g.add_vertex_raw (v.id, label (&v.id), Some (unsorted_attrs (&v.attrs)))?;
+        }
+        for e in &i.edges
+        {
+            g.add_edge_raw (e.source, label (&e.source), e.target, label (&e.target), Some (unsorted_attrs (&e.attrs)), e.weight)?;
+        }
+        Ok (g)
+    }
+}
+
+pub fn write_json<G, W> (g: &G, writer: W)
+    -> Result<(), error::GraphError>
+    where
+        G: InterchangeFormat,
+        G::Weight: Serialize,
+        W: io::Write
+{
+    serde_json::to_writer (writer, &g.to_interchange ()?)
+        .map_err (|e| error::GraphError::ConversionError (format! ("Failed to write interchange json: {}", e)))
+}
+
+pub fn read_json<G, R> (reader: R)
+    -> Result<G, error::GraphError>
+    where
+        G: InterchangeFormat,
+        G::Weight: graph::Weight + for<'de> Deserialize<'de>,
+        R: io::Read
+{
+    let i = serde_json::from_reader::<R, Interchange<G::Weight>> (reader)
+        .map_err (|e| error::GraphError::DataError (format! ("Failed to read interchange json: {}", e)))?;
+    G::from_interchange (&i)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::graph;
+    use std::collections;
+    use std::sync;
+    use super::InterchangeFormat;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| {
+            let _ = env_logger::try_init ();
+        });
+    }
+
+    #[test]
+    fn test_graph ()
+    {
+        init ();
+        let mut g = graph::Graph::new_with_name ("g");
+        g.add_edge_raw (3, 1, 5).expect ("Failed to add edge");
+        g.add_edge_raw (1, 2, 0).expect ("Failed to add edge");
+        g.add_vertex_raw (9).expect ("Failed to add vertex");
+
+        let i = g.to_interchange ().expect ("Failed to convert graph");
+        assert_eq! (serde_json::to_string (&i).expect ("Failed to serialize"), r#"{"version":1,"kind":"graph","name":"g","vertices":[{"id":1},{"id":2},{"id":3},{"id":9}],"edges":[{"source":1,"target":2,"weight":0},{"source":3,"target":1,"weight":5}]}"#);
+        assert_eq! (graph::Graph::from_interchange (&i).expect ("Failed to rebuild graph"), g);
+        assert! (graph::UGraph::from_interchange (&i).is_err ());
+    }
+
+    #[test]
+    fn test_ugraph ()
+    {
+        init ();
+        let mut g = graph::UGraph::new ();
+        g.add_edge_raw (3, 1, 5).expect ("Failed to add edge");
+        g.add_edge_raw (2, 3, 1).expect ("Failed to add edge");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_json (&g, &mut out).expect ("Failed to write json");
+        let h = super::read_json::<graph::UGraph, _> (out.as_slice ()).expect ("Failed to read json");
+        assert_eq! (h, g);
+    }
+
+    #[test]
+    fn test_weighted ()
+    {
+        init ();
+        let mut g = graph::Graph::<f64>::new_weighted ();
+        g.add_edge_raw (1, 2, 0.5).expect ("Failed to add edge");
+        let i = g.to_interchange ().expect ("Failed to convert graph");
+        assert_eq! (serde_json::to_string (&i).expect ("Failed to serialize"), r#"{"version":1,"kind":"graph","name":"","vertices":[{"id":1},{"id":2}],"edges":[{"source":1,"target":2,"weight":0.5}]}"#);
+
+        let mut out = Vec::<u8>::new ();
+        super::write_json (&g, &mut out).expect ("Failed to write json");
+        assert_eq! (super::read_json::<graph::Graph<f64>, _> (out.as_slice ()).expect ("Failed to read json"), g);
+        assert! (super::read_json::<graph::Graph, _> (out.as_slice ()).is_err ());
+
+        let input = r#"{"version":1,"kind":"labelled_ugraph","vertices":[{"id":1,"label":"a"},{"id":2,"label":"b"}],"edges":[{"source":1,"target":2}]}"#;
+        let h = super::read_json::<graph::LabelledUGraph<u32>, _> (input.as_bytes ()).expect ("Failed to read json");
+        assert_eq! (h.edges ().values ().copied ().collect::<Vec<_>> (), vec![0u32]);
+    }
+
+    #[test]
+    fn test_labelled_graph ()
+    {
+        init ();
+        let mut g = graph::LabelledGraph::new_with_name ("lg");
+        let attrs = collections::HashMap::<String, graph::AttributeValue>::from ([ ( String::from ("colour"), graph::AttributeValue::from ("red") ) ]);
+        g.add_vertex (String::from ("a"), Some (attrs.clone ())).expect ("Failed to add vertex");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (attrs.clone ()), 2).expect ("Failed to add edge");
+        g.add_vertex (String::from ("c"), None).expect ("Failed to add vertex");
+
+        let mut out = Vec::<u8>::new ();
+        super::write_json (&g, &mut out).expect ("Failed to write json");
+        let mut h = super::read_json::<graph::LabelledGraph, _> (out.as_slice ()).expect ("Failed to read json");
+
+        assert! (crate::eq::labels_and_attrs_eq (&g, &h).expect ("Failed eq check"), "Round trip changed the graph");
+        assert_eq! (h.vertex ("a").expect ("Failed to find a"), g.vertex ("a").expect ("Failed to find a"));
+
+        // New vertices must not reuse loaded ids
+        let d_id = h.add_vertex (String::from ("d"), None).expect ("Failed to add vertex");
+        assert! (!g.vertices ().contains (&d_id));
+    }
+
+    #[test]
+    fn test_labelled_ugraph ()
+    {
+        init ();
+        let mut g = graph::LabelledUGraph::new ();
+        let attrs = collections::HashMap::<String, graph::AttributeValue>::from ([ ( String::from ("n"), graph::AttributeValue::from (4i64) ) ]);
+        g.add_edge_weighted (String::from ("b"), String::from ("a"), Some (attrs.clone ()), 2).expect ("Failed to add edge");
+        g.vertex_attrs_mut ("a").expect ("Failed to find a").1.extend (attrs.clone ());
+
+        let i = g.to_interchange ().expect ("Failed to convert graph");
+        let h = graph::LabelledUGraph::from_interchange (&i).expect ("Failed to rebuild graph");
+
+        assert_eq! (h.to_interchange ().expect ("Failed to convert graph"), i);
+        assert_eq! (h.vertex_attrs ("a").expect ("Failed to find a").1, &attrs);
+        assert_eq! (h.edge_attrs ( &(String::from ("a"), String::from ("b")) ).expect ("Failed to find edge").1, &attrs);
+    }
+
+    #[test]
+    fn test_self_loops ()
+    {
+        init ();
+        let mut g = graph::UGraph::new ();
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge_raw (1, 1, 2).expect ("Failed to add edge");
+
+        let i = g.to_interchange ().expect ("Failed to convert graph");
+        assert_eq! (serde_json::to_string (&i).expect ("Failed to serialize"), r#"{"version":1,"kind":"ugraph","name":"","vertices":[{"id":1}],"edges":[{"source":1,"target":1,"weight":2}]}"#);
+        assert_eq! (graph::UGraph::from_interchange (&i).expect ("Failed to rebuild graph"), g);
+
+        let r = super::read_json::<graph::UGraph, _> (r#"{"version":1,"kind":"ugraph","self_loops":false,"vertices":[{"id":1}],"edges":[{"source":1,"target":1}]}"#.as_bytes ());
+        assert_eq! (r.unwrap_err ().to_string (), "Data error: Edge (1, 1) is a self loop");
+
+        let mut g = graph::LabelledGraph::new ();
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge (String::from ("a"), String::from ("a"), None).expect ("Failed to add edge a -> a");
+        let i = g.to_interchange ().expect ("Failed to convert graph");
+        assert_eq! (i.self_loops, Some (true));
+        assert! (graph::LabelledGraph::from_interchange (&i).expect ("Failed to rebuild graph").self_loops ());
+        let r = super::read_json::<graph::LabelledGraph, _> (r#"{"version":1,"kind":"labelled_graph","vertices":[{"id":1,"label":"a"}],"edges":[{"source":1,"target":1}]}"#.as_bytes ());
+        assert_eq! (r.unwrap_err ().to_string (), "Data error: Edge (1, 1) is a self loop");
+    }
+
+    #[test]
+    fn test_invalid ()
+    {
+        init ();
+        let cases = [
+            ( r#"{"version":2,"kind":"labelled_graph","vertices":[],"edges":[]}"#, "Data error: Unsupported interchange version 2, expected 1" ),
+            ( r#"{"version":1,"kind":"graph","vertices":[],"edges":[]}"#, "Data error: Interchange kind Graph cannot be loaded as LabelledGraph" ),
+            ( r#"{"version":1,"kind":"labelled_graph","vertices":[{"id":1,"label":"a"},{"id":1,"label":"b"}],"edges":[]}"#, "Data error: Duplicate vertex id 1" ),
+            ( r#"{"version":1,"kind":"labelled_graph","vertices":[{"id":1,"label":"a"},{"id":2,"label":"a"}],"edges":[]}"#, "Data error: Duplicate vertex label a" ),
+            ( r#"{"version":1,"kind":"labelled_graph","vertices":[{"id":1}],"edges":[]}"#, "Data error: Vertex 1 has no label" ),
+            ( r#"{"version":1,"kind":"labelled_graph","vertices":[{"id":1,"label":"a"}],"edges":[{"source":1,"target":2}]}"#, "Data error: Edge (1, 2) refers to a missing vertex" ),
+            ( r#"{"version":1,"kind":"labelled_graph","vertices":[{"id":1,"label":"a"},{"id":2,"label":"b"}],"edges":[{"source":1,"target":2},{"source":1,"target":2}]}"#, "Data error: Duplicate edge (1, 2)" ),
+            ( r#"{"version":1,"kind":"labelled_graph""#, "Data error: Failed to read interchange json: EOF while parsing an object at line 1 column 36" )
+        ];
+
+        for (input, expected) in cases
+        {
+            let r = super::read_json::<graph::LabelledGraph, _> (input.as_bytes ());
+            assert_eq! (r.unwrap_err ().to_string (), expected, "Unexpected error for {}", input);
+        }
+    }
+}
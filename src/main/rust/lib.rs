@@ -1,22 +1,33 @@
 
 use std::collections;
 use std::fs;
-use std::io::Write;
 use std::iter;
 
 pub mod algo;
+pub mod binary;
+pub mod csr;
+pub mod dot;
+pub mod edgelist;
 pub mod eq;
 pub mod error;
 pub mod graph;
+pub mod graphml;
+pub mod index;
+pub mod interchange;
+pub mod multigraph;
 pub mod prng;
+pub mod schema;
 pub mod sd;
+pub mod view;
 
-pub fn ugraph_from_graph (g: &graph::Graph)
-    -> Result<graph::UGraph, error::GraphError>
+pub fn ugraph_from_graph<W> (g: &graph::Graph<W>)
+    -> Result<graph::UGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
 {
     let edges = g.edges ()
         .iter ()
-        .try_fold (collections::HashMap::<(usize,usize),i64>::new (), |mut acc, (e, w)| {
+        .try_fold (collections::HashMap::<(usize,usize),W>::new (), |mut acc, (e, w)| {
             let t = if e.0 < e.1 { ( e.0, e.1 ) } else { ( e.1, e.0 ) };
             if let Some (wo) = acc.get (&t)
             {
@@ -45,6 +56,7 @@ pub fn ugraph_from_graph (g: &graph::Graph)
     Ok (graph::UGraph {
         name: g.name (),
         vertices: g.vertices ().clone (),
+        self_loops: g.self_loops (),
         edges: edges,
         connected: connected
     })
@@ -142,14 +154,7 @@ pub fn graph_to_dot (graph: graph::Graph, file_path: &str)
     -> Result<(), error::GraphError>
 {
     let mut file = fs::File::create (file_path)?;
-
-    write! (file, "digraph {} {{\n", graph.name ())?;
-    for (edge, _weight) in graph.edges ()
-    {
-        write! (file, "\t{} -> {};\n", edge.0, edge.1)?;
-    }
-    write! (file, "}}\n")?;
-    Ok (())
+    dot::write_graph (&graph, &mut file, &dot::DotOptions::new ())
 }
 
 #[cfg(test)]
@@ -197,6 +202,22 @@ mod tests_lib
         super::graph_to_dot (g, "simple.dot").expect ("Failed to write dot");
     }
 
+    #[test]
+    fn test_graph_to_dot_output ()
+    {
+        let mut g = graph::Graph::new_with_name ("g");
+        g.add_edge_raw (2, 1, 0).expect ("added edge 2,1");
+        g.add_edge_raw (1, 3, 0).expect ("added edge 1,3");
+        g.add_vertex_raw (4).expect ("added vertex 4");
+
+        let path = std::env::temp_dir ().join (format! ("graph_to_dot_{}.dot", std::process::id ()));
+        super::graph_to_dot (g, path.to_str ().expect ("Failed to get temp path")).expect ("Failed to write dot");
+        let s = std::fs::read_to_string (&path).expect ("Failed to read dot");
+        std::fs::remove_file (&path).expect ("Failed to remove dot");
+
+        assert_eq! (s, "digraph \"g\" {\n\t1;\n\t2;\n\t3;\n\t4;\n\t1 -> 3;\n\t2 -> 1;\n}\n");
+    }
+
     #[test]
     fn test_to_undirected ()
     {
@@ -209,5 +230,21 @@ mod tests_lib
         assert! (gu.has_edge_raw (&(1,2)));
         assert! (gu.has_edge_raw (&(2,1)));
     }
+
+    #[test]
+    fn test_to_undirected_self_loop ()
+    {
+        let mut g = graph::Graph::new ();
+        g.set_self_loops (true).expect ("Failed to allow self loops");
+        g.add_edge_raw (1,1,4).expect ("added edge 1,1 4");
+        g.add_edge_raw (1,2,1).expect ("added edge 1,2 1");
+
+        let gu = super::ugraph_from_graph (&g).expect ("Failed to convert to undirected");
+
+        assert! (gu.self_loops ());
+        assert_eq! (gu.edges ().get (&(1,1)), Some (&4));
+        assert_eq! (gu.neighbours (&1).unwrap (), std::collections::HashSet::from ([1,2]));
+        assert! (gu.validate ().is_ok ());
+    }
 }
 
@@ -0,0 +1,1010 @@
+// Directed multigraphs and their conversions to and from the simple
+// directed and undirected graph types
+//
+// Every edge has its own id, weight and attributes, so any number of
+// edges may join the same ordered pair of vertices. A simple Graph is kept
+// alongside the edges as the adjacency skeleton: it has one edge per
+// connected pair, weighted with the lightest of the parallel edges, which
+// is what GraphAny::weight reports to the shortest path algorithms.
+//
+// Converting to the simple types collapses parallel edges with a
+// MergePolicy; converting to the undirected types also collapses the two
+// directions of a pair. Converting from the undirected types adds an edge
+// each way per undirected edge, so any policy but Sum converts back to the
+// same graph.
+//
+// Weights are generic as for the simple graphs, MergePolicy::Sum adds
+// them up with Weight::checked_combine and fails on overflow. Labels are
+// Strings and attributes the usual attribute maps.
+use crate::error;
+use crate::graph;
+use crate::graph::AttributeValue;
+use std::borrow;
+use std::collections;
+use std::fmt;
+use std::hash;
+use std::sync;
+
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum MergePolicy
+{
+    // fail unless the parallel edges agree on weight and attributes
+    Strict,
+    // keep the edge with the lowest id
+    First,
+    // keep the edge with the highest id
+    Last,
+    // keep the lightest edge, ties go to the lowest id
+    Min,
+    // keep the heaviest edge, ties go to the lowest id
+    Max,
+    // add up the weights, attributes of later edges replace earlier ones
+    Sum
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct MultiEdge<W = i64>
+{
+    pub source: usize,
+    pub target: usize,
+    pub weight: W,
+    pub attrs: collections::HashMap<String, AttributeValue>
+}
+
+#[derive(Clone,Debug)]
+pub struct MultiGraph<W = i64>
+{
+    edge_id: usize,
+    edges: collections::BTreeMap<usize, MultiEdge<W>>,
+    graph: graph::Graph<W>,
+    parallel: collections::HashMap<(usize, usize), collections::BTreeSet<usize>>
+}
+
+#[derive(Clone,Debug)]
+pub struct LabelledMultiGraph<W = i64>
+{
+    vertex_id: sync::Arc::<sync::atomic::AtomicUsize>,
+    graph: MultiGraph<W>,
+    vertex_attrs: collections::HashMap<usize, collections::HashMap<String, AttributeValue>>,
+    vertex_label: collections::HashMap<usize, String>,
+    vertex_lookup: collections::HashMap<String, usize>
+}
+
+impl Default for MultiGraph
+{
+    fn default ()
+        -> Self
+    {
+        Self::new ()
+    }
+}
+
+impl MultiGraph
+{
+    pub fn new () -> Self
+    {
+        Self::new_weighted ()
+    }
+
+    pub fn new_with_name (name: &str) -> Self
+    {
+        Self::new_weighted_with_name (name)
+    }
+}
+
+impl<W> MultiGraph<W>
+    where
+        W: graph::Weight
+{
+    pub fn new_weighted () -> Self
+    {
+        Self::new_weighted_with_name ("")
+    }
+
+    pub fn new_weighted_with_name (name: &str) -> Self
+    {
+        Self {
+            edge_id: 0,
+            edges: collections::BTreeMap::<usize, MultiEdge<W>>::new (),
+            graph: graph::Graph::new_weighted_with_name (name),
+            parallel: collections::HashMap::<(usize, usize), collections::BTreeSet<usize>>::new ()
+        }
+    }
+
+    pub fn add_edge_raw (&mut self, a: usize, b: usize, weight: W, attrs: Option<collections::HashMap::<String, AttributeValue>>)
+        -> Result<usize, error::GraphError>
+    {
+        if a == b && !self.graph.self_loops ()
+        {
+            return Err (error::GraphError::EdgeError (String::from ("edge vertices must be distinct")));
+        }
+        weight.check ()?;
+        let e_id = self.edge_id;
+        self.edge_id += 1;
+        self.edges.insert (e_id, MultiEdge { source: a, target: b, weight, attrs: attrs.unwrap_or_default () });
+        self.parallel.entry ( (a, b) ).or_default ().insert (e_id);
+        self.update_skeleton (&(a, b))?;
+        Ok (e_id)
+    }
+
+    pub fn add_vertex_raw (&mut self, a: usize)
+        -> Result<(), error::GraphError>
+    {
+        self.graph.add_vertex_raw (a)
+    }
+
+    pub fn edge (&self, e_id: &usize)
+        -> Result<&MultiEdge<W>, error::GraphError>
+    {
+        self.edges.get (e_id).ok_or (error::GraphError::EdgeError (format! ("Edge id: {} not found in graph", e_id)))
+    }
+
+    pub fn edge_attrs_mut (&mut self, e_id: &usize)
+        -> Result<&mut collections::HashMap<String, AttributeValue>, error::GraphError>
+    {
+        self.edges.get_mut (e_id)
+            .map (|e| &mut e.attrs)
+            .ok_or (error::GraphError::EdgeError (format! ("Edge id: {} not found in graph", e_id)))
+    }
+
+    pub fn edges (&self)
+        -> &collections::BTreeMap<usize, MultiEdge<W>>
+    {
+        &self.edges
+    }
+
+    // ids of the edges from a to b, in insertion order
+    pub fn edges_between (&self, ev: &(usize, usize))
+        -> Vec<usize>
+    {
+        self.parallel.get (ev).map (|x| x.iter ().copied ().collect ()).unwrap_or_default ()
+    }
+
+    pub fn graph (&self)
+        -> &graph::Graph<W>
+    {
+        &self.graph
+    }
+
+    pub fn name (&self)
+        -> String
+    {
+        self.graph.name ()
+    }
+
+    pub fn remove_edge (&mut self, e_id: &usize)
+        -> Result<MultiEdge<W>, error::GraphError>
+    {
+        let e = self.edges.remove (e_id).ok_or (error::GraphError::EdgeError (format! ("Edge id: {} not found in graph", e_id)))?;
+        let ev = (e.source, e.target);
+        if let Some (ids) = self.parallel.get_mut (&ev)
+        {
+            ids.remove (e_id);
+        }
+        self.update_skeleton (&ev)?;
+        Ok (e)
+    }
+
+    pub fn remove_vertex_raw (&mut self, a: &usize)
+        -> Result<(), error::GraphError>
+    {
+        let incident = self.graph.incident (a)?;
+        for ev in incident
+        {
+            for e_id in self.parallel.remove (&ev).unwrap_or_default ()
+            {
+                self.edges.remove (&e_id);
+            }
+        }
+        self.graph.remove_vertex_raw (a)
+    }
+
+    pub fn rename (&mut self, name: String)
+    {
+        self.graph.rename (name);
+    }
+
+    pub fn self_loops (&self)
+        -> bool
+    {
+        self.graph.self_loops ()
+    }
+
+    pub fn set_self_loops (&mut self, allow: bool)
+        -> Result<(), error::GraphError>
+    {
+        self.graph.set_self_loops (allow)
+    }
+
+    pub fn vertices (&self)
+        -> &collections::HashSet<usize>
+    {
+        self.graph.vertices ()
+    }
+
+    fn update_skeleton (&mut self, ev: &(usize, usize))
+        -> Result<(), error::GraphError>
+    {
+        let lightest = self.parallel.get (ev)
+            .and_then (|ids| ids.iter ().map (|e_id| self.edges[e_id].weight).min_by (|x, y| x.compare (y)));
+        match lightest
+        {
+            Some (w) => self.graph.add_edge_raw (ev.0, ev.1, w),
+            None => {
+                self.parallel.remove (ev);
+                if self.graph.has_edge_raw (ev) { self.graph.remove_edge_raw (&ev.0, &ev.1) } else { Ok (()) }
+            }
+        }
+    }
+}
+
+impl LabelledMultiGraph
+{
+    pub fn new () -> Self
+    {
+        Self::new_weighted ()
+    }
+
+    pub fn new_with_name (name: &str) -> Self
+    {
+        Self::new_weighted_with_name (name)
+    }
+}
+
+impl<W> LabelledMultiGraph<W>
+    where
+        W: graph::Weight
+{
+    pub fn new_weighted () -> Self
+    {
+        Self::new_weighted_with_name ("")
+    }
+
+    pub fn new_weighted_with_name (name: &str) -> Self
+    {
+        let mut graph = MultiGraph::new_weighted_with_name (name);
+        // only fails on a graph that already has loops
+        let _ = graph.set_self_loops (false);
+        Self {
+            vertex_id: sync::Arc::new (sync::atomic::AtomicUsize::new (1)),
+            graph,
+            vertex_attrs: collections::HashMap::<usize, collections::HashMap::<String, AttributeValue>>::new (),
+            vertex_label: collections::HashMap::<usize, String>::new (),
+            vertex_lookup: collections::HashMap::<String, usize>::new ()
+        }
+    }
+
+    pub fn add_edge (&mut self, a: String, b: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
+        -> Result<usize, error::GraphError>
+    {
+        self.add_edge_weighted (a, b, attrs, W::zero ())
+    }
+
+    pub fn add_edge_weighted (&mut self, a: String, b: String, attrs: Option<collections::HashMap::<String, AttributeValue>>, weight: W)
+        -> Result<usize, error::GraphError>
+    {
+        if a == b && !self.graph.self_loops ()
+        {
+            Err (error::GraphError::EdgeError (String::from ("edge vertices must be distinct")))
+        }
+        else
+        {
+            weight.check ()?;
+            // create b before a for nice vertex ids
+            let b_id = if let Some (b_id) = self.vertex_lookup.get (&b) { *b_id } else { self.add_vertex (b, None)? };
+            let a_id = if let Some (a_id) = self.vertex_lookup.get (&a) { *a_id } else { self.add_vertex (a, None)? };
+            self.graph.add_edge_raw (a_id, b_id, weight, attrs)
+        }
+    }
+
+    pub fn add_vertex (&mut self, a: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
+        -> Result<usize, error::GraphError>
+    {
+        let vertex_exists = self.vertex_lookup.contains_key (&a);
+        let a_id = *self.vertex_lookup.entry (a.clone ()).or_insert_with (|| self.vertex_id.fetch_add (1, sync::atomic::Ordering::Relaxed));
+
+        if ! vertex_exists
+        {
+            self.graph.add_vertex_raw (a_id)?;
+            self.vertex_label.insert (a_id, a);
+        }
+        self.vertex_attrs.insert (a_id, attrs.unwrap_or_default ());
+        Ok (a_id)
+    }
+
+    pub fn add_vertex_raw (&mut self, a_id: usize, a: String, attrs: Option<collections::HashMap::<String, AttributeValue>>)
+        -> Result<usize, error::GraphError>
+    {
+        match (self.vertex_lookup.get (&a), self.vertex_label.get (&a_id))
+        {
+            (Some (a_id_e), _) if *a_id_e != a_id => Err (error::GraphError::VertexError (format! ("vertex already exists but has a different id {} != {}", a_id, a_id_e))),
+            (None, Some (_)) => Err (error::GraphError::VertexError (format! ("vertex id {} already exists but has a different label", a_id))),
+            _ => {
+                self.vertex_id.fetch_max (a_id + 1, sync::atomic::Ordering::Relaxed);
+                self.vertex_lookup.insert (a.clone (), a_id);
+                self.vertex_label.insert (a_id, a);
+                self.vertex_attrs.insert (a_id, attrs.unwrap_or_default ());
+                self.graph.add_vertex_raw (a_id)?;
+                Ok (a_id)
+            }
+        }
+    }
+
+    pub fn edge (&self, e_id: &usize)
+        -> Result<&MultiEdge<W>, error::GraphError>
+    {
+        self.graph.edge (e_id)
+    }
+
+    pub fn edge_attrs_mut (&mut self, e_id: &usize)
+        -> Result<&mut collections::HashMap<String, AttributeValue>, error::GraphError>
+    {
+        self.graph.edge_attrs_mut (e_id)
+    }
+
+    pub fn edge_labels (&self, e_id: &usize)
+        -> Result<(String, String), error::GraphError>
+    {
+        let e = self.graph.edge (e_id)?;
+        Ok ( (self.vertex_label (&e.source)?, self.vertex_label (&e.target)?) )
+    }
+
+    pub fn edges_between (&self, (a, b): &(String, String))
+        -> Result<Vec<usize>, error::GraphError>
+    {
+        Ok (self.graph.edges_between (&(self.vertex (a)?, self.vertex (b)?)))
+    }
+
+    pub fn graph (&self)
+        -> &MultiGraph<W>
+    {
+        &self.graph
+    }
+
+    pub fn remove_edge (&mut self, e_id: &usize)
+        -> Result<MultiEdge<W>, error::GraphError>
+    {
+        self.graph.remove_edge (e_id)
+    }
+
+    pub fn self_loops (&self)
+        -> bool
+    {
+        self.graph.self_loops ()
+    }
+
+    // As with LabelledGraph, the inner graph starts with self loops off and
+    // they have to be allowed first
+    pub fn set_self_loops (&mut self, allow: bool)
+        -> Result<(), error::GraphError>
+    {
+        self.graph.set_self_loops (allow)
+    }
+
+    pub fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, error::GraphError>
+        where
+            String: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        self.vertex_lookup.get (a).copied ().ok_or (error::GraphError::VertexError (format! ("Failed to find vertex: {:?}", a)))
+    }
+
+    pub fn vertex_attrs (&self, a: &str)
+        -> Result<&collections::HashMap<String, AttributeValue>, error::GraphError>
+    {
+        self.vertex_attrs_raw (&self.vertex (a)?)
+    }
+
+    pub fn vertex_attrs_raw (&self, a_id: &usize)
+        -> Result<&collections::HashMap<String, AttributeValue>, error::GraphError>
+    {
+        self.vertex_attrs.get (a_id).ok_or (error::GraphError::VertexError (format! ("Failed to find vertex attributes for id: {}", a_id)))
+    }
+
+    pub fn vertex_label (&self, a_id: &usize)
+        -> Result<String, error::GraphError>
+    {
+        self.vertex_label.get (a_id).cloned ().ok_or (error::GraphError::VertexError (format! ("Failed to find vertex with id: {}", a_id)))
+    }
+}
+
+impl Default for LabelledMultiGraph
+{
+    fn default ()
+        -> Self
+    {
+        Self::new ()
+    }
+}
+
+fn merge<W> (policy: MergePolicy, ev: &(usize, usize), edges: &[&MultiEdge<W>])
+    -> Result<(W, collections::HashMap<String, AttributeValue>), error::GraphError>
+    where
+        W: graph::Weight
+{
+    let first = edges.first ().ok_or (error::GraphError::ConversionError (format! ("No edges to merge for {:?}", ev)))?;
+    let picked = match policy
+    {
+        MergePolicy::Strict => {
+            if let Some (e) = edges.iter ().find (|e| e.weight != first.weight)
+            {
+                return Err (error::GraphError::ConversionError (format! ("Cannot merge parallel edges {:?} with weights {:?} and {:?}", ev, first.weight, e.weight)));
+            }
+            if edges.iter ().any (|e| e.attrs != first.attrs)
+            {
+                return Err (error::GraphError::ConversionError (format! ("Cannot merge parallel edges {:?} with different attributes", ev)));
+            }
+            first
+        },
+        MergePolicy::First => first,
+        MergePolicy::Last => edges.last ().unwrap (),
+        MergePolicy::Min => edges.iter ().min_by (|x, y| x.weight.compare (&y.weight)).unwrap (),
+        MergePolicy::Max => edges.iter ().rev ().max_by (|x, y| x.weight.compare (&y.weight)).unwrap (),
+        MergePolicy::Sum => {
+            let attrs = edges.iter ().fold (collections::HashMap::<String, AttributeValue>::new (), |mut acc, item| {
+                acc.extend (item.attrs.iter ().map (|(k, v)| (k.clone (), v.clone ())));
+                acc
+            });
+            let weight = edges.iter ().try_fold (W::zero (), |acc, e| acc.checked_combine (&e.weight))
+                .ok_or (error::GraphError::ConversionError (format! ("Sum of parallel edges {:?} overflows", ev)))?;
+            return Ok ( (weight, attrs) );
+        }
+    };
+    Ok ( (picked.weight, picked.attrs.clone ()) )
+}
+
+// parallel edges grouped by pair, ordered by edge id within each group
+fn grouped<W> (g: &MultiGraph<W>, undirected: bool)
+    -> collections::BTreeMap<(usize, usize), Vec<&MultiEdge<W>>>
+{
+    g.edges.values ().fold (collections::BTreeMap::<(usize, usize), Vec<&MultiEdge<W>>>::new (), |mut acc, item| {
+        let ev = if undirected && item.target < item.source { (item.target, item.source) } else { (item.source, item.target) };
+        acc.entry (ev).or_default ().push (item);
+        acc
+    })
+}
+
+pub fn from_graph<W> (g: &graph::Graph<W>)
+    -> Result<MultiGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = MultiGraph::new_weighted_with_name (&g.name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.vertices ()
+    {
+        r.add_vertex_raw (*v)?;
+    }
+    let edges = g.edges ().iter ().collect::<collections::BTreeMap<_,_>> ();
+    for (ev, w) in edges
+    {
+        r.add_edge_raw (ev.0, ev.1, *w, None)?;
+    }
+    Ok (r)
+}
+
+pub fn from_labelled_graph<W> (g: &graph::LabelledGraph<W>)
+    -> Result<LabelledMultiGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = LabelledMultiGraph::new_weighted_with_name (&g.graph ().name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.graph ().vertices ()
+    {
+        r.add_vertex_raw (*v, g.vertex_label (v)?, Some (g.vertex_attrs_raw (v)?.clone ()))?;
+    }
+    let edges = g.edges ().iter ().collect::<collections::BTreeMap<_,_>> ();
+    for (ev, w) in edges
+    {
+        r.graph.add_edge_raw (ev.0, ev.1, *w, Some (g.edge_attrs_raw (ev)?.clone ()))?;
+    }
+    Ok (r)
+}
+
+pub fn from_ugraph<W> (g: &graph::UGraph<W>)
+    -> Result<MultiGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = MultiGraph::new_weighted_with_name (&g.name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.vertices ()
+    {
+        r.add_vertex_raw (*v)?;
+    }
+    let edges = g.edges ().iter ().collect::<collections::BTreeMap<_,_>> ();
+    for (ev, w) in edges
+    {
+        r.add_edge_raw (ev.0, ev.1, *w, None)?;
+        if ev.0 != ev.1
+        {
+            r.add_edge_raw (ev.1, ev.0, *w, None)?;
+        }
+    }
+    Ok (r)
+}
+
+pub fn from_labelled_ugraph<W> (g: &graph::LabelledUGraph<W>)
+    -> Result<LabelledMultiGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = LabelledMultiGraph::new_weighted_with_name (&g.graph ().name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.graph ().vertices ()
+    {
+        r.add_vertex_raw (*v, g.vertex_label (v)?, Some (g.vertex_attrs_raw (v)?.clone ()))?;
+    }
+    let edges = g.edges ().iter ().collect::<collections::BTreeMap<_,_>> ();
+    for (ev, w) in edges
+    {
+        let attrs = g.edge_attrs_raw (ev)?;
+        r.graph.add_edge_raw (ev.0, ev.1, *w, Some (attrs.clone ()))?;
+        if ev.0 != ev.1
+        {
+            r.graph.add_edge_raw (ev.1, ev.0, *w, Some (attrs.clone ()))?;
+        }
+    }
+    Ok (r)
+}
+
+pub fn to_graph<W> (g: &MultiGraph<W>, policy: MergePolicy)
+    -> Result<graph::Graph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = graph::Graph::new_weighted_with_name (&g.name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.vertices ()
+    {
+        r.add_vertex_raw (*v)?;
+    }
+    for (ev, edges) in grouped (g, false)
+    {
+        r.add_edge_raw (ev.0, ev.1, merge (policy, &ev, &edges)?.0)?;
+    }
+    Ok (r)
+}
+
+pub fn to_ugraph<W> (g: &MultiGraph<W>, policy: MergePolicy)
+    -> Result<graph::UGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = graph::UGraph::new_weighted_with_name (&g.name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.vertices ()
+    {
+        r.add_vertex_raw (*v)?;
+    }
+    for (ev, edges) in grouped (g, true)
+    {
+        r.add_edge_raw (ev.0, ev.1, merge (policy, &ev, &edges)?.0)?;
+    }
+    Ok (r)
+}
+
+pub fn to_labelled_graph<W> (g: &LabelledMultiGraph<W>, policy: MergePolicy)
+    -> Result<graph::LabelledGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = graph::LabelledGraph::new_weighted_with_name (&g.graph.name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.graph.vertices ()
+    {
+        r.add_vertex_raw (*v, g.vertex_label (v)?, Some (g.vertex_attrs_raw (v)?.clone ()))?;
+    }
+    for (ev, edges) in grouped (&g.graph, false)
+    {
+        let (w, attrs) = merge (policy, &ev, &edges)?;
+        r.add_edge_raw (ev.0, g.vertex_label (&ev.0)?, ev.1, g.vertex_label (&ev.1)?, Some (attrs), w)?;
+    }
+    Ok (r)
+}
+
+pub fn to_labelled_ugraph<W> (g: &LabelledMultiGraph<W>, policy: MergePolicy)
+    -> Result<graph::LabelledUGraph<W>, error::GraphError>
+    where
+        W: graph::Weight
+{
+    let mut r = graph::LabelledUGraph::new_weighted_with_name (&g.graph.name ());
+    r.set_self_loops (g.self_loops ())?;
+    for v in g.graph.vertices ()
+    {
+        r.add_vertex_raw (*v, g.vertex_label (v)?, Some (g.vertex_attrs_raw (v)?.clone ()))?;
+    }
+    for (ev, edges) in grouped (&g.graph, true)
+    {
+        let (w, attrs) = merge (policy, &ev, &edges)?;
+        r.add_edge_raw (ev.0, g.vertex_label (&ev.0)?, ev.1, g.vertex_label (&ev.1)?, Some (attrs), w)?;
+    }
+    Ok (r)
+}
+
+impl<W> graph::GraphAny for MultiGraph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::GraphAny::adjacent (&self.graph, a)
+    }
+
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::GraphAny::adjacent_iter (&self.graph, a)
+    }
+
+    // one item per parallel edge
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
+    {
+        Box::new (self.edges.values ().map (|e| ( (e.source, e.target), e.weight )))
+    }
+
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
+    {
+        self.graph.has_edge_raw (ev)
+    }
+
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        self.graph.neighbours (a)
+    }
+
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::GraphAny::neighbours_iter (&self.graph, a)
+    }
+
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
+    {
+        self.graph.vertices ()
+    }
+
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, error::GraphError>
+    {
+        graph::GraphAny::weight (&self.graph, ev)
+    }
+}
+
+impl<W> graph::DiGraphAny for MultiGraph<W>
+    where
+        W: graph::Weight
+{
+    fn inbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        self.graph.inbound (a)
+    }
+
+    fn inbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::DiGraphAny::inbound_iter (&self.graph, a)
+    }
+
+    fn is_sink (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        self.graph.is_sink (a)
+    }
+
+    fn is_source (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        self.graph.is_source (a)
+    }
+
+    fn outbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        self.graph.outbound (a)
+    }
+
+    fn outbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::DiGraphAny::outbound_iter (&self.graph, a)
+    }
+
+    fn sinks (&self)
+        -> collections::HashSet<usize>
+    {
+        self.graph.sinks ()
+    }
+
+    fn sources (&self)
+        -> collections::HashSet<usize>
+    {
+        self.graph.sources ()
+    }
+}
+
+impl<W> graph::GraphAny for LabelledMultiGraph<W>
+    where
+        W: graph::Weight
+{
+    type Weight = W;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::GraphAny::adjacent (&self.graph, a)
+    }
+
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::GraphAny::adjacent_iter (&self.graph, a)
+    }
+
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), W)> + '_>
+    {
+        graph::GraphAny::edges_iter (&self.graph)
+    }
+
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
+    {
+        graph::GraphAny::has_edge_raw (&self.graph, ev)
+    }
+
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::GraphAny::neighbours (&self.graph, a)
+    }
+
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::GraphAny::neighbours_iter (&self.graph, a)
+    }
+
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
+    {
+        self.graph.vertices ()
+    }
+
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<W, error::GraphError>
+    {
+        graph::GraphAny::weight (&self.graph, ev)
+    }
+}
+
+impl<W> graph::DiGraphAny for LabelledMultiGraph<W>
+    where
+        W: graph::Weight
+{
+    fn inbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::DiGraphAny::inbound (&self.graph, a)
+    }
+
+    fn inbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::DiGraphAny::inbound_iter (&self.graph, a)
+    }
+
+    fn is_sink (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        graph::DiGraphAny::is_sink (&self.graph, a)
+    }
+
+    fn is_source (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        graph::DiGraphAny::is_source (&self.graph, a)
+    }
+
+    fn outbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::DiGraphAny::outbound (&self.graph, a)
+    }
+
+    fn outbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::DiGraphAny::outbound_iter (&self.graph, a)
+    }
+
+    fn sinks (&self)
+        -> collections::HashSet<usize>
+    {
+        graph::DiGraphAny::sinks (&self.graph)
+    }
+
+    fn sources (&self)
+        -> collections::HashSet<usize>
+    {
+        graph::DiGraphAny::sources (&self.graph)
+    }
+}
+
+impl<W> graph::LabelledGraphAny for LabelledMultiGraph<W>
+    where
+        W: graph::Weight
+{
+    type Label = String;
+
+    fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, error::GraphError>
+        where
+            String: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        self.vertex (a)
+    }
+
+    fn vertex_label (&self, a_id: &usize)
+        -> Result<String, error::GraphError>
+    {
+        self.vertex_label (a_id)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::MergePolicy;
+    use crate::algo;
+    use crate::graph;
+    use std::collections;
+    use std::sync;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| { let _ = env_logger::try_init (); });
+    }
+
+    #[test]
+    fn test_parallel_edges ()
+    {
+        init ();
+        let mut g = super::MultiGraph::new ();
+        let e0 = g.add_edge_raw (1,2,7,None).expect ("Failed to add edge 1 -> 2");
+        let e1 = g.add_edge_raw (1,2,3,Some (collections::HashMap::from ([(String::from ("route"), graph::AttributeValue::from ("express"))]))).expect ("Failed to add edge 1 -> 2");
+        let e2 = g.add_edge_raw (2,1,4,None).expect ("Failed to add edge 2 -> 1");
+
+        assert_eq! (g.edges ().len (), 3);
+        assert_eq! (g.edges_between (&(1,2)), vec![e0, e1]);
+        assert_eq! (g.edge (&e1).unwrap ().attrs["route"], graph::AttributeValue::from ("express"));
+        assert_eq! (graph::GraphAny::weight (&g, &(1,2)).unwrap (), 3);
+        assert_eq! (graph::GraphAny::edges_iter (&g).count (), 3);
+
+        g.remove_edge (&e1).expect ("Failed to remove edge");
+        assert_eq! (graph::GraphAny::weight (&g, &(1,2)).unwrap (), 7);
+        g.remove_edge (&e0).expect ("Failed to remove edge");
+        assert! (!graph::GraphAny::has_edge_raw (&g, &(1,2)));
+        assert! (g.remove_edge (&e0).is_err ());
+
+        g.remove_vertex_raw (&1).expect ("Failed to remove vertex 1");
+        assert! (g.edge (&e2).is_err ());
+        assert_eq! (g.vertices (), &collections::HashSet::from ([2]));
+    }
+
+    #[test]
+    fn test_merge_policies ()
+    {
+        init ();
+        let mut g = super::MultiGraph::new_with_name ("routes");
+        g.add_edge_raw (1,2,7,None).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (1,2,3,None).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (1,2,5,None).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (2,1,1,None).expect ("Failed to add edge 2 -> 1");
+
+        let weight = |policy| super::to_graph (&g, policy).expect ("Failed to_graph").edges ()[&(1,2)];
+        assert_eq! (weight (MergePolicy::First), 7);
+        assert_eq! (weight (MergePolicy::Last), 5);
+        assert_eq! (weight (MergePolicy::Min), 3);
+        assert_eq! (weight (MergePolicy::Max), 7);
+        assert_eq! (weight (MergePolicy::Sum), 15);
+        assert_eq! (super::to_graph (&g, MergePolicy::Strict).unwrap_err ().to_string (), "Conversion error: Cannot merge parallel edges (1, 2) with weights 7 and 3");
+
+        let ug = super::to_ugraph (&g, MergePolicy::Min).expect ("Failed to_ugraph");
+        assert_eq! (ug.edges (), &collections::HashMap::from ([( (1,2), 1 )]));
+        assert_eq! (ug.name (), "routes");
+
+        let sg = super::to_graph (&g, MergePolicy::Min).expect ("Failed to_graph");
+        let r = super::from_graph (&sg).expect ("Failed from_graph");
+        assert_eq! (r.edges ().len (), 2);
+        assert_eq! (super::to_graph (&r, MergePolicy::Strict).expect ("Failed to_graph").edges (), sg.edges ());
+
+        g.add_edge_raw (2,1,i64::MAX,None).expect ("Failed to add edge 2 -> 1");
+        assert_eq! (super::to_graph (&g, MergePolicy::Sum).unwrap_err ().to_string (), "Conversion error: Sum of parallel edges (2, 1) overflows");
+    }
+
+    #[test]
+    fn test_from_undirected ()
+    {
+        init ();
+        let mut ug = graph::UGraph::new_with_name ("u");
+        ug.add_edge_raw (2,1,3).expect ("Failed to add edge 1 -- 2");
+        ug.add_edge_raw (2,2,4).expect ("Failed to add edge 2 -- 2");
+        ug.add_vertex_raw (5).expect ("Failed to add vertex 5");
+        let g = super::from_ugraph (&ug).expect ("Failed from_ugraph");
+        assert_eq! (g.edges ().len (), 3);
+        assert_eq! (graph::GraphAny::weight (&g, &(2,1)).unwrap (), 3);
+        assert_eq! (graph::GraphAny::weight (&g, &(1,2)).unwrap (), 3);
+        assert_eq! (super::to_ugraph (&g, MergePolicy::Strict).expect ("Failed to_ugraph"), ug);
+        assert_eq! (super::to_ugraph (&g, MergePolicy::Sum).expect ("Failed to_ugraph").edges ()[&(1,2)], 6);
+
+        let mut lug = graph::LabelledUGraph::new ();
+        lug.set_self_loops (true).expect ("Failed to allow self loops");
+        lug.add_edge_weighted (String::from ("a"), String::from ("b"), Some (collections::HashMap::from ([(String::from ("mode"), graph::AttributeValue::from ("bus"))])), 2).expect ("Failed to add edge a -- b");
+        lug.add_edge (String::from ("b"), String::from ("b"), None).expect ("Failed to add edge b -- b");
+        let lg = super::from_labelled_ugraph (&lug).expect ("Failed from_labelled_ugraph");
+        assert! (lg.self_loops ());
+        assert_eq! (lg.edges_between (&(String::from ("b"), String::from ("a"))).unwrap ().len (), 1);
+        let back = super::to_labelled_ugraph (&lg, MergePolicy::Strict).expect ("Failed to_labelled_ugraph");
+        assert_eq! (back, lug);
+    }
+
+    #[test]
+    fn test_labelled ()
+    {
+        init ();
+        let mut g = super::LabelledMultiGraph::new ();
+        let bus = g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (collections::HashMap::from ([(String::from ("mode"), graph::AttributeValue::from ("bus"))])), 10).expect ("Failed to add edge a -> b");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (collections::HashMap::from ([(String::from ("mode"), graph::AttributeValue::from ("rail"))])), 4).expect ("Failed to add edge a -> b");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), None, 1).expect ("Failed to add edge b -> c");
+
+        assert_eq! (g.edges_between (&(String::from ("a"), String::from ("b"))).unwrap ().len (), 2);
+        assert_eq! (g.edge_labels (&bus).unwrap (), (String::from ("a"), String::from ("b")));
+        assert_eq! (g.add_edge (String::from ("a"), String::from ("a"), None).unwrap_err ().to_string (), "Edge error: edge vertices must be distinct");
+
+        let r = algo::multi_source_dijkstra_labelled (&g, &collections::HashSet::from ([String::from ("a")])).expect ("Failed dijkstra");
+        assert_eq! (r["a"].0["c"], 5);
+
+        let lg = super::to_labelled_graph (&g, MergePolicy::Max).expect ("Failed to_labelled_graph");
+        let (_, attrs) = lg.edge_attrs (&(String::from ("a"), String::from ("b"))).expect ("Failed to find edge a -> b");
+        assert_eq! (attrs["mode"], graph::AttributeValue::from ("bus"));
+        assert_eq! (lg.vertex ("c").unwrap (), g.vertex ("c").unwrap ());
+
+        let back = super::from_labelled_graph (&lg).expect ("Failed from_labelled_graph");
+        assert_eq! (back.graph ().edges ().len (), 2);
+        assert_eq! (super::to_labelled_ugraph (&back, MergePolicy::Strict).expect ("Failed to_labelled_ugraph").edges ().len (), 2);
+    }
+
+    #[test]
+    fn test_weighted ()
+    {
+        init ();
+        let mut g = super::MultiGraph::<f64>::new_weighted ();
+        g.add_edge_raw (1,2,0.5,None).expect ("Failed to add edge 1 -> 2");
+        g.add_edge_raw (1,2,0.25,None).expect ("Failed to add edge 1 -> 2");
+        assert! (g.add_edge_raw (1,2,f64::NAN,None).is_err ());
+        assert_eq! (g.edges ().len (), 2);
+        assert_eq! (graph::GraphAny::weight (&g, &(1,2)).unwrap (), 0.25);
+        assert_eq! (super::to_graph (&g, MergePolicy::Sum).expect ("Failed to_graph").edges ()[&(1,2)], 0.75);
+        assert_eq! (super::to_graph (&g, MergePolicy::Max).expect ("Failed to_graph").edges ()[&(1,2)], 0.5);
+
+        let mut lg = super::LabelledMultiGraph::<u32>::new_weighted ();
+        lg.add_edge_weighted (String::from ("a"), String::from ("b"), None, 2).expect ("Failed to add edge a -> b");
+        lg.add_edge (String::from ("a"), String::from ("b"), None).expect ("Failed to add edge a -> b");
+        let ug = super::to_labelled_ugraph (&lg, MergePolicy::Sum).expect ("Failed to_labelled_ugraph");
+        assert_eq! (ug.edges ().values ().copied ().collect::<Vec<_>> (), vec![2u32]);
+    }
+}
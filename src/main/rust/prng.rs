@@ -92,7 +92,7 @@ mod tests
 
     fn init ()
     {
-        INIT.call_once (env_logger::init);
+        INIT.call_once (|| { let _ = env_logger::try_init (); });
     }
 
     #[test]
@@ -0,0 +1,264 @@
+use crate::error;
+use crate::graph;
+use serde::{Serialize,Deserialize};
+use std::collections;
+
+// A schema declares the attributes vertex or edge payloads should carry. Each
+// declared attribute has an expected variant and is either required or
+// optional. A default fills in a missing attribute before the check, so a
+// required attribute with a default never fails for being absent. Optional
+// attributes may also hold Null. Attributes the schema does not declare are
+// left alone.
+//
+// A schema serialized with a graph is enforced when the graph is
+// deserialized, payloads that violate it fail the whole graph.
+
+#[derive(Clone,Copy,Debug,Deserialize,Serialize,PartialEq,Eq,Hash)]
+pub enum AttributeKind
+{
+    BooleanLiteral,
+    BooleanMap,
+    IntegerLiteral,
+    FloatLiteral,
+    StringLiteral,
+    StringArray,
+    StringMap,
+    StringSet,
+    Bytes,
+    FloatArray,
+    IntegerArray,
+    List,
+    Map,
+    Null
+}
+
+impl AttributeKind
+{
+    pub fn of (value: &graph::AttributeValue)
+        -> Self
+    {
+        match value
+        {
+            graph::AttributeValue::BooleanLiteral (_) => AttributeKind::BooleanLiteral,
+            graph::AttributeValue::BooleanMap (_) => AttributeKind::BooleanMap,
+            graph::AttributeValue::IntegerLiteral (_) => AttributeKind::IntegerLiteral,
+            graph::AttributeValue::FloatLiteral (_) => AttributeKind::FloatLiteral,
+            graph::AttributeValue::StringLiteral (_) => AttributeKind::StringLiteral,
+            graph::AttributeValue::StringArray (_) => AttributeKind::StringArray,
+            graph::AttributeValue::StringMap (_) => AttributeKind::StringMap,
+            graph::AttributeValue::StringSet (_) => AttributeKind::StringSet,
+            graph::AttributeValue::Bytes (_) => AttributeKind::Bytes,
+            graph::AttributeValue::FloatArray (_) => AttributeKind::FloatArray,
+            graph::AttributeValue::IntegerArray (_) => AttributeKind::IntegerArray,
+            graph::AttributeValue::List (_) => AttributeKind::List,
+            graph::AttributeValue::Map (_) => AttributeKind::Map,
+            graph::AttributeValue::Null => AttributeKind::Null
+        }
+    }
+}
+
+#[derive(Clone,Debug,Deserialize,Serialize,PartialEq)]
+pub struct AttributeSpec
+{
+    pub kind: AttributeKind,
+    pub required: bool,
+    #[serde(default)]
+    pub default: Option<graph::AttributeValue>
+}
+
+impl AttributeSpec
+{
+    pub fn required (kind: AttributeKind) -> Self
+    {
+        Self { kind, required: true, default: None }
+    }
+
+    pub fn optional (kind: AttributeKind) -> Self
+    {
+        Self { kind, required: false, default: None }
+    }
+
+    pub fn with_default (mut self, value: graph::AttributeValue) -> Self
+    {
+        self.default = Some (value);
+        self
+    }
+}
+
+#[derive(Clone,Debug,Default,Deserialize,Serialize,PartialEq)]
+pub struct AttributeSchema
+{
+    pub attributes: collections::BTreeMap<String, AttributeSpec>
+}
+
+impl AttributeSchema
+{
+    pub fn new () -> Self
+    {
+        Self::default ()
+    }
+
+    pub fn with_attribute (mut self, name: &str, spec: AttributeSpec) -> Self
+    {
+        self.attributes.insert (name.to_string (), spec);
+        self
+    }
+
+    pub fn apply_defaults (&self, attrs: &mut graph::Attributes)
+    {
+        for (name, spec) in &self.attributes
+        {
+            if let Some (default) = &spec.default && !attrs.contains_key (name)
+            {
+                attrs.insert (name.clone (), default.clone ());
+            }
+        }
+    }
+
+    // Violations of attrs as they would stand once the defaults are applied
+    pub fn violations (&self, attrs: &graph::Attributes)
+        -> Vec<String>
+    {
+        let mut violations = Vec::<String>::new ();
+        for (name, spec) in &self.attributes
+        {
+            match attrs.get (name).or (spec.default.as_ref ())
+            {
+                None => {
+                    if spec.required
+                    {
+                        violations.push (format! ("missing required attribute {}", name));
+                    }
+                },
+                Some (value) => {
+                    let kind = AttributeKind::of (value);
+                    let optional_null = kind == AttributeKind::Null && !spec.required;
+                    if kind != spec.kind && !optional_null
+                    {
+                        violations.push (format! ("attribute {} is {:?}, expected {:?}", name, kind, spec.kind));
+                    }
+                }
+            }
+        }
+        violations
+    }
+
+    // Fills in the defaults of a payload that satisfies the schema, or leaves
+    // it untouched and reports what is wrong
+    pub fn conform<T> (&self, payload: &mut T, context: &str)
+        -> Result<(), error::GraphError>
+        where
+            T: graph::Payload
+    {
+        if let Some (attrs) = payload.attributes_mut ()
+        {
+            let violations = self.violations (attrs);
+            if !violations.is_empty ()
+            {
+                return Err (error::GraphError::SchemaError (format! ("{}: {}", context, violations.join (", "))));
+            }
+            self.apply_defaults (attrs);
+        }
+        Ok (())
+    }
+}
+
+#[derive(Clone,Debug,Default,Deserialize,Serialize,PartialEq)]
+pub struct GraphSchema
+{
+    #[serde(default)]
+    pub vertex: AttributeSchema,
+    #[serde(default)]
+    pub edge: AttributeSchema
+}
+
+impl GraphSchema
+{
+    pub fn new () -> Self
+    {
+        Self::default ()
+    }
+
+    pub fn with_vertex_attribute (mut self, name: &str, spec: AttributeSpec) -> Self
+    {
+        self.vertex.attributes.insert (name.to_string (), spec);
+        self
+    }
+
+    pub fn with_edge_attribute (mut self, name: &str, spec: AttributeSpec) -> Self
+    {
+        self.edge.attributes.insert (name.to_string (), spec);
+        self
+    }
+}
+
+// Every violation in the payloads of a labelled graph, vertices then edges in id order
+pub (crate) fn check_payloads<V, E, L> (schema: &GraphSchema, vertex_label: &collections::HashMap<usize, L>, vertex_attrs: &collections::HashMap<usize, V>, edge_attrs: &collections::HashMap<(usize,usize), E>)
+    -> Vec<error::GraphError>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        L: graph::Label
+{
+    let mut errors = Vec::<error::GraphError>::new ();
+    let mut vs = vertex_attrs.iter ().filter_map (|(a_id, v)| v.attributes ().map (|attrs| (*a_id, attrs))).collect::<Vec<_>> ();
+    vs.sort_by_key (|(a_id, _)| *a_id);
+    for (a_id, attrs) in vs
+    {
+        let label = vertex_label.get (&a_id).map (|a| format! ("{:?}", a)).unwrap_or_default ();
+        for violation in schema.vertex.violations (attrs)
+        {
+            errors.push (error::GraphError::SchemaError (format! ("vertex {}:{}: {}", a_id, label, violation)));
+        }
+    }
+    let mut es = edge_attrs.iter ().filter_map (|(e, v)| v.attributes ().map (|attrs| (*e, attrs))).collect::<Vec<_>> ();
+    es.sort_by_key (|(e, _)| *e);
+    for ((a_id, b_id), attrs) in es
+    {
+        for violation in schema.edge.violations (attrs)
+        {
+            errors.push (error::GraphError::SchemaError (format! ("edge ({},{}): {}", a_id, b_id, violation)));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::graph;
+    use std::sync;
+    use super::*;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| {
+            let _ = env_logger::try_init ();
+        });
+    }
+
+    #[test]
+    fn test_violations ()
+    {
+        init ();
+        let schema = AttributeSchema::new ()
+            .with_attribute ("weight", AttributeSpec::required (AttributeKind::IntegerLiteral))
+            .with_attribute ("colour", AttributeSpec::optional (AttributeKind::StringLiteral).with_default (graph::AttributeValue::from ("red")))
+            .with_attribute ("note", AttributeSpec::optional (AttributeKind::StringLiteral));
+
+        let mut attrs = graph::Attributes::from ([ (String::from ("weight"), graph::AttributeValue::from (3i64)), (String::from ("note"), graph::AttributeValue::Null) ]);
+        assert! (schema.violations (&attrs).is_empty ());
+        schema.conform (&mut attrs, "v").expect ("Failed to conform");
+        assert_eq! (attrs["colour"], graph::AttributeValue::from ("red"));
+
+        let mut attrs = graph::Attributes::from ([ (String::from ("colour"), graph::AttributeValue::from (1i64)) ]);
+        assert_eq! (schema.violations (&attrs), vec![
+            String::from ("attribute colour is IntegerLiteral, expected StringLiteral"),
+            String::from ("missing required attribute weight")
+        ]);
+        assert_eq! (schema.conform (&mut attrs, "v").unwrap_err ().to_string (), "Schema error: v: attribute colour is IntegerLiteral, expected StringLiteral, missing required attribute weight");
+        assert_eq! (attrs.len (), 1);
+    }
+}
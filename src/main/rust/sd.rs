@@ -62,5 +62,76 @@ pub mod key_usize
     }
 }
 
+pub mod key_any
+{
+    // Maps keyed by arbitrary types, e.g. tuples, which JSON objects cannot
+    // hold. Written as a sequence of (key, value) pairs, but maps with plain
+    // keys written by earlier versions are still read
+    use serde::{self, Deserializer, Serializer};
+    use std::collections;
+    use std::fmt;
+    use std::hash;
+    use std::marker;
+
+    struct PairsVisitor<K, V> (marker::PhantomData<(K, V)>);
+
+    impl<'de, K, V> serde::de::Visitor<'de> for PairsVisitor<K, V>
+    where
+        K: serde::Deserialize<'de> + Eq + hash::Hash,
+        V: serde::Deserialize<'de>
+    {
+        type Value = collections::HashMap<K, V>;
+
+        fn expecting (&self, formatter: &mut fmt::Formatter)
+            -> fmt::Result
+        {
+            formatter.write_str ("a sequence of key value pairs or a map")
+        }
+
+        fn visit_seq<A> (self, mut seq: A)
+            -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>
+        {
+            let mut r = collections::HashMap::<K, V>::with_capacity (seq.size_hint ().unwrap_or (0));
+            while let Some ((k, v)) = seq.next_element::<(K, V)> ()?
+            {
+                r.insert (k, v);
+            }
+            Ok (r)
+        }
 
+        fn visit_map<A> (self, mut map: A)
+            -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>
+        {
+            let mut r = collections::HashMap::<K, V>::with_capacity (map.size_hint ().unwrap_or (0));
+            while let Some ((k, v)) = map.next_entry::<K, V> ()?
+            {
+                r.insert (k, v);
+            }
+            Ok (r)
+        }
+    }
 
+    pub fn deserialize<'de, D, K, V> (deserializer: D)
+        -> Result<collections::HashMap<K, V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        K: serde::Deserialize<'de> + Eq + hash::Hash,
+        V: serde::Deserialize<'de>
+    {
+        deserializer.deserialize_any (PairsVisitor (marker::PhantomData))
+    }
+
+    pub fn serialize<S, K, V> (hs: &collections::HashMap<K, V>, serializer: S)
+        -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K: serde::Serialize,
+        V: serde::Serialize
+    {
+        serializer.collect_seq (hs.iter ())
+    }
+}
@@ -0,0 +1,453 @@
+// Read-only filtered views over the graph types
+//
+// A view borrows its graph and keeps only the ids of the vertices that pass
+// the vertex filter; edges are filtered lazily as they are visited, so
+// nothing is cloned until `materialize` is asked for an owned graph. An edge
+// is in the view when both endpoints are and it passes the edge filter.
+// Views implement GraphAny, and DiGraphAny and LabelledGraphAny when the
+// viewed graph does, so the algo routines run on them directly. Undirected
+// edges may be handed to the edge filter in either orientation.
+use crate::error;
+use crate::graph;
+use std::borrow;
+use std::collections;
+use std::fmt;
+use std::hash;
+
+type EdgeFilter<'a> = Box<dyn Fn (&(usize, usize)) -> bool + 'a>;
+
+pub struct GraphView<'a, G>
+    where
+        G: graph::GraphAny
+{
+    graph: &'a G,
+    vertices: collections::HashSet<usize>,
+    edge_filter: EdgeFilter<'a>
+}
+
+// Filters on ids and weights, for any graph type
+pub fn view<'a, G, VF, EF> (g: &'a G, vertex_filter: VF, edge_filter: EF)
+    -> GraphView<'a, G>
+    where
+        G: graph::GraphAny,
+        VF: Fn (usize) -> bool,
+        EF: Fn (&(usize, usize), G::Weight) -> bool + 'a
+{
+    GraphView {
+        graph: g,
+        vertices: g.vertices ().iter ().copied ().filter (|a| vertex_filter (*a)).collect (),
+        edge_filter: Box::new (move |ev| g.weight (ev).is_ok_and (|w| edge_filter (ev, w)))
+    }
+}
+
+// Filters that also see the labels and payloads of a labelled graph
+pub fn view_labelled_graph<'a, W, V, E, L, VF, EF> (g: &'a graph::LabelledGraph<W, V, E, L>, vertex_filter: VF, edge_filter: EF)
+    -> GraphView<'a, graph::LabelledGraph<W, V, E, L>>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label,
+        VF: Fn (usize, &L, &V) -> bool,
+        EF: Fn (&(usize, usize), W, &E) -> bool + 'a
+{
+    GraphView {
+        graph: g,
+        vertices: g.vertices ().iter ()
+            .copied ()
+            .filter (|a| matches! ( (g.vertex_label (a), g.vertex_attrs_raw (a)), (Ok (l), Ok (v)) if vertex_filter (*a, &l, v) ))
+            .collect (),
+        edge_filter: Box::new (move |ev| matches! ( (graph::GraphAny::weight (g, ev), g.edge_attrs_raw (ev)), (Ok (w), Ok (e)) if edge_filter (ev, w, e) ))
+    }
+}
+
+pub fn view_labelled_ugraph<'a, W, V, E, L, VF, EF> (g: &'a graph::LabelledUGraph<W, V, E, L>, vertex_filter: VF, edge_filter: EF)
+    -> GraphView<'a, graph::LabelledUGraph<W, V, E, L>>
+    where
+        V: graph::Payload,
+        E: graph::Payload,
+        W: graph::Weight,
+        L: graph::Label,
+        VF: Fn (usize, &L, &V) -> bool,
+        EF: Fn (&(usize, usize), W, &E) -> bool + 'a
+{
+    GraphView {
+        graph: g,
+        vertices: g.vertices ().iter ()
+            .copied ()
+            .filter (|a| matches! ( (g.vertex_label (a), g.vertex_attrs_raw (a)), (Ok (l), Ok (v)) if vertex_filter (*a, &l, v) ))
+            .collect (),
+        edge_filter: Box::new (move |ev| matches! ( (graph::GraphAny::weight (g, ev), g.edge_attrs_raw (ev)), (Ok (w), Ok (e)) if edge_filter (ev, w, e) ))
+    }
+}
+
+impl<'a, G> GraphView<'a, G>
+    where
+        G: graph::GraphAny
+{
+    pub fn graph (&self)
+        -> &'a G
+    {
+        self.graph
+    }
+
+    fn check_vertex (&self, a: &usize)
+        -> Result<(), error::GraphError>
+    {
+        if self.vertices.contains (a)
+        {
+            Ok (())
+        }
+        else
+        {
+            Err (error::GraphError::VertexError (format! ("Vertex: {} not found in view", a)))
+        }
+    }
+
+    fn keep_edge (&self, ev: &(usize, usize))
+        -> bool
+    {
+        self.vertices.contains (&ev.0) && self.vertices.contains (&ev.1) && (self.edge_filter) (ev)
+    }
+
+    // Edges sorted so the materialized graphs do not depend on hash order
+    fn sorted_edges (&self)
+        -> Vec<((usize, usize), G::Weight)>
+    {
+        let mut edges = graph::GraphAny::edges_iter (self).collect::<Vec<_>> ();
+        edges.sort_by_key (|(e, _)| *e);
+        edges
+    }
+
+    fn sorted_vertices (&self)
+        -> Vec<usize>
+    {
+        let mut vertices = self.vertices.iter ().copied ().collect::<Vec<_>> ();
+        vertices.sort ();
+        vertices
+    }
+}
+
+impl<W> GraphView<'_, graph::Graph<W>>
+    where
+        W: graph::Weight
+{
+    pub fn materialize (&self)
+        -> Result<graph::Graph<W>, error::GraphError>
+    {
+        let mut g = graph::Graph::new_weighted_with_name (&self.graph.name ());
+        g.set_self_loops (self.graph.self_loops ())?;
+        for a in self.sorted_vertices ()
+        {
+            g.add_vertex_raw (a)?;
+        }
+        for ((a, b), w) in self.sorted_edges ()
+        {
+            g.add_edge_raw (a, b, w)?;
+        }
+        Ok (g)
+    }
+}
+
+impl<W> GraphView<'_, graph::UGraph<W>>
+    where
+        W: graph::Weight
+{
+    pub fn materialize (&self)
+        -> Result<graph::UGraph<W>, error::GraphError>
+    {
+        let mut g = graph::UGraph::new_weighted_with_name (&self.graph.name ());
+        g.set_self_loops (self.graph.self_loops ())?;
+        for a in self.sorted_vertices ()
+        {
+            g.add_vertex_raw (a)?;
+        }
+        for ((a, b), w) in self.sorted_edges ()
+        {
+            g.add_edge_raw (a, b, w)?;
+        }
+        Ok (g)
+    }
+}
+
+// The materialized labelled graphs keep vertex ids, payloads and the schema,
+// but not attribute indexes
+impl<W, V, E, L> GraphView<'_, graph::LabelledGraph<W, V, E, L>>
+    where
+        V: graph::Payload + Clone,
+        E: graph::Payload + Clone,
+        W: graph::Weight,
+        L: graph::Label
+{
+    pub fn materialize (&self)
+        -> Result<graph::LabelledGraph<W, V, E, L>, error::GraphError>
+    {
+        let mut g = graph::LabelledGraph::new_weighted_with_name (&self.graph.graph ().name ());
+        g.set_self_loops (self.graph.self_loops ())?;
+        for a in self.sorted_vertices ()
+        {
+            g.add_vertex_raw (a, self.graph.vertex_label (&a)?, Some (self.graph.vertex_attrs_raw (&a)?.clone ()))?;
+        }
+        for ((a, b), w) in self.sorted_edges ()
+        {
+            g.add_edge_raw (a, self.graph.vertex_label (&a)?, b, self.graph.vertex_label (&b)?, Some (self.graph.edge_attrs_raw (&(a, b))?.clone ()), w)?;
+        }
+        g.set_schema (self.graph.schema ().cloned ())?;
+        Ok (g)
+    }
+}
+
+impl<W, V, E, L> GraphView<'_, graph::LabelledUGraph<W, V, E, L>>
+    where
+        V: graph::Payload + Clone,
+        E: graph::Payload + Clone,
+        W: graph::Weight,
+        L: graph::Label
+{
+    pub fn materialize (&self)
+        -> Result<graph::LabelledUGraph<W, V, E, L>, error::GraphError>
+    {
+        let mut g = graph::LabelledUGraph::new_weighted_with_name (&self.graph.graph ().name ());
+        g.set_self_loops (self.graph.self_loops ())?;
+        for a in self.sorted_vertices ()
+        {
+            g.add_vertex_raw (a, self.graph.vertex_label (&a)?, Some (self.graph.vertex_attrs_raw (&a)?.clone ()))?;
+        }
+        for ((a, b), w) in self.sorted_edges ()
+        {
+            g.add_edge_raw (a, self.graph.vertex_label (&a)?, b, self.graph.vertex_label (&b)?, Some (self.graph.edge_attrs_raw (&(a, b))?.clone ()), w)?;
+        }
+        g.set_schema (self.graph.schema ().cloned ())?;
+        Ok (g)
+    }
+}
+
+impl<G> graph::GraphAny for GraphView<'_, G>
+    where
+        G: graph::GraphAny
+{
+    type Weight = G::Weight;
+
+    fn adjacent (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        Ok (self.adjacent_iter (a)?.collect ())
+    }
+
+    fn adjacent_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        self.check_vertex (a)?;
+        let a = *a;
+        Ok (Box::new (self.graph.adjacent_iter (&a)?.filter (move |b| self.keep_edge (&(a, *b)))))
+    }
+
+    fn edges_iter (&self)
+        -> Box<dyn Iterator<Item = ((usize, usize), G::Weight)> + '_>
+    {
+        Box::new (self.graph.edges_iter ().filter (|(e, _)| self.keep_edge (e)))
+    }
+
+    fn has_edge_raw (&self, ev: &(usize, usize))
+        -> bool
+    {
+        self.graph.has_edge_raw (ev) && self.keep_edge (ev)
+    }
+
+    fn neighbours (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        Ok (self.neighbours_iter (a)?.collect ())
+    }
+
+    fn neighbours_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        self.check_vertex (a)?;
+        let a = *a;
+        Ok (Box::new (self.graph.neighbours_iter (&a)?.filter (move |b| self.has_edge_raw (&(a, *b)) || self.has_edge_raw (&(*b, a)))))
+    }
+
+    fn vertices (&self)
+        -> &collections::HashSet<usize>
+    {
+        &self.vertices
+    }
+
+    fn weight (&self, ev: &(usize, usize))
+        -> Result<G::Weight, error::GraphError>
+    {
+        if self.has_edge_raw (ev)
+        {
+            self.graph.weight (ev)
+        }
+        else
+        {
+            Err (error::GraphError::EdgeError (format! ("No edge found for {:?}", ev)))
+        }
+    }
+}
+
+impl<G> graph::DiGraphAny for GraphView<'_, G>
+    where
+        G: graph::DiGraphAny
+{
+    fn inbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        Ok (self.inbound_iter (a)?.collect ())
+    }
+
+    fn inbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        self.check_vertex (a)?;
+        let a = *a;
+        Ok (Box::new (self.graph.inbound_iter (&a)?.filter (move |b| self.keep_edge (&(*b, a)))))
+    }
+
+    fn is_sink (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        Ok (self.outbound_iter (a)?.next ().is_none ())
+    }
+
+    fn is_source (&self, a: &usize)
+        -> Result<bool, error::GraphError>
+    {
+        Ok (self.inbound_iter (a)?.next ().is_none ())
+    }
+
+    fn outbound (&self, a: &usize)
+        -> Result<collections::HashSet<usize>, error::GraphError>
+    {
+        graph::GraphAny::adjacent (self, a)
+    }
+
+    fn outbound_iter (&self, a: &usize)
+        -> Result<Box<dyn Iterator<Item = usize> + '_>, error::GraphError>
+    {
+        graph::GraphAny::adjacent_iter (self, a)
+    }
+
+    fn sinks (&self)
+        -> collections::HashSet<usize>
+    {
+        self.vertices.iter ().filter (|a| self.is_sink (a).unwrap_or (false)).copied ().collect ()
+    }
+
+    fn sources (&self)
+        -> collections::HashSet<usize>
+    {
+        self.vertices.iter ().filter (|a| self.is_source (a).unwrap_or (false)).copied ().collect ()
+    }
+}
+
+impl<G> graph::LabelledGraphAny for GraphView<'_, G>
+    where
+        G: graph::LabelledGraphAny
+{
+    type Label = G::Label;
+
+    fn vertex<Q> (&self, a: &Q)
+        -> Result<usize, error::GraphError>
+        where
+            G::Label: borrow::Borrow<Q>,
+            Q: hash::Hash + Eq + fmt::Debug + ?Sized
+    {
+        let a_id = self.graph.vertex (a)?;
+        self.check_vertex (&a_id)?;
+        Ok (a_id)
+    }
+
+    fn vertex_label (&self, a_id: &usize)
+        -> Result<G::Label, error::GraphError>
+    {
+        self.check_vertex (a_id)?;
+        self.graph.vertex_label (a_id)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::algo;
+    use crate::graph;
+    use crate::graph::{DiGraphAny, GraphAny};
+    use std::collections;
+    use std::sync;
+
+    static INIT: sync::Once = sync::Once::new ();
+
+    fn init ()
+    {
+        INIT.call_once (|| {
+            let _ = env_logger::try_init ();
+        });
+    }
+
+    #[test]
+    fn test_view_graph ()
+    {
+        init ();
+        let mut g = graph::Graph::new_with_name ("g");
+        g.add_edge_raw (1, 2, 1).expect ("Failed to add edge");
+        g.add_edge_raw (2, 3, 1).expect ("Failed to add edge");
+        g.add_edge_raw (1, 3, 5).expect ("Failed to add edge");
+        g.add_edge_raw (3, 4, 1).expect ("Failed to add edge");
+
+        // Drop vertex 4 and the cheap shortcut through 2
+        let v = super::view (&g, |a| a != 4, |e, _| *e != (2, 3));
+        assert_eq! (v.vertices (), &collections::HashSet::from ([1, 2, 3]));
+        assert! (!v.has_edge_raw (&(2, 3)) && v.has_edge_raw (&(1, 3)) && !v.has_edge_raw (&(3, 4)));
+        assert_eq! (v.sinks (), collections::HashSet::from ([2, 3]));
+        assert_eq! (v.inbound (&3).expect ("Failed to get inbound"), collections::HashSet::from ([1]));
+        assert_eq! (v.neighbours (&3).expect ("Failed to get neighbours"), collections::HashSet::from ([1]));
+        assert! (v.adjacent (&4).is_err ());
+        assert! (v.weight (&(2, 3)).is_err ());
+
+        let r = algo::multi_source_dijkstra (&v, &collections::HashSet::from ([1])).expect ("Failed dijkstra");
+        assert_eq! ( (r[&1].0[&3], &r[&1].1[&3]), (5, &vec![1, 3]) );
+        assert_eq! (algo::descendants (&v, 2).expect ("Failed descendants"), collections::HashSet::new ());
+        let order = algo::topological_sort (&v).expect ("Failed sort");
+        assert_eq! (order[0], 1);
+
+        let h = v.materialize ().expect ("Failed to materialize");
+        assert_eq! (h.name (), "g");
+        assert_eq! (h.edges (), &collections::HashMap::from ([ ((1, 2), 1), ((1, 3), 5) ]));
+        assert_eq! (g.edges ().len (), 4);
+    }
+
+    #[test]
+    fn test_view_labelled ()
+    {
+        init ();
+        let kind = |k: &str| graph::Attributes::from ([ (String::from ("kind"), graph::AttributeValue::from (k)) ]);
+        let mut g = graph::LabelledGraph::new ();
+        g.add_vertex (String::from ("a"), Some (kind ("gene"))).expect ("Failed to add vertex");
+        g.add_vertex (String::from ("b"), Some (kind ("gene"))).expect ("Failed to add vertex");
+        g.add_vertex (String::from ("c"), Some (kind ("rna"))).expect ("Failed to add vertex");
+        g.add_edge_weighted (String::from ("a"), String::from ("b"), Some (kind ("x")), 2).expect ("Failed to add edge");
+        g.add_edge_weighted (String::from ("b"), String::from ("c"), Some (kind ("x")), 2).expect ("Failed to add edge");
+        g.add_edge_weighted (String::from ("b"), String::from ("a"), Some (kind ("y")), 2).expect ("Failed to add edge");
+
+        let v = super::view_labelled_graph (&g, |_, _, attrs| attrs.get ("kind") == Some (&graph::AttributeValue::from ("gene")), |_, w, attrs| w > 1 && attrs.contains_key ("kind") && attrs["kind"] == graph::AttributeValue::from ("x"));
+        let paths = algo::single_shortest_path_labelled (&v, "a").expect ("Failed shortest path");
+        assert_eq! (paths, collections::HashMap::from ([ (String::from ("a"), vec![String::from ("a")]), (String::from ("b"), vec![String::from ("a"), String::from ("b")]) ]));
+        assert! (algo::single_shortest_path_labelled (&v, "c").is_err ());
+
+        let h = v.materialize ().expect ("Failed to materialize");
+        assert_eq! (h.vertex_labels (), collections::HashSet::from ([ String::from ("a"), String::from ("b") ]));
+        assert_eq! (h.vertex ("b").expect ("Failed to find b"), g.vertex ("b").expect ("Failed to find b"));
+        assert! (h.has_edge ( &(String::from ("a"), String::from ("b")) ) && !h.has_edge ( &(String::from ("b"), String::from ("a")) ));
+        assert_eq! (h.vertex_attrs ("a").expect ("Failed to find a").1, &kind ("gene"));
+
+        let mut ug = graph::LabelledUGraph::new ();
+        ug.add_edge (String::from ("b"), String::from ("a"), Some (kind ("x"))).expect ("Failed to add edge");
+        ug.add_edge (String::from ("c"), String::from ("b"), Some (kind ("y"))).expect ("Failed to add edge");
+        let uv = super::view_labelled_ugraph (&ug, |_, _, _| true, |_, _, attrs| attrs["kind"] == graph::AttributeValue::from ("x"));
+        let b = ug.vertex ("b").expect ("Failed to find b");
+        assert_eq! (uv.neighbours (&b).expect ("Failed to get neighbours"), collections::HashSet::from ([ ug.vertex ("a").expect ("Failed to find a") ]));
+        assert_eq! (uv.materialize ().expect ("Failed to materialize").edges ().len (), 1);
+    }
+}